@@ -25,23 +25,29 @@ pub async fn req(request_data: CheckCaptchaRequest) -> BackendRes<bool> {
         .map_err(|_| BackendError::RequestParamInvalid("".to_string()))?;
 
     let check_res = match kind {
-        Usage::Register => Captcha::check(&contact, &captcha, kind),
+        Usage::Register => Captcha::check(&contact, &captcha, kind).await,
         _ => {
             let user =
                 UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&contact))
                     .await
                     .map_err(|e| {
-                        if e.to_string().contains("DBError::DataNotFound") {
+                        if e.is_not_found() {
                             AccountManagerError::PhoneOrEmailNotRegister.into()
                         } else {
-                            BackendError::InternalError(e.to_string())
+                            BackendError::from(e)
                         }
                     })?
                     .into_inner();
-            Captcha::check(&user.id.to_string(), &captcha, kind)
+            Captcha::check(&user.id.to_string(), &captcha, kind).await
         }
     };
 
-    let is_ok = check_res.is_ok();
-    Ok(Some(is_ok))
+    //验证码作废了要让客户端提示重新获取
+    match check_res {
+        Ok(()) => Ok(Some(true)),
+        Err(BackendError::AccountManager(AccountManagerError::CaptchaTooManyAttempts(num))) => {
+            Err(AccountManagerError::CaptchaTooManyAttempts(num))?
+        }
+        Err(_) => Ok(Some(false)),
+    }
 }
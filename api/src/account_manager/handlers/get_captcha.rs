@@ -33,7 +33,7 @@ pub struct GetCaptchaWithTokenRequest {
     kind: String,
 }
 
-fn get(
+async fn get(
     device_id: String,
     contact: String,
     kind: Usage,
@@ -47,23 +47,23 @@ fn get(
         None => contact.clone(),
     };
 
-    if let Some(data) = captcha::get_captcha(&storage_key, &kind)? {
-        let past_time = now_millis() - data.created_at;
-        
-        if past_time <= CAPTCHA_REQUEST_INTERVAL {
-            let remain_time = CAPTCHA_REQUEST_INTERVAL - past_time;
-            let remain_secs = (remain_time / 1000) as u8;
+    if let Some(data) = captcha::get_captcha(&storage_key, &kind).await? {
+        let past_time = now_millis().saturating_sub(data.last_sent_at);
+        //同一联系方式发送越多，下一次需要等待越久
+        let interval = captcha::request_interval(data.sent_times);
+        if past_time <= interval {
+            let remain_time = interval - past_time;
+            let remain_secs = remain_time / 1000;
             Err(CaptchaRequestTooFrequently(remain_secs))?;
         } else if past_time <= CAPTCHA_EXPAIRE_TIME {
             debug!("send new code cover former code");
         } else {
-            //delete old and regenerate new
-            let _ = data.delete();
+            debug!("former code expired, regenerate new");
         }
     }
 
     let captcha = Captcha::new(storage_key, device_id, kind);
-    captcha.store()?;
+    captcha.store().await?;
 
     let content = format!(
         "[ChainLess] Your captcha is: {}, valid for 10 minutes.",
@@ -84,7 +84,7 @@ fn get(
     });
 
     //delete expired captcha
-    Captcha::clean_up_expired()?;
+    Captcha::clean_up_expired().await?;
     Ok(None)
 }
 
@@ -104,10 +104,10 @@ pub async fn without_token_req(request_data: GetCaptchaWithoutTokenRequest) -> B
             let user_info = UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&contact))
                 .await
                 .map_err(|err| {
-                    if err.to_string().contains("DBError::DataNotFound") {
+                    if err.is_not_found() {
                         AccountManagerError::PhoneOrEmailNotRegister.into()
                     } else {
-                        BackendError::InternalError(err.to_string())
+                        BackendError::from(err)
                     }
                 })?
                 .into_inner();
@@ -127,22 +127,22 @@ pub async fn without_token_req(request_data: GetCaptchaWithoutTokenRequest) -> B
                 }
             }
 
-            get(device_id, contact, kind, Some(user_info.id))
+            get(device_id, contact, kind, Some(user_info.id)).await
         }
         Register => {
             let find_res = UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&contact)).await;
             if find_res.is_ok() {
                 Err(AccountManagerError::PhoneOrEmailAlreadyRegister)?;
             }
-            get(device_id, contact, kind, None)
+            get(device_id, contact, kind, None).await
         }
         Login => match UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&contact)).await {
-            Ok(info) => get(device_id, contact, kind, Some(info.into_inner().id)),
+            Ok(info) => get(device_id, contact, kind, Some(info.into_inner().id)).await,
             Err(err) => {
-                if err.to_string().contains("DBError::DataNotFound") {
+                if err.is_not_found() {
                     Err(AccountManagerError::PhoneOrEmailNotRegister)?
                 } else {
-                    Err(BackendError::InternalError(err.to_string()))?
+                    Err(err)?
                 }
             }
         },
@@ -187,5 +187,5 @@ pub async fn with_token_req(
         }
     }
 
-    get(device_id, contact, kind, Some(user_id))
+    get(device_id, contact, kind, Some(user_id)).await
 }
@@ -21,10 +21,10 @@ pub async fn req(request_data: GetUserDeviceRoleRequest) -> BackendRes<KeyRole>
     let user = UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&contact))
         .await
         .map_err(|e| {
-            if e.to_string().contains("DBError::DataNotFound") {
+            if e.is_not_found() {
                 AccountManagerError::PhoneOrEmailNotRegister.into()
             } else {
-                BackendError::InternalError(e.to_string())
+                BackendError::from(e)
             }
         })?
         .into_inner();
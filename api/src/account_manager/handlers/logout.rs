@@ -0,0 +1,12 @@
+use actix_web::HttpRequest;
+
+use common::error_code::BackendRes;
+use models::user_session;
+
+use crate::utils::token_auth;
+
+pub async fn req(req: HttpRequest) -> BackendRes<String> {
+    let (user_id, device_id, _) = token_auth::validate_session(&req).await?;
+    user_session::revoke(user_id, &device_id).await?;
+    Ok(None::<String>)
+}
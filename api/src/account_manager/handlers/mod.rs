@@ -1,12 +1,15 @@
 pub mod check_captcha;
 pub mod contact_is_used;
-pub mod gen_token;
 pub mod get_captcha;
 pub mod get_user_device_role;
 pub mod login;
+pub mod logout;
+pub mod refresh_token;
 pub mod register;
 pub mod replenish_contact;
 pub mod reset_password;
+pub mod revoke_session;
+pub mod session_list;
 pub mod user_info;
 
 /*****
@@ -15,9 +18,9 @@ fn get_user_info() -> Result<UserInfo,BackendError>{
     match find_res {
         Ok(info) => Ok(info.user_info),
         Err(err) => {
-            if err.to_string().contains("DataNotFound") {
+            if matches!(err, DbError::NotFound) {
                Err(AccountManagerError::UserIdNotExist)
-            } else if err.to_string().contains("RepeatedData") {
+            } else if matches!(err, DbError::Repeated) {
                Err(AccountManagerError::UserIdNotExist)
             } else {
                 Err(ExternalServiceError::DBError(err.to_string()))
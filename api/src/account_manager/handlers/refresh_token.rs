@@ -0,0 +1,16 @@
+use common::error_code::BackendRes;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::session::{self, SessionToken};
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenRequest {
+    refresh_token: String,
+}
+
+pub async fn req(request_data: RefreshTokenRequest) -> BackendRes<SessionToken> {
+    let RefreshTokenRequest { refresh_token } = request_data;
+    let token = session::refresh(&refresh_token).await?;
+    Ok(Some(token))
+}
@@ -5,6 +5,7 @@ use models::airdrop::{AirdropEntity, AirdropFilter};
 use models::device_info::DeviceInfoEntity;
 //use log::{debug, info};
 use crate::utils::captcha::{Captcha, ContactType, Usage};
+use crate::utils::session::SessionToken;
 
 use common::error_code::BackendRes;
 use models::account_manager::{UserFilter};
@@ -62,7 +63,7 @@ async fn register(
     predecessor_invite_code: String,
     password: String,
     contact_type: ContactType,
-) -> BackendRes<String> {
+) -> BackendRes<SessionToken> {
     //check userinfo form db
     let find_res =
         UserInfoEntity::find(UserFilter::ByPhoneOrEmail(&contact)).await?;
@@ -71,7 +72,7 @@ async fn register(
     }
 
     //store user info
-    Captcha::check_and_delete(&contact, &captcha, Usage::Register)?;
+    Captcha::check_and_delete(&contact, &captcha, Usage::Register).await?;
 
     let this_user_id = gen_user_id().await?;
     let mut view = UserInfoEntity::new_with_specified(this_user_id, &password.hash());
@@ -108,12 +109,8 @@ async fn register(
     let device = DeviceInfoEntity::new_with_specified(&device_id, &device_brand, this_user_id);
     device.insert().await?;
 
-    let token = crate::utils::token_auth::create_jwt(
-        this_user_id,
-        token_version,
-        &device_id,
-        &device_brand,
-    )?;
+    let token =
+        crate::utils::session::open(this_user_id, token_version, &device_id, &device_brand).await?;
     info!("user {} register successfully", contact);
     Ok(Some(token))
 }
@@ -121,7 +118,7 @@ async fn register(
 pub mod by_email {
     use super::*;
 
-    pub async fn req(request_data: RegisterByEmailRequest) -> BackendRes<String> {
+    pub async fn req(request_data: RegisterByEmailRequest) -> BackendRes<SessionToken> {
         let RegisterByEmailRequest {
             device_id,
             device_brand,
@@ -147,7 +144,7 @@ pub mod by_email {
 pub mod by_phone {
     use super::*;
 
-    pub async fn req(request_data: RegisterByPhoneRequest) -> BackendRes<String> {
+    pub async fn req(request_data: RegisterByPhoneRequest) -> BackendRes<SessionToken> {
         let RegisterByPhoneRequest {
             device_id,
             device_brand,
@@ -35,7 +35,7 @@ pub async fn req(req: HttpRequest, request_data: ReplenishContactRequest) -> Bac
         contact: replenish_contact,
         captcha,
     } = request_data;
-    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::ReplenishContact)?;
+    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::ReplenishContact).await?;
 
     let replenish_contact_type: ContactType = replenish_contact.parse()?;
 
@@ -1,6 +1,7 @@
 use actix_web::{web, HttpRequest};
 use blockchain::multi_sig::MultiSig;
 use blockchain::ContractClient;
+use common::data_structures::login_attempt::LoginAttemptResult;
 use common::data_structures::KeyRole;
 use common::hash::Hash;
 use models::device_info::{DeviceInfoEntity, DeviceInfoFilter};
@@ -11,10 +12,11 @@ use serde::{Deserialize, Serialize};
 use tracing::debug;
 
 use crate::utils::captcha::{Captcha, Usage};
-use crate::utils::judge_role_by_strategy;
+use crate::utils::{judge_role_by_strategy, login_lock};
 use common::error_code::{AccountManagerError::*, WalletError};
-use common::error_code::{BackendError, BackendRes};
+use common::error_code::BackendRes;
 use models::account_manager::{UserFilter, UserUpdater};
+use models::user_session;
 use models::{account_manager::UserInfoEntity, PsqlOp};
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -27,7 +29,7 @@ pub struct ResetPasswordRequest {
 }
 
 pub async fn req(
-    _req: HttpRequest,
+    req: HttpRequest,
     request_data: web::Json<ResetPasswordRequest>,
 ) -> BackendRes<String> {
     debug!("start reset_password");
@@ -71,7 +73,8 @@ pub async fn req(
         &user_info.id.to_string(),
         &captcha,
         Usage::ResetLoginPassword,
-    )?;
+    )
+    .await?;
 
     //modify user's password  at db
     UserInfoEntity::update_single(
@@ -79,12 +82,12 @@ pub async fn req(
         UserFilter::ById(&user_info.id),
     )
     .await?;
+    //所有设备都要用新密码重新登陆
+    user_session::revoke_all(user_info.id).await?;
 
     //clear retry status after login by captcha
-    let retry_storage = &mut super::login::LOGIN_RETRY
-        .lock()
-        .map_err(|e| BackendError::InternalError(e.to_string()))?;
-    retry_storage.remove(&user_info.id);
+    let ip = login_lock::client_ip(&req);
+    login_lock::record(user_info.id, &device_id, &ip, LoginAttemptResult::Unlocked).await?;
 
     Ok(None::<String>)
 }
@@ -0,0 +1,44 @@
+use actix_web::HttpRequest;
+
+use common::data_structures::KeyRole;
+use common::error_code::{BackendRes, WalletError};
+use models::device_info::{DeviceInfoEntity, DeviceInfoFilter};
+use models::{user_session, PsqlOp};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{get_user_context, judge_role_by_strategy, token_auth};
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeSessionRequest {
+    device_id: String,
+}
+
+pub async fn req(req: HttpRequest, request_data: RevokeSessionRequest) -> BackendRes<String> {
+    let (user_id, _, device_id, _) = token_auth::validate_credentials(&req).await?;
+    let RevokeSessionRequest {
+        device_id: target_device_id,
+    } = request_data;
+
+    //只有主设备可以踢掉从设备的会话
+    let context = get_user_context(&user_id, &device_id).await?;
+    let role = context.role()?;
+    if role != KeyRole::Master {
+        Err(WalletError::UneligiableRole(role, KeyRole::Master))?;
+    }
+
+    let target_device =
+        DeviceInfoEntity::find_single(DeviceInfoFilter::ByDeviceUser(&target_device_id, &user_id))
+            .await?
+            .into_inner();
+    let target_role = judge_role_by_strategy(
+        context.strategy.as_ref(),
+        target_device.hold_pubkey.as_deref(),
+    )?;
+    if target_role != KeyRole::Servant {
+        Err(WalletError::UneligiableRole(target_role, KeyRole::Servant))?;
+    }
+
+    user_session::revoke(user_id, &target_device_id).await?;
+    Ok(None::<String>)
+}
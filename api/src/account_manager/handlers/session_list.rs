@@ -0,0 +1,48 @@
+use actix_web::HttpRequest;
+
+use common::data_structures::KeyRole;
+use common::error_code::BackendRes;
+use common::utils::time::now_millis;
+use models::device_info::{DeviceInfoEntity, DeviceInfoFilter};
+use models::{user_session, PsqlOp};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{get_user_context, judge_role_by_strategy, token_auth};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct SessionListResponse {
+    pub device_id: String,
+    pub device_brand: String,
+    pub key_role: KeyRole,
+    pub last_active_at: u64,
+    pub refresh_expire_at: u64,
+    pub is_current: bool,
+}
+
+pub async fn req(req: HttpRequest) -> BackendRes<Vec<SessionListResponse>> {
+    let (user_id, device_id, session_id) = token_auth::validate_session(&req).await?;
+
+    let sessions = user_session::list(user_id, now_millis()).await?;
+    let devices = DeviceInfoEntity::find(DeviceInfoFilter::ByUser(&user_id)).await?;
+    let context = get_user_context(&user_id, &device_id).await?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| -> anyhow::Result<SessionListResponse> {
+            let hold_pubkey = devices
+                .iter()
+                .find(|device| device.device_info.id == session.device_id)
+                .and_then(|device| device.device_info.hold_pubkey.as_deref());
+            let key_role = judge_role_by_strategy(context.strategy.as_ref(), hold_pubkey)?;
+            Ok(SessionListResponse {
+                is_current: session.session_id == session_id,
+                device_id: session.device_id,
+                device_brand: session.device_brand,
+                key_role,
+                last_active_at: session.last_active_at,
+                refresh_expire_at: session.refresh_expire_at,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Some(sessions))
+}
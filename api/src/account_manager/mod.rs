@@ -10,10 +10,12 @@ use handlers::get_captcha::GetCaptchaWithoutTokenRequest;
 use handlers::get_user_device_role::GetUserDeviceRoleRequest;
 use handlers::login::LoginByCaptchaRequest;
 use handlers::login::LoginRequest;
+use handlers::refresh_token::RefreshTokenRequest;
 use handlers::register::RegisterByEmailRequest;
 use handlers::register::RegisterByPhoneRequest;
 use handlers::replenish_contact::ReplenishContactRequest;
 use handlers::reset_password::ResetPasswordRequest;
+use handlers::revoke_session::RevokeSessionRequest;
 
 use tracing::debug;
 
@@ -119,7 +121,7 @@ async fn contact_is_used(
 
 * @apiExample {curl} Example usage:
 * curl -X GET "http://120.232.251.101:8066/accountManager/contactIsUsed?contact=test000001@gmail.com"
-* @apiSuccess {String=0,1,2,2008} status_code         状态码.
+* @apiSuccess {String=0,1,2,2008,2019} status_code         状态码.
 * @apiSuccess {String} msg 状态信息
 * @apiSuccess {bool} data                验证码是否存在
 * @apiSampleRequest http://120.232.251.101:8066/accountManager/contactIsUsed
@@ -191,9 +193,13 @@ async fn user_info(req: HttpRequest) -> impl Responder {
     curl -X POST http://120.232.251.101:8066/accountManager/registerByEmail -H "Content-Type: application/json" -d
   '{"deviceId": "123","email": "test000001@gmail.com","captcha":"000000","password":"123456789","encryptedPrikey": "123",
    "pubkey": "7d2e7d073257358277821954b0b0d173077f6504e50a8fefe3ac02e2bff9ee3e"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2006,2013,2016} status_code         状态码.
+* @apiSuccess {String=0,1,2002,2003,2004,2019,2006,2013,2016} status_code         状态码.
 * @apiSuccess {String} msg                 状态详情
-* @apiSuccess {String} data                token值.
+* @apiSuccess {Object} data                登陆会话的token.
+* @apiSuccess {String} data.access_token         access token，放在Authorization头里.
+* @apiSuccess {Number} data.access_expire_at     access token的过期时间.
+* @apiSuccess {String} data.refresh_token        refresh token，用于换新的token.
+* @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
 * @apiSampleRequest http://120.232.251.101:8066/accountManager/registerByEmail
 */
 
@@ -225,9 +231,13 @@ async fn register_by_email(
 *    curl -X POST http://120.232.251.101:8066/accountManager/registerByPhone -H "Content-Type: application/json" -d
   '{"deviceId": "123","phoneNumber": "+86 13682000011","captcha":"000000","password":"123456789","encryptedPrikey": "123",
    "pubkey": "7d2e7d073257358277821954b0b0d173077f6504e50a8fefe3ac02e2bff9ee33","predecessorInviteCode":"1"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2006,2013,2016} status_code         状态码.
+* @apiSuccess {String=0,1,2002,2003,2004,2019,2006,2013,2016} status_code         状态码.
 * @apiSuccess {String} msg          状态详情
-* @apiSuccess {String} data         token值.
+* @apiSuccess {Object} data                登陆会话的token.
+* @apiSuccess {String} data.access_token         access token，放在Authorization头里.
+* @apiSuccess {Number} data.access_expire_at     access token的过期时间.
+* @apiSuccess {String} data.refresh_token        refresh token，用于换新的token.
+* @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
 * @apiSampleRequest http://120.232.251.101:8066/accountManager/registerByEmail
 */
 #[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
@@ -257,7 +267,11 @@ async fn register_by_phone(
  *  '{"deviceId": "1234","contact": "test000001@gmail.com","password":"123456789"}'
 * @apiSuccess {String=0,1,2008,2009,2012} status_code         状态码.
 * @apiSuccess {String} msg                  状态详情
- * @apiSuccess {String} data                token值.
+ * @apiSuccess {Object} data                登陆会话的token.
+ * @apiSuccess {String} data.access_token         access token，放在Authorization头里.
+ * @apiSuccess {Number} data.access_expire_at     access token的过期时间.
+ * @apiSuccess {String} data.refresh_token        refresh token，用于换新的token.
+ * @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
  * @apiSampleRequest http://120.232.251.101:8066/accountManager/login
  */
 #[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
@@ -270,7 +284,7 @@ async fn login_by_password(
     debug!("{}", serde_json::to_string(&request_data.0).unwrap());
     gen_extra_respond(
         get_lang(&req),
-        handlers::login::req_by_password(request_data.into_inner()).await,
+        handlers::login::req_by_password(req, request_data.into_inner()).await,
     )
 }
 
@@ -315,9 +329,13 @@ async fn get_user_device_role(
  * @apiExample {curl} Example usage:
  *    curl -X POST http://120.232.251.101:8066/accountManager/login -H "Content-Type: application/json" -d
  *  '{"deviceId": "1234","contact": "test000001@gmail.com","password":"123456789"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2008} status_code         状态码.
+* @apiSuccess {String=0,1,2002,2003,2004,2019,2008} status_code         状态码.
 * @apiSuccess {String} msg  状态详情
- * @apiSuccess {String} data                token值.
+ * @apiSuccess {Object} data                登陆会话的token.
+ * @apiSuccess {String} data.access_token         access token，放在Authorization头里.
+ * @apiSuccess {Number} data.access_expire_at     access token的过期时间.
+ * @apiSuccess {String} data.refresh_token        refresh token，用于换新的token.
+ * @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
  * @apiSampleRequest http://120.232.251.101:8066/accountManager/login
  */
 #[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
@@ -329,7 +347,7 @@ async fn login_by_captcha(
     debug!("{}", serde_json::to_string(&request_data.0).unwrap());
     gen_extra_respond(
         get_lang(&req),
-        handlers::login::req_by_captcha(request_data.into_inner()).await,
+        handlers::login::req_by_captcha(req, request_data.into_inner()).await,
     )
 }
 
@@ -345,7 +363,7 @@ async fn login_by_captcha(
 * @apiExample {curl} Example usage:
   curl -X POST http://120.232.251.101:8066/accountManager/resetPassword -H "Content-Type: application/json"
  -d '{"deviceId": "123","contact": "test000001@gmail.com","captcha":"287695","newPassword":"123456788"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2008,3008} status_code         状态码.
+* @apiSuccess {String=0,1,2002,2003,2004,2019,2008,3008} status_code         状态码.
 * @apiSuccess {String} msg                 状态详情
 * @apiSuccess {String} data                null
 * @apiSampleRequest http://120.232.251.101:8066/accountManager/resetPassword
@@ -375,7 +393,7 @@ async fn reset_password(
 * @apiExample {curl} Example usage:
   curl -X POST http://120.232.251.101:8066/accountManager/ -H "Content-Type: application/json"
  -d '{"deviceId": "123","contact": "test000001@gmail.com","captcha":"287695","newPassword":"123456788"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2008,3008} status_code         状态码.
+* @apiSuccess {String=0,1,2002,2003,2004,2019,2008,3008} status_code         状态码.
 * @apiSuccess {String} msg                 状态详情
 * @apiSuccess {String} data                null
 * @apiSampleRequest http://120.232.251.101:8066/accountManager/replenishContact
@@ -395,23 +413,104 @@ async fn replenish_contact(
 }
 
 /**
- * @api {post} /accountManager/genToken   生成新的token
+ * @api {post} /accountManager/refreshToken   用refresh token换新的token
  * @apiVersion 0.0.1
- * @apiName GenToken
+ * @apiName RefreshToken
+ * @apiGroup AccountManager
+ * @apiBody {String} refreshToken   登陆或上次刷新拿到的refresh token，用过之后作废
+ * @apiExample {curl} Example usage:
+ *    curl -X POST http://120.232.251.101:8066/accountManager/refreshToken -H "Content-Type: application/json" -d
+ *  '{"refreshToken": "0123456789abcdef"}'
+* @apiSuccess {String=0,1,5} status_code         状态码.
+* @apiSuccess {String} msg  状态详情
+ * @apiSuccess {Object} data                登陆会话的token.
+ * @apiSuccess {String} data.access_token         access token，放在Authorization头里.
+ * @apiSuccess {Number} data.access_expire_at     access token的过期时间.
+ * @apiSuccess {String} data.refresh_token        refresh token，用于换新的token.
+ * @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
+ * @apiSampleRequest http://120.232.251.101:8066/accountManager/refreshToken
+ */
+#[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
+#[post("/accountManager/refreshToken")]
+async fn refresh_token(
+    req: HttpRequest,
+    request_data: web::Json<RefreshTokenRequest>,
+) -> impl Responder {
+    gen_extra_respond(
+        get_lang(&req),
+        handlers::refresh_token::req(request_data.into_inner()).await,
+    )
+}
+
+/**
+ * @api {post} /accountManager/logout   登出当前设备
+ * @apiVersion 0.0.1
+ * @apiName Logout
  * @apiGroup AccountManager
  * @apiHeader {String} Authorization  user's access token
  * @apiExample {curl} Example usage:
- *    curl -X POST http://120.232.251.101:8066/accountManager/ssssss -H "Content-Type: application/json" -d
- *  '{"deviceId": "1234","contact": "test000001@gmail.com","password":"123456789"}'
-* @apiSuccess {String=0,1,2002,2003,2004,2008} status_code         状态码.
+ *    curl -X POST http://120.232.251.101:8066/accountManager/logout -H "Content-Type: application/json"
+* @apiSuccess {String=0,1,5} status_code         状态码.
 * @apiSuccess {String} msg  状态详情
- * @apiSuccess {String} data                token值.
- * @apiSampleRequest http://120.232.251.101:8066/accountManager/genToken
+ * @apiSuccess {String} data                null
+ * @apiSampleRequest http://120.232.251.101:8066/accountManager/logout
  */
 #[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
-#[post("/accountManager/genToken")]
-async fn gen_token(req: HttpRequest) -> impl Responder {
-    gen_extra_respond(get_lang(&req), handlers::gen_token::req(req).await)
+#[post("/accountManager/logout")]
+async fn logout(req: HttpRequest) -> impl Responder {
+    gen_extra_respond(get_lang(&req), handlers::logout::req(req).await)
+}
+
+/**
+ * @api {get} /accountManager/sessionList   当前用户各设备的登陆会话
+ * @apiVersion 0.0.1
+ * @apiName SessionList
+ * @apiGroup AccountManager
+ * @apiHeader {String} Authorization  user's access token
+ * @apiExample {curl} Example usage:
+ *    curl -X GET http://120.232.251.101:8066/accountManager/sessionList
+* @apiSuccess {String=0,1,5} status_code         状态码.
+* @apiSuccess {String} msg  状态详情
+ * @apiSuccess {Object[]} data                会话列表，最近活跃的在前.
+ * @apiSuccess {String} data.device_id            设备ID.
+ * @apiSuccess {String} data.device_brand         手机型号.
+ * @apiSuccess {String=Master,Servant,Undefined} data.key_role   设备角色.
+ * @apiSuccess {Number} data.last_active_at       最近一次登陆或刷新的时间.
+ * @apiSuccess {Number} data.refresh_expire_at    refresh token的过期时间.
+ * @apiSuccess {bool} data.is_current             是否是当前会话.
+ * @apiSampleRequest http://120.232.251.101:8066/accountManager/sessionList
+ */
+#[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
+#[get("/accountManager/sessionList")]
+async fn session_list(req: HttpRequest) -> impl Responder {
+    gen_extra_respond(get_lang(&req), handlers::session_list::req(req).await)
+}
+
+/**
+ * @api {post} /accountManager/revokeSession   主设备踢掉从设备的登陆会话
+ * @apiVersion 0.0.1
+ * @apiName RevokeSession
+ * @apiGroup AccountManager
+ * @apiBody {String} deviceId   从设备ID
+ * @apiHeader {String} Authorization  user's access token
+ * @apiExample {curl} Example usage:
+ *    curl -X POST http://120.232.251.101:8066/accountManager/revokeSession -H "Content-Type: application/json" -d
+ *  '{"deviceId": "1234"}'
+* @apiSuccess {String=0,1,5,3008} status_code         状态码.
+* @apiSuccess {String} msg  状态详情
+ * @apiSuccess {String} data                null
+ * @apiSampleRequest http://120.232.251.101:8066/accountManager/revokeSession
+ */
+#[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
+#[post("/accountManager/revokeSession")]
+async fn revoke_session(
+    req: HttpRequest,
+    request_data: web::Json<RevokeSessionRequest>,
+) -> impl Responder {
+    gen_extra_respond(
+        get_lang(&req),
+        handlers::revoke_session::req(req, request_data.into_inner()).await,
+    )
 }
 
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
@@ -427,7 +526,10 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         .service(get_captcha_without_token)
         .service(check_captcha)
         .service(get_user_device_role)
-        .service(gen_token)
+        .service(refresh_token)
+        .service(logout)
+        .service(session_list)
+        .service(revoke_session)
         .service(replenish_contact)
         .service(reset_password);
 }
@@ -437,7 +539,7 @@ mod tests {
     use super::*;
 
     use crate::{test_contact_is_used, test_login, test_register, test_reset_password, test_service_call, test_user_info};
-    use crate::utils::api_test::{gen_some_accounts_with_new_key,init};
+    use crate::utils::api_test::{gen_some_accounts_with_new_key,init,on_memory_backend};
     use actix_web::body::MessageBody;
     use actix_web::http::header;
     use actix_web::{test};
@@ -446,6 +548,9 @@ mod tests {
     use serde_json::json;
 
     use crate::utils::respond::BackendRespond;
+    use crate::utils::session::SessionToken;
+    use models::audit_event::{AuditEventEntity, AuditEventFilter, AuditEventQuery};
+    use models::PsqlOp;
 
 
     #[actix_web::test]
@@ -486,4 +591,174 @@ mod tests {
         let info = test_user_info!(service,sender_master);
         println!("{:?}", info);
     }
+
+    #[actix_web::test]
+    async fn test_oversized_body_rejected() {
+        let app = init().await;
+        let service = actix_web::test::init_service(app).await;
+        //审计读取请求体之前就拒绝，不会把整个请求体读进内存
+        let payload = "a".repeat(common::constants::JSON_BODY_LIMIT + 1);
+        let req = actix_web::test::TestRequest::post()
+            .uri("/accountManager/registerByEmail")
+            .insert_header(header::ContentType::json())
+            .set_payload(payload)
+            .to_request();
+        let err = test::try_call_service(&service, req).await.err().unwrap();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    //不依赖postgresql和链节点，直接调用actix服务，每个测试有自己的内存后端和模拟链
+    #[actix_web::test]
+    async fn test_all_braced_register_on_memory_backend() {
+        on_memory_backend(async {
+            models::general::init_system_config().await.unwrap();
+            let app = init().await;
+            let service = actix_web::test::init_service(app).await;
+
+            let (sender_master, _sender_servant, _sender_newcommer, _receiver) = gen_some_accounts_with_new_key();
+            let payload = json!({
+                "deviceId":  sender_master.device.id,
+                "deviceBrand": sender_master.device.brand,
+                "email": sender_master.user.contact,
+                "captcha": "000000",
+                "password": sender_master.user.password,
+                "predecessorInviteCode":"chainless.hk"
+            });
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/registerByEmail",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            assert_eq!(res.status_code, 0);
+
+            let url = format!("/accountManager/contactIsUsed?contact={}", sender_master.user.contact);
+            let res: BackendRespond<ContactIsUsedResponse> =
+                crate::test_actix_call!(service, "get", &url, None::<String>, None::<String>);
+            assert_eq!(res.status_code, 0);
+            println!("used_res {:?}",res.data);
+
+            //注册请求和业务数据一起留下了审计记录，密码和验证码已脱敏
+            let store = models::memory::backend().unwrap();
+            let query = AuditEventQuery {
+                endpoint: Some("/accountManager/registerByEmail".to_string()),
+                limit: 10,
+                ..Default::default()
+            };
+            let events = models::LOCAL_CLI
+                .scope(
+                    std::sync::Arc::new(models::unit_of_work::UnitOfWork::memory(store)),
+                    AuditEventEntity::find(AuditEventFilter::ByQuery(&query)),
+                )
+                .await
+                .unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].event.result_code, 0);
+            assert!(!events[0].event.params.contains(&sender_master.user.password));
+            assert!(events[0].event.params.contains("predecessorInviteCode"));
+        })
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_all_braced_session_on_memory_backend() {
+        on_memory_backend(async {
+            models::general::init_system_config().await.unwrap();
+            let app = init().await;
+            let service = actix_web::test::init_service(app).await;
+
+            let (sender_master, _sender_servant, _sender_newcommer, _receiver) = gen_some_accounts_with_new_key();
+            let payload = json!({
+                "deviceId":  sender_master.device.id,
+                "deviceBrand": sender_master.device.brand,
+                "email": sender_master.user.contact,
+                "captcha": "000000",
+                "password": sender_master.user.password,
+                "predecessorInviteCode":"chainless.hk"
+            });
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/registerByEmail",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            let login_token = res.data.unwrap();
+
+            let payload = json!({"refreshToken": login_token.refresh_token});
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/refreshToken",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            assert_eq!(res.status_code, 0);
+            let refreshed_token = res.data.unwrap();
+            assert_ne!(refreshed_token.refresh_token, login_token.refresh_token);
+
+            //access token不能给自己续期，只有refresh token能换新的token
+            let payload = json!({"refreshToken": refreshed_token.access_token});
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/refreshToken",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            assert_eq!(res.status_code, 5);
+            let req = actix_web::test::TestRequest::post()
+                .uri("/accountManager/genToken")
+                .insert_header((
+                    header::AUTHORIZATION,
+                    format!("bearer {}", refreshed_token.access_token),
+                ))
+                .to_request();
+            let res = test::call_service(&service, req).await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+            //refresh token只能用一次
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/refreshToken",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            assert_eq!(res.status_code, 5);
+
+            let res: BackendRespond<String> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/logout",
+                None::<String>,
+                Some(refreshed_token.access_token.clone())
+            );
+            assert_eq!(res.status_code, 0);
+
+            //登出之后access token和refresh token都不能再用
+            let res: BackendRespond<UserInfoResponse> = crate::test_actix_call!(
+                service,
+                "get",
+                "/accountManager/userInfo",
+                None::<String>,
+                Some(login_token.access_token)
+            );
+            assert_eq!(res.status_code, 5);
+            let payload = json!({"refreshToken": refreshed_token.refresh_token});
+            let res: BackendRespond<SessionToken> = crate::test_actix_call!(
+                service,
+                "post",
+                "/accountManager/refreshToken",
+                Some(payload.to_string()),
+                None::<String>
+            );
+            assert_eq!(res.status_code, 5);
+        })
+        .await;
+    }
 }
@@ -5,6 +5,7 @@ use blockchain::airdrop::Airdrop;
 use common::data_structures::KeyRole;
 use models::{
     airdrop::{AirdropEntity, AirdropFilter, AirdropUpdater},
+    error::DbError,
     PsqlOp,
 };
 use serde::{Deserialize, Serialize};
@@ -59,7 +60,7 @@ pub async fn req(req: HttpRequest, request_data: BindBtcAddressRequest) -> Backe
                 Err(AirdropError::BtcAddressAlreadyUsed)?;
             }
         },
-        Err(e) if e.to_string().contains("DataNotFound") => {
+        Err(DbError::NotFound) => {
             //do nothing
         },
         Err(e) => Err(e)?,
@@ -3,7 +3,7 @@ use blockchain::bridge_on_near::Bridge;
 use blockchain::ContractClient;
 use common::data_structures::CoinType;
 use common::data_structures::KeyRole;
-use common::utils::math::coin_amount::display2raw;
+use common::utils::math::coin_amount::CoinAmount;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -11,7 +11,6 @@ use crate::utils::get_user_context;
 use crate::utils::token_auth;
 use crate::wallet::handlers::*;
 use common::error_code::BackendRes;
-use common::error_code::WalletError;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct GenDepositResponse {
@@ -40,14 +39,14 @@ pub async fn req(
     check_role(role, KeyRole::Master)?;
 
     let GenDepositSigRequest { coin, amount } = request_data;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
-
     let coin: CoinType = coin
         .parse()
         .map_err(|_e| BridgeError::CoinNotSupport("".to_string()))?;
-    if coin == CoinType::DW20 {
+    if !coin.bridge_enabled()? {
         Err(BridgeError::CoinNotSupport(coin.to_string()))?
     }
+    //签名给eth合约用的，按erc20的精度
+    let amount = CoinAmount::from_display(coin.clone(), &amount)?.to_erc20_raw()?;
 
     let bridge_cli = ContractClient::<Bridge>::new_query_cli().await?;
 
@@ -14,7 +14,7 @@ use anyhow::Result;
 
 use common::data_structures::CoinType;
 
-use common::{error_code::BackendRes, utils::math::coin_amount::raw2display};
+use common::{error_code::BackendRes, utils::math::coin_amount::CoinAmount};
 use serde::{Deserialize, Serialize};
 
 use super::paginate_vec;
@@ -54,11 +54,12 @@ async fn list_chainless_order_ids(main_account: &str) -> Result<Vec<String>> {
 }
 
 pub async fn list_external_orders(main_account: &str) -> Result<Vec<EthBridgeOrderEntity>> {
-    EthBridgeOrderEntity::find(BridgeOrderFilter::ByTypeAndAccountId(
+    let orders = EthBridgeOrderEntity::find(BridgeOrderFilter::ByTypeAndAccountId(
         OrderType::Deposit,
         main_account,
     ))
-    .await
+    .await?;
+    Ok(orders)
 }
 
 pub(crate) async fn req(
@@ -83,12 +84,14 @@ pub(crate) async fn req(
             status = DepositStatus::ChainLessSuccessful
         }
 
+        //eth上的订单，数量是erc20的精度
+        let amount = CoinAmount::from_erc20_raw(order.order.coin.clone(), order.order.amount)?;
         all_order.push(ListDepositOrderResponse {
             order_id: order.order.id,
             chain_id: 1500,
             account_id: order.order.chainless_acc,
             symbol: order.order.coin,
-            amount: raw2display(order.order.amount),
+            amount: amount.to_display()?,
             address: order.order.eth_addr,
             status,
             updated_at: order.updated_at,
@@ -17,7 +17,7 @@ use anyhow::Result;
 
 use common::data_structures::CoinType;
 
-use common::{error_code::BackendRes, utils::math::coin_amount::raw2display};
+use common::{error_code::BackendRes, utils::math::coin_amount::CoinAmount};
 
 use serde::{Deserialize, Serialize};
 
@@ -55,11 +55,12 @@ pub async fn list_chainless_orders(main_account: &str) -> Result<Vec<(u128, Brid
 }
 
 pub async fn list_external_orders(main_account: &str) -> Result<Vec<EthBridgeOrderEntity>> {
-    EthBridgeOrderEntity::find(BridgeOrderFilter::ByTypeAndAccountId(
+    let orders = EthBridgeOrderEntity::find(BridgeOrderFilter::ByTypeAndAccountId(
         OrderType::Withdraw,
         main_account,
     ))
-    .await
+    .await?;
+    Ok(orders)
 }
 
 pub(crate) async fn req(
@@ -109,12 +110,13 @@ pub(crate) async fn req(
             .into_iter()
             .filter_map(|x| x.signature)
             .collect();
+        let symbol: CoinType = parse_str(info.symbol)?;
         all_order.push(ListWithdrawOrderResponse {
             order_id: id.to_string(),
             chain_id: 1500,
             account_id: info.account_id.to_string(),
-            symbol: parse_str(info.symbol)?,
-            amount: raw2display(info.amount),
+            amount: CoinAmount::from_raw(symbol.clone(), info.amount).to_display()?,
+            symbol,
             address: info.address,
             status,
             signatures,
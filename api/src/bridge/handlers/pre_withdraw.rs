@@ -7,7 +7,7 @@ use blockchain::multi_sig::MultiSig;
 use blockchain::ContractClient;
 use common::data_structures::coin_transaction::{CoinSendStage, TxType};
 use common::data_structures::{CoinType, KeyRole};
-use common::utils::math::coin_amount::display2raw;
+use common::utils::math::coin_amount::CoinAmount;
 use common::utils::time::now_millis;
 
 use serde::{Deserialize, Serialize};
@@ -60,13 +60,18 @@ pub(crate) async fn req(
     } = request_data;
 
     let expire_at = now_millis() + TX_EXPAIRE_TIME;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
+    let coin_type =
+        CoinType::from_str(&coin).map_err(|e| BackendError::RequestParamInvalid(e.to_string()))?;
+    if !coin_type.bridge_enabled()? {
+        Err(BridgeError::CoinNotSupport(coin_type.to_string()))?
+    }
+    let amount = CoinAmount::from_display(coin_type.clone(), &amount)?;
+    //提到eth上的数量不能超出erc20的精度
+    amount.to_erc20_raw()?;
+    let amount = amount.raw;
     if amount == 0 {
         Err(WalletError::FobidTransferZero)?;
     }
-
-    let coin_type =
-        CoinType::from_str(&coin).map_err(|e| BackendError::RequestParamInvalid(e.to_string()))?;
     let from = main_account.clone();
 
     let available_balance = get_available_amount(&from, &coin_type).await?;
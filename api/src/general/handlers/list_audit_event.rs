@@ -0,0 +1,57 @@
+use actix_web::HttpRequest;
+
+use common::error_code::{BackendError, BackendRes};
+use models::audit_event::{AuditEventEntity, AuditEventFilter, AuditEventQuery};
+use models::PsqlOp;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::token_auth;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 200;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAuditEventRequest {
+    pub user_id: Option<u32>,
+    pub device_id: Option<String>,
+    pub endpoint: Option<String>,
+    pub trace_id: Option<String>,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+pub async fn req(
+    req: HttpRequest,
+    request_data: ListAuditEventRequest,
+) -> BackendRes<Vec<AuditEventEntity>> {
+    token_auth::validate_admin_key(&req)?;
+    let ListAuditEventRequest {
+        user_id,
+        device_id,
+        endpoint,
+        trace_id,
+        start_time,
+        end_time,
+        limit,
+    } = request_data;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        Err(BackendError::RequestParamInvalid(format!(
+            "limit should be in 1..={}",
+            MAX_LIMIT
+        )))?;
+    }
+    let query = AuditEventQuery {
+        user_id,
+        device_id,
+        endpoint,
+        trace_id,
+        start_at: start_time,
+        end_at: end_time,
+        limit,
+    };
+    let events = AuditEventEntity::find(AuditEventFilter::ByQuery(&query)).await?;
+    Ok(Some(events))
+}
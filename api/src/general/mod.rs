@@ -1 +1,57 @@
+//! support tools http service
+pub mod handlers;
 
+use actix_web::{get, web, HttpRequest, Responder};
+
+use handlers::list_audit_event::ListAuditEventRequest;
+use tracing::debug;
+
+use crate::utils::respond::gen_extra_respond;
+use crate::utils::respond::get_lang;
+use crate::utils::respond::get_trace_id;
+
+/**
+ * @api {get} /general/listAuditEvent 客服查询审计记录
+ * @apiVersion 0.0.1
+ * @apiName ListAuditEvent
+ * @apiGroup General
+ * @apiQuery {Number} [userId]     用户id
+ * @apiQuery {String} [deviceId]   设备id
+ * @apiQuery {String} [endpoint]   接口路径，如/wallet/preSendMoney
+ * @apiQuery {String} [traceId]    请求的trace_id
+ * @apiQuery {Number} [startTime]  开始时间戳(毫秒)
+ * @apiQuery {Number} [endTime]    结束时间戳(毫秒)
+ * @apiQuery {Number} [limit]      返回条数，默认50，最多200
+ * @apiHeader {String} Admin-Key  support admin key
+ * @apiExample {curl} Example usage:
+ *   curl -X GET "http://120.232.251.101:8066/general/listAuditEvent?userId=1&limit=20"
+ *   -H 'Admin-Key:xxxx'
+ * @apiSuccess {String=0,1,2,5} status_code         状态码.
+ * @apiSuccess {String} msg 状态信息
+ * @apiSuccess {Object[]} data                审计记录，按时间倒序
+ * @apiSuccess {Number} data.event.user_id       用户id
+ * @apiSuccess {String} data.event.device_id     设备id
+ * @apiSuccess {String} data.event.device_brand  设备品牌
+ * @apiSuccess {String} data.event.endpoint      接口路径
+ * @apiSuccess {String} data.event.params        脱敏之后的请求参数
+ * @apiSuccess {Number} data.event.result_code   返回的错误码
+ * @apiSuccess {String} data.event.trace_id      请求的trace_id
+ * @apiSuccess {String} data.created_at          请求时间
+ * @apiSampleRequest http://120.232.251.101:8066/general/listAuditEvent
+ */
+#[tracing::instrument(skip_all,fields(trace_id = get_trace_id(&req)))]
+#[get("/general/listAuditEvent")]
+async fn list_audit_event(
+    req: HttpRequest,
+    request_data: web::Query<ListAuditEventRequest>,
+) -> impl Responder {
+    debug!("{}", serde_json::to_string(&request_data.0).unwrap());
+    gen_extra_respond(
+        get_lang(&req),
+        handlers::list_audit_event::req(req, request_data.into_inner()).await,
+    )
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_audit_event);
+}
@@ -20,20 +20,26 @@ pub mod wallet;
 use actix_http::{header, Payload};
 
 use actix_cors::Cors;
-use actix_web::{error::ErrorInternalServerError, http, App, HttpServer};
-use common::log::generate_trace_id;
+use actix_web::error::{ErrorInternalServerError, ErrorPayloadTooLarge};
+use actix_web::{http, web, App, HttpMessage, HttpServer};
+use common::constants::JSON_BODY_LIMIT;
 use env_logger::Env;
 
-use models::general::{clean_conn, gen_db_cli};
-use tracing::{debug, info};
+use models::general::gen_request_unit_of_work;
+use models::unit_of_work::UnitOfWork;
+use tracing::{debug, info, warn};
+use utils::audit::AuditContext;
+use utils::respond::get_trace_id;
 
-use std::{cell::RefCell, future::{ready, Ready}, sync::Arc};
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web::{Bytes, BytesMut},
     Error,
 };
 use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
 
 fn print_body(req: &ServiceRequest) {
     match req.parts().1 {
@@ -57,7 +63,7 @@ pub struct MoreLog;
 // `B` - type of response's body
 impl<S, B> Transform<S, ServiceRequest> for MoreLog
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -68,17 +74,17 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(MoreLogMiddleware { service }))
+        ready(Ok(MoreLogMiddleware { service: Rc::new(service) }))
     }
 }
 
 pub struct MoreLogMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for MoreLogMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -88,7 +94,7 @@ where
 
     forward_ready!(service);
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
         debug!(
             "new_requested: {} ,{}, {},{:?}",
             req.method(),
@@ -98,16 +104,34 @@ where
         );
         print_body(&req);
         let method = req.method().to_string();
+        let service = self.service.clone();
 
-        let fut = self.service.call(req);
         Box::pin(async move {
-            let log_id = generate_trace_id();
-
-            let (db_cli, conn_ptr) = gen_db_cli(&method).await.map_err(ErrorInternalServerError)?;
-            debug!("log_id:{} : create local cli",log_id);
-            //todo: 最好LOCAL_CLI的初始化放在modles模块
-            models::LOCAL_CLI.scope(RefCell::new(Some(Arc::new(db_cli))), async move {
-                let res = fut.await;
+            let log_id = get_trace_id(req.request());
+            //没有Request-Id的请求补上，handler的日志和审计记录用同一个trace_id
+            if !req.headers().contains_key("Request-Id") {
+                req.headers_mut().insert(
+                    header::HeaderName::from_static("request-id"),
+                    header::HeaderValue::from_str(&log_id).map_err(ErrorInternalServerError)?,
+                );
+            }
+            let audit = if method == "POST" {
+                let body = take_body(&mut req).await?;
+                Some(AuditContext::new(req.request(), &body, &log_id))
+            } else {
+                None
+            };
+
+            //请求结束、连接还回连接池之后才释放permit
+            let (uow, _permit) = gen_request_unit_of_work(&method)
+                .await
+                .map_err(ErrorInternalServerError)?;
+            //handler里也可以通过req.extensions()拿到当前请求的UnitOfWork
+            req.extensions_mut().insert(uow.clone());
+            debug!("log_id:{} : create unit of work",log_id);
+            //请求被取消或者panic时uow被drop，未结束的事务由数据库回滚
+            models::LOCAL_CLI.scope(uow.clone(), async move {
+                let res = service.call(req).await;
                 let default_code = header::HeaderValue::from_str("1").unwrap();
                 let err_code = res.as_ref().map(|res|{
                     let value = res.headers().get(
@@ -115,33 +139,90 @@ where
                     ).unwrap_or(&default_code);
                     value.to_str().unwrap().parse::<u16>().unwrap()
                 });
-
                 //只有post正确完成之后才commit，否则都回滚
                 match (res.as_ref(),method.as_str(),err_code) {
                     (Ok(_),"POST",Ok(0)) => {
-                        models::general::commit().await.map_err(ErrorInternalServerError)?;
+                        //审计记录和业务数据在同一个事务里提交
+                        if let Some(audit) = &audit {
+                            if let Err(err) = audit.record(0).await {
+                                uow.rollback().await.map_err(ErrorInternalServerError)?;
+                                return Err(ErrorInternalServerError(err));
+                            }
+                        }
+                        uow.commit().await.map_err(ErrorInternalServerError)?;
                     },
-                    _ => {
-                        models::general::rollback().await.map_err(ErrorInternalServerError)?;
+                    (_,_,err_code) => {
+                        uow.rollback().await.map_err(ErrorInternalServerError)?;
+                        //失败的请求在回滚之后单独记录
+                        if let Some(audit) = &audit {
+                            let result_code = err_code.unwrap_or(1);
+                            if let Err(err) = audit.record(result_code).await {
+                                warn!("log_id:{} : record audit event failed {}", log_id, err);
+                            }
+                        }
                     }
                 };
-                clean_conn(conn_ptr);
-                debug!("log_id:{} : clean local cli",log_id);
+                debug!("log_id:{} : finish unit of work",log_id);
                 res
-            }).await
+            }).await.map(|res| {
+                //响应还要发送一段时间，不再占着连接
+                res.request().extensions_mut().remove::<Arc<UnitOfWork>>();
+                res
+            })
             
         })
     }
 }
 
+//读出请求体之后再放回去，handler还能正常解析，超过json的上限直接返回413
+async fn take_body(req: &mut ServiceRequest) -> Result<Bytes, Error> {
+    let too_large = || {
+        ErrorPayloadTooLarge(format!(
+            "request body is larger than {} bytes",
+            JSON_BODY_LIMIT
+        ))
+    };
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.map_or(false, |len| len > JSON_BODY_LIMIT) {
+        return Err(too_large());
+    }
+    //chunked的请求没有长度，边读边检查
+    let mut payload = req.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > JSON_BODY_LIMIT {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let (_, mut h1_payload) = actix_http::h1::Payload::create(true);
+    h1_payload.unread_data(body.clone());
+    req.set_payload(h1_payload.into());
+    Ok(body)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     common::log::init_logger();
+    //数据库结构没有迁移到最新版本时拒绝启动
+    models::migration::check_up_to_date()
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+    //没有可用的token签名密钥时拒绝启动
+    utils::jwt_keys::check()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
     info!("Service Start");
     let service: String = format!("0.0.0.0:{}", common::env::CONF.api_port);
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     HttpServer::new(move || {
         App::new()
+            .app_data(web::JsonConfig::default().limit(JSON_BODY_LIMIT))
             .wrap(MoreLog)
             .wrap(
                 Cors::default()
@@ -158,6 +239,7 @@ async fn main() -> std::io::Result<()> {
             .configure(wallet::configure_routes)
             .configure(bridge::configure_routes)
             .configure(airdrop::configure_routes)
+            .configure(general::configure_routes)
     })
     .bind(service)?
     .run()
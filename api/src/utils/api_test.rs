@@ -1,12 +1,17 @@
 use crate::MoreLog;
 use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
-use actix_web::App;
 use actix_web::Error;
+use actix_web::{web, App};
+use blockchain::mock_chain::MockChain;
 use blockchain::multi_sig::MultiSig;
+use common::constants::JSON_BODY_LIMIT;
 use common::encrypt::ed25519_key_gen;
+use models::memory::MemoryStore;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::env;
+use std::future::Future;
+use std::sync::Arc;
 //use common::data_structures::wallet::{AccountMessage, SendStage};
 use common::utils::math::random_num;
 
@@ -54,11 +59,22 @@ pub async fn init() -> App<
     //clear_contract().await;
     //models::general::table_all_clear().await;
     App::new()
+        .app_data(web::JsonConfig::default().limit(JSON_BODY_LIMIT))
         .wrap(MoreLog)
         .configure(crate::account_manager::configure_routes)
         .configure(crate::wallet::configure_routes)
         .configure(crate::bridge::configure_routes)
         .configure(crate::airdrop::configure_routes)
+        .configure(crate::general::configure_routes)
+}
+
+/// run the test on a memory backend and a mock chain of its own,
+/// the service has to be built and called inside `task`
+pub async fn on_memory_backend<F: Future>(task: F) -> F::Output {
+    let store = Arc::new(MemoryStore::default());
+    let chain = Arc::new(MockChain::default());
+    chain.register_relayers().await;
+    models::memory::scope(store, blockchain::mock_chain::scope(chain, task)).await
 }
 
 pub fn simulate_sender_master() -> TestWulianApp2 {
@@ -346,14 +362,14 @@ macro_rules! test_register {
                 "predecessorInviteCode":"chainless.hk"
             });
 
-            let res: BackendRespond<String> = test_service_call!(
+            let res: BackendRespond<crate::utils::session::SessionToken> = test_service_call!(
                 $service,
                 "post",
                 "/accountManager/registerByEmail",
                 Some(payload.to_string()),
                 None::<String>
             );
-            $app.user.token = Some(res.data.unwrap());
+            $app.user.token = Some(res.data.unwrap().access_token);
     }};
 }
 
@@ -398,14 +414,14 @@ macro_rules! test_login {
                 "contact": $app.user.contact,
                 "password": $app.user.password
             });
-            let res: BackendRespond<String> = test_service_call!(
+            let res: BackendRespond<crate::utils::session::SessionToken> = test_service_call!(
                 $service,
                 "post",
                 "/accountManager/login",
                 Some(payload.to_string()),
                 None::<String>
             );
-            $app.user.token = Some(res.data.unwrap());
+            $app.user.token = Some(res.data.unwrap().access_token);
     }};
 }
 
@@ -533,18 +549,21 @@ macro_rules! test_get_fees_priority {
 
 #[macro_export]
 macro_rules! test_tx_list {
-    ($service:expr, $app:expr,$role:expr,$counterparty:expr,$per_page:expr,$page:expr) => {{
-        let url = match $counterparty {
+    ($service:expr, $app:expr,$role:expr,$counterparty:expr,$per_page:expr,$cursor:expr) => {{
+        let mut url = match $counterparty {
             Some(acc) => {
-                format!("/wallet/txList?txRole={}&counterparty={}&perPage={}&page={}",
-                $role.to_string(),acc,$per_page,$page)
+                format!("/wallet/v2/txList?txRole={}&counterparty={}&perPage={}",
+                $role.to_string(),acc,$per_page)
             },
             None =>{
-                format!("/wallet/txList?txRole={}&perPage={}&page={}",
-                $role,$per_page,$page)
+                format!("/wallet/v2/txList?txRole={}&perPage={}",
+                $role,$per_page)
             }
         };
-        let res: BackendRespond<Vec<$crate::wallet::handlers::tx_list::CoinTxViewResponse>> = test_service_call!(
+        if let Some(cursor) = $cursor {
+            url = format!("{}&cursor={}", url, cursor);
+        }
+        let res: BackendRespond<$crate::wallet::handlers::tx_list::TxListResponse> = test_service_call!(
             $service,
             "get",
             &url,
@@ -552,7 +571,7 @@ macro_rules! test_tx_list {
             Some($app.user.token.clone().unwrap())
         );
         assert_eq!(res.status_code,0);
-        res.data
+        res.data.map(|list| list.txs)
     }};
 }
 
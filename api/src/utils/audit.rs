@@ -0,0 +1,98 @@
+//! audit of the mutating requests, see `MoreLog` in route.rs
+
+use actix_web::HttpRequest;
+use models::audit_event::AuditEventEntity;
+use models::error::DbResult;
+use models::PsqlOp;
+use serde_json::Value;
+
+use crate::utils::token_auth::token_identity;
+
+//字段名包含这些关键字的参数不落库
+const SENSITIVE_FIELDS: [&str; 8] = [
+    "password", "pwd", "captcha", "prikey", "secret", "token", "answer", "anwser",
+];
+const MAX_PARAMS_LEN: usize = 4096;
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_FIELDS.iter().any(|word| key.contains(word)) {
+                    *field = Value::String("***".to_string());
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// json body with the sensitive fields masked, other bodies are not recorded
+pub fn sanitize_params(body: &[u8]) -> String {
+    if body.is_empty() {
+        return "".to_string();
+    }
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string().chars().take(MAX_PARAMS_LEN).collect()
+        }
+        Err(_) => "<non-json body>".to_string(),
+    }
+}
+
+/// everything of the audit event known before the handler runs
+pub struct AuditContext {
+    user_id: Option<u32>,
+    device: Option<(String, String)>,
+    endpoint: String,
+    params: String,
+    trace_id: String,
+}
+
+impl AuditContext {
+    pub fn new(req: &HttpRequest, body: &[u8], trace_id: &str) -> Self {
+        let identity = token_identity(req);
+        AuditContext {
+            user_id: identity.as_ref().map(|(user_id, _, _)| *user_id),
+            device: identity.map(|(_, device_id, device_brand)| (device_id, device_brand)),
+            endpoint: req.path().to_string(),
+            params: sanitize_params(body),
+            trace_id: trace_id.to_string(),
+        }
+    }
+
+    /// insert the event with the unit of work of current task
+    pub async fn record(&self, result_code: u16) -> DbResult<()> {
+        AuditEventEntity::new_with_specified(
+            self.user_id,
+            self.device.clone(),
+            &self.endpoint,
+            &self.params,
+            result_code,
+            &self.trace_id,
+        )
+        .insert()
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_params() {
+        let body = r#"{"contact":"a@gmail.com","password":"123","secretInfo":{"encryptedPrikeyByPassword":"abc"},"amount":"1"}"#;
+        let params: Value = serde_json::from_str(&sanitize_params(body.as_bytes())).unwrap();
+        assert_eq!(params["contact"], "a@gmail.com");
+        assert_eq!(params["password"], "***");
+        assert_eq!(params["secretInfo"], "***");
+        assert_eq!(params["amount"], "1");
+        assert_eq!(sanitize_params(b"a=1"), "<non-json body>");
+    }
+}
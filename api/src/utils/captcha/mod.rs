@@ -1,26 +1,23 @@
 pub mod email;
 pub mod sms;
+pub mod store;
 
-use std::collections::HashMap;
 use std::str::FromStr;
 
+use common::data_structures::captcha::CaptchaRecord;
 use common::error_code::{BackendError};
+use common::hash::hash_str;
 use common::utils::math::random_num;
-use lazy_static::lazy_static;
-use std::sync::Mutex;
 use tracing::debug;
 use common::env::ServiceMode;
 use regex::Regex;
 use common::error_code::AccountManagerError::*;
-use common::error_code::BackendError::InternalError;
 use common::constants::*;
 use common::utils::time::now_millis;
 
 use strum_macros::{Display, EnumString};
 
-lazy_static! {
-    static ref CODE_STORAGE: Mutex<HashMap<(String, Usage), Captcha>> = Mutex::new(HashMap::new());
-}
+use self::store::captcha_store;
 
 #[derive(PartialEq, Debug)]
 pub enum ContactType {
@@ -61,19 +58,20 @@ pub enum Usage {
     NewcomerSwitchMaster,
 }
 
-pub fn get_captcha(user: &str, kind: &Usage) -> Result<Option<Captcha>, BackendError> {
+pub async fn get_captcha(user: &str, kind: &Usage) -> Result<Option<CaptchaRecord>, BackendError> {
     debug!("get_captcha_find_{}_{}", user, kind.to_string());
+    captcha_store().load(user, &kind.to_string()).await
+}
 
-    let code_storage = &CODE_STORAGE
-        .lock()
-        .map_err(|e| InternalError(e.to_string()))?;
+//存储里只保存哈希，owner和用途一起参与哈希
+fn code_hash(user: &str, kind: &Usage, code: &str) -> String {
+    hash_str(&format!("{}:{}:{}", user, kind, code))
+}
 
-    debug!("get_all_captcha {:?}", code_storage);
-    let value = code_storage
-        .get(&(user.to_owned(), kind.to_owned()))
-        .as_ref()
-        .map(|&x| x.to_owned());
-    Ok(value)
+//连续发送时请求间隔翻倍，发送记录清理之后重新计算
+pub fn request_interval(sent_times: u32) -> u64 {
+    let times = sent_times.saturating_sub(1).min(16);
+    (CAPTCHA_REQUEST_INTERVAL << times).min(CAPTCHA_MAX_REQUEST_INTERVAL)
 }
 
 pub fn gen_random_verify_code() -> String {
@@ -132,63 +130,102 @@ impl Captcha {
         self.expiration_time <= now_millis()
     }
 
-    pub fn store(&self) -> Result<(), BackendError> {
-        let code_storage = &mut CODE_STORAGE
-            .lock()
-            .map_err(|e| InternalError(e.to_string()))?;
-        code_storage.insert((self.owner.to_string(), self.kind.clone()), self.clone());
-        debug!("_eddy_store_{:?}", code_storage);
-
-        Ok(())
-    }
-
-    pub fn delete(&self) -> Result<(), BackendError> {
-        let code_storage = &mut CODE_STORAGE
-            .lock()
-            .map_err(|e| InternalError(e.to_string()))?;
-        code_storage.remove(&(self.owner.to_string(), self.kind.clone()));
-        Ok(())
+    pub async fn store(&self) -> Result<(), BackendError> {
+        captcha_store()
+            .issue(
+                &self.owner,
+                &self.kind.to_string(),
+                &self.device_id,
+                &code_hash(&self.owner, &self.kind, &self.code),
+                self.created_at,
+                self.expiration_time,
+            )
+            .await
     }
 
-    pub fn check(user: &str, code: &str, kind: Usage) -> Result<(), BackendError> {
-        if common::env::CONF.service_mode != ServiceMode::Product
+    //测试环境的万能验证码
+    fn is_test_code(code: &str) -> bool {
+        common::env::CONF.service_mode != ServiceMode::Product
             && common::env::CONF.service_mode != ServiceMode::Dev
             && code.eq("000000")
-        {
+    }
+
+    pub async fn check(user: &str, code: &str, kind: Usage) -> Result<(), BackendError> {
+        if Self::is_test_code(code) {
             return Ok(());
         }
 
-        if let Some(data) = get_captcha(user, &kind)? {
-            if data.code != code {
-                Err(CaptchaIncorrect)?
-            } else if data.code == code && data.is_expired() {
-                Err(CaptchaExpired)?
-            } else {
+        let store = captcha_store();
+        let usage = kind.to_string();
+        //比较和计数在同一条语句里，并发猜测不会绕过次数限制
+        let guess = store
+            .guess(
+                user,
+                &usage,
+                &code_hash(user, &kind, code),
+                CAPTCHA_MAX_ATTEMPTS,
+            )
+            .await?;
+        match guess {
+            Some(guess) if guess.matched => {
+                if guess.expire_at <= now_millis() {
+                    Err(CaptchaExpired)?
+                }
                 Ok(())
             }
-        } else {
-            Err(CaptchaNotFound)?
+            Some(guess) => {
+                debug!(
+                    "{} guessed {} captcha wrong {} times",
+                    user, kind, guess.attempts
+                );
+                if guess.attempts >= CAPTCHA_MAX_ATTEMPTS {
+                    Err(CaptchaTooManyAttempts(CAPTCHA_MAX_ATTEMPTS))?
+                }
+                Err(CaptchaIncorrect)?
+            }
+            None => match store.load(user, &usage).await? {
+                //输错次数用完而作废的，提示重新获取
+                Some(data) if !data.code_hash.is_empty() => {
+                    Err(CaptchaTooManyAttempts(CAPTCHA_MAX_ATTEMPTS))?
+                }
+                //用过的验证码只保留发送记录
+                _ => Err(CaptchaNotFound)?,
+            },
         }
     }
 
-    pub fn check_and_delete(user: &str, code: &str, kind: Usage) -> Result<(), BackendError> {
-        Self::check(user, code, kind.clone())?;
-        let code_storage = &mut CODE_STORAGE
-            .lock()
-            .map_err(|e| InternalError(e.to_string()))?;
-        code_storage.remove(&(user.to_string(), kind));
-        Ok(())
+    pub async fn check_and_delete(user: &str, code: &str, kind: Usage) -> Result<(), BackendError> {
+        let store = captcha_store();
+        let usage = kind.to_string();
+        if Self::is_test_code(code) {
+            return store.consume(user, &usage).await;
+        }
+        //带条件的兑换，同一个验证码只有一个请求能用掉
+        let hash = code_hash(user, &kind, code);
+        if store
+            .redeem(user, &usage, &hash, CAPTCHA_MAX_ATTEMPTS, now_millis())
+            .await?
+        {
+            return Ok(());
+        }
+        //兑换失败时按普通校验计数并给出原因
+        Self::check(user, code, kind).await?;
+        Err(CaptchaNotFound)?
     }
 
-    pub fn clean_up_expired() -> Result<(),BackendError>{
-        let code_storage = &mut CODE_STORAGE
-            .lock()
-            .map_err(|e| InternalError(e.to_string()))?;
-        
-        code_storage.retain(|_k,v| {
-            !v.is_expired()
-        });
-        Ok(())
+    pub async fn clean_up_expired() -> Result<(),BackendError>{
+        captcha_store().clean_up_expired(now_millis()).await
     }
-    //todo: restrict map size
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_interval() {
+        assert_eq!(request_interval(0), CAPTCHA_REQUEST_INTERVAL);
+        assert_eq!(request_interval(1), CAPTCHA_REQUEST_INTERVAL);
+        assert_eq!(request_interval(3), CAPTCHA_REQUEST_INTERVAL * 4);
+        assert_eq!(request_interval(u32::MAX), CAPTCHA_MAX_REQUEST_INTERVAL);
+    }
+}
@@ -0,0 +1,298 @@
+//! where the issued captchas are kept, the postgresql store is shared by all api instances,
+//! the memory one only lives in the current process and is meant for tests
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use async_trait::async_trait;
+use common::constants::CAPTCHA_SEND_RECORD_TIME;
+use common::data_structures::captcha::CaptchaRecord;
+use common::error_code::BackendError;
+use common::error_code::BackendError::InternalError;
+use lazy_static::lazy_static;
+use models::captcha::{self, CaptchaGuess};
+use models::general::try_run_api_call;
+
+#[async_trait]
+pub trait CaptchaStore: Send + Sync {
+    async fn load(&self, owner: &str, usage: &str) -> Result<Option<CaptchaRecord>, BackendError>;
+
+    /// overwrite the former code of (owner, usage), clear its attempts and count the send
+    async fn issue(
+        &self,
+        owner: &str,
+        usage: &str,
+        device_id: &str,
+        code_hash: &str,
+        now: u64,
+        expire_at: u64,
+    ) -> Result<(), BackendError>;
+
+    /// invalidate the code, the send records are kept
+    async fn consume(&self, owner: &str, usage: &str) -> Result<(), BackendError>;
+
+    /// compare the code and count a wrong guess at once,
+    /// None if the code is used or out of attempts
+    async fn guess(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+    ) -> Result<Option<CaptchaGuess>, BackendError>;
+
+    /// use up the valid code, false if it was redeemed by another request
+    async fn redeem(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+        now: u64,
+    ) -> Result<bool, BackendError>;
+
+    /// drop the expired codes whose send records are out of date too
+    async fn clean_up_expired(&self, now: u64) -> Result<(), BackendError>;
+}
+
+/// the captcha table, every call commits on its own so the attempts
+/// and the consumed codes survive the rollback of a failed request,
+/// its connection comes from the part of the pool which requests can't hold
+pub struct PgCaptchaStore;
+
+#[async_trait]
+impl CaptchaStore for PgCaptchaStore {
+    async fn load(&self, owner: &str, usage: &str) -> Result<Option<CaptchaRecord>, BackendError> {
+        Ok(try_run_api_call("", captcha::load(owner, usage)).await??)
+    }
+
+    async fn issue(
+        &self,
+        owner: &str,
+        usage: &str,
+        device_id: &str,
+        code_hash: &str,
+        now: u64,
+        expire_at: u64,
+    ) -> Result<(), BackendError> {
+        let task = captcha::issue(owner, usage, device_id, code_hash, now, expire_at);
+        Ok(try_run_api_call("", task).await??)
+    }
+
+    async fn consume(&self, owner: &str, usage: &str) -> Result<(), BackendError> {
+        Ok(try_run_api_call("", captcha::consume(owner, usage)).await??)
+    }
+
+    async fn guess(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+    ) -> Result<Option<CaptchaGuess>, BackendError> {
+        let task = captcha::guess(owner, usage, code_hash, max_attempts);
+        Ok(try_run_api_call("", task).await??)
+    }
+
+    async fn redeem(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+        now: u64,
+    ) -> Result<bool, BackendError> {
+        let task = captcha::redeem(owner, usage, code_hash, max_attempts, now);
+        Ok(try_run_api_call("", task).await??)
+    }
+
+    async fn clean_up_expired(&self, now: u64) -> Result<(), BackendError> {
+        let task = captcha::clean_up(now, now.saturating_sub(CAPTCHA_SEND_RECORD_TIME));
+        Ok(try_run_api_call("", task).await??)
+    }
+}
+
+/// the former in-process map keyed by (owner, usage)
+#[derive(Default)]
+pub struct MemoryCaptchaStore {
+    codes: Mutex<HashMap<(String, String), CaptchaRecord>>,
+}
+
+impl MemoryCaptchaStore {
+    fn codes(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<(String, String), CaptchaRecord>>, BackendError>
+    {
+        self.codes.lock().map_err(|e| InternalError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl CaptchaStore for MemoryCaptchaStore {
+    async fn load(&self, owner: &str, usage: &str) -> Result<Option<CaptchaRecord>, BackendError> {
+        let codes = self.codes()?;
+        Ok(codes.get(&(owner.to_owned(), usage.to_owned())).cloned())
+    }
+
+    async fn issue(
+        &self,
+        owner: &str,
+        usage: &str,
+        device_id: &str,
+        code_hash: &str,
+        now: u64,
+        expire_at: u64,
+    ) -> Result<(), BackendError> {
+        let mut codes = self.codes()?;
+        let record = codes
+            .entry((owner.to_owned(), usage.to_owned()))
+            .or_insert_with(|| CaptchaRecord {
+                owner: owner.to_owned(),
+                usage: usage.to_owned(),
+                device_id: "".to_string(),
+                code_hash: "".to_string(),
+                attempts: 0,
+                sent_times: 0,
+                last_sent_at: 0,
+                expire_at: 0,
+            });
+        record.device_id = device_id.to_owned();
+        record.code_hash = code_hash.to_owned();
+        record.attempts = 0;
+        record.sent_times += 1;
+        record.last_sent_at = now;
+        record.expire_at = expire_at;
+        Ok(())
+    }
+
+    async fn consume(&self, owner: &str, usage: &str) -> Result<(), BackendError> {
+        let mut codes = self.codes()?;
+        if let Some(record) = codes.get_mut(&(owner.to_owned(), usage.to_owned())) {
+            record.code_hash.clear();
+        }
+        Ok(())
+    }
+
+    async fn guess(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+    ) -> Result<Option<CaptchaGuess>, BackendError> {
+        let mut codes = self.codes()?;
+        let record = match codes.get_mut(&(owner.to_owned(), usage.to_owned())) {
+            Some(record) if !record.code_hash.is_empty() && record.attempts < max_attempts => {
+                record
+            }
+            _ => return Ok(None),
+        };
+        let matched = record.code_hash == code_hash;
+        if !matched {
+            record.attempts += 1;
+        }
+        Ok(Some(CaptchaGuess {
+            matched,
+            attempts: record.attempts,
+            expire_at: record.expire_at,
+        }))
+    }
+
+    async fn redeem(
+        &self,
+        owner: &str,
+        usage: &str,
+        code_hash: &str,
+        max_attempts: u32,
+        now: u64,
+    ) -> Result<bool, BackendError> {
+        let mut codes = self.codes()?;
+        match codes.get_mut(&(owner.to_owned(), usage.to_owned())) {
+            Some(record)
+                if !record.code_hash.is_empty()
+                    && record.code_hash == code_hash
+                    && record.attempts < max_attempts
+                    && record.expire_at > now =>
+            {
+                record.code_hash.clear();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn clean_up_expired(&self, now: u64) -> Result<(), BackendError> {
+        let sent_before = now.saturating_sub(CAPTCHA_SEND_RECORD_TIME);
+        let mut codes = self.codes()?;
+        codes.retain(|_k, v| v.expire_at > now || v.last_sent_at >= sent_before);
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref MEMORY_STORE: RwLock<Option<Arc<MemoryCaptchaStore>>> = RwLock::new(None);
+}
+
+/// keep the captchas of the whole process in memory, for the tests without a database
+pub fn enable_memory() -> Arc<MemoryCaptchaStore> {
+    let mut store = MEMORY_STORE.write().unwrap();
+    store.get_or_insert_with(Default::default).clone()
+}
+
+pub fn captcha_store() -> Arc<dyn CaptchaStore> {
+    match MEMORY_STORE.read().unwrap().clone() {
+        Some(memory) => memory,
+        None => Arc::new(PgCaptchaStore),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_captcha_store() {
+        let store = MemoryCaptchaStore::default();
+        store
+            .issue("a@gmail.com", "Register", "1", "hash1", 1000, 2000)
+            .await
+            .unwrap();
+        let guess = store
+            .guess("a@gmail.com", "Register", "hash0", 5)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!guess.matched);
+        assert_eq!(guess.attempts, 1);
+        store
+            .issue("a@gmail.com", "Register", "1", "hash2", 1500, 2500)
+            .await
+            .unwrap();
+        assert!(store
+            .redeem("a@gmail.com", "Register", "hash2", 5, 2000)
+            .await
+            .unwrap());
+        assert!(!store
+            .redeem("a@gmail.com", "Register", "hash2", 5, 2000)
+            .await
+            .unwrap());
+        let record = store
+            .load("a@gmail.com", "Register")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(record.code_hash.is_empty());
+        assert_eq!(record.attempts, 0);
+        assert_eq!(record.sent_times, 2);
+
+        store
+            .clean_up_expired(1500 + CAPTCHA_SEND_RECORD_TIME + 1)
+            .await
+            .unwrap();
+        assert!(store
+            .load("a@gmail.com", "Register")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,53 @@
+//! lockout of the password login, the policy is `login_lock` of the config
+
+use actix_web::HttpRequest;
+use common::data_structures::login_attempt::LoginAttemptResult;
+use common::env::CONF;
+use common::error_code::AccountManagerError::AccountLocked;
+use common::error_code::BackendError;
+use common::utils::time::now_millis;
+use models::general::try_run_api_call;
+use models::login_attempt::{self, LoginGate};
+
+pub fn client_ip(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// the attempt commits on its own, a rejected login rolls back everything else
+pub async fn record(
+    user_id: u32,
+    device_id: &str,
+    ip: &str,
+    result: LoginAttemptResult,
+) -> Result<(), BackendError> {
+    let task = login_attempt::record(user_id, device_id, ip, result, now_millis());
+    Ok(try_run_api_call("", task).await??)
+}
+
+/// check the lockout and record the password attempt at once, AccountLocked while the lock
+/// lasts, otherwise the remaining chances after one more wrong password
+pub async fn attempt(
+    user_id: u32,
+    device_id: &str,
+    ip: &str,
+    passed: bool,
+) -> Result<u8, BackendError> {
+    let policy = &CONF.login_lock;
+    let task = login_attempt::attempt(
+        user_id,
+        device_id,
+        ip,
+        passed,
+        now_millis(),
+        policy.max_failures,
+        policy.unlock_secs * 1000,
+    );
+    //单独的事务，同一个用户的并发登陆在判断和记录之间排队
+    match try_run_api_call("POST", task).await?? {
+        LoginGate::Locked(unlock_time) => Err(AccountLocked(unlock_time / 1000))?,
+        LoginGate::Recorded(remain_chance) => Ok(remain_chance),
+    }
+}
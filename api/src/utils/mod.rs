@@ -14,9 +14,13 @@ use models::{
 };
 
 pub mod api_test;
+pub mod audit;
 pub mod btc_aggregated_api;
 pub mod captcha;
+pub mod jwt_keys;
+pub mod login_lock;
 pub mod respond;
+pub mod session;
 pub mod token_auth;
 pub mod wallet_grades;
 
@@ -71,10 +75,10 @@ pub async fn judge_role_by_user_id(device_key: Option<&str>, id: &u32) -> Result
     let user_info = UserInfoEntity::find_single(UserFilter::ById(id))
         .await
         .map_err(|err| {
-            if err.to_string().contains("DBError::DataNotFound") {
+            if err.is_not_found() {
                 WalletError::MainAccountNotExist(err.to_string()).into()
             } else {
-                BackendError::InternalError(err.to_string())
+                BackendError::from(err)
             }
         })?
         .into_inner();
@@ -110,10 +114,10 @@ pub async fn get_user_context(user_id: &u32, device_id: &str) -> Result<UserCont
     let user_info = UserInfoEntity::find_single(UserFilter::ById(user_id))
         .await
         .map_err(|err| {
-            if err.to_string().contains("DBError::DataNotFound") {
+            if err.is_not_found() {
                 WalletError::MainAccountNotExist(err.to_string()).into()
             } else {
-                BackendError::InternalError(err.to_string())
+                BackendError::from(err)
             }
         })?
         .into_inner();
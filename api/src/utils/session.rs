@@ -0,0 +1,107 @@
+//! login sessions of the devices, a short-lived access token plus a rotating refresh token,
+//! the lifetimes are `session` of the config
+
+use common::env::CONF;
+use common::error_code::BackendError;
+use common::error_code::BackendError::Authorization;
+use common::hash::hash_str;
+use common::utils::math::generate_random_hex_string;
+use common::utils::time::now_millis;
+use models::account_manager::{UserFilter, UserInfoEntity};
+use models::user_session;
+use models::PsqlOp;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::token_auth;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionToken {
+    pub access_token: String,
+    pub access_expire_at: u64,
+    pub refresh_token: String,
+    pub refresh_expire_at: u64,
+}
+
+fn refresh_expire_at(now: u64) -> u64 {
+    now + CONF.session.refresh_token_secs * 1000
+}
+
+/// start a new session on the device after login or register,
+/// the former session of the device is replaced
+pub async fn open(
+    user_id: u32,
+    token_version: u32,
+    device_id: &str,
+    device_brand: &str,
+) -> Result<SessionToken, BackendError> {
+    let now = now_millis();
+    let refresh_token = generate_random_hex_string(64);
+    let refresh_expire_at = refresh_expire_at(now);
+    let session_id = user_session::open(
+        user_id,
+        device_id,
+        device_brand,
+        &hash_str(&refresh_token),
+        now,
+        refresh_expire_at,
+    )
+    .await?;
+    let access_token = token_auth::create_jwt(
+        user_id,
+        token_version,
+        &session_id,
+        device_id,
+        device_brand,
+        now,
+    )?;
+    Ok(SessionToken {
+        access_token,
+        access_expire_at: token_auth::access_token_expire_at(now),
+        refresh_token,
+        refresh_expire_at,
+    })
+}
+
+/// swap the refresh token for a new token pair, the old refresh token can't be used again
+pub async fn refresh(refresh_token: &str) -> Result<SessionToken, BackendError> {
+    let now = now_millis();
+    let old_hash = hash_str(refresh_token);
+    let session = user_session::load_by_refresh_token(&old_hash)
+        .await?
+        .ok_or(Authorization("Refresh token is invalid".to_string()))?;
+    if now > session.refresh_expire_at {
+        Err(Authorization("Refresh token has expired".to_string()))?;
+    }
+    let user_info = UserInfoEntity::find_single(UserFilter::ById(&session.user_id))
+        .await?
+        .into_inner();
+
+    let new_refresh_token = generate_random_hex_string(64);
+    let refresh_expire_at = refresh_expire_at(now);
+    let swapped = user_session::rotate(
+        &session.session_id,
+        &old_hash,
+        &hash_str(&new_refresh_token),
+        now,
+        refresh_expire_at,
+    )
+    .await?;
+    //并发刷新时只有一个能成功
+    if !swapped {
+        Err(Authorization("Refresh token is invalid".to_string()))?;
+    }
+    let access_token = token_auth::create_jwt(
+        session.user_id,
+        user_info.token_version,
+        &session.session_id,
+        &session.device_id,
+        &session.device_brand,
+        now,
+    )?;
+    Ok(SessionToken {
+        access_token,
+        access_expire_at: token_auth::access_token_expire_at(now),
+        refresh_token: new_refresh_token,
+        refresh_expire_at,
+    })
+}
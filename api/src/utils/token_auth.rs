@@ -1,8 +1,9 @@
 use actix_web::HttpRequest;
-use jsonwebtoken::{decode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use models::account_manager::{UserFilter, UserInfoEntity};
+use models::user_session;
 use models::PsqlOp;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 
 use actix_web::http::header;
 
@@ -11,10 +12,13 @@ use common::error_code::BackendError::Authorization;
 use common::prelude::*;
 use common::utils::time::now_millis;
 
+use crate::utils::jwt_keys;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct Claims {
     user_id: u32,
     version: u32,
+    session_id: String,
     device_id: String,
     device_brand: String,
     iat: u64,
@@ -25,6 +29,7 @@ impl Claims {
     pub fn new(
         user_id: u32,
         version: u32,
+        session_id: &str,
         device_id: &str,
         device_brand: &str,
         iat: u64,
@@ -33,6 +38,7 @@ impl Claims {
         Self {
             user_id,
             version,
+            session_id: session_id.to_owned(),
             device_id: device_id.to_owned(),
             device_brand: device_brand.to_owned(),
             iat,
@@ -41,38 +47,96 @@ impl Claims {
     }
 }
 
+/// access tokens issued at `iat` expire at the returned millis
+pub fn access_token_expire_at(iat: u64) -> u64 {
+    iat + CONF.session.access_token_secs * 1000
+}
+
 pub fn create_jwt(
     user_id: u32,
     version: u32,
+    session_id: &str,
     device_id: &str,
     device_brand: &str,
+    iat: u64,
 ) -> Result<String, BackendError> {
-    let iat = now_millis();
-
-    let exp = iat + TOKEN_EXPAIRE_TIME;
+    let exp = access_token_expire_at(iat);
 
-    let claims = Claims::new(user_id, version, device_id, device_brand, iat, exp);
+    let claims = Claims::new(
+        user_id,
+        version,
+        session_id,
+        device_id,
+        device_brand,
+        iat,
+        exp,
+    );
 
-    jsonwebtoken::encode(
-        &Header::new(Algorithm::HS256),
-        &claims,
-        &EncodingKey::from_secret(TOKEN_SECRET_KEY.as_bytes()),
-    )
-    .map_err(|e| e.to_string().into())
+    jwt_keys::key_set()
+        .encode(&claims)
+        .map_err(|e| e.to_string().into())
 }
 
 fn validate_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(TOKEN_SECRET_KEY.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    )
-    .map(|data| data.claims)
+    jwt_keys::key_set().decode::<Claims>(token)
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let auth_str = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    if auth_str.starts_with("bearer ") || auth_str.starts_with("Bearer ") {
+        Some(&auth_str["bearer ".len()..])
+    } else {
+        None
+    }
+}
+
+/// user and device carried by the token, only the signature is checked,
+/// used to tag the request before the handler authenticates it
+pub fn token_identity(req: &HttpRequest) -> Option<(u32, String, String)> {
+    let claim_dat = validate_jwt(bearer_token(req)?).ok()?;
+    Some((
+        claim_dat.user_id,
+        claim_dat.device_id,
+        claim_dat.device_brand,
+    ))
+}
+
+/// support interfaces are guarded by the `Admin-Key` header
+pub fn validate_admin_key(req: &HttpRequest) -> Result<(), BackendError> {
+    let admin_key = SUPPORT_ADMIN_KEY
+        .as_ref()
+        .ok_or(Authorization("Support interface is disabled".to_string()))?;
+    let req_key = req
+        .headers()
+        .get("Admin-Key")
+        .and_then(|key| key.to_str().ok())
+        .ok_or(Authorization("No Admin-Key header".to_string()))?;
+    //按常量时间比较，不从耗时上泄露匹配了多少字节
+    if !bool::from(req_key.as_bytes().ct_eq(admin_key.as_bytes())) {
+        Err(Authorization("Admin-Key is invalid".to_string()))?
+    }
+    Ok(())
 }
 
 pub async fn validate_credentials(
     req: &HttpRequest,
 ) -> Result<(u32, u32, String, String), BackendError> {
+    let claim_dat = authenticate(req).await?;
+    Ok((
+        claim_dat.user_id,
+        claim_dat.version,
+        claim_dat.device_id,
+        claim_dat.device_brand,
+    ))
+}
+
+/// (user_id,device_id,session_id) of the request, for the session management interfaces
+pub async fn validate_session(req: &HttpRequest) -> Result<(u32, String, String), BackendError> {
+    let claim_dat = authenticate(req).await?;
+    Ok((claim_dat.user_id, claim_dat.device_id, claim_dat.session_id))
+}
+
+async fn authenticate(req: &HttpRequest) -> Result<Claims, BackendError> {
     let auth_header = req
         .headers()
         .get(header::AUTHORIZATION)
@@ -91,10 +155,10 @@ pub async fn validate_credentials(
             let user_info = UserInfoEntity::find_single(UserFilter::ById(&claim_dat.user_id))
                 .await
                 .map_err(|err| {
-                    if err.to_string().contains("DBError::DataNotFound") {
+                    if err.is_not_found() {
                         WalletError::MainAccountNotExist(err.to_string()).into()
                     } else {
-                        BackendError::InternalError(err.to_string())
+                        BackendError::from(err)
                     }
                 })?
                 .into_inner();
@@ -103,12 +167,13 @@ pub async fn validate_credentials(
                 Err(Authorization("TokenVersionInvalid".to_string()))?
             }
 
-            Ok((
-                claim_dat.user_id,
-                claim_dat.version,
-                claim_dat.device_id.clone(),
-                claim_dat.device_brand,
-            ))
+            //登出、被主设备踢掉或者同一设备重新登陆之后，会话就不存在了
+            let session = user_session::load(&claim_dat.session_id).await?;
+            if session.map_or(true, |session| session.user_id != claim_dat.user_id) {
+                Err(Authorization("Session has been revoked".to_string()))?
+            }
+
+            Ok(claim_dat)
         }
     } else {
         Err(Authorization("Token is invalid or malformed".to_string()))?
@@ -121,7 +186,7 @@ mod tests {
 
     #[test]
     fn test_account_login_auth() {
-        let token = create_jwt(1, 1, "", "huawei").unwrap();
+        let token = create_jwt(1, 1, "session1", "", "huawei", now_millis()).unwrap();
         println!("res {}", token);
     }
 }
@@ -1,15 +1,14 @@
 use crate::utils::token_auth;
 use actix_web::HttpRequest;
-use blockchain::coin::Coin;
-
 use blockchain::multi_sig::MultiSig;
+use blockchain::portfolio::{portfolio, Portfolio};
 use blockchain::ContractClient;
 use common::data_structures::{get_support_coin_list, CoinType};
 
-use common::error_code::parse_str;
 use common::error_code::BackendError::InternalError;
 use common::error_code::BackendRes;
-use common::utils::math::coin_amount::raw2display;
+use common::utils::math::coin_amount::{raw2display, CoinAmount};
+use futures_util::future::try_join_all;
 use models::account_manager::{UserFilter, UserInfoEntity};
 
 use models::PsqlOp;
@@ -55,96 +54,93 @@ pub async fn req(
     let main_account = user_info.main_account.as_ref();
     let coin_list = get_support_coin_list();
     let mul_cli = ContractClient::<MultiSig>::new_query_cli().await?;
+    //子账户的持仓上限都在主账户的策略里，只查一次
+    let strategy = match main_account {
+        Some(main_account) => Some(
+            mul_cli
+                .get_strategy(main_account)
+                .await?
+                .ok_or(InternalError("".to_string()))?,
+        ),
+        None => None,
+    };
+    let sub_accounts = strategy
+        .as_ref()
+        .map(|strategy| {
+            strategy
+                .sub_confs
+                .keys()
+                .map(|x| Some(x.to_string()))
+                .collect::<Vec<Option<String>>>()
+        })
+        .unwrap_or_default();
 
     let check_accounts = match request_data.kind {
         AccountType::Main => vec![user_info.main_account.clone()],
-        AccountType::AllSub => {
-            if main_account.is_some() {
-                let strategy = mul_cli
-                    .get_strategy(main_account.unwrap())
-                    .await?
-                    .ok_or(InternalError("".to_string()))?;
-                strategy
-                    .sub_confs
-                    .iter()
-                    .map(|x| Some(x.0.to_string()))
-                    .collect::<Vec<Option<String>>>()
-            } else {
-                vec![]
-            }
-        }
+        AccountType::AllSub => sub_accounts,
         AccountType::All => {
             let mut all = vec![user_info.main_account.clone()];
-            if main_account.is_some() {
-                let strategy = mul_cli
-                    .get_strategy(&main_account.unwrap())
-                    .await?
-                    .ok_or(InternalError("".to_string()))?;
-
-                let mut sub = strategy
-                    .sub_confs
-                    .iter()
-                    .map(|x| Some(x.0.to_string()))
-                    .collect::<Vec<Option<String>>>();
-                all.append(&mut sub);
-            }
+            all.extend(sub_accounts);
             all
         }
         AccountType::Single(acc) => vec![Some(acc)],
     };
 
-    let multi_cli = blockchain::ContractClient::<MultiSig>::new_query_cli().await?;
+    //所有账户所有币种的余额和价格并发查询
+    let portfolios: Vec<Option<Portfolio>> =
+        try_join_all(check_accounts.iter().map(|account| async move {
+            match (main_account, account) {
+                (Some(_), Some(account)) => portfolio(account).await.map(Some),
+                _ => Ok(None),
+            }
+        }))
+        .await?;
+
     let mut coin_balance_map = vec![];
     for coin in coin_list {
         let mut account_balance = vec![];
 
         for (index, account) in check_accounts.iter().enumerate() {
-            let coin_cli: ContractClient<Coin> =
-                ContractClient::<Coin>::new_query_cli(coin.clone()).await?;
-            let (balance_on_chain, hold_limit) = if main_account.is_some() {
-                let balance = coin_cli
-                    .get_balance(account.as_ref().unwrap())
-                    .await?
-                    .unwrap_or("0".to_string());
-
-                let hold_limit = if index == 0 {
-                    None
-                } else {
-                    let strategy = multi_cli
-                        .get_strategy(main_account.unwrap())
-                        .await?
-                        .ok_or("")?;
-                    let sub_confs = strategy.sub_confs;
-                    let hold_limit = sub_confs
-                        .get(account.as_ref().unwrap())
-                        .ok_or("")?
-                        .hold_value_limit;
-                    Some(raw2display(hold_limit))
-                };
-
-                (balance, hold_limit)
-            } else {
-                ("0".to_string(), Some("0.0".to_string()))
+            let (total_balance, total_dollar_value, hold_limit) = match &portfolios[index] {
+                Some(portfolio) => {
+                    let (balance, value) = portfolio
+                        .get(&coin)
+                        .map_or((0, 0), |balance| (balance.balance, balance.value()));
+                    let hold_limit = if index == 0 {
+                        None
+                    } else {
+                        let hold_limit = strategy
+                            .as_ref()
+                            .ok_or("")?
+                            .sub_confs
+                            .get(account.as_ref().unwrap())
+                            .ok_or("")?
+                            .hold_value_limit;
+                        Some(raw2display(hold_limit))
+                    };
+                    (balance, value, hold_limit)
+                }
+                None => (0, 0, Some("0.0".to_string())),
             };
             let freezn_amount = if account.is_none() {
                 0
             } else {
                 super::get_freezn_amount(account.as_ref().unwrap(), &coin).await
             };
-            let total_balance = parse_str(balance_on_chain)?;
             debug!(
                 "coin:{},total_balance:{},freezn_amount:{}",
                 coin, total_balance, freezn_amount
             );
-            let available_balance = total_balance - freezn_amount;
-            let total_dollar_value = super::get_value(&coin, total_balance).await;
+            let total_balance = CoinAmount::from_raw(coin.clone(), total_balance);
+            let freezn_amount = CoinAmount::from_raw(coin.clone(), freezn_amount);
+            let available_balance = total_balance.checked_sub(&freezn_amount)?;
             let total_rmb_value = total_dollar_value / 7;
             let balance = BalanceDetail {
                 account_id: account.clone().unwrap_or("".to_string()),
                 coin: coin.clone(),
-                total_balance: raw2display(total_balance),
-                available_balance: raw2display(available_balance),
-                freezn_amount: raw2display(freezn_amount),
+                total_balance: total_balance.to_display()?,
+                available_balance: available_balance.to_display()?,
+                freezn_amount: freezn_amount.to_display()?,
                 total_dollar_value: raw2display(total_dollar_value),
                 total_rmb_value: raw2display(total_rmb_value),
                 hold_limit,
@@ -23,12 +23,12 @@ pub async fn req(req: HttpRequest, request_data: CancelSendMoneyRequest) -> Back
     super::check_role(role, KeyRole::Master)?;
 
     let CancelSendMoneyRequest { order_id } = request_data;
-    let tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id)).await?;
+    let mut tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id)).await?;
 
     //cann't cancle when status is ReceiverRejected、SenderCanceled、SenderReconfirmed and MultiSigExpired
     if tx.transaction.stage.clone() >= CoinSendStage::ReceiverRejected {
         Err(WalletError::TxStageIllegal(
-            tx.transaction.stage,
+            tx.transaction.stage.clone(),
             CoinSendStage::ReceiverRejected,
         ))?;
     } else {
@@ -36,11 +36,8 @@ pub async fn req(req: HttpRequest, request_data: CancelSendMoneyRequest) -> Back
             Err(WalletError::TxExpired)?;
         }
 
-        CoinTxEntity::update_single(
-            CoinTxUpdater::Stage(CoinSendStage::SenderCanceled),
-            CoinTxFilter::ByOrderId(&order_id),
-        )
-        .await?;
+        tx.update_with_version(CoinTxUpdater::Stage(CoinSendStage::SenderCanceled))
+            .await?;
     }
     Ok(None)
 }
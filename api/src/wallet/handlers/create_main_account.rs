@@ -48,7 +48,7 @@ pub(crate) async fn req(
         captcha,
     } = request_data;
 
-    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::SetSecurity)?;
+    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::SetSecurity).await?;
 
     //store user info
     let user_info = UserInfoEntity::find_single(UserFilter::ById(&user_id))
@@ -2,13 +2,12 @@ use actix_web::HttpRequest;
 use crate::utils::{get_main_account, token_auth};
 use common::error_code::BackendRes;
 use common::{
-    data_structures::CoinType,
-    error_code::{to_param_invalid_error, WalletError},
-    utils::math::coin_amount::{display2raw, raw2display},
+    data_structures::CoinType, error_code::to_param_invalid_error,
+    utils::math::coin_amount::CoinAmount,
 };
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct EstimateTransferFeeResponse {
     pub coin: CoinType,
     pub amount: String,
@@ -32,13 +31,13 @@ pub(crate) async fn req(
 
     let EstimateTransferFeeRequest { coin, amount } = request_data;
     let coin: CoinType = coin.parse().map_err(to_param_invalid_error)?;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
+    let amount = CoinAmount::from_display(coin.clone(), &amount)?.raw;
     let (coin, amount, balance_enough) =
         super::estimate_transfer_fee(&main_account, &coin, amount).await?;
 
     Ok(Some(EstimateTransferFeeResponse {
+        amount: CoinAmount::from_raw(coin.clone(), amount).to_display()?,
         coin,
-        amount: raw2display(amount),
         balance_enough,
     }))
 }
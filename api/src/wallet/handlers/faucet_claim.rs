@@ -3,8 +3,9 @@ use actix_web::HttpRequest;
 use blockchain::coin::Coin;
 use blockchain::multi_sig::MultiSig;
 use blockchain::ContractClient;
-use common::data_structures::{get_support_coin_list, CoinType};
+use common::data_structures::get_support_coin_list;
 use common::error_code::BackendError;
+use common::utils::math::coin_amount::CoinAmount;
 
 use common::error_code::BackendRes;
 
@@ -34,11 +35,8 @@ pub async fn req(req: HttpRequest, req_data: FaucetClaimRequest) -> BackendRes<S
     for coin in coin_list {
         let mut coin_cli: ContractClient<Coin> =
             ContractClient::<Coin>::new_update_cli(coin.clone()).await?;
-        let amount = if coin.eq(&CoinType::ETH) {
-            10000000000000000
-        } else {
-            100000000000000000000
-        };
+        let amount = if coin.eth_native()? { "0.01" } else { "100" };
+        let amount = CoinAmount::from_display(coin.clone(), amount)?.raw;
         let _balance = coin_cli.send_coin(&account, amount).await?;
     }
     Ok(None)
@@ -33,7 +33,7 @@ pub(crate) async fn req(
         newcomer_pubkey,
         captcha,
     } = request_data;
-    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::NewcomerSwitchMaster)?;
+    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::NewcomerSwitchMaster).await?;
 
     let context = get_user_context(&user_id, &device_id).await?;
     let (main_account, _) = context.account_strategy()?;
@@ -31,7 +31,7 @@ pub(crate) async fn req(
 ) -> BackendRes<GenReplaceKeyResponse> {
     let (user_id, _, device_id, _) = token_auth::validate_credentials(&req).await?;
     let GenServantSwitchMasterRequest { captcha } = data;
-    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::ServantSwitchMaster)?;
+    Captcha::check_and_delete(&user_id.to_string(), &captcha, Usage::ServantSwitchMaster).await?;
 
     let servant_pubkey =
         DeviceInfoEntity::find_single(DeviceInfoFilter::ByDeviceUser(&device_id, &user_id))
@@ -5,8 +5,8 @@ use common::data_structures::CoinType;
 use crate::utils::token_auth;
 
 use common::{
-    error_code::{BackendError, BackendRes, WalletError},
-    utils::math::coin_amount::display2raw,
+    error_code::{BackendError, BackendRes},
+    utils::math::coin_amount::CoinAmount,
 };
 use serde::{Deserialize, Serialize};
 
@@ -26,7 +26,7 @@ pub(crate) async fn req(req: HttpRequest, request_data: GetNeedSigNumRequest) ->
     let coin_type: CoinType = coin
         .parse()
         .map_err(|_e| BackendError::RequestParamInvalid("coin not support".to_string()))?;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
+    let amount = CoinAmount::from_display(coin_type.clone(), &amount)?.raw;
     let need_sig_num = super::get_servant_need(&strategy.multi_sig_ranks, &coin_type, amount).await;
     Ok(Some(need_sig_num))
 }
@@ -12,7 +12,7 @@ use common::data_structures::{
 
 use common::error_code::BackendRes;
 use common::error_code::{BackendError, WalletError};
-use common::utils::math::coin_amount::raw2display;
+use common::utils::math::coin_amount::{AmountError, CoinAmount};
 use common::utils::math::hex_to_bs58;
 use common::utils::time::now_millis;
 
@@ -56,6 +56,8 @@ pub struct GetTxResponse {
     pub unsigned_device: Vec<ServentSigDetail>,
     pub tx_type: TxType,
     pub chain_status: TxStatusOnChain,
+    pub retry_times: u32,
+    pub failure_reason: Option<String>,
     pub fees_detail: Vec<FeesDetailResponse>,
     pub updated_at: String,
     pub created_at: String,
@@ -98,10 +100,10 @@ pub async fn req(req: HttpRequest, request_data: GetTxRequest) -> BackendRes<Get
     let tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
         .await
         .map_err(|e| {
-            if e.to_string().contains("DBError::DataNotFound") {
+            if e.is_not_found() {
                 WalletError::OrderNotFound(order_id).into()
             } else {
-                BackendError::InternalError(e.to_string())
+                BackendError::from(e)
             }
         })?;
 
@@ -167,11 +169,13 @@ pub async fn req(req: HttpRequest, request_data: GetTxRequest) -> BackendRes<Get
         get_actual_fee(&tx.transaction.sender, tx_id)
             .await?
             .into_iter()
-            .map(|(fee_coin, amount)| FeesDetailResponse {
-                fee_coin,
-                fee_amount: raw2display(amount),
+            .map(|(fee_coin, amount)| {
+                Ok(FeesDetailResponse {
+                    fee_amount: CoinAmount::from_raw(fee_coin.clone(), amount).to_display()?,
+                    fee_coin,
+                })
             })
-            .collect()
+            .collect::<Result<Vec<_>, AmountError>>()?
     } else {
         let (fee_coin, fee_amount, _balance_enough) = super::estimate_transfer_fee(
             &tx.transaction.sender,
@@ -180,8 +184,8 @@ pub async fn req(req: HttpRequest, request_data: GetTxRequest) -> BackendRes<Get
         )
         .await?;
         vec![FeesDetailResponse {
+            fee_amount: CoinAmount::from_raw(fee_coin.clone(), fee_amount).to_display()?,
             fee_coin,
-            fee_amount: raw2display(fee_amount),
         }]
     };
 
@@ -204,11 +208,12 @@ pub async fn req(req: HttpRequest, request_data: GetTxRequest) -> BackendRes<Get
     let tx = GetTxResponse {
         order_id: tx.transaction.order_id,
         tx_id: tx.transaction.tx_id,
+        amount: CoinAmount::from_raw(tx.transaction.coin_type.clone(), tx.transaction.amount)
+            .to_display()?,
         coin_type: tx.transaction.coin_type,
         from: tx.transaction.sender,
         to,
         to_account_id: tx.transaction.receiver,
-        amount: raw2display(tx.transaction.amount),
         expire_at: tx.transaction.expire_at,
         memo: tx.transaction.memo,
         stage,
@@ -219,6 +224,8 @@ pub async fn req(req: HttpRequest, request_data: GetTxRequest) -> BackendRes<Get
         unsigned_device,
         tx_type: tx.transaction.tx_type,
         chain_status: tx.transaction.chain_status,
+        retry_times: tx.transaction.retry_times,
+        failure_reason: tx.transaction.failure_reason,
         fees_detail,
         updated_at: tx.updated_at,
         created_at: tx.created_at,
@@ -1,10 +1,8 @@
-use std::num::ParseIntError;
-
 use anyhow::Result;
 use blockchain::{
-    coin::Coin,
     fees_call::FeesCall,
     multi_sig::{MultiSig, MultiSigRank, StrategyData},
+    portfolio::{coin_balance, coin_price},
     ContractClient,
 };
 use common::{
@@ -114,16 +112,10 @@ pub async fn get_freezn_amount(account: &str, coin: &CoinType) -> u128 {
 }
 
 pub async fn get_available_amount(account_id: &str, coin: &CoinType) -> BackendRes<u128> {
-    let coin_cli = ContractClient::<Coin>::new_query_cli(coin.clone())
+    let total = coin_balance(account_id, coin)
         .await
         .map_err(|err| ChainError(err.to_string()))?;
-    let balance = coin_cli
-        .get_balance(account_id)
-        .await
-        .unwrap()
-        .unwrap_or("0".to_string());
     let freezn_amount = get_freezn_amount(account_id, coin).await;
-    let total: u128 = balance.parse().unwrap();
     if total < freezn_amount {
         Err(BackendError::InternalError(format!(
             "{}(total) more than {}(freezn_amount)",
@@ -135,12 +127,8 @@ pub async fn get_available_amount(account_id: &str, coin: &CoinType) -> BackendR
 }
 
 //calculate total value for dollar
-//目前的场景转账超过300兆才会溢出
-//由于取整造成的精度丢失可以忽略
 pub async fn get_value(coin: &CoinType, amount: u128) -> u128 {
-    let fees_cli = ContractClient::<FeesCall>::new_query_cli().await.unwrap();
-    let (base_amount, quote_amount) = fees_cli.get_coin_price(coin).await.unwrap();
-    amount * quote_amount / base_amount
+    coin_price(coin).await.unwrap().value_of(amount)
 }
 
 pub async fn get_servant_need(strategy: &Vec<MultiSigRank>, coin: &CoinType, amount: u128) -> u8 {
@@ -163,10 +151,10 @@ pub async fn get_session_state(
     let user = UserInfoEntity::find_single(UserFilter::ById(&user_id))
         .await
         .map_err(|err| {
-            if err.to_string().contains("DBError::DataNotFound") {
+            if err.is_not_found() {
                 WalletError::MainAccountNotExist(err.to_string()).into()
             } else {
-                BackendError::InternalError(err.to_string())
+                BackendError::from(err)
             }
         })?;
 
@@ -196,13 +184,19 @@ pub fn check_role(current: KeyRole, require: KeyRole) -> Result<(), WalletError>
     Ok(())
 }
 
+pub fn check_transfer_enabled(coin: &CoinType) -> Result<(), BackendError> {
+    if !coin.transfer_enabled()? {
+        Err(WalletError::CoinTransferDisabled(coin.to_string()))?;
+    }
+    Ok(())
+}
+
 pub async fn get_fees_priority(main_account: &str) -> BackendRes<Vec<CoinType>> {
     let fees_call_cli = blockchain::ContractClient::<FeesCall>::new_query_cli().await?;
     let fees_priority = fees_call_cli.get_fees_priority(main_account).await?;
     Ok(Some(fees_priority))
 }
 
-//fixme: 查一次最多rpc调用 1 + 5 * 2
 //检查所有的手续费币是否全部小于1u
 pub async fn check_have_base_fee(main_account: &str) -> Result<(), BackendError> {
     let fee_coins = get_fees_priority(main_account)
@@ -210,16 +204,10 @@ pub async fn check_have_base_fee(main_account: &str) -> Result<(), BackendError>
         .ok_or(InternalError("not set fees priority".to_string()))?;
 
     for fee_coin in fee_coins {
-        let coin_cli: ContractClient<Coin> =
-            ContractClient::<Coin>::new_query_cli(fee_coin.clone()).await?;
-        let balance = coin_cli.get_balance(main_account).await?;
-        if balance.is_none() {
+        let mut balance = coin_balance(main_account, &fee_coin).await?;
+        if balance == 0 {
             continue;
         }
-        let mut balance = balance
-            .unwrap()
-            .parse()
-            .map_err(|e: ParseIntError| e.to_string())?;
         let freezn_amount = get_freezn_amount(main_account, &fee_coin).await;
         balance -= freezn_amount;
 
@@ -258,13 +246,7 @@ pub async fn estimate_transfer_fee(
     //todo:
     let mut estimate_res = Default::default();
     for (index, fee_coin) in fee_coins.into_iter().enumerate() {
-        let coin_cli: ContractClient<Coin> =
-            ContractClient::<Coin>::new_query_cli(fee_coin.clone()).await?;
-
-        let mut balance = match coin_cli.get_balance(main_account).await? {
-            Some(balance) => parse_str(balance)?,
-            None => continue,
-        };
+        let mut balance = coin_balance(main_account, &fee_coin).await?;
 
         if &fee_coin == coin {
             if amount >= balance {
@@ -283,20 +265,14 @@ pub async fn estimate_transfer_fee(
         );
 
         if balance_value > fee_value {
-            //fixme: repeat code
-            let fees_cli = ContractClient::<FeesCall>::new_query_cli().await?;
-            let (base_amount, quote_amount) = fees_cli.get_coin_price(&fee_coin).await?;
-            let fee_coin_amount = fee_value * base_amount / quote_amount;
+            let fee_coin_amount = coin_price(&fee_coin).await?.amount_of(fee_value);
             estimate_res = (fee_coin, fee_coin_amount, true);
 
             break;
         }
 
         if index == 0 {
-            //fixme: repeat code
-            let fees_cli = ContractClient::<FeesCall>::new_query_cli().await?;
-            let (base_amount, quote_amount) = fees_cli.get_coin_price(&fee_coin).await?;
-            let fee_coin_amount = fee_value * base_amount / quote_amount;
+            let fee_coin_amount = coin_price(&fee_coin).await?.amount_of(fee_value);
             estimate_res = (fee_coin, fee_coin_amount, false);
         }
     }
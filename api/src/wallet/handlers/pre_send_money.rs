@@ -5,8 +5,8 @@ use blockchain::ContractClient;
 use common::constants::TX_EXPAIRE_TIME;
 use common::data_structures::coin_transaction::{CoinSendStage, TxType};
 
-use common::data_structures::KeyRole;
-use common::utils::math::coin_amount::display2raw;
+use common::data_structures::{CoinType, KeyRole};
+use common::utils::math::coin_amount::CoinAmount;
 use common::utils::time::now_millis;
 
 use serde::{Deserialize, Serialize};
@@ -50,7 +50,9 @@ pub(crate) async fn req(
         is_forced,
     } = request_data;
     let expire_at = now_millis() + TX_EXPAIRE_TIME;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
+    let coin_type: CoinType = coin.parse().map_err(to_param_invalid_error)?;
+    super::check_transfer_enabled(&coin_type)?;
+    let amount = CoinAmount::from_display(coin_type.clone(), &amount)?.raw;
     if amount == 0 {
         Err(WalletError::FobidTransferZero)?;
     }
@@ -65,10 +67,10 @@ pub(crate) async fn req(
         let receiver = UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(&to))
             .await
             .map_err(|err| {
-                if err.to_string().contains("DBError::DataNotFound") {
+                if err.is_not_found() {
                     AccountManagerError::PhoneOrEmailNotRegister.into()
                 } else {
-                    BackendError::InternalError(err.to_string())
+                    BackendError::from(err)
                 }
             })?
             .into_inner();
@@ -81,10 +83,10 @@ pub(crate) async fn req(
         let _receiver = UserInfoEntity::find_single(UserFilter::ByMainAccount(&to))
             .await
             .map_err(|err| {
-                if err.to_string().contains("DBError::DataNotFound") {
+                if err.is_not_found() {
                     WalletError::MainAccountNotExist(err.to_string()).into()
                 } else {
-                    BackendError::InternalError(err.to_string())
+                    BackendError::from(err)
                 }
             })?;
         (to, None)
@@ -92,7 +94,6 @@ pub(crate) async fn req(
     if to_account_id == main_account {
         Err(WalletError::ForbideTransferSelf)?
     }
-    let coin_type = coin.parse().map_err(to_param_invalid_error)?;
 
     let available_balance = super::get_available_amount(&main_account, &coin_type).await?;
     let available_balance = available_balance.unwrap_or(0);
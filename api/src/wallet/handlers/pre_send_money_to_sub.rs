@@ -6,7 +6,7 @@ use common::constants::TX_EXPAIRE_TIME;
 use common::data_structures::coin_transaction::{CoinSendStage, TxType};
 
 use common::data_structures::KeyRole;
-use common::utils::math::coin_amount::display2raw;
+use common::utils::math::coin_amount::CoinAmount;
 use common::utils::time::now_millis;
 
 use tracing::{debug, error};
@@ -49,11 +49,12 @@ pub(crate) async fn req(
         memo,
     } = request_data;
     let expire_at = now_millis() + TX_EXPAIRE_TIME;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
+    let coin_type = parse_str(&coin)?;
+    super::check_transfer_enabled(&coin_type)?;
+    let amount = CoinAmount::from_display(coin_type.clone(), &amount)?.raw;
     if amount == 0 {
         Err(WalletError::FobidTransferZero)?;
     }
-    let coin_type = parse_str(&coin)?;
     let from = main_account.clone();
 
     let available_balance = super::get_available_amount(&from, &coin_type).await?;
@@ -35,7 +35,7 @@ pub(crate) async fn req(
         is_agreed,
     } = request_data;
 
-    let coin_tx =
+    let mut coin_tx =
         CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
             .await?;
     if now_millis() > coin_tx.transaction.expire_at {
@@ -43,7 +43,7 @@ pub(crate) async fn req(
     }
     if coin_tx.transaction.stage != CoinSendStage::SenderSigCompleted {
         Err(WalletError::TxStageIllegal(
-            coin_tx.transaction.stage,
+            coin_tx.transaction.stage.clone(),
             CoinSendStage::SenderSigCompleted,
         ))?;
     }
@@ -66,22 +66,22 @@ pub(crate) async fn req(
                 servant_sigs,
                 &coin_tx.transaction.sender,
                 &coin_tx.transaction.receiver,
-                coin_tx.transaction.coin_type,
+                coin_tx.transaction.coin_type.clone(),
                 coin_tx.transaction.amount,
                 coin_tx.transaction.expire_at,
             )
             .await?;
-        CoinTxEntity::update_single(
-            CoinTxUpdater::ChainTxInfo(&tx_id, &chain_raw_tx, CoinSendStage::ReceiverApproved),
-            CoinTxFilter::ByOrderId(&order_id),
-        )
-        .await?;
+        coin_tx
+            .update_with_version(CoinTxUpdater::ChainTxInfo(
+                &tx_id,
+                &chain_raw_tx,
+                CoinSendStage::ReceiverApproved,
+            ))
+            .await?;
     } else {
-        CoinTxEntity::update_single(
-            CoinTxUpdater::Stage(CoinSendStage::ReceiverRejected),
-            CoinTxFilter::ByOrderId(&order_id),
-        )
-        .await?;
+        coin_tx
+            .update_with_version(CoinTxUpdater::Stage(CoinSendStage::ReceiverRejected))
+            .await?;
     };
 
     Ok(None)
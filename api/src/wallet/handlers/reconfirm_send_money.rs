@@ -35,7 +35,7 @@ pub async fn req(req: HttpRequest, request_data: ReconfirmSendMoneyRequest) -> B
 
     super::check_role(role, KeyRole::Master)?;
 
-    let coin_tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
+    let mut coin_tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
             .await?;
     if now_millis() > coin_tx.transaction.expire_at {
         Err(WalletError::TxExpired)?;
@@ -64,7 +64,7 @@ pub async fn req(req: HttpRequest, request_data: ReconfirmSendMoneyRequest) -> B
         info!("coin_tx {:?} is a tx which send money to sub", coin_tx);
 
         //提前进行签名校验
-        let data = coin_tx.transaction.coin_tx_raw;
+        let data = coin_tx.transaction.coin_tx_raw.clone();
         let sign_info: PubkeySignInfo = confirmed_sig.as_str().parse()?;
         if !ed25519_verify_hex(&data, &sign_info.pubkey, &sign_info.signature)? {
             Err(BackendError::RequestParamInvalid(
@@ -86,27 +86,28 @@ pub async fn req(req: HttpRequest, request_data: ReconfirmSendMoneyRequest) -> B
                 servant_sigs,
                 &coin_tx.transaction.sender,
                 &coin_tx.transaction.receiver,
-                coin_tx.transaction.coin_type,
+                coin_tx.transaction.coin_type.clone(),
                 coin_tx.transaction.amount,
                 coin_tx.transaction.expire_at,
             )
             .await?;
 
         //todo:txid?
-        CoinTxEntity::update_single(
-            CoinTxUpdater::TxidStageChainStatus(
+        //保存二次确认签名，上链失败时由relayer重新提交
+        coin_tx
+            .update_with_version(CoinTxUpdater::TxidSigStageChainStatus(
                 &tx_id,
+                &confirmed_sig,
                 CoinSendStage::SenderReconfirmed,
                 TxStatusOnChain::Pending,
-            ),
-            CoinTxFilter::ByOrderId(&order_id),
-        )
-        .await?;
+            ))
+            .await?;
     } else {
         //提前进行签名校验
         let data = coin_tx
             .transaction
             .tx_id
+            .clone()
             .ok_or(BackendError::InternalError("".to_string()))?;
         let pubkey = current_strategy.master_pubkey;
         if !ed25519_verify_hex(&data, &pubkey, &confirmed_sig)? {
@@ -121,14 +122,12 @@ pub async fn req(req: HttpRequest, request_data: ReconfirmSendMoneyRequest) -> B
             &confirmed_sig,
         )
         .await?;
-        CoinTxEntity::update_single(
-            CoinTxUpdater::StageChainStatus(
+        coin_tx
+            .update_with_version(CoinTxUpdater::StageChainStatus(
                 CoinSendStage::SenderReconfirmed,
                 TxStatusOnChain::Pending,
-            ),
-            CoinTxFilter::ByOrderId(&order_id),
-        )
-        .await?;
+            ))
+            .await?;
     }
     Ok(None)
 }
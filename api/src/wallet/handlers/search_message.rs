@@ -1,6 +1,6 @@
 use actix_web::HttpRequest;
 
-use common::utils::math::coin_amount::raw2display;
+use common::utils::math::coin_amount::{AmountError, CoinAmount};
 use common::utils::time::now_millis;
 
 use secret_store::SecretStore;
@@ -71,23 +71,29 @@ pub(crate) async fn req(req: HttpRequest) -> BackendRes<SearchMessageResponse> {
     let mut tx_msg = coin_txs
         .into_iter()
         .filter(|x| now_millis() <= x.transaction.expire_at)
-        .map(|tx| CoinTransactionResponse {
-            order_id: tx.transaction.order_id,
-            tx_id: tx.transaction.tx_id,
-            coin_type: tx.transaction.coin_type,
-            from: tx.transaction.sender,
-            to: tx.transaction.receiver,
-            amount: raw2display(tx.transaction.amount),
-            expire_at: tx.transaction.expire_at,
-            memo: tx.transaction.memo,
-            stage: tx.transaction.stage,
-            coin_tx_raw: tx.transaction.coin_tx_raw,
-            chain_tx_raw: tx.transaction.chain_tx_raw,
-            signatures: tx.transaction.signatures,
-            tx_type: tx.transaction.tx_type,
-            chain_status: tx.transaction.chain_status,
+        .map(|tx| {
+            Ok(CoinTransactionResponse {
+                order_id: tx.transaction.order_id,
+                tx_id: tx.transaction.tx_id,
+                amount: CoinAmount::from_raw(
+                    tx.transaction.coin_type.clone(),
+                    tx.transaction.amount,
+                )
+                .to_display()?,
+                coin_type: tx.transaction.coin_type,
+                from: tx.transaction.sender,
+                to: tx.transaction.receiver,
+                expire_at: tx.transaction.expire_at,
+                memo: tx.transaction.memo,
+                stage: tx.transaction.stage,
+                coin_tx_raw: tx.transaction.coin_tx_raw,
+                chain_tx_raw: tx.transaction.chain_tx_raw,
+                signatures: tx.transaction.signatures,
+                tx_type: tx.transaction.tx_type,
+                chain_status: tx.transaction.chain_status,
+            })
         })
-        .collect::<Vec<CoinTransactionResponse>>();
+        .collect::<Result<Vec<CoinTransactionResponse>, AmountError>>()?;
 
     messages.coin_tx.append(&mut tx_msg);
 
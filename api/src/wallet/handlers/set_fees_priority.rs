@@ -46,6 +46,14 @@ pub async fn req(req: HttpRequest, request_data: SetFeesPriorityRequest) -> Back
                 .map_err(|e| BackendError::RequestParamInvalid(e.to_string()))
         })
         .collect::<Result<Vec<CoinType>, BackendError>>()?;
+    for coin in &fees_priority {
+        if !coin.fee_enabled()? {
+            Err(BackendError::RequestParamInvalid(format!(
+                "{} can't pay fees",
+                coin
+            )))?;
+        }
+    }
 
     let tx_id = fees_call_cli
         .set_fees_priority(&main_account, fees_priority)
@@ -1,14 +1,13 @@
 use crate::utils::token_auth;
 use actix_web::HttpRequest;
-use blockchain::coin::Coin;
-
 use blockchain::multi_sig::MultiSig;
+use blockchain::portfolio::coin_balance;
 use blockchain::ContractClient;
 use common::data_structures::CoinType;
 
+use common::error_code::BackendError;
 use common::error_code::BackendRes;
-use common::error_code::{to_internal_error, BackendError};
-use common::utils::math::coin_amount::raw2display;
+use common::utils::math::coin_amount::{raw2display, CoinAmount};
 use models::account_manager::{UserFilter, UserInfoEntity};
 
 use models::PsqlOp;
@@ -61,19 +60,12 @@ pub async fn req(
         None => (main_account.clone(), None),
     };
 
-    let coin_cli: ContractClient<Coin> =
-        ContractClient::<Coin>::new_query_cli(coin.clone()).await?;
-    let balance = coin_cli
-        .get_balance(&dist_account)
-        .await?
-        .unwrap_or("0".to_string());
-
-    let total_balance = balance.parse().map_err(to_internal_error)?;
+    let total_balance = coin_balance(&dist_account, &coin).await?;
     debug!("coin:{},total_balance:{}", coin, total_balance);
     let total_dollar_value = super::get_value(&coin, total_balance).await;
     let total_rmb_value = total_dollar_value / 7;
     let balance = SingleBalanceResponse {
-        total_balance: raw2display(total_balance),
+        total_balance: CoinAmount::from_raw(coin, total_balance).to_display()?,
         total_dollar_value: raw2display(total_dollar_value),
         total_rmb_value: raw2display(total_rmb_value),
         hold_limit,
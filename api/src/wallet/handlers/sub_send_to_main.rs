@@ -7,7 +7,7 @@ use common::data_structures::CoinType;
 
 use common::data_structures::KeyRole;
 use common::encrypt::ed25519_verify_hex;
-use common::utils::math::coin_amount::display2raw;
+use common::utils::math::coin_amount::CoinAmount;
 
 use tracing::error;
 
@@ -44,11 +44,11 @@ pub async fn req(req: HttpRequest, request_data: SubSendToMainRequest) -> Backen
         coin: coin_id,
         amount,
     } = request_data;
-    let amount = display2raw(&amount).map_err(|_e| WalletError::UnSupportedPrecision)?;
-
     let coin_type: CoinType = coin_id
         .parse()
         .map_err(|_e| BackendError::RequestParamInvalid("coin not support".to_string()))?;
+    super::check_transfer_enabled(&coin_type)?;
+    let amount = CoinAmount::from_display(coin_type.clone(), &amount)?.raw;
 
     let mut cli = ContractClient::<MultiSig>::new_update_cli().await?;
 
@@ -95,6 +95,7 @@ pub async fn req(req: HttpRequest, request_data: SubSendToMainRequest) -> Backen
     );
     coin_info.transaction.tx_type = TxType::SubToMain;
     coin_info.transaction.tx_id = Some(tx_id.clone());
+    coin_info.transaction.confirmed_sig = Some(sub_sig.signature);
     coin_info.insert().await?;
     Ok(Some(tx_id))
 }
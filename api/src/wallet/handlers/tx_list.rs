@@ -1,7 +1,7 @@
 use crate::utils::{get_main_account, token_auth};
 use actix_web::HttpRequest;
 
-use common::data_structures::coin_transaction::CoinSendStage;
+use common::data_structures::coin_transaction::{CoinSendStage, TxRole};
 use common::data_structures::TxStatusOnChain;
 use common::data_structures::{coin_transaction::TxType, CoinType};
 use common::error_code::to_param_invalid_error;
@@ -9,17 +9,16 @@ use common::error_code::to_param_invalid_error;
 use common::error_code::BackendError;
 
 use common::error_code::BackendRes;
-use common::utils::math::coin_amount::raw2display;
+use common::utils::math::coin_amount::CoinAmount;
 use common::utils::time::now_millis;
 use models::account_manager::{UserFilter, UserInfoEntity};
-use models::coin_transfer::{CoinTxEntity, CoinTxFilter};
+use models::coin_transfer::{CoinTxEntity, CoinTxFilter, TxCursor, TxListQuery};
 use models::device_info::{DeviceInfoEntity, DeviceInfoFilter};
 
 use models::PsqlOp;
 use serde::{Deserialize, Serialize};
 
 use super::ServentSigDetail;
-use anyhow::Result;
 
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -27,7 +26,25 @@ pub struct TxListRequest {
     tx_role: String,
     counterparty: Option<String>,
     per_page: u32,
-    page: u32,
+    //旧版本接口的页码，从1开始
+    page: Option<u32>,
+    //上一页返回的next_cursor，第一页不传
+    cursor: Option<String>,
+    coin_type: Option<String>,
+    tx_type: Option<String>,
+    stage: Option<String>,
+    chain_status: Option<String>,
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TxListResponse {
+    pub txs: Vec<CoinTxViewResponse>,
+    //没有更多数据时为空
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -46,11 +63,12 @@ pub struct CoinTxViewResponse {
     pub signatures: Vec<ServentSigDetail>,
     pub tx_type: TxType,
     pub chain_status: TxStatusOnChain,
+    pub retry_times: u32,
+    pub failure_reason: Option<String>,
     pub updated_at: String,
     pub created_at: String,
 }
 
-//todo:
 pub enum FilterType {
     OrderId,
     AccountId,
@@ -72,75 +90,141 @@ pub fn get_filter_type(data: &str) -> Result<FilterType, BackendError> {
     }
 }
 
-pub async fn req(
-    req: HttpRequest,
-    request_data: TxListRequest,
-) -> BackendRes<Vec<CoinTxViewResponse>> {
-    let (user_id, _, _, _) = token_auth::validate_credentials(&req).await?;
+fn parse_option<T>(data: Option<String>) -> Result<Option<T>, BackendError>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error,
+{
+    data.map(|data| data.parse().map_err(to_param_invalid_error))
+        .transpose()
+}
 
-    let main_account = get_main_account(&user_id).await?;
+fn gen_list_query(request_data: &TxListRequest) -> Result<TxListQuery, BackendError> {
     let TxListRequest {
-        tx_role,
-        counterparty,
         per_page,
         page,
-    } = request_data;
+        cursor,
+        coin_type,
+        tx_type,
+        stage,
+        chain_status,
+        min_amount,
+        max_amount,
+        start_time,
+        end_time,
+        ..
+    } = request_data.clone();
     if per_page > 1000 {
         Err(BackendError::RequestParamInvalid(
             "per_page is too big".to_string(),
         ))?;
     }
-    let tx_role = tx_role.parse().map_err(to_param_invalid_error)?;
+    let cursor = cursor
+        .map(|data| TxCursor::decode(&data))
+        .transpose()
+        .map_err(|e| BackendError::RequestParamInvalid(e.to_string()))?;
+    let coin_type: Option<CoinType> = parse_option(coin_type)?;
+    //各币种链上的精度不同，金额只能在指定的币种里按它的精度比较
+    let parse_amount = |data: String| -> Result<u128, BackendError> {
+        let coin = coin_type.clone().ok_or(BackendError::RequestParamInvalid(
+            "amount filter requires coin_type".to_string(),
+        ))?;
+        Ok(CoinAmount::from_display(coin, &data)?.raw)
+    };
+    let min_amount = min_amount.map(&parse_amount).transpose()?;
+    let max_amount = max_amount.map(&parse_amount).transpose()?;
+    Ok(TxListQuery {
+        order_id: None,
+        coin_type,
+        tx_type: parse_option(tx_type)?,
+        stage: parse_option(stage)?,
+        chain_status: parse_option(chain_status)?,
+        min_amount,
+        max_amount,
+        start_at: start_time,
+        end_at: end_time,
+        cursor,
+        limit: per_page,
+        offset: page.unwrap_or(1).saturating_sub(1).saturating_mul(per_page),
+    })
+}
+
+async fn find_txs(
+    req: &HttpRequest,
+    request_data: TxListRequest,
+) -> Result<(Vec<CoinTxEntity>, TxListQuery), BackendError> {
+    let (user_id, _, _, _) = token_auth::validate_credentials(req).await?;
+
+    let main_account = get_main_account(&user_id).await?;
+    let mut query = gen_list_query(&request_data)?;
+    let TxListRequest {
+        tx_role,
+        counterparty,
+        ..
+    } = request_data;
+    let tx_role: TxRole = tx_role.parse().map_err(to_param_invalid_error)?;
 
     //filter by tx_order_id 、account_id 、phone、mail or eth_addr
-    //fixme:
-    let find_res = if let Some(data) = counterparty.as_deref() {
+    let txs = if let Some(data) = counterparty.as_deref() {
         let filter_type = match get_filter_type(data) {
             Ok(t) => t,
-            Err(_) => return Ok(Some(vec![])),
+            Err(_) => return Ok((vec![], query)),
         };
         match filter_type {
-            FilterType::OrderId => CoinTxEntity::find(CoinTxFilter::ByOrderId(data)).await,
+            //只能查到自己作为当前角色的订单
+            FilterType::OrderId => {
+                query.order_id = Some(data.to_string());
+                CoinTxEntity::find(CoinTxFilter::ByTxRoleCursor(
+                    tx_role,
+                    &main_account,
+                    None,
+                    &query,
+                ))
+                .await?
+            }
             FilterType::AccountId => {
-                CoinTxEntity::find(CoinTxFilter::ByTxRolePage(
+                CoinTxEntity::find(CoinTxFilter::ByTxRoleCursor(
                     tx_role,
                     &main_account,
                     Some(data),
-                    per_page,
-                    page,
+                    &query,
                 ))
-                .await
+                .await?
             }
             FilterType::Phone | FilterType::Mail => {
-                if let Ok(counterparty_main_account) =
-                    UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(data)).await
-                {
-                    CoinTxEntity::find(CoinTxFilter::ByTxRolePage(
-                        tx_role,
-                        &main_account,
-                        Some(&counterparty_main_account.user_info.main_account.unwrap()),
-                        per_page,
-                        page,
-                    ))
-                    .await
-                } else {
-                    return Ok(None);
+                let counterparty_main_account =
+                    match UserInfoEntity::find_single(UserFilter::ByPhoneOrEmail(data)).await {
+                        Ok(user) => user.user_info.main_account,
+                        Err(_) => return Ok((vec![], query)),
+                    };
+                match counterparty_main_account {
+                    Some(account) => {
+                        CoinTxEntity::find(CoinTxFilter::ByTxRoleCursor(
+                            tx_role,
+                            &main_account,
+                            Some(&account),
+                            &query,
+                        ))
+                        .await?
+                    }
+                    //对方还没有主账户，不会有交易
+                    None => vec![],
                 }
             }
         }
     } else {
-        CoinTxEntity::find(CoinTxFilter::ByTxRolePage(
+        CoinTxEntity::find(CoinTxFilter::ByTxRoleCursor(
             tx_role,
             &main_account,
             None,
-            per_page,
-            page,
+            &query,
         ))
-        .await
+        .await?
     };
+    Ok((txs, query))
+}
 
-    let txs = find_res?;
-
+async fn gen_view(txs: Vec<CoinTxEntity>) -> Result<Vec<CoinTxViewResponse>, BackendError> {
     let mut view_txs = vec![];
     for tx in txs {
         let stage = if now_millis() > tx.transaction.expire_at {
@@ -160,13 +244,14 @@ pub async fn req(
             };
             sigs.push(sig);
         }
+        let amount = CoinAmount::from_raw(tx.transaction.coin_type.clone(), tx.transaction.amount);
         view_txs.push(CoinTxViewResponse {
             order_id: tx.transaction.order_id,
             tx_id: tx.transaction.tx_id,
             coin_type: tx.transaction.coin_type,
             from: tx.transaction.sender,
             to: tx.transaction.receiver,
-            amount: raw2display(tx.transaction.amount),
+            amount: amount.to_display()?,
             expire_at: tx.transaction.expire_at,
             memo: tx.transaction.memo,
             stage,
@@ -175,9 +260,34 @@ pub async fn req(
             signatures: sigs,
             tx_type: tx.transaction.tx_type,
             chain_status: tx.transaction.chain_status,
+            retry_times: tx.transaction.retry_times,
+            failure_reason: tx.transaction.failure_reason,
             updated_at: tx.updated_at,
             created_at: tx.created_at,
         });
     }
-    Ok(Some(view_txs))
+    Ok(view_txs)
+}
+
+/// the former page numbered list, kept for the clients before the cursor
+pub async fn req(
+    req: HttpRequest,
+    request_data: TxListRequest,
+) -> BackendRes<Vec<CoinTxViewResponse>> {
+    let (txs, _query) = find_txs(&req, request_data).await?;
+    Ok(Some(gen_view(txs).await?))
+}
+
+pub async fn req_v2(req: HttpRequest, request_data: TxListRequest) -> BackendRes<TxListResponse> {
+    let (txs, query) = find_txs(&req, request_data).await?;
+    //取满一页才可能有下一页
+    let next_cursor = if query.limit > 0 && txs.len() == query.limit as usize {
+        txs.last().map(|tx| TxCursor::of(tx).encode())
+    } else {
+        None
+    };
+    Ok(Some(TxListResponse {
+        txs: gen_view(txs).await?,
+        next_cursor,
+    }))
 }
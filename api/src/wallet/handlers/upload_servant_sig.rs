@@ -48,7 +48,7 @@ pub async fn req(req: HttpRequest, request_data: UploadTxSignatureRequest) -> Ba
         CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
             .await?;
 
-    let data = tx.transaction.coin_tx_raw;
+    let data = tx.transaction.coin_tx_raw.clone();
 
     if !ed25519_verify_hex(&data, &sign_info.pubkey, &sign_info.signature)? {
         Err(BackendError::RequestParamInvalid(
@@ -58,7 +58,7 @@ pub async fn req(req: HttpRequest, request_data: UploadTxSignatureRequest) -> Ba
 
     if tx.transaction.stage != CoinSendStage::Created {
         Err(WalletError::TxStageIllegal(
-            tx.transaction.stage,
+            tx.transaction.stage.clone(),
             CoinSendStage::Created,
         ))?;
     }
@@ -66,13 +66,11 @@ pub async fn req(req: HttpRequest, request_data: UploadTxSignatureRequest) -> Ba
         Err(WalletError::TxExpired)?;
     }
 
-    tx.transaction.signatures.push(signature);
+    let mut signatures = tx.transaction.signatures.clone();
+    signatures.push(signature);
     //fixme: repeat update twice
-    CoinTxEntity::update_single(
-        CoinTxUpdater::Signature(tx.transaction.signatures.clone()),
-        CoinTxFilter::ByOrderId(&order_id),
-    )
-    .await?;
+    tx.update_with_version(CoinTxUpdater::Signature(signatures))
+        .await?;
 
     //collect enough signatures
     let multi_cli = blockchain::ContractClient::<MultiSig>::new_update_cli().await?;
@@ -94,11 +92,8 @@ pub async fn req(req: HttpRequest, request_data: UploadTxSignatureRequest) -> Ba
         if tx.transaction.tx_type == TxType::MainToSub
             || tx.transaction.tx_type == TxType::MainToBridge
         {
-            CoinTxEntity::update_single(
-                CoinTxUpdater::Stage(CoinSendStage::ReceiverApproved),
-                CoinTxFilter::ByOrderId(&order_id),
-            )
-            .await?;
+            tx.update_with_version(CoinTxUpdater::Stage(CoinSendStage::ReceiverApproved))
+                .await?;
         //给其他主账户转是用户自己签名，需要生成tx_raw
         } else if tx.transaction.tx_type == TxType::Forced {
             //todo: 83~102 line is redundant，txid生成在gen_send_money的时候进行了
@@ -117,24 +112,22 @@ pub async fn req(req: HttpRequest, request_data: UploadTxSignatureRequest) -> Ba
                     servant_sigs,
                     &tx.transaction.sender,
                     &tx.transaction.receiver,
-                    tx.transaction.coin_type,
+                    tx.transaction.coin_type.clone(),
                     tx.transaction.amount,
                     tx.transaction.expire_at,
                 )
                 .await?;
 
-            CoinTxEntity::update_single(
-                CoinTxUpdater::ChainTxInfo(&tx_id, &chain_tx_raw, CoinSendStage::ReceiverApproved),
-                CoinTxFilter::ByOrderId(&order_id),
-            )
+            tx.update_with_version(CoinTxUpdater::ChainTxInfo(
+                &tx_id,
+                &chain_tx_raw,
+                CoinSendStage::ReceiverApproved,
+            ))
             .await?;
         //非子账户非强制的话，签名收集够了则需要收款方进行确认
         } else {
-            CoinTxEntity::update_single(
-                CoinTxUpdater::Stage(CoinSendStage::SenderSigCompleted),
-                CoinTxFilter::ByOrderId(&order_id),
-            )
-            .await?;
+            tx.update_with_version(CoinTxUpdater::Stage(CoinSendStage::SenderSigCompleted))
+                .await?;
         }
     }
     Ok(None)
@@ -40,7 +40,9 @@ pub struct OrderEventInfo {
     //create order time for withdraw
     //deadline for deposit
     pub timestamp: u128, //deadline:u128,
-                         //timestamp:u128
+    //timestamp:u128
+    pub height: u64,
+    pub block_hash: String,
 }
 
 #[derive(Debug)]
@@ -232,17 +234,19 @@ impl EthContractClient<Bridge> {
         })
     }
 
-    pub async fn filter_deposit_event(&self, block_hash: &str) -> Result<Vec<OrderEventInfo>> {
+    /// deposit events in the blocks `[from, to]`, with the block of each event
+    pub async fn filter_deposit_event(&self, from: u64, to: u64) -> Result<Vec<OrderEventInfo>> {
         let contract = BridgeCA::new(self.contract_addr, self.client.clone());
-        let deposit_orders: Vec<DepositFilter> = contract
+        let deposit_orders: Vec<(DepositFilter, LogMeta)> = contract
             .deposit_filter()
-            .at_block_hash(H256::from_str(block_hash)?)
-            .query()
+            .from_block(from)
+            .to_block(to)
+            .query_with_meta()
             .await?;
 
         let diposit_order = deposit_orders
             .iter()
-            .map(|order| {
+            .map(|(order, meta)| {
                 Ok(OrderEventInfo {
                     id: order.deposit_id.to_string(),
                     order_type: OrderType::Deposit,
@@ -251,23 +255,27 @@ impl EthContractClient<Bridge> {
                     coin: order.symbol.parse()?,
                     amount: order.amount.as_u128(),
                     timestamp: order.deadline.as_u128(),
+                    height: meta.block_number.as_u64(),
+                    block_hash: hex::encode(meta.block_hash.as_bytes()),
                 })
             })
             .collect::<Result<Vec<OrderEventInfo>>>()?;
         Ok(diposit_order)
     }
 
-    pub async fn filter_withdraw_event(&self, block_hash: &str) -> Result<Vec<OrderEventInfo>> {
+    /// withdraw events in the blocks `[from, to]`, with the block of each event
+    pub async fn filter_withdraw_event(&self, from: u64, to: u64) -> Result<Vec<OrderEventInfo>> {
         let contract = BridgeCA::new(self.contract_addr, self.client.clone());
-        let deposit_orders: Vec<WithdrawFilter> = contract
+        let withdraw_orders: Vec<(WithdrawFilter, LogMeta)> = contract
             .withdraw_filter()
-            .at_block_hash(H256::from_str(block_hash)?)
-            .query()
+            .from_block(from)
+            .to_block(to)
+            .query_with_meta()
             .await?;
 
-        let diposit_order = deposit_orders
+        let withdraw_order = withdraw_orders
             .iter()
-            .map(|order| {
+            .map(|(order, meta)| {
                 Ok(OrderEventInfo {
                     id: order.id.to_string(),
                     order_type: OrderType::Withdraw,
@@ -276,10 +284,12 @@ impl EthContractClient<Bridge> {
                     coin: order.symbol.parse()?,
                     amount: order.amount.as_u128(),
                     timestamp: order.timestamp.as_u128(),
+                    height: meta.block_number.as_u64(),
+                    block_hash: hex::encode(meta.block_hash.as_bytes()),
                 })
             })
             .collect::<Result<Vec<OrderEventInfo>>>()?;
-        Ok(diposit_order)
+        Ok(withdraw_order)
     }
 }
 
@@ -283,7 +283,7 @@ impl ContractClient<Bridge> {
     ) -> Result<(String, u64, u64)> {
         let deadline = (now_millis() + BRIDGE_DEPOSIT_EXPIRE_TIME) / 1000;
         let cid = now_millis();
-        let amount = if coin == CoinType::ETH {
+        let amount = if coin.eth_native()? {
             U256::zero()
         } else {
             U256::from(amount)
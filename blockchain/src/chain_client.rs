@@ -0,0 +1,276 @@
+//! the near node operations used by the contract clients,
+//! served by the json rpc node or by [`MockChain`](crate::mock_chain::MockChain) in tests
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use common::data_structures::TxStatusOnChain;
+use near_crypto::PublicKey;
+use near_jsonrpc_client::errors::{JsonRpcError, JsonRpcServerError};
+use near_jsonrpc_client::methods;
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_jsonrpc_primitives::types::transactions::{RpcTransactionError, TransactionInfo};
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, BlockId, BlockReference, Finality, FunctionArgs};
+use near_primitives::views::{
+    AccessKeyList, ActionView, ExecutionStatusView, FinalExecutionStatus, QueryRequest,
+};
+use tracing::warn;
+
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    async fn final_block_hash(&self) -> Result<CryptoHash>;
+
+    /// (nonce, latest block hash) of the access key
+    async fn view_access_key(
+        &self,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<(u64, CryptoHash)>;
+
+    async fn view_access_key_list(&self, account_id: &AccountId) -> Result<AccessKeyList>;
+
+    /// native balance in yoctoNEAR, which pays the gas
+    async fn view_account_balance(&self, account_id: &AccountId) -> Result<u128>;
+
+    /// raw json result of a view function
+    async fn view_call(
+        &self,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>>;
+
+    /// same as `view_call`, but read the state of the given block
+    async fn view_call_at(
+        &self,
+        block_hash: CryptoHash,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>>;
+
+    /// wait until the transaction is executed, a failed execution is returned as [`TxExecutionFailure`]
+    async fn broadcast_tx_commit(&self, tx: SignedTransaction) -> Result<CryptoHash>;
+
+    async fn tx_status(&self, tx_hash: CryptoHash) -> Result<TxStatusOnChain>;
+
+    /// the function call carried by the transaction, it must be its only action
+    async fn tx_call(&self, tx_hash: CryptoHash) -> Result<TxCall>;
+}
+
+/// the transaction is on chain but its execution failed, e.g. rejected by the contract,
+/// unlike the transport errors its nonce is used up
+#[derive(Debug, thiserror::Error)]
+#[error("tx {tx_hash} failed: {reason}")]
+pub struct TxExecutionFailure {
+    pub tx_hash: CryptoHash,
+    pub reason: String,
+}
+
+/// a single function call transaction, enough to submit it again
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxCall {
+    pub signer_id: AccountId,
+    pub receiver_id: AccountId,
+    pub method_name: String,
+    pub args: String,
+}
+
+/// the node configured by `chain_rpc`
+pub struct RpcChainClient;
+
+#[async_trait]
+impl ChainClient for RpcChainClient {
+    async fn final_block_hash(&self) -> Result<CryptoHash> {
+        let block = crate::rpc_call(methods::block::RpcBlockRequest {
+            block_reference: BlockReference::Finality(Finality::Final),
+        })
+        .await?;
+        Ok(block.header.hash)
+    }
+
+    async fn view_access_key(
+        &self,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<(u64, CryptoHash)> {
+        let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: account_id.clone(),
+                public_key: public_key.clone(),
+            },
+        })
+        .await?;
+
+        match access_key_query_response.kind {
+            QueryResponseKind::AccessKey(access_key) => {
+                Ok((access_key.nonce, access_key_query_response.block_hash))
+            }
+            _ => Err(anyhow!("failed to extract current nonce")),
+        }
+    }
+
+    async fn view_access_key_list(&self, account_id: &AccountId) -> Result<AccessKeyList> {
+        let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKeyList {
+                account_id: account_id.clone(),
+            },
+        })
+        .await?;
+
+        match access_key_query_response.kind {
+            QueryResponseKind::AccessKeyList(list) => Ok(list),
+            _ => Err(anyhow!("failed to extract access key list")),
+        }
+    }
+
+    async fn view_account_balance(&self, account_id: &AccountId) -> Result<u128> {
+        let account_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccount {
+                account_id: account_id.clone(),
+            },
+        })
+        .await?;
+
+        match account_query_response.kind {
+            QueryResponseKind::ViewAccount(account) => Ok(account.amount),
+            _ => Err(anyhow!("failed to extract account")),
+        }
+    }
+
+    async fn view_call(
+        &self,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>> {
+        let block_reference = BlockReference::Finality(Finality::Final);
+        view_function(block_reference, contract, method_name, args).await
+    }
+
+    async fn view_call_at(
+        &self,
+        block_hash: CryptoHash,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>> {
+        let block_reference = BlockReference::BlockId(BlockId::Hash(block_hash));
+        view_function(block_reference, contract, method_name, args).await
+    }
+
+    async fn broadcast_tx_commit(&self, tx: SignedTransaction) -> Result<CryptoHash> {
+        let request = methods::broadcast_tx_commit::RpcBroadcastTxCommitRequest {
+            signed_transaction: tx,
+        };
+        let rep = crate::rpc_call(request).await?;
+        if let FinalExecutionStatus::Failure(error) = rep.status {
+            Err(TxExecutionFailure {
+                tx_hash: rep.transaction.hash,
+                reason: error.to_string(),
+            })?
+        }
+        Ok(rep.transaction.hash)
+    }
+
+    async fn tx_status(&self, tx_hash: CryptoHash) -> Result<TxStatusOnChain> {
+        let tx_status_request = methods::tx::RpcTransactionStatusRequest {
+            transaction_info: TransactionInfo::TransactionId {
+                tx_hash,
+                sender_account_id: "node0".parse()?,
+            },
+        };
+
+        let tx_status = crate::rpc_call(tx_status_request).await?;
+
+        let status = if let FinalExecutionStatus::SuccessValue(_value) = tx_status.status {
+            let mut status = TxStatusOnChain::Successful;
+            for outcome in tx_status.receipts_outcome {
+                match outcome.outcome.status {
+                    ExecutionStatusView::Unknown => {
+                        unreachable!("");
+                    }
+                    ExecutionStatusView::Failure(_) => {
+                        status = TxStatusOnChain::Failed;
+                        break;
+                    }
+                    ExecutionStatusView::SuccessValue(_) => {}
+                    ExecutionStatusView::SuccessReceiptId(_) => {}
+                }
+            }
+            status
+        } else if let FinalExecutionStatus::Failure(error) = tx_status.status {
+            warn!("tx_id({}) is failed: {}", tx_hash, error.to_string());
+            TxStatusOnChain::Failed
+        } else {
+            TxStatusOnChain::Pending
+        };
+        Ok(status)
+    }
+
+    async fn tx_call(&self, tx_hash: CryptoHash) -> Result<TxCall> {
+        let tx_status_request = methods::tx::RpcTransactionStatusRequest {
+            transaction_info: TransactionInfo::TransactionId {
+                tx_hash,
+                sender_account_id: "node0".parse()?,
+            },
+        };
+        let tx = crate::rpc_call(tx_status_request).await?.transaction;
+        match tx.actions.as_slice() {
+            [ActionView::FunctionCall {
+                method_name, args, ..
+            }] => Ok(TxCall {
+                signer_id: tx.signer_id,
+                receiver_id: tx.receiver_id,
+                method_name: method_name.clone(),
+                args: String::from_utf8(args.clone().into())?,
+            }),
+            _ => Err(anyhow!("tx {} isn't a single function call", tx_hash)),
+        }
+    }
+}
+
+async fn view_function(
+    block_reference: BlockReference,
+    contract: &AccountId,
+    method_name: &str,
+    args: &str,
+) -> Result<Vec<u8>> {
+    let request = methods::query::RpcQueryRequest {
+        block_reference,
+        request: QueryRequest::CallFunction {
+            account_id: contract.clone(),
+            method_name: method_name.to_string(),
+            args: FunctionArgs::from(args.to_string().into_bytes()),
+        },
+    };
+    let rep = crate::rpc_call(request).await?;
+    match rep.kind {
+        QueryResponseKind::CallResult(result) => Ok(result.result),
+        _ => Err(anyhow!("kind must be contract call")),
+    }
+}
+
+/// the mock chain within its scope, otherwise the rpc node
+pub fn chain_client() -> Arc<dyn ChainClient> {
+    match crate::mock_chain::backend() {
+        Some(mock) => mock,
+        None => Arc::new(RpcChainClient),
+    }
+}
+
+/// the error the node answers when it refuses to accept the transaction,
+/// kept structured so the callers can tell a stale nonce from other failures
+pub fn invalid_tx_error(context: InvalidTxError) -> anyhow::Error {
+    JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+        RpcTransactionError::InvalidTransaction { context },
+    ))
+    .into()
+}
@@ -1,23 +1,22 @@
 use common::data_structures::coin_transaction::CoinTransaction;
 
 use near_primitives::borsh::BorshDeserialize;
+use near_primitives::hash::CryptoHash;
 use near_primitives::transaction::{Action, FunctionCallAction, Transaction};
-use near_primitives::types::{AccountId, BlockReference, Finality, FunctionArgs};
+use near_primitives::types::AccountId;
 use std::ops::Deref;
 use std::str::FromStr;
 
 use hex;
 use lazy_static::lazy_static;
-use near_jsonrpc_client::methods;
-use near_jsonrpc_primitives::types::query::QueryResponseKind;
 use near_primitives::transaction::Action::FunctionCall;
-use near_primitives::views::QueryRequest;
 
 use common::data_structures::CoinType;
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::chain_client::chain_client;
 use crate::ContractClient;
 use anyhow::Result;
 
@@ -70,38 +69,25 @@ pub fn decode_coin_transfer(tx_raw: &str) -> Result<CoinTransaction, Box<dyn std
 }
 
 async fn get_balance(account: &AccountId) -> Result<u128> {
-    let request = methods::query::RpcQueryRequest {
-        block_reference: BlockReference::Finality(Finality::Final),
-        request: QueryRequest::CallFunction {
-            account_id: (*crate::coin::DW20_CID).clone(),
-            method_name: "ft_balance_of".to_string(),
-            args: FunctionArgs::from(
-                json!({
-                    "account_id":account.to_string()
-                })
-                .to_string()
-                .into_bytes(),
-            ),
-        },
-    };
-    let rep = crate::rpc_call(request).await?;
-
-    if let QueryResponseKind::CallResult(result) = rep.kind {
-        let amount_str: String = String::from_utf8(result.result)?.split('\"').collect();
-        Ok(u128::from_str(&amount_str)?)
-    } else {
-        unreachable!()
-    }
+    let args = json!({
+        "account_id":account.to_string()
+    })
+    .to_string();
+    let result = chain_client()
+        .view_call(&crate::coin::DW20_CID, "ft_balance_of", &args)
+        .await?;
+    let amount_str: String = String::from_utf8(result)?.split('\"').collect();
+    Ok(u128::from_str(&amount_str)?)
 }
 
 impl ContractClient<Coin> {
     pub async fn new_update_cli(coin: CoinType) -> Result<Self> {
-        let contract = coin.to_string();
+        let contract = coin.to_account_id()?.to_string();
         Self::gen_cli(&contract).await
     }
 
     pub async fn new_query_cli(coin: CoinType) -> Result<Self> {
-        let contract = coin.to_string();
+        let contract = coin.to_account_id()?.to_string();
         Self::gen_cli_without_relayer(&contract).await
     }
 
@@ -120,6 +106,17 @@ impl ContractClient<Coin> {
         let args_str = json!({"account_id": user_account_id}).to_string();
         self.query_call("ft_balance_of", &args_str).await
     }
+
+    pub async fn get_balance_at(
+        &self,
+        block_hash: CryptoHash,
+        account_id: &str,
+    ) -> Result<Option<String>> {
+        let user_account_id = AccountId::from_str(account_id)?;
+        let args_str = json!({"account_id": user_account_id}).to_string();
+        self.query_call_at(block_hash, "ft_balance_of", &args_str)
+            .await
+    }
 }
 
 #[cfg(test)]
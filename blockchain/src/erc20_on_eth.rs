@@ -25,7 +25,7 @@ abigen!(
 impl EthContractClient<Erc20> {
     pub fn new(coin: &CoinType) -> Result<EthContractClient<Erc20>> {
         let coin_erc20_ca = coin
-            .erc20_ca()
+            .erc20_ca()?
             .ok_or(anyhow!("coin {} not support", coin))?;
         let ca: H160 = Address::from_str(&coin_erc20_ca)?;
         //addr: cb5afaa026d3de65de0ddcfb1a464be8960e334a
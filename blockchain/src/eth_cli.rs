@@ -60,4 +60,12 @@ pub mod general {
             Some(block) => Ok(Some(block)),
         }
     }
+
+    /// hex hash of the block, none when the chain hasn't reached the height
+    pub async fn get_block_hash(height: u64) -> Result<Option<String>> {
+        let block = get_block(height).await?;
+        Ok(block
+            .and_then(|block| block.hash)
+            .map(|hash| hex::encode(hash.as_bytes())))
+    }
 }
@@ -35,7 +35,10 @@ impl ContractClient<FeesCall> {
     ) -> Result<String> {
         //todo: verify user's ecdsa signature
         let account_id = AccountId::from_str(account_id)?;
-        let tokens: Vec<AccountId> = tokens.iter().map(|coin| coin.to_account_id()).collect();
+        let tokens = tokens
+            .iter()
+            .map(|coin| coin.to_account_id())
+            .collect::<Result<Vec<AccountId>, _>>()?;
         let args_str = json!({
             "user_id":  account_id,
             "tokens": tokens,
@@ -64,7 +67,7 @@ impl ContractClient<FeesCall> {
     //后台不做乘法计算，允许这里精度丢失
     pub async fn get_coin_price(&self, coin: &CoinType) -> Result<(u128, u128)> {
         let args_str = json!({
-            "id":  coin.to_account_id(),
+            "id":  coin.to_account_id()?,
         })
         .to_string();
         let (base_amount, quote_amount): (String, String) =
@@ -80,8 +83,8 @@ impl ContractClient<FeesCall> {
         Ok(price)
     }
 
-    //base_fee
-    pub async fn get_tx_base_fee(&self, tx_id: &str) -> Result<(CoinType, u128)> {
+    //base_fee，没有扣过手续费的交易返回None
+    pub async fn get_tx_base_fee(&self, tx_id: &str) -> Result<Option<(CoinType, u128)>> {
         //let value = (user_id, fees_id, fees_amount, tx_hash, memo);
         //AccountId, AccountId, u128, Option<String>, String
 
@@ -89,18 +92,15 @@ impl ContractClient<FeesCall> {
             "hsh":  hex_to_bs58(tx_id)?,
         })
         .to_string();
-        let (_user_id, fees_id, fees_amount, _tx_hash, _memo): (
-            String,
-            String,
-            u128,
-            Option<String>,
-            String,
-        ) = self
-            .query_call("get_tx_with_hash", &args_str)
-            .await?
-            .unwrap();
-        let coin: CoinType = fees_id.parse()?;
-        Ok((coin, fees_amount))
+        let fee: Option<(String, String, u128, Option<String>, String)> =
+            self.query_call("get_tx_with_hash", &args_str).await?;
+        match fee {
+            Some((_user_id, fees_id, fees_amount, _tx_hash, _memo)) => {
+                let coin: CoinType = fees_id.parse()?;
+                Ok(Some((coin, fees_amount)))
+            }
+            None => Ok(None),
+        }
     }
 
     pub async fn get_user_txs(
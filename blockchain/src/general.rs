@@ -1,46 +1,36 @@
 use common::data_structures::TxStatusOnChain;
 use common::utils::math::hex_to_bs58;
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature};
-use near_jsonrpc_client::methods::broadcast_tx_commit::RpcBroadcastTxCommitResponse;
 use near_jsonrpc_client::methods::EXPERIMENTAL_check_tx::SignedTransaction;
 use near_jsonrpc_client::{methods, MethodCallResult};
-use near_jsonrpc_primitives::types::query::QueryResponseKind;
-use near_primitives::views::{AccessKeyList, ExecutionStatusView, FinalExecutionStatus};
+use near_primitives::hash::CryptoHash;
+use near_primitives::views::AccessKeyList;
 use std::str::FromStr;
 
 use near_primitives::borsh::BorshDeserialize;
 use near_primitives::transaction::Transaction;
-use near_primitives::types::{AccountId, BlockReference};
-
-use near_jsonrpc_primitives::types::transactions::TransactionInfo;
+use near_primitives::types::AccountId;
 
 use hex;
 //use log::debug;
 use anyhow::{anyhow, Ok, Result};
-use tracing::{debug, warn};
+use tracing::debug;
+
+use crate::chain_client::chain_client;
+use crate::ContractClient;
 
 //todo: contract_addr type change into AccountId
 pub async fn gen_transaction(signer: &InMemorySigner, contract_addr: &str) -> Result<Transaction> {
-    let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
-        block_reference: BlockReference::latest(),
-        request: near_primitives::views::QueryRequest::ViewAccessKey {
-            account_id: signer.account_id.clone(),
-            public_key: signer.public_key.clone(),
-        },
-    })
-    .await?;
-
-    let current_nonce = match access_key_query_response.kind {
-        QueryResponseKind::AccessKey(access_key) => access_key.nonce,
-        _ => Err(anyhow::anyhow!("failed to extract current nonce"))?,
-    };
+    let (current_nonce, block_hash) = chain_client()
+        .view_access_key(&signer.account_id, &signer.public_key)
+        .await?;
 
     Ok(Transaction {
         signer_id: signer.account_id.clone(),
         public_key: signer.public_key.clone(),
         nonce: current_nonce + 1,
         receiver_id: contract_addr.parse()?,
-        block_hash: access_key_query_response.block_hash,
+        block_hash,
         actions: vec![],
     })
 }
@@ -50,25 +40,16 @@ pub async fn gen_transaction_with_caller(
     caller_pubkey: PublicKey,
     contract_addr: &str,
 ) -> Result<Transaction> {
-    let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
-        block_reference: BlockReference::latest(),
-        request: near_primitives::views::QueryRequest::ViewAccessKey {
-            account_id: caller_account_id.clone(),
-            public_key: caller_pubkey.clone(),
-        },
-    })
-    .await?;
+    let (current_nonce, block_hash) = chain_client()
+        .view_access_key(&caller_account_id, &caller_pubkey)
+        .await?;
 
-    let current_nonce = match access_key_query_response.kind {
-        QueryResponseKind::AccessKey(access_key) => access_key.nonce,
-        _ => Err(anyhow::anyhow!("failed to extract current nonce"))?,
-    };
     Ok(Transaction {
         signer_id: caller_account_id,
         public_key: caller_pubkey,
         nonce: current_nonce + 1,
         receiver_id: contract_addr.parse()?,
-        block_hash: access_key_query_response.block_hash,
+        block_hash,
         actions: vec![],
     })
 }
@@ -87,27 +68,18 @@ pub async fn gen_transaction_with_caller_with_nonce(
         contract_addr.to_string(),
         add_nonce.to_string()
     );
-    let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
-        block_reference: BlockReference::latest(),
-        request: near_primitives::views::QueryRequest::ViewAccessKey {
-            account_id: caller_account_id.clone(),
-            public_key: caller_pubkey.clone(),
-        },
-    })
-    .await?;
+    let (current_nonce, block_hash) = chain_client()
+        .view_access_key(&caller_account_id, &caller_pubkey)
+        .await?;
 
     debug!("line_{}___{}", line!(), caller_account_id.to_string());
 
-    let current_nonce = match access_key_query_response.kind {
-        QueryResponseKind::AccessKey(access_key) => access_key.nonce,
-        _ => Err(anyhow::anyhow!("failed to extract current nonce"))?,
-    };
     Ok(Transaction {
         signer_id: caller_account_id,
         public_key: caller_pubkey,
         nonce: current_nonce + add_nonce as u64,
         receiver_id: contract_addr.parse()?,
-        block_hash: access_key_query_response.block_hash,
+        block_hash,
         actions: vec![],
     })
 }
@@ -126,58 +98,29 @@ pub fn pubkey_from_hex_str(key: &str) -> Result<PublicKey> {
 
 pub async fn get_access_key_list(account_str: &str) -> Result<AccessKeyList> {
     let account_id = AccountId::from_str(account_str)?;
-    let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
-        block_reference: BlockReference::latest(),
-        request: near_primitives::views::QueryRequest::ViewAccessKeyList {
-            account_id: account_id.clone(),
-        },
-    })
-    .await?;
-
-    match access_key_query_response.kind {
-        QueryResponseKind::AccessKeyList(list) => Ok(list),
-        _ => Err(anyhow::anyhow!("failed to extract current nonce"))?,
-    }
+    chain_client().view_access_key_list(&account_id).await
 }
 
 pub async fn tx_status(tx_id: &str) -> Result<TxStatusOnChain> {
     let tx_id = hex_to_bs58(tx_id)?;
-    let tx_status_request = methods::tx::RpcTransactionStatusRequest {
-        transaction_info: TransactionInfo::TransactionId {
-            tx_hash: tx_id
-                .parse()
-                .map_err(|_e| anyhow!("tx_id to tx_hash".to_string()))?,
-            sender_account_id: "node0".parse()?,
-        },
-    };
-
-    let tx_status = crate::rpc_call(tx_status_request).await?;
+    let tx_hash = tx_id
+        .parse()
+        .map_err(|_e| anyhow!("tx_id to tx_hash".to_string()))?;
+    chain_client().tx_status(tx_hash).await
+}
 
-    let status = if let FinalExecutionStatus::SuccessValue(_value) = tx_status.status {
-        let mut status = TxStatusOnChain::Successful;
-        for outcome in tx_status.receipts_outcome {
-            match outcome.outcome.status {
-                ExecutionStatusView::Unknown => {
-                    unreachable!("");
-                    //status = TxStatusOnChain::Failed;
-                    //break;
-                }
-                ExecutionStatusView::Failure(_) => {
-                    status = TxStatusOnChain::Failed;
-                    break;
-                }
-                ExecutionStatusView::SuccessValue(_) => {}
-                ExecutionStatusView::SuccessReceiptId(_) => {}
-            }
-        }
-        status
-    } else if let FinalExecutionStatus::Failure(error) = tx_status.status {
-        warn!("tx_id({}) is failed: {}", tx_id, error.to_string());
-        TxStatusOnChain::Failed
-    } else {
-        TxStatusOnChain::Pending
-    };
-    Ok(status)
+/// submit the function call of a failed relayer tx again with a new nonce,
+/// the txs signed by the user's keys can't be rebuilt here
+pub async fn resubmit_by_relayer(tx_id: &str) -> Result<String> {
+    let tx_hash = hex_to_bs58(tx_id)?
+        .parse()
+        .map_err(|_e| anyhow!("tx_id to tx_hash".to_string()))?;
+    let call = chain_client().tx_call(tx_hash).await?;
+    if call.signer_id.as_str() != common::env::CONF.relayer_pool.account_id {
+        Err(anyhow!("tx {} isn't signed by the relayer", tx_id))?;
+    }
+    let mut cli = ContractClient::<()>::gen_cli(call.receiver_id.as_str()).await?;
+    cli.commit_by_relayer(&call.method_name, &call.args).await
 }
 
 pub async fn safe_gen_transaction(
@@ -185,26 +128,19 @@ pub async fn safe_gen_transaction(
     caller_pubkey: &str,
     contract_addr: &str,
 ) -> Result<Transaction> {
-    let access_key_query_response = crate::rpc_call(methods::query::RpcQueryRequest {
-        block_reference: BlockReference::latest(),
-        request: near_primitives::views::QueryRequest::ViewAccessKey {
-            account_id: AccountId::from_str(caller_account_id)?,
-            public_key: PublicKey::from_str(caller_pubkey)?,
-        },
-    })
-    .await?;
-
-    let current_nonce = match access_key_query_response.kind {
-        QueryResponseKind::AccessKey(access_key) => access_key.nonce,
-        _ => panic!("{:?}", "failed to extract current nonce"),
-    };
+    let (current_nonce, block_hash) = chain_client()
+        .view_access_key(
+            &AccountId::from_str(caller_account_id)?,
+            &PublicKey::from_str(caller_pubkey)?,
+        )
+        .await?;
 
     Ok(Transaction {
         signer_id: AccountId::from_str(caller_account_id)?,
         public_key: PublicKey::from_str(caller_pubkey)?,
         nonce: current_nonce + 1,
         receiver_id: contract_addr.parse()?,
-        block_hash: access_key_query_response.block_hash,
+        block_hash,
         actions: vec![],
     })
 }
@@ -216,8 +152,8 @@ pub async fn broadcast_tx_commit_from_raw(tx_str: &str, sig_str: &str) -> Result
     let transaction = Transaction::deserialize(&mut tx_hex.as_slice())?;
     debug!("{:?}", transaction);
     let signature = Signature::from_parts(KeyType::ED25519, &sign_hex)?;
-    let rest = broadcast_tx_commit(transaction, signature).await?;
-    debug!("broadcast_tx_commit_from_raw {:?}", rest.status);
+    let tx_hash = broadcast_tx_commit(transaction, signature).await?;
+    debug!("broadcast_tx_commit_from_raw {}", tx_hash);
     Ok(())
 }
 
@@ -236,12 +172,9 @@ pub async fn broadcast_tx_commit_from_raw2(tx_str: &str, sig_str: &str) -> Resul
 pub async fn broadcast_tx_commit(
     transaction: Transaction,
     sig_data: Signature,
-) -> Result<RpcBroadcastTxCommitResponse> {
+) -> Result<CryptoHash> {
     let tx = SignedTransaction::new(sig_data, transaction);
-    let request = methods::broadcast_tx_commit::RpcBroadcastTxCommitRequest {
-        signed_transaction: tx,
-    };
-    crate::rpc_call(request).await.map_err(|e| e.into())
+    chain_client().broadcast_tx_commit(tx).await
 }
 
 pub async fn call<M>(request: M) -> MethodCallResult<M::Response, M::Error>
@@ -4,10 +4,13 @@
 
 pub mod airdrop;
 pub mod bridge_on_near;
+pub mod chain_client;
 pub mod coin;
 pub mod erc20_on_eth;
 pub mod general;
+pub mod mock_chain;
 pub mod multi_sig;
+pub mod portfolio;
 
 pub mod bridge_on_eth;
 pub mod eth_cli;
@@ -17,28 +20,29 @@ mod relayer;
 use general::pubkey_from_hex_str;
 use lazy_static::lazy_static;
 use near_jsonrpc_client::{methods, JsonRpcClient};
-use near_jsonrpc_primitives::types::{query::QueryResponseKind, transactions::TransactionInfo};
+use near_jsonrpc_primitives::types::transactions::TransactionInfo;
 //use near_jsonrpc_client::methods::EXPERIMENTAL_tx_status::TransactionInfo;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use common::prelude::*;
 use near_crypto::{InMemorySigner, PublicKey, Signer};
 use near_primitives::{
     account::{AccessKey, AccessKeyPermission},
     borsh::{self},
+    hash::CryptoHash,
     transaction::{
         Action, AddKeyAction, CreateAccountAction, DeleteKeyAction, FunctionCallAction,
         SignedTransaction, Transaction, TransferAction,
     },
-    types::{AccountId, BlockReference, Finality, FunctionArgs},
-    views::{FinalExecutionStatus, QueryRequest},
+    types::AccountId,
 };
-use relayer::{wait_for_idle_relayer, Relayer};
+use relayer::{is_execution_failure, is_nonce_error, wait_for_idle_relayer, Relayer};
 use serde::de::DeserializeOwned;
 
 use std::marker::PhantomData;
 use tokio::sync::MutexGuard;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
+use crate::chain_client::chain_client;
 use crate::general::gen_transaction_with_caller_with_nonce;
 
 lazy_static! {
@@ -75,6 +79,9 @@ where
     unreachable!()
 }
 
+//同一笔调用最多重新同步nonce的次数
+const MAX_NONCE_RESYNC_TIMES: u32 = 2;
+
 pub struct ContractClient<T> {
     pub deployed_at: AccountId,
     //对于query的访问不需要签名，给none
@@ -202,7 +209,36 @@ impl<T> ContractClient<T> {
         Ok((txid, raw_str))
     }
 
+    //nonce过期或者冲突时重新同步nonce再重试，广播失败计入relayer的失败次数
     async fn commit_by_relayer(&mut self, method_name: &str, args: &str) -> Result<String> {
+        let mut resync_times = 0;
+        loop {
+            match self.try_commit_by_relayer(method_name, args).await {
+                Ok(txid) => {
+                    self.relayer.as_mut().unwrap().record_success();
+                    return Ok(txid);
+                }
+                Err(err) if is_nonce_error(&err) && resync_times < MAX_NONCE_RESYNC_TIMES => {
+                    let relayer = self.relayer.as_mut().unwrap();
+                    warn!(
+                        "index_relayer_{} call {} with stale nonce: {}",
+                        relayer.derive_index, method_name, err
+                    );
+                    relayer.resync_nonce().await?;
+                    resync_times += 1;
+                }
+                Err(err) => {
+                    //nonce一直对不上时本地nonce作废，下次使用之前重新同步
+                    if is_nonce_error(&err) {
+                        self.relayer.as_mut().unwrap().nonce = None;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    async fn try_commit_by_relayer(&mut self, method_name: &str, args: &str) -> Result<String> {
         debug!("method_name: {},args: {}", method_name, args);
         let mut transaction = self
             .gen_tx(
@@ -213,9 +249,15 @@ impl<T> ContractClient<T> {
             )
             .await?;
         //todo: relayer用到的不止这一个地方
-        let this_nonce = self.relayer.as_ref().unwrap().nonce.ok_or(anyhow!(""))? + 1;
-        info!("index_relayer_{}_this_nonce {}", self.relayer.as_ref().unwrap().derive_index,this_nonce);
-        self.relayer.as_mut().unwrap().nonce = Some(this_nonce);
+        let relayer = self.relayer.as_mut().unwrap();
+        //上一笔失败之后本地nonce已作废
+        let current_nonce = match relayer.nonce {
+            Some(nonce) => nonce,
+            None => relayer.resync_nonce().await?,
+        };
+        let this_nonce = current_nonce + 1;
+        info!("index_relayer_{}_this_nonce {}", relayer.derive_index, this_nonce);
+        relayer.nonce = Some(this_nonce);
         transaction.nonce = this_nonce;
         //relayer_sign
         let signature = self
@@ -224,17 +266,19 @@ impl<T> ContractClient<T> {
 
         let tx = SignedTransaction::new(signature, transaction.clone());
         //todo: commit是否有必要，直接用async？
-        let request = methods::broadcast_tx_commit::RpcBroadcastTxCommitRequest {
-            signed_transaction: tx.clone(),
-        };
-
         debug!("call commit_by_relayer txid {}", &tx.get_hash().to_string());
 
-        let rep = crate::rpc_call(request).await?;
-        if let FinalExecutionStatus::Failure(error) = rep.status {
-            Err(anyhow!(error.to_string()))?
+        match chain_client().broadcast_tx_commit(tx).await {
+            Ok(_txid) => {}
+            //合约拒绝的交易也已经上链，nonce已经用掉，保留本地nonce且不计入失败次数
+            Err(err) if is_execution_failure(&err) => Err(err)?,
+            Err(err) if is_nonce_error(&err) => Err(err)?,
+            //节点或网络的错误，交易可能上链也可能丢失，下次使用之前重新同步nonce
+            Err(err) => {
+                self.relayer.as_mut().unwrap().record_failure();
+                Err(err)?
+            }
         }
-        let _txid = rep.transaction.hash.to_string();
 
         let hash = transaction.get_hash_and_size().0.as_bytes().to_owned();
         let txid = hex::encode(hash);
@@ -251,24 +295,26 @@ impl<T> ContractClient<T> {
         method_name: &str,
         args: &str,
     ) -> Result<Option<R>> {
-        let request = methods::query::RpcQueryRequest {
-            block_reference: BlockReference::Finality(Finality::Final),
-            request: QueryRequest::CallFunction {
-                account_id: (self.deployed_at).clone(),
-                method_name: method_name.to_string(),
-                args: FunctionArgs::from(args.to_string().into_bytes()),
-            },
-        };
-        let rep = crate::rpc_call(request).await?;
+        let result = chain_client()
+            .view_call(&self.deployed_at, method_name, args)
+            .await?;
+        let amount_str: String = String::from_utf8(result)?;
+        debug!("query_res1 {}", amount_str);
+        println!("query_res1 {}", amount_str);
+        Ok(serde_json::from_str::<Option<R>>(&amount_str)?)
+    }
 
-        if let QueryResponseKind::CallResult(result) = rep.kind {
-            let amount_str: String = String::from_utf8(result.result)?;
-            debug!("query_res1 {}", amount_str);
-            println!("query_res1 {}", amount_str);
-            Ok(serde_json::from_str::<Option<R>>(&amount_str)?)
-        } else {
-            Err(anyhow!("kind must be contract call".to_string()))?
-        }
+    //读取指定区块的状态，同一批查询的结果彼此一致
+    async fn query_call_at<R: DeserializeOwned>(
+        &self,
+        block_hash: CryptoHash,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Option<R>> {
+        let result = chain_client()
+            .view_call_at(block_hash, &self.deployed_at, method_name, args)
+            .await?;
+        Ok(serde_json::from_slice::<Option<R>>(&result)?)
     }
 }
 
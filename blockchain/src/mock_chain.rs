@@ -0,0 +1,565 @@
+//! in-process near node，used by tests which shouldn't depend on a live chain
+//!
+//! within [`scope`], every [`chain_client`](crate::chain_client::chain_client) call is served here,
+//! the node keeps the access keys, multi-sig strategies, nep-141 balances and tx statuses,
+//! the other contract methods are accepted without any effect and their view results
+//! can be preset with [`MockChain::set_view`]
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use common::data_structures::TxStatusOnChain;
+use near_crypto::PublicKey;
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, SignedTransaction};
+use near_primitives::types::AccountId;
+use near_primitives::views::{
+    AccessKeyInfoView, AccessKeyList, AccessKeyPermissionView, AccessKeyView,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::chain_client::{invalid_tx_error, ChainClient, TxCall, TxExecutionFailure};
+use crate::multi_sig::{MultiSigRank, StrategyData, SubAccConf};
+
+#[derive(Default)]
+struct MockState {
+    block_height: u64,
+    //account -> (pubkey, nonce)
+    access_keys: BTreeMap<String, Vec<(PublicKey, u64)>>,
+    strategies: HashMap<String, StrategyData>,
+    //account -> yoctoNEAR
+    native_balances: HashMap<String, u128>,
+    //(coin contract, account) -> balance
+    balances: HashMap<(String, String), u128>,
+    tx_statuses: HashMap<CryptoHash, TxStatusOnChain>,
+    tx_calls: HashMap<CryptoHash, TxCall>,
+    //(contract, method) -> json result
+    views: HashMap<(String, String), String>,
+}
+
+impl MockState {
+    fn block_hash(&self) -> CryptoHash {
+        CryptoHash::hash_bytes(&self.block_height.to_le_bytes())
+    }
+
+    fn nonce_of(&self, account_id: &str, public_key: &PublicKey) -> Option<u64> {
+        self.access_keys
+            .get(account_id)?
+            .iter()
+            .find(|(key, _)| key == public_key)
+            .map(|(_, nonce)| *nonce)
+    }
+
+    fn add_key(&mut self, account_id: &str, public_key: PublicKey) {
+        let keys = self.access_keys.entry(account_id.to_string()).or_default();
+        if !keys.iter().any(|(key, _)| *key == public_key) {
+            keys.push((public_key, 0));
+        }
+    }
+
+    fn transfer(&mut self, coin: &str, from: &str, to: &str, amount: u128) -> Result<()> {
+        let from_key = (coin.to_string(), from.to_string());
+        let balance = self.balances.get(&from_key).copied().unwrap_or_default();
+        if balance < amount {
+            Err(anyhow!(
+                "{} balance of {} is not enough: {} < {}",
+                coin,
+                from,
+                balance,
+                amount
+            ))?;
+        }
+        self.balances.insert(from_key, balance - amount);
+        *self
+            .balances
+            .entry((coin.to_string(), to.to_string()))
+            .or_default() += amount;
+        Ok(())
+    }
+
+    fn strategy_mut(&mut self, account_id: &str) -> Result<&mut StrategyData> {
+        self.strategies
+            .get_mut(account_id)
+            .ok_or_else(|| anyhow!("strategy of {} not found", account_id))
+    }
+
+    //只模拟会改变余额和多签配置的合约方法
+    fn function_call(
+        &mut self,
+        signer: &str,
+        contract: &str,
+        method_name: &str,
+        args: &[u8],
+    ) -> Result<()> {
+        let args: Value = serde_json::from_slice(args).unwrap_or(Value::Null);
+        let str_arg = |name: &str| -> Result<String> {
+            args[name]
+                .as_str()
+                .map(|arg| arg.to_string())
+                .ok_or_else(|| anyhow!("{} missing in args of {}", name, method_name))
+        };
+        match method_name {
+            "set_strategy2" => {
+                let strategy = StrategyData {
+                    master_pubkey: str_arg("master_pubkey")?,
+                    multi_sig_ranks: serde_json::from_value(args["rank_arr"].clone())?,
+                    servant_pubkeys: serde_json::from_value(args["servant_pubkeys"].clone())?,
+                    sub_confs: serde_json::from_value(args["sub_confs"].clone())?,
+                };
+                self.strategies
+                    .insert(str_arg("user_account_id")?, strategy);
+            }
+            "update_rank" => {
+                let ranks: Vec<MultiSigRank> = serde_json::from_value(args["rank_arr"].clone())?;
+                self.strategy_mut(&str_arg("user_account_id")?)?
+                    .multi_sig_ranks = ranks;
+            }
+            "update_servant_pubkey" | "update_servant_pubkey_and_master" | "update_master" => {
+                let strategy = self.strategy_mut(&str_arg("user_account_id")?)?;
+                if let Some(servants) = args.get("servant_device_pubkey") {
+                    strategy.servant_pubkeys = serde_json::from_value(servants.clone())?;
+                }
+                if let Some(master) = args.get("master_pubkey") {
+                    strategy.master_pubkey = serde_json::from_value(master.clone())?;
+                }
+            }
+            "update_subaccount_hold_limit" => {
+                let subaccount = str_arg("subaccount")?;
+                let hold_limit: u128 = serde_json::from_value(args["hold_limit"].clone())?;
+                self.strategy_mut(&str_arg("user_account_id")?)?
+                    .sub_confs
+                    .get_mut(&subaccount)
+                    .ok_or_else(|| anyhow!("subaccount {} not found", subaccount))?
+                    .hold_value_limit = hold_limit;
+            }
+            "add_subaccounts" => {
+                let subaccounts: BTreeMap<String, SubAccConf> =
+                    serde_json::from_value(args["new_sub"].clone())?;
+                self.strategy_mut(&str_arg("main_account_id")?)?
+                    .sub_confs
+                    .extend(subaccounts);
+            }
+            "remove_subaccounts" => {
+                let subaccounts: Vec<String> = serde_json::from_value(args["accounts"].clone())?;
+                let strategy = self.strategy_mut(&str_arg("main_account_id")?)?;
+                for subaccount in subaccounts {
+                    strategy.sub_confs.remove(&subaccount);
+                }
+            }
+            "remove_account_strategy" => {
+                self.strategies.remove(&str_arg("acc")?);
+            }
+            "ft_transfer" => {
+                //金额有字符串和数字两种写法
+                let amount = match &args["amount"] {
+                    Value::String(amount) => amount.parse()?,
+                    amount => serde_json::from_value(amount.clone())?,
+                };
+                self.transfer(contract, signer, &str_arg("receiver_id")?, amount)?;
+            }
+            "send_money" | "internal_transfer_main_to_sub" | "internal_withdraw" => {
+                let coin_tx: MockCoinTx = serde_json::from_value(args["coin_tx"].clone())?;
+                let from = coin_tx.from.unwrap_or_default();
+                let to = coin_tx.to.unwrap_or_default();
+                self.transfer(&coin_tx.coin_id, &from, &to, coin_tx.amount)?;
+            }
+            "internal_transfer_sub_to_main" => {
+                let coin_tx: MockCoinTx = serde_json::from_value(args["coin_tx"].clone())?;
+                let sub = args["sub_sig"]["account_id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("sub_sig missing"))?
+                    .to_string();
+                let main = str_arg("main_account_id")?;
+                self.transfer(&coin_tx.coin_id, &sub, &main, coin_tx.amount)?;
+            }
+            _ => debug!("mock chain ignore {}.{}", contract, method_name),
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, signed: &SignedTransaction) -> Result<()> {
+        let tx = &signed.transaction;
+        let signer = tx.signer_id.to_string();
+        let receiver = tx.receiver_id.to_string();
+        for action in &tx.actions {
+            match action {
+                //转账给隐式账户即创建
+                Action::Transfer(_) => {
+                    if self.access_keys.get(&receiver).is_none() {
+                        let pubkey = PublicKey::from_near_implicit_account(&tx.receiver_id)?;
+                        self.add_key(&receiver, pubkey);
+                    }
+                }
+                Action::CreateAccount(_) => {
+                    if self.access_keys.contains_key(&receiver) {
+                        Err(anyhow!("account {} already exist", receiver))?;
+                    }
+                    self.access_keys.insert(receiver.clone(), vec![]);
+                }
+                Action::AddKey(add) => self.add_key(&receiver, add.public_key.clone()),
+                Action::DeleteKey(delete) => {
+                    if let Some(keys) = self.access_keys.get_mut(&receiver) {
+                        keys.retain(|(key, _)| *key != delete.public_key);
+                    }
+                }
+                Action::FunctionCall(call) => {
+                    self.function_call(&signer, &receiver, &call.method_name, &call.args)?
+                }
+                _ => debug!("mock chain ignore action {:?}", action),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct MockCoinTx {
+    from: Option<String>,
+    to: Option<String>,
+    coin_id: String,
+    amount: u128,
+}
+
+#[derive(Default)]
+pub struct MockChain {
+    state: Mutex<MockState>,
+}
+
+impl MockChain {
+    /// create the account with a full access key, like an implicit account or a relayer
+    pub fn add_access_key(&self, account_id: &str, public_key: PublicKey) {
+        self.state.lock().unwrap().add_key(account_id, public_key);
+    }
+
+    //直接改余额也算出了一个新块，否则按块缓存的余额不会刷新
+    pub fn set_balance(&self, coin_contract: &str, account_id: &str, amount: u128) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .balances
+            .insert((coin_contract.to_string(), account_id.to_string()), amount);
+        state.block_height += 1;
+    }
+
+    pub fn set_native_balance(&self, account_id: &str, amount: u128) {
+        self.state
+            .lock()
+            .unwrap()
+            .native_balances
+            .insert(account_id.to_string(), amount);
+    }
+
+    pub fn set_strategy(&self, account_id: &str, strategy: StrategyData) {
+        self.state
+            .lock()
+            .unwrap()
+            .strategies
+            .insert(account_id.to_string(), strategy);
+    }
+
+    /// json result returned by a view method which isn't modelled by the mock
+    pub fn set_view(&self, contract: &str, method_name: &str, result: Value) {
+        self.state.lock().unwrap().views.insert(
+            (contract.to_string(), method_name.to_string()),
+            result.to_string(),
+        );
+    }
+
+    /// e.g. keep a tx pending to test the scanner
+    pub fn set_tx_status(&self, tx_hash: CryptoHash, status: TxStatusOnChain) {
+        self.state
+            .lock()
+            .unwrap()
+            .tx_statuses
+            .insert(tx_hash, status);
+    }
+
+    /// register every key of the relayer pool in `CONF`
+    pub async fn register_relayers(&self) {
+        for relayer in crate::relayer::MULTI_SIG_RELAYER_POOL.iter() {
+            let relayer = relayer.lock().await;
+            self.add_access_key(
+                relayer.signer.account_id.as_ref(),
+                relayer.signer.public_key.clone(),
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl ChainClient for MockChain {
+    async fn final_block_hash(&self) -> Result<CryptoHash> {
+        Ok(self.state.lock().unwrap().block_hash())
+    }
+
+    async fn view_access_key(
+        &self,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+    ) -> Result<(u64, CryptoHash)> {
+        let state = self.state.lock().unwrap();
+        let nonce = state
+            .nonce_of(account_id.as_ref(), public_key)
+            .ok_or_else(|| anyhow!("access key {} of {} not found", public_key, account_id))?;
+        Ok((nonce, state.block_hash()))
+    }
+
+    async fn view_access_key_list(&self, account_id: &AccountId) -> Result<AccessKeyList> {
+        let state = self.state.lock().unwrap();
+        //和节点一样，不存在的账户返回空列表
+        let keys = state
+            .access_keys
+            .get(account_id.as_str())
+            .into_iter()
+            .flatten()
+            .map(|(public_key, nonce)| AccessKeyInfoView {
+                public_key: public_key.clone(),
+                access_key: AccessKeyView {
+                    nonce: *nonce,
+                    permission: AccessKeyPermissionView::FullAccess,
+                },
+            })
+            .collect();
+        Ok(AccessKeyList { keys })
+    }
+
+    async fn view_account_balance(&self, account_id: &AccountId) -> Result<u128> {
+        let state = self.state.lock().unwrap();
+        if !state.access_keys.contains_key(account_id.as_str()) {
+            Err(anyhow!("account {} not found", account_id))?;
+        }
+        Ok(state
+            .native_balances
+            .get(account_id.as_str())
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn view_call(
+        &self,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let args: Value = serde_json::from_str(args).unwrap_or(Value::Null);
+        let result = match method_name {
+            "get_strategy" => {
+                let account_id = args["user_account_id"].as_str().unwrap_or_default();
+                serde_json::to_string(&state.strategies.get(account_id))?
+            }
+            "ft_balance_of" => {
+                let account_id = args["account_id"].as_str().unwrap_or_default();
+                let balance = state
+                    .balances
+                    .get(&(contract.to_string(), account_id.to_string()))
+                    .copied()
+                    .unwrap_or_default();
+                json!(balance.to_string()).to_string()
+            }
+            _ => state
+                .views
+                .get(&(contract.to_string(), method_name.to_string()))
+                .cloned()
+                .unwrap_or_else(|| "null".to_string()),
+        };
+        Ok(result.into_bytes())
+    }
+
+    //没有保存历史状态，总是返回最新的
+    async fn view_call_at(
+        &self,
+        _block_hash: CryptoHash,
+        contract: &AccountId,
+        method_name: &str,
+        args: &str,
+    ) -> Result<Vec<u8>> {
+        self.view_call(contract, method_name, args).await
+    }
+
+    async fn broadcast_tx_commit(&self, tx: SignedTransaction) -> Result<CryptoHash> {
+        let mut state = self.state.lock().unwrap();
+        let tx_hash = tx.get_hash();
+        let transaction = &tx.transaction;
+        if !tx
+            .signature
+            .verify(tx_hash.as_ref(), &transaction.public_key)
+        {
+            Err(anyhow!("invalid signature of tx {}", tx_hash))?;
+        }
+        let signer = transaction.signer_id.to_string();
+        let nonce = state
+            .nonce_of(&signer, &transaction.public_key)
+            .ok_or_else(|| anyhow!("access key of {} not found", signer))?;
+        if transaction.nonce <= nonce {
+            debug!(
+                "invalid nonce {} of {}, current is {}",
+                transaction.nonce, signer, nonce
+            );
+            Err(invalid_tx_error(InvalidTxError::InvalidNonce {
+                tx_nonce: transaction.nonce,
+                ak_nonce: nonce,
+            }))?;
+        }
+        for (key, key_nonce) in state.access_keys.get_mut(&signer).unwrap().iter_mut() {
+            if *key == transaction.public_key {
+                *key_nonce = transaction.nonce;
+            }
+        }
+        state.block_height += 1;
+        if let [Action::FunctionCall(call)] = transaction.actions.as_slice() {
+            let tx_call = TxCall {
+                signer_id: transaction.signer_id.clone(),
+                receiver_id: transaction.receiver_id.clone(),
+                method_name: call.method_name.clone(),
+                args: String::from_utf8_lossy(&call.args).to_string(),
+            };
+            state.tx_calls.insert(tx_hash, tx_call);
+        }
+
+        //执行失败时整笔交易的状态都不生效
+        let snapshot = (
+            state.access_keys.clone(),
+            state.strategies.clone(),
+            state.balances.clone(),
+        );
+        match state.execute(&tx) {
+            Ok(()) => {
+                state
+                    .tx_statuses
+                    .insert(tx_hash, TxStatusOnChain::Successful);
+                Ok(tx_hash)
+            }
+            Err(error) => {
+                let (access_keys, strategies, balances) = snapshot;
+                state.access_keys = access_keys;
+                state.strategies = strategies;
+                state.balances = balances;
+                state.tx_statuses.insert(tx_hash, TxStatusOnChain::Failed);
+                Err(TxExecutionFailure {
+                    tx_hash,
+                    reason: error.to_string(),
+                })?
+            }
+        }
+    }
+
+    async fn tx_status(&self, tx_hash: CryptoHash) -> Result<TxStatusOnChain> {
+        self.state
+            .lock()
+            .unwrap()
+            .tx_statuses
+            .get(&tx_hash)
+            .cloned()
+            .ok_or_else(|| anyhow!("tx {} not found", tx_hash))
+    }
+
+    async fn tx_call(&self, tx_hash: CryptoHash) -> Result<TxCall> {
+        self.state
+            .lock()
+            .unwrap()
+            .tx_calls
+            .get(&tx_hash)
+            .cloned()
+            .ok_or_else(|| anyhow!("tx {} isn't a single function call", tx_hash))
+    }
+}
+
+tokio::task_local! {
+    static MOCK_CHAIN: Arc<MockChain>;
+}
+
+/// run the task on the mock chain, the other tasks and the tasks spawned by it
+/// still talk to the rpc node
+pub async fn scope<F: Future>(chain: Arc<MockChain>, task: F) -> F::Output {
+    MOCK_CHAIN.scope(chain, task).await
+}
+
+pub fn backend() -> Option<Arc<MockChain>> {
+    MOCK_CHAIN.try_with(|chain| chain.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use common::prelude::CHAINLESS_DEFAULT_GAS_LIMIT;
+    use near_crypto::{InMemorySigner, KeyType, Signer};
+    use near_primitives::transaction::{FunctionCallAction, Transaction};
+
+    use super::*;
+
+    async fn ft_transfer(
+        mock: &MockChain,
+        signer: &InMemorySigner,
+        receiver: &str,
+        amount: u128,
+    ) -> Result<CryptoHash> {
+        let (nonce, block_hash) = mock
+            .view_access_key(&signer.account_id, &signer.public_key)
+            .await?;
+        let transaction = Transaction {
+            signer_id: signer.account_id.clone(),
+            public_key: signer.public_key.clone(),
+            nonce: nonce + 1,
+            receiver_id: "usdt.test".parse()?,
+            block_hash,
+            actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                method_name: "ft_transfer".to_string(),
+                args: json!({"receiver_id": receiver, "amount": amount.to_string()})
+                    .to_string()
+                    .into_bytes(),
+                gas: CHAINLESS_DEFAULT_GAS_LIMIT,
+                deposit: 0,
+            }))],
+        };
+        let signature = signer.sign(transaction.get_hash_and_size().0.as_ref());
+        mock.broadcast_tx_commit(SignedTransaction::new(signature, transaction))
+            .await
+    }
+
+    async fn balance_of(mock: &MockChain, account_id: &str) -> String {
+        let args = json!({ "account_id": account_id }).to_string();
+        let result = mock
+            .view_call(&"usdt.test".parse().unwrap(), "ft_balance_of", &args)
+            .await
+            .unwrap();
+        serde_json::from_slice(&result).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_chain_ft_transfer() {
+        let mock = MockChain::default();
+        let signer =
+            InMemorySigner::from_seed("alice.test".parse().unwrap(), KeyType::ED25519, "alice");
+        mock.add_access_key("alice.test", signer.public_key.clone());
+        mock.set_balance("usdt.test", "alice.test", 100);
+
+        let tx_hash = ft_transfer(&mock, &signer, "bob.test", 30).await.unwrap();
+        assert_eq!(
+            mock.tx_status(tx_hash).await.unwrap(),
+            TxStatusOnChain::Successful
+        );
+        assert_eq!(balance_of(&mock, "bob.test").await, "30");
+
+        //余额不足整笔交易失败，余额不变
+        let err = ft_transfer(&mock, &signer, "bob.test", 1000).await;
+        assert!(err.unwrap_err().is::<TxExecutionFailure>());
+        assert_eq!(balance_of(&mock, "alice.test").await, "70");
+        let (nonce, _) = mock
+            .view_access_key(&signer.account_id, &signer.public_key)
+            .await
+            .unwrap();
+        assert_eq!(nonce, 2);
+
+        //没有私钥的账户不能发交易
+        let stranger =
+            InMemorySigner::from_seed("bob.test".parse().unwrap(), KeyType::ED25519, "bob");
+        assert!(ft_transfer(&mock, &stranger, "alice.test", 1)
+            .await
+            .is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use common::data_structures::{get_support_coin_list, PubkeySignInfo};
+use common::data_structures::PubkeySignInfo;
 
 use common::utils::time::now_millis;
 use hex::ToHex;
@@ -12,16 +12,17 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::str::FromStr;
 
+use near_primitives::borsh::BorshDeserialize;
+use near_primitives::transaction::{Action, Transaction};
 use near_primitives::types::AccountId;
 
 use common::data_structures::CoinType;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::coin::Coin;
-use crate::fees_call::FeesCall;
 use crate::general::get_access_key_list;
 use crate::general::pubkey_from_hex_str;
+use crate::portfolio::portfolio;
 
 use crate::ContractClient;
 use common::utils::math::*;
@@ -71,18 +72,7 @@ impl ContractClient<MultiSig> {
     }
 
     pub async fn get_total_value(&self, account_str: &str) -> Result<u128> {
-        let coins = get_support_coin_list();
-        let mut total_value = 0;
-        let fees_cli = ContractClient::<FeesCall>::new_update_cli().await?;
-        for coin in coins {
-            let coin_cli = ContractClient::<Coin>::new_update_cli(coin.clone()).await?;
-            let balance = coin_cli.get_balance(account_str).await?;
-            let balance: u128 = balance.unwrap_or("0".to_string()).parse()?;
-            //get price from contract
-            let (base, quote) = fees_cli.get_coin_price(&coin).await?;
-            total_value += balance * quote / base;
-        }
-        Ok(total_value)
+        Ok(portfolio(account_str).await?.total_value())
     }
 
     //fixeme:
@@ -367,7 +357,7 @@ impl ContractClient<MultiSig> {
         let coin_tx = CoinTx {
             from: AccountId::from_str(from)?,
             to: AccountId::from_str(to)?,
-            coin_id: coin.to_account_id(),
+            coin_id: coin.to_account_id()?,
             amount: transfer_amount,
             expire_at,
             memo: None,
@@ -392,7 +382,7 @@ impl ContractClient<MultiSig> {
     ) -> Result<String> {
         let main_account_id: AccountId = AccountId::from_str(main_account)?;
         let coin_tx = SubAccCoinTx {
-            coin_id: coin.to_account_id(),
+            coin_id: coin.to_account_id()?,
             amount: transfer_amount,
         };
 
@@ -423,7 +413,7 @@ impl ContractClient<MultiSig> {
         let coin_tx = CoinTx {
             from: AccountId::from_str(from)?,
             to: AccountId::from_str(to)?,
-            coin_id: coin.to_account_id(),
+            coin_id: coin.to_account_id()?,
             amount: transfer_amount,
             expire_at,
             memo: None,
@@ -451,7 +441,7 @@ impl ContractClient<MultiSig> {
         let coin_tx_info = CoinTx {
             from: AccountId::from_str(sender_id)?,
             to: AccountId::from_str(receiver_id)?,
-            coin_id: coin.to_account_id(),
+            coin_id: coin.to_account_id()?,
             amount: transfer_amount,
             expire_at,
             memo: None,
@@ -476,7 +466,7 @@ impl ContractClient<MultiSig> {
         let coin_tx = CoinTx {
             from: AccountId::from_str(from)?,
             to: AccountId::from_str(to)?,
-            coin_id: coin.to_account_id(),
+            coin_id: coin.to_account_id()?,
             amount: transfer_amount,
             expire_at,
             memo: None,
@@ -542,6 +532,20 @@ pub fn get_pubkey(pri_key_str: &str) -> Result<String> {
     Ok(pubkey.encode_hex())
 }
 
+/// 从send_money的原始交易里取出合约登记的tx_index，不是send_money交易时返回None
+pub fn send_money_tx_index(tx_raw: &str) -> Result<Option<u64>> {
+    let transaction = Transaction::try_from_slice(&hex::decode(tx_raw)?)?;
+    for action in &transaction.actions {
+        if let Action::FunctionCall(call) = action {
+            if call.method_name == "send_money" {
+                let args: serde_json::Value = serde_json::from_slice(&call.args)?;
+                return Ok(args["tx_index"].as_u64());
+            }
+        }
+    }
+    Ok(None)
+}
+
 fn pubkey_from_hex(hex_str: &str) -> Result<PublicKey> {
     println!("pubkey_from_hex {}", hex_str);
     let sender_id = AccountId::from_str(hex_str)?;
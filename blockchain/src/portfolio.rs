@@ -0,0 +1,173 @@
+//! balances and prices of the supported coins,
+//! prices are cached for a while and balances are cached until a new block is final
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use common::data_structures::{get_support_coin_list, CoinType};
+use common::utils::time::now_millis;
+use futures::future::try_join_all;
+use lazy_static::lazy_static;
+use near_primitives::hash::CryptoHash;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::chain_client::chain_client;
+use crate::coin::Coin;
+use crate::fees_call::FeesCall;
+use crate::ContractClient;
+
+//价格缓存时间
+const PRICE_TTL_MILLIS: u64 = 10 * 1000;
+
+lazy_static! {
+    //coin -> (fetched_at, price)
+    static ref PRICE_CACHE: Mutex<HashMap<String, (u64, CoinPrice)>> = Mutex::new(HashMap::new());
+    //(coin, account) -> (block_hash, balance)
+    static ref BALANCE_CACHE: Mutex<HashMap<(String, String), (CryptoHash, u128)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// `base` amount of the coin is worth `quote` dollar
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct CoinPrice {
+    pub base: u128,
+    pub quote: u128,
+}
+
+impl CoinPrice {
+    //目前的场景转账超过300兆才会溢出，取整造成的精度丢失可以忽略
+    pub fn value_of(&self, amount: u128) -> u128 {
+        amount * self.quote / self.base
+    }
+
+    pub fn amount_of(&self, value: u128) -> u128 {
+        value * self.base / self.quote
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CoinBalance {
+    pub coin: CoinType,
+    pub balance: u128,
+    pub price: CoinPrice,
+}
+
+impl CoinBalance {
+    pub fn value(&self) -> u128 {
+        self.price.value_of(self.balance)
+    }
+}
+
+/// balances of every supported coin of an account, read at the same final block
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Portfolio {
+    pub account_id: String,
+    pub coins: Vec<CoinBalance>,
+}
+
+impl Portfolio {
+    pub fn get(&self, coin: &CoinType) -> Option<&CoinBalance> {
+        self.coins.iter().find(|balance| balance.coin == *coin)
+    }
+
+    pub fn balance_of(&self, coin: &CoinType) -> u128 {
+        self.get(coin).map_or(0, |balance| balance.balance)
+    }
+
+    pub fn total_value(&self) -> u128 {
+        self.coins.iter().map(CoinBalance::value).sum()
+    }
+}
+
+pub async fn coin_price(coin: &CoinType) -> Result<CoinPrice> {
+    let now = now_millis();
+    if let Some((fetched_at, price)) = PRICE_CACHE.lock().unwrap().get(&coin.to_string()) {
+        if now < fetched_at + PRICE_TTL_MILLIS {
+            return Ok(*price);
+        }
+    }
+    let fees_cli = ContractClient::<FeesCall>::new_query_cli().await?;
+    let (base, quote) = fees_cli.get_coin_price(coin).await?;
+    let price = CoinPrice { base, quote };
+    debug!("refresh price of {}: {:?}", coin, price);
+    PRICE_CACHE
+        .lock()
+        .unwrap()
+        .insert(coin.to_string(), (now, price));
+    Ok(price)
+}
+
+async fn balance_at(block_hash: CryptoHash, account_id: &str, coin: &CoinType) -> Result<u128> {
+    let key = (coin.to_string(), account_id.to_string());
+    if let Some((cached_at, balance)) = BALANCE_CACHE.lock().unwrap().get(&key) {
+        if *cached_at == block_hash {
+            return Ok(*balance);
+        }
+    }
+    let coin_cli = ContractClient::<Coin>::new_query_cli(coin.clone()).await?;
+    //和缓存的键是同一个区块，缓存命中与否结果都一样
+    let balance = match coin_cli.get_balance_at(block_hash, account_id).await? {
+        Some(balance) => balance.parse()?,
+        None => 0,
+    };
+    BALANCE_CACHE
+        .lock()
+        .unwrap()
+        .insert(key, (block_hash, balance));
+    Ok(balance)
+}
+
+pub async fn coin_balance(account_id: &str, coin: &CoinType) -> Result<u128> {
+    let block_hash = chain_client().final_block_hash().await?;
+    balance_at(block_hash, account_id, coin).await
+}
+
+/// query the balances and prices of all supported coins concurrently
+pub async fn portfolio(account_id: &str) -> Result<Portfolio> {
+    let block_hash = chain_client().final_block_hash().await?;
+    let coins = try_join_all(get_support_coin_list().into_iter().map(|coin| async move {
+        let (balance, price) =
+            tokio::try_join!(balance_at(block_hash, account_id, &coin), coin_price(&coin))?;
+        Ok::<_, anyhow::Error>(CoinBalance {
+            coin,
+            balance,
+            price,
+        })
+    }))
+    .await?;
+    Ok(Portfolio {
+        account_id: account_id.to_string(),
+        coins,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portfolio_value() {
+        let price = CoinPrice { base: 1, quote: 2 };
+        let portfolio = Portfolio {
+            account_id: "1.test".to_string(),
+            coins: vec![
+                CoinBalance {
+                    coin: CoinType::USDT,
+                    balance: 10,
+                    price: CoinPrice { base: 1, quote: 1 },
+                },
+                CoinBalance {
+                    coin: CoinType::BTC,
+                    balance: 3,
+                    price,
+                },
+            ],
+        };
+        assert_eq!(portfolio.total_value(), 16);
+        assert_eq!(portfolio.balance_of(&CoinType::BTC), 3);
+        assert_eq!(portfolio.balance_of(&CoinType::ETH), 0);
+        assert_eq!(price.amount_of(price.value_of(5)), 5);
+    }
+}
@@ -1,25 +1,118 @@
 use common::env::RelayerPool;
 
 use lazy_static::lazy_static;
-use near_jsonrpc_client::methods::query::RpcQueryRequest;
 use std::str::FromStr;
 
 use near_crypto::{InMemorySigner, KeyType, SecretKey};
 
-use near_primitives::{hash::hash, types::BlockReference};
+use near_jsonrpc_client::errors::{JsonRpcError, JsonRpcServerError};
+use near_jsonrpc_primitives::types::transactions::RpcTransactionError;
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::hash;
 
 use near_primitives::types::AccountId;
 
 //use log::debug;
 use anyhow::{anyhow, Result};
+use common::utils::time::now_millis;
 use tokio::sync::{Mutex, MutexGuard};
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
+
+use crate::chain_client::{chain_client, TxExecutionFailure};
+
+//连续失败这么多次之后暂停使用
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+const QUARANTINE_MILLIS: u64 = 60 * 1000;
+//relayer账户余额低于1 near时告警
+const MIN_RELAYER_BALANCE: u128 = 1_000_000_000_000_000_000_000_000;
 
 pub struct Relayer {
     pub derive_index: u32,
     pub signer: InMemorySigner,
     /// 用到的当前的时候再去初始化，初始化完成之后再本地维护
     pub nonce: Option<u64>,
+    pub consecutive_failures: u32,
+    /// 隔离到这个时间点(毫秒)之前不再分配
+    pub quarantined_until: Option<u64>,
+}
+
+impl Relayer {
+    pub fn new(derive_index: u32, signer: InMemorySigner) -> Self {
+        Relayer {
+            derive_index,
+            signer,
+            nonce: None,
+            consecutive_failures: 0,
+            quarantined_until: None,
+        }
+    }
+
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined_until
+            .map_or(false, |until| now_millis() < until)
+    }
+
+    /// query the nonce on chain again, the local one is discarded
+    pub async fn resync_nonce(&mut self) -> Result<u64> {
+        let client = chain_client();
+        let (nonce, _block_hash) = client
+            .view_access_key(&self.signer.account_id, &self.signer.public_key)
+            .await?;
+        info!(
+            "index_relayer_{} nonce resynced from {:?} to {}",
+            self.derive_index, self.nonce, nonce
+        );
+        self.nonce = Some(nonce);
+        //顺便检查手续费余额
+        match client.view_account_balance(&self.signer.account_id).await {
+            Ok(balance) if balance < MIN_RELAYER_BALANCE => warn!(
+                "relayer account {} is running out of gas, balance {}",
+                self.signer.account_id, balance
+            ),
+            Ok(_) => {}
+            Err(err) => warn!(
+                "query balance of relayer {} failed: {}",
+                self.signer.account_id, err
+            ),
+        }
+        Ok(nonce)
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.quarantined_until = None;
+    }
+
+    /// the tx may be dropped or executed, the nonce is resynced before next use
+    pub fn record_failure(&mut self) {
+        self.nonce = None;
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            warn!(
+                "index_relayer_{} failed {} times in a row, quarantined",
+                self.derive_index, self.consecutive_failures
+            );
+            self.quarantined_until = Some(now_millis() + QUARANTINE_MILLIS);
+        }
+    }
+}
+
+/// the tx was executed and failed, the nonce is used up and the relayer itself is fine
+pub fn is_execution_failure(err: &anyhow::Error) -> bool {
+    err.is::<TxExecutionFailure>()
+}
+
+/// the node rejected the tx because of a stale nonce or block hash, it's worth a resync and retry,
+/// a timeout is never retried here since the tx may still be executed
+pub fn is_nonce_error(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<JsonRpcError<RpcTransactionError>>(),
+        Some(JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+            RpcTransactionError::InvalidTransaction {
+                context: InvalidTxError::InvalidNonce { .. } | InvalidTxError::Expired
+            }
+        )))
+    )
 }
 
 impl AsRef<InMemorySigner> for Relayer {
@@ -36,11 +129,7 @@ lazy_static! {
         let mut pool = vec![];
         for derive_index in 1..=derive_size {
             let signer = chainless_sub_signer(&account_id,&seed,derive_index).unwrap();
-            pool.push(Mutex::new(Relayer{
-                derive_index,
-                signer,
-                nonce: None
-            }));
+            pool.push(Mutex::new(Relayer::new(derive_index, signer)));
         }
         pool
     };
@@ -50,10 +139,10 @@ lazy_static! {
 pub fn find_idle_relayer() -> Option<MutexGuard<'static, Relayer>> {
     for relayer in MULTI_SIG_RELAYER_POOL.iter() {
         match relayer.try_lock() {
-            Ok(guard) => {
+            Ok(guard) if !guard.is_quarantined() => {
                 return Some(guard);
             }
-            Err(_) => continue,
+            _ => continue,
         }
     }
     None
@@ -63,30 +152,14 @@ pub async fn wait_for_idle_relayer() -> Result<MutexGuard<'static, Relayer>> {
     loop {
         match find_idle_relayer() {
             Some(mut x) => {
-                let current_nonce = if let Some(num) = x.nonce{
-                    num
-                }else{
-                    let access_key_query_response = crate::rpc_call(RpcQueryRequest {
-                        block_reference: BlockReference::latest(),
-                        request: near_primitives::views::QueryRequest::ViewAccessKey {
-                            account_id: x.signer.account_id.clone(),
-                            public_key: x.signer.public_key.clone(),
-                        },
-                    })
-                    .await?;
-
-                    let current_nonce = match access_key_query_response.kind {
-                        near_jsonrpc_primitives::types::query::QueryResponseKind::AccessKey(access_key) => access_key.nonce,
-                        _ => Err(anyhow::anyhow!("failed to extract current nonce"))?,
-                    };
-                    current_nonce            
-                };
-                x.nonce = Some(current_nonce + 1);
+                if x.nonce.is_none() {
+                    x.resync_nonce().await?;
+                }
                 debug!("find idle index_relayer_{}", x.derive_index);
                 return Ok(x);
             }
             None => {
-                warn!("relayer is busy");
+                warn!("relayer is busy or quarantined");
                 tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
                 continue;
             }
@@ -109,6 +182,7 @@ pub fn chainless_sub_signer(account_id: &str, seed: &str, index: u32) -> Result<
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chain_client::invalid_tx_error;
     use crate::relayer::wait_for_idle_relayer;
     use common::log::init_logger;
     use near_crypto::Signer;
@@ -124,6 +198,52 @@ mod tests {
     use near_primitives::types::AccountId;
     use tracing::error;
 
+    #[test]
+    fn test_relayer_health() {
+        let seed = "e48815443073117d29a8fab50c9f3feb80439c196d4d9314400e8e715e231849";
+        let signer = chainless_sub_signer("relayer.test", seed, 1).unwrap();
+        let mut relayer = Relayer::new(1, signer);
+        relayer.nonce = Some(10);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            relayer.record_failure();
+        }
+        assert!(relayer.nonce.is_none());
+        assert!(!relayer.is_quarantined());
+        relayer.record_failure();
+        assert!(relayer.is_quarantined());
+        relayer.record_success();
+        assert!(!relayer.is_quarantined());
+
+        assert!(is_nonce_error(&invalid_tx_error(
+            InvalidTxError::InvalidNonce {
+                tx_nonce: 5,
+                ak_nonce: 7
+            }
+        )));
+        assert!(is_nonce_error(&invalid_tx_error(InvalidTxError::Expired)));
+        assert!(!is_nonce_error(&invalid_tx_error(
+            InvalidTxError::NonceTooLarge {
+                tx_nonce: 5,
+                upper_bound: 3
+            }
+        )));
+        //超时的交易可能已经上链，不能换nonce重发
+        let timeout: anyhow::Error = JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+            RpcTransactionError::TimeoutError,
+        ))
+        .into();
+        assert!(!is_nonce_error(&timeout));
+        assert!(!is_nonce_error(&anyhow!("Transaction has expired")));
+        //合约拒绝的交易已经执行，不是relayer的问题
+        let rejected: anyhow::Error = TxExecutionFailure {
+            tx_hash: Default::default(),
+            reason: "Smart contract panicked".to_string(),
+        }
+        .into();
+        assert!(is_execution_failure(&rejected));
+        assert!(!is_nonce_error(&rejected));
+    }
+
     #[tokio::test]
     async fn test_relayer_pool() {
         init_logger();
@@ -1,6 +1,6 @@
 use crate::utils::{
     math::BASE_DECIMAL,
-    time::{DAY7, MINUTE1, MINUTE10, MINUTE2, MINUTE5},
+    time::{DAY1, HOUR1, MINUTE1, MINUTE10, MINUTE5},
 };
 
 /***
@@ -8,8 +8,6 @@ use crate::utils::{
 pub const CAPTCHA_REQUEST_INTERVAL: u64 = MINUTE1;
 //验证码的有效时间
 pub const CAPTCHA_EXPAIRE_TIME: u64 = MINUTE10;
-//密码重试导致锁定的解锁时间
-pub const LOGIN_UNLOCK_TIME: u64 = MINUTE30;
 //token 有效时间
 pub const TOKEN_EXPAIRE_TIME: u64 = DAY7;
 //无链的链上交互基础费用
@@ -26,10 +24,12 @@ pub const BRIDGE_DEPOSIT_EXPIRE_TIME: u64 = MINUTE30;
 pub const CAPTCHA_REQUEST_INTERVAL: u64 = MINUTE1;
 //验证码的有效时间
 pub const CAPTCHA_EXPAIRE_TIME: u64 = MINUTE1;
-//密码重试导致锁定的解锁时间
-pub const LOGIN_UNLOCK_TIME: u64 = MINUTE2;
-//token 有效时间
-pub const TOKEN_EXPAIRE_TIME: u64 = DAY7;
+//验证码发送记录的保留时间，用于统计发送频率
+pub const CAPTCHA_SEND_RECORD_TIME: u64 = DAY1;
+//连续发送时请求间隔翻倍，最长的间隔
+pub const CAPTCHA_MAX_REQUEST_INTERVAL: u64 = HOUR1;
+//验证码输错这么多次之后作废
+pub const CAPTCHA_MAX_ATTEMPTS: u32 = 5;
 //无链的链上交互基础费用
 pub const MIN_BASE_FEE: u128 = BASE_DECIMAL;
 //默认的基础交互gas数量
@@ -43,9 +43,6 @@ pub const BRIDGE_DEPOSIT_EXPIRE_TIME: u64 = MINUTE5;
 // 参考币安的主网确认块数
 pub const ETH_TX_CONFIRM_BLOCK_NUM: u8 = 6;
 
-//密码登陆错误允许的重试次数
-pub const LOGIN_BY_PASSWORD_RETRY_NUM: u8 = 5;
-
 //邀请码长度规则
 pub const INVITE_CODE_MIN_LEN: u8 = 4;
 pub const INVITE_CODE_MAX_LEN: u8 = 20;
@@ -55,8 +52,13 @@ pub const CHAINLESS_AIRDROP: &str = "ChainlessAirdrop";
 
 pub const INVITE_URL: &str = "https://test1.chainless.top/download?code=";
 
+//请求体的上限，json解析和审计读取请求体共用
+pub const JSON_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
 //数据库连接池大小
 pub const PG_POOL_SIZE: usize = 60;
+//等待连接池空闲连接的上限，超时的请求直接失败
+pub const PG_POOL_WAIT_SECS: u64 = 10;
 
 //子账户创建限制(每天多少个)
 pub const SUBACCOUNT_AMOUNT_LIMIT: u16 = 3;
@@ -0,0 +1,16 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// one committed or rejected POST request, the table is append-only
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct AuditEvent {
+    pub event_id: String,
+    //登录前的请求没有用户和设备信息
+    pub user_id: Option<u32>,
+    pub device_id: Option<String>,
+    pub device_brand: Option<String>,
+    pub endpoint: String,
+    //去掉密码、验证码等敏感字段之后的请求参数
+    pub params: String,
+    pub result_code: u16,
+    pub trace_id: String,
+}
@@ -79,4 +79,5 @@ pub struct EthBridgeOrder {
     //pub status: String,            //订单状态
     pub status: EthOrderStatus, //WithdrawStatus,DepositStatus
     pub height: u64,
+    pub block_hash: String, //订单所在区块，用于发现分叉
 }
@@ -0,0 +1,19 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// issued captcha of a contact, only the hash of the code is kept
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct CaptchaRecord {
+    //邮箱或者手机号，已登陆时是user_id
+    pub owner: String,
+    //验证码用途
+    pub usage: String,
+    pub device_id: String,
+    //sha256(owner:usage:code)，用过之后置空
+    pub code_hash: String,
+    //当前验证码输错的次数
+    pub attempts: u32,
+    //记录保留期内累计发送的次数
+    pub sent_times: u32,
+    pub last_sent_at: u64,
+    pub expire_at: u64,
+}
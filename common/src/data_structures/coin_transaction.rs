@@ -1,4 +1,5 @@
 use super::{CoinType, TxStatusOnChain};
+use crate::env::{RetryConf, CONF};
 
 use serde_derive::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
@@ -25,6 +26,18 @@ pub enum TxType {
     MainToBridge,
 }
 
+impl TxType {
+    /// retry policy of a failed transaction rebuilt and resubmitted by the relayer,
+    /// the others are signed by the sender's master key and need to be signed again
+    pub fn retry_conf(&self) -> Option<&'static RetryConf> {
+        match self {
+            TxType::MainToSub => Some(&CONF.tx_retry.main_to_sub),
+            TxType::SubToMain => Some(&CONF.tx_retry.sub_to_main),
+            TxType::Normal | TxType::Forced | TxType::MainToBridge => None,
+        }
+    }
+}
+
 impl TxRole {
     pub fn counterparty(&self) -> Self {
         match self {
@@ -69,6 +82,12 @@ pub struct CoinTransaction {
     pub tx_type: TxType,
     pub chain_status: TxStatusOnChain,
     pub receiver_contact: Option<String>,
+    //上链失败后重新提交的次数
+    pub retry_times: u32,
+    //最后一次上链失败的原因
+    pub failure_reason: Option<String>,
+    //主设备的二次确认签名(子账户转出时为子账户的签名)，重新提交时使用
+    pub confirmed_sig: Option<String>,
 }
 
 //sub to main
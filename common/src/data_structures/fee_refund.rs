@@ -0,0 +1,35 @@
+use super::CoinType;
+
+use serde_derive::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug, Eq, Hash, EnumString, Display)]
+pub enum FeeRefundStatus {
+    /// 重新提交之前失败的那笔交易，手续费还没有核对
+    Unchecked,
+    /// 已记录待退还
+    Pending,
+    /// 退款交易已发出，结果未知之前不会再发
+    Refunding,
+    /// 已退还
+    Refunded,
+    /// 退款失败，需要人工核对之后处理
+    Failed,
+    /// 该交易没有扣过手续费
+    NoFee,
+}
+
+/// fee refund of a failed or cancelled coin transaction, at most one per tx_id
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct FeeRefund {
+    //被扣手续费的链上交易
+    pub tx_id: String,
+    pub order_id: String,
+    //退款的接收方，用户的主账户
+    pub account_id: String,
+    pub fee_coin: Option<CoinType>,
+    pub amount: u128,
+    pub status: FeeRefundStatus,
+    pub refund_tx_id: Option<String>,
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,25 @@
+use serde_derive::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug, Eq, Hash, EnumString, Display)]
+pub enum LoginAttemptResult {
+    /// 密码错误
+    Failed,
+    /// 登陆成功，之前的错误次数不再计算
+    Succeeded,
+    /// 锁定到期、重置密码或者管理员解锁，之前的错误次数不再计算
+    Unlocked,
+}
+
+/// one password login attempt of a user, the table is append-only
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct LoginAttempt {
+    pub attempt_id: String,
+    pub user_id: u32,
+    //管理员解锁时为空
+    pub device_id: String,
+    pub ip: String,
+    pub result: LoginAttemptResult,
+    //毫秒时间戳
+    pub attempted_at: u64,
+}
@@ -1,17 +1,28 @@
 pub mod account_manager;
 pub mod airdrop;
+pub mod audit_event;
 pub mod bridge;
+pub mod captcha;
 pub mod coin_transaction;
 pub mod device_info;
+pub mod fee_refund;
+pub mod login_attempt;
+pub mod outbox;
+pub mod scanner_checkpoint;
 pub mod secret_store;
+pub mod user_session;
 pub mod wallet_namage_record;
 
+use std::borrow::Cow;
+use std::fmt;
 use std::str::FromStr;
 
+use crate::env::CoinConf;
 use crate::{env::CONF as global_conf, error_code::*};
 use near_primitives::types::AccountId;
 use serde_derive::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
+use thiserror::Error;
 
 use self::{coin_transaction::CoinTransaction, secret_store::SecretStore};
 
@@ -52,82 +63,126 @@ pub enum OpStatus {
     Idle,
 }
 
+//注册表里所有的币，包括已经关闭转账或跨链的
 pub fn get_support_coin_list() -> Vec<CoinType> {
-    vec![
-        CoinType::BTC,
-        CoinType::ETH,
-        CoinType::USDT,
-        CoinType::USDC,
-        CoinType::CLY,
-        CoinType::DW20,
-    ]
-}
-
-pub fn get_support_coin_list_without_cly() -> Vec<CoinType> {
-    vec![
-        CoinType::BTC,
-        CoinType::ETH,
-        CoinType::USDT,
-        //CoinType::USDC,
-        CoinType::DW20,
-    ]
-}
-
-#[derive(Deserialize, Serialize, Debug, Clone, EnumString, Display, PartialEq, Default)]
-pub enum CoinType {
-    #[default]
-    #[strum(ascii_case_insensitive, to_string = "btc")]
-    BTC,
-    #[strum(ascii_case_insensitive, to_string = "eth")]
-    ETH,
-    #[strum(ascii_case_insensitive, to_string = "usdt")]
-    USDT,
-    #[strum(ascii_case_insensitive, to_string = "usdc")]
-    USDC,
-    #[strum(ascii_case_insensitive, to_string = "cly")]
-    CLY,
-    #[strum(ascii_case_insensitive, to_string = "dw20")]
-    DW20,
+    global_conf
+        .coins
+        .iter()
+        .map(|conf| CoinType(Cow::Owned(conf.symbol.clone())))
+        .collect()
 }
 
+/// symbol of a coin in the registry of `[[coin]]` config
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CoinType(Cow<'static, str>);
+
+/// the coin was parsed before but its `[[coin]]` config is missing or broken now
+#[derive(Error, Debug, PartialEq)]
+pub enum RegistryError {
+    #[error("coin {0} not in registry")]
+    NotListed(CoinType),
+    #[error("near contract {0} of the registry is invalid")]
+    InvalidContract(String),
+}
+
+//业务代码按注册表处理，固定的币只给测试用
+#[cfg(any(test, feature = "testing"))]
 impl CoinType {
-    pub fn to_account_id(&self) -> AccountId {
-        AccountId::from_str(&self.to_string()).unwrap()
-    }
-
-    pub fn erc20_ca(&self) -> Option<String> {
-        match self {
-            CoinType::BTC => Some(global_conf.eth_wbtc_contract.clone()),
-            CoinType::ETH => None,
-            CoinType::USDT => Some(global_conf.eth_usdt_contract.clone()),
-            CoinType::USDC => Some(global_conf.eth_usdc_contract.clone()),
-            CoinType::CLY => Some(global_conf.eth_cly_contract.clone()),
-            CoinType::DW20 => None,
-        }
+    pub const BTC: CoinType = CoinType(Cow::Borrowed("btc"));
+    pub const ETH: CoinType = CoinType(Cow::Borrowed("eth"));
+    pub const USDT: CoinType = CoinType(Cow::Borrowed("usdt"));
+    pub const USDC: CoinType = CoinType(Cow::Borrowed("usdc"));
+    pub const CLY: CoinType = CoinType(Cow::Borrowed("cly"));
+    pub const DW20: CoinType = CoinType(Cow::Borrowed("dw20"));
+}
+
+impl CoinType {
+    pub fn symbol(&self) -> &str {
+        &self.0
     }
 
-    //todo: config by env
-    pub fn erc20_decimal(&self) -> Option<u8> {
-        match self {
-            CoinType::BTC => Some(18),
-            //is token_decimal rather than coin_decimal
-            CoinType::ETH => Some(18),
-            CoinType::USDT => Some(18),
-            CoinType::USDC => Some(18),
-            CoinType::CLY => Some(18),
-            CoinType::DW20 => None,
-        }
+    fn conf(&self) -> Result<&'static CoinConf, RegistryError> {
+        global_conf
+            .coins
+            .iter()
+            .find(|conf| conf.symbol == self.symbol())
+            .ok_or_else(|| RegistryError::NotListed(self.clone()))
     }
 
-    pub fn nep21_decimal(&self) -> u8 {
-        match self {
-            CoinType::BTC => 18,
-            CoinType::ETH => 18,
-            CoinType::USDT => 18,
-            CoinType::USDC => 18,
-            CoinType::CLY => 18,
-            CoinType::DW20 => 18,
-        }
+    pub fn to_account_id(&self) -> Result<AccountId, RegistryError> {
+        let conf = self.conf()?;
+        let contract = conf.near_contract.as_ref().unwrap_or(&conf.symbol);
+        AccountId::from_str(contract).map_err(|_| RegistryError::InvalidContract(contract.clone()))
+    }
+
+    pub fn erc20_ca(&self) -> Result<Option<String>, RegistryError> {
+        Ok(self.conf()?.erc20_contract.clone())
+    }
+
+    pub fn erc20_decimal(&self) -> Result<Option<u8>, RegistryError> {
+        Ok(self.conf()?.erc20_decimal)
+    }
+
+    pub fn nep21_decimal(&self) -> Result<u8, RegistryError> {
+        Ok(self.conf()?.near_decimal)
+    }
+
+    pub fn display_decimal(&self) -> Result<u8, RegistryError> {
+        Ok(self.conf()?.display_decimal)
+    }
+
+    /// the native coin of eth, deposited to the bridge as the tx value instead of an erc20 approval
+    pub fn eth_native(&self) -> Result<bool, RegistryError> {
+        Ok(self.conf()?.eth_native)
+    }
+
+    pub fn transfer_enabled(&self) -> Result<bool, RegistryError> {
+        Ok(self.conf()?.transfer_enabled)
+    }
+
+    pub fn bridge_enabled(&self) -> Result<bool, RegistryError> {
+        Ok(self.conf()?.bridge_enabled)
+    }
+
+    pub fn fee_enabled(&self) -> Result<bool, RegistryError> {
+        Ok(self.conf()?.fee_enabled)
+    }
+}
+
+impl fmt::Display for CoinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+//合约返回的是near上的合约地址，所以也按合约地址查找
+impl FromStr for CoinType {
+    type Err = strum::ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        global_conf
+            .coins
+            .iter()
+            .find(|conf| {
+                conf.symbol.eq_ignore_ascii_case(s) || conf.near_contract.as_deref() == Some(s)
+            })
+            .map(|conf| CoinType(Cow::Owned(conf.symbol.clone())))
+            .ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
+//和之前的枚举保持一致，序列化为大写
+impl serde::Serialize for CoinType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_uppercase())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for CoinType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = <String as serde::Deserialize>::deserialize(deserializer)?;
+        symbol
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("coin {} not support", symbol)))
     }
 }
 
@@ -168,3 +223,20 @@ impl FromStr for PubkeySignInfo {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coin_registry() {
+        let usdt: CoinType = "USDT".parse().unwrap();
+        assert_eq!(usdt, CoinType::USDT);
+        assert_eq!(usdt.to_string(), "usdt");
+        assert_eq!(serde_json::to_string(&usdt).unwrap(), "\"USDT\"");
+        let coin: CoinType = serde_json::from_str("\"USDT\"").unwrap();
+        assert_eq!(coin, usdt);
+        assert!("not_listed".parse::<CoinType>().is_err());
+        assert_eq!(get_support_coin_list().len(), global_conf.coins.len());
+    }
+}
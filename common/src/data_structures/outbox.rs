@@ -0,0 +1,41 @@
+use serde_derive::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug, Eq, Hash, EnumString, Display)]
+pub enum OutboxEventType {
+    /// 转账订单进度变化，payload为order_id、from、to
+    CoinTxStageChanged,
+    /// 发起钱包管理操作(添加从设备、更新策略等)，payload为完整的记录
+    WalletManageRequested,
+    /// 钱包管理操作上链结果，payload为record_id、status
+    WalletManageStatusChanged,
+    /// 跨链订单状态变化，payload为order_type、order_id、status
+    BridgeOrderStatusChanged,
+    /// 多签转账过期，通知发起方，payload为order_id、sender、coin_type、amount
+    CoinTxExpired,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug, Eq, Hash, EnumString, Display)]
+pub enum OutboxStatus {
+    /// 待投递
+    Pending,
+    /// 所有的sink都已投递成功
+    Published,
+    /// 重试次数用完，需要人工处理
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct OutboxEvent {
+    pub event_id: String,
+    pub event_type: OutboxEventType,
+    //事件所属的业务数据id，如order_id、record_id
+    pub aggregate_id: String,
+    //json
+    pub payload: String,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    //毫秒时间戳，投递失败之后退避到这个时间再重试
+    pub next_attempt_at: u64,
+}
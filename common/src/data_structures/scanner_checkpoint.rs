@@ -0,0 +1,12 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// progress of a chain scanner task
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct ScannerCheckpoint {
+    //任务名，如eth_bridge
+    pub task: String,
+    //已处理的最高区块
+    pub height: u64,
+    //为空时以链上的为准
+    pub block_hash: String,
+}
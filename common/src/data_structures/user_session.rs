@@ -0,0 +1,16 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// login session of a device, only the hash of the refresh token is kept
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct UserSession {
+    //每次登陆都会换新，access token里带着它
+    pub session_id: String,
+    pub user_id: u32,
+    pub device_id: String,
+    pub device_brand: String,
+    //sha256(refresh_token)，每次刷新都会换新
+    pub refresh_token_hash: String,
+    pub refresh_expire_at: u64,
+    //最近一次登陆或刷新的时间
+    pub last_active_at: u64,
+}
@@ -40,4 +40,6 @@ pub struct WalletManageRecord {
     pub operator_device_brand: String,
     pub tx_ids: Vec<String>,
     pub status: TxStatusOnChain,
+    //上链失败后重新提交的次数
+    pub retry_times: u32,
 }
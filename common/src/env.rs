@@ -5,6 +5,7 @@ use std::{env, fs};
 use std::fmt::Debug;
 
 use serde::Deserialize;
+use thiserror::Error;
 
 #[derive(Deserialize, Debug, PartialEq, EnumString, Display)]
 pub enum ServiceMode {
@@ -53,6 +54,254 @@ pub struct RelayerPool {
     pub derive_size: u32,
 }
 
+fn enabled() -> bool {
+    true
+}
+
+/// one asset of the coin registry
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CoinConf {
+    /// lowercase symbol, also the name stored in db
+    pub symbol: String,
+    /// nep141 contract on near, default to the symbol
+    pub near_contract: Option<String>,
+    /// none for native eth and the coins not on eth
+    pub erc20_contract: Option<String>,
+    pub near_decimal: u8,
+    pub erc20_decimal: Option<u8>,
+    /// decimal places shown to user
+    pub display_decimal: u8,
+    /// native coin of eth, locked to the bridge by value rather than an erc20 contract
+    #[serde(default)]
+    pub eth_native: bool,
+    #[serde(default = "enabled")]
+    pub transfer_enabled: bool,
+    #[serde(default = "enabled")]
+    pub bridge_enabled: bool,
+    #[serde(default = "enabled")]
+    pub fee_enabled: bool,
+}
+
+//10^38以内的精度换算才不会超出u128
+const MAX_COIN_DECIMAL: u8 = 38;
+
+/// the `[[coin]]` registry which can't be loaded
+#[derive(Error, Debug, PartialEq)]
+pub enum CoinConfError {
+    #[error("coin symbol {0} must be lowercase")]
+    NotLowercase(String),
+    #[error("coin {0} is listed more than once")]
+    Duplicated(String),
+    #[error("{1} of coin {0} is larger than 38")]
+    DecimalTooLarge(String, &'static str),
+}
+
+/// check the registry once when the config is loaded, the coin lookups rely on it
+pub fn check_coins(coins: &[CoinConf]) -> Result<(), CoinConfError> {
+    for (index, coin) in coins.iter().enumerate() {
+        let symbol = &coin.symbol;
+        if symbol.is_empty() || *symbol != symbol.to_lowercase() {
+            Err(CoinConfError::NotLowercase(symbol.clone()))?;
+        }
+        if coins[..index].iter().any(|former| former.symbol == *symbol) {
+            Err(CoinConfError::Duplicated(symbol.clone()))?;
+        }
+        for (name, decimal) in [
+            ("near_decimal", Some(coin.near_decimal)),
+            ("erc20_decimal", coin.erc20_decimal),
+            ("display_decimal", Some(coin.display_decimal)),
+        ] {
+            if decimal.map_or(false, |decimal| decimal > MAX_COIN_DECIMAL) {
+                Err(CoinConfError::DecimalTooLarge(symbol.clone(), name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct OutboxConf {
+    /// events are posted to it as json when set
+    pub webhook_url: Option<String>,
+    /// events are appended to it line by line when set
+    pub log_file: Option<String>,
+    pub batch_size: u32,
+    /// delay before the first retry of a failed event, doubled on every failure
+    pub retry_base_secs: u64,
+    /// upper bound of the retry delay
+    pub max_backoff_secs: u64,
+}
+
+impl Default for OutboxConf {
+    fn default() -> Self {
+        OutboxConf {
+            webhook_url: None,
+            log_file: None,
+            batch_size: 100,
+            retry_base_secs: 5,
+            max_backoff_secs: 600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct EthBridgeConf {
+    /// blocks covered by one eth_getLogs query
+    pub batch_size: u64,
+    /// pause once the newest block is scanned
+    pub poll_interval_millis: u64,
+    /// upper bound of the backoff after rpc errors
+    pub max_backoff_secs: u64,
+}
+
+impl Default for EthBridgeConf {
+    fn default() -> Self {
+        EthBridgeConf {
+            batch_size: 100,
+            poll_interval_millis: 1000,
+            max_backoff_secs: 60,
+        }
+    }
+}
+
+/// a scanner task working in rounds, once a round handles less than `batch_size` rows
+/// the task sleeps `interval_secs` before the next one,
+/// a section given in the config must list both keys
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct SweepConf {
+    pub batch_size: u32,
+    pub interval_secs: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ArchiveConf {
+    /// finished or expired coin transactions untouched for so long are archived
+    pub coin_tx_retention_days: u64,
+    #[serde(flatten)]
+    pub sweep: SweepConf,
+}
+
+impl Default for ArchiveConf {
+    fn default() -> Self {
+        ArchiveConf {
+            coin_tx_retention_days: 30,
+            sweep: SweepConf {
+                batch_size: 500,
+                interval_secs: 600,
+            },
+        }
+    }
+}
+
+fn refund_fee_sweep() -> SweepConf {
+    SweepConf {
+        batch_size: 100,
+        interval_secs: 60,
+    }
+}
+
+fn coin_tx_expiry_sweep() -> SweepConf {
+    SweepConf {
+        batch_size: 100,
+        interval_secs: 30,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct LoginLockConf {
+    /// wrong passwords in a row before the account is locked
+    pub max_failures: u8,
+    /// how long the account stays locked
+    pub unlock_secs: u64,
+}
+
+impl Default for LoginLockConf {
+    fn default() -> Self {
+        LoginLockConf {
+            max_failures: 5,
+            unlock_secs: 1800,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct SessionConf {
+    /// lifetime of the access token carried by every request
+    pub access_token_secs: u64,
+    /// lifetime of the refresh token, renewed on every refresh
+    pub refresh_token_secs: u64,
+}
+
+impl Default for SessionConf {
+    fn default() -> Self {
+        SessionConf {
+            access_token_secs: 1800,
+            refresh_token_secs: 30 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JwtKeyConf {
+    /// `kid` header of the tokens signed by the key
+    pub kid: String,
+    /// pem file of the ES256 public key
+    pub public_key: String,
+    /// pkcs8 pem file of the ES256 private key, only the signing key needs it
+    pub private_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct JwtConf {
+    /// kid of the key signing new tokens
+    pub signing_kid: String,
+    /// every listed key is accepted for verification, so the former key
+    /// is kept here until the tokens it signed have expired
+    pub keys: Vec<JwtKeyConf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConf {
+    /// times a failed tx is submitted again by the relayer, 0 disables the retry
+    pub max_times: u32,
+    /// delay before the first retry, doubled after every retry
+    pub backoff_secs: u64,
+}
+
+impl Default for RetryConf {
+    fn default() -> Self {
+        RetryConf {
+            max_times: 3,
+            backoff_secs: 30,
+        }
+    }
+}
+
+impl RetryConf {
+    /// delay since the last attempt before retrying a tx which was retried `retried` times
+    pub fn delay_millis(&self, retried: u32) -> u64 {
+        (self.backoff_secs * 1000).saturating_mul(1 << retried.min(16))
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct TxRetryConf {
+    /// coin transfers from the main account to its sub account
+    pub main_to_sub: RetryConf,
+    /// coin transfers from the sub account to its main account
+    pub sub_to_main: RetryConf,
+    /// wallet manage operations submitted by the relayer
+    pub wallet_manage: RetryConf,
+}
+
 ///read config data for env
 #[derive(Deserialize, Debug)]
 pub struct EnvConf {
@@ -75,14 +324,39 @@ pub struct EnvConf {
     pub chain_rpc: String,
     pub stmp: Smtp,
     pub sms: Sms,
-    pub eth_wbtc_contract: String,
-    pub eth_usdt_contract: String,
-    pub eth_usdc_contract: String,
-    pub eth_dw20_contract: String,
-    pub eth_cly_contract: String,
+    /// the coin registry
+    #[serde(rename = "coin")]
+    pub coins: Vec<CoinConf>,
     /// BTC aggregated API service
     pub btc_aggregated_api_base_uri: String,
     pub error_code_path: String,
+    /// sinks of the outbox publisher in scanner
+    #[serde(default)]
+    pub outbox: OutboxConf,
+    /// retention of the coin transaction archive task in scanner
+    #[serde(default)]
+    pub archive: ArchiveConf,
+    /// block scanning of the eth_bridge task in scanner
+    #[serde(default)]
+    pub eth_bridge: EthBridgeConf,
+    /// rounds of the fee refund task in scanner, over the failed or cancelled coin transactions
+    #[serde(default = "refund_fee_sweep")]
+    pub refund_fee: SweepConf,
+    /// rounds of the expiry task in scanner, over the expired multi-sig orders
+    #[serde(default = "coin_tx_expiry_sweep")]
+    pub coin_tx_expiry: SweepConf,
+    /// lockout policy of the password login
+    #[serde(default)]
+    pub login_lock: LoginLockConf,
+    /// lifetime of the login session tokens
+    #[serde(default)]
+    pub session: SessionConf,
+    /// key set signing the access tokens, required in Product mode
+    #[serde(default)]
+    pub jwt: JwtConf,
+    /// resubmission of the failed relayer txs in scanner
+    #[serde(default)]
+    pub tx_retry: TxRetryConf,
 }
 
 lazy_static! {
@@ -91,22 +365,58 @@ lazy_static! {
             env::var_os("CONFIG").expect("CONFIG environment variable required"),
         )
         .expect("Unable to read the `CONFIG` specified file");
-        toml::from_str(content.as_str()).expect("contents of configuration file invalid")
-    };
-    pub static ref TOKEN_SECRET_KEY: String = {
-        if let Some(value) = env::var_os("TOKEN_SECRET_KEY") {
-            value.to_str().unwrap().parse().unwrap()
-        } else {
-            "your_secret_key".to_string()
+        let conf: EnvConf =
+            toml::from_str(content.as_str()).expect("contents of configuration file invalid");
+        if let Err(err) = check_coins(&conf.coins) {
+            panic!("coin registry of configuration file invalid: {}", err);
         }
+        conf
     };
+    //客服查询审计记录的密钥，未设置时查询接口不可用
+    pub static ref SUPPORT_ADMIN_KEY: Option<String> = env::var("SUPPORT_ADMIN_KEY")
+        .ok()
+        .filter(|key| !key.is_empty());
 }
 
 #[cfg(test)]
 mod tests {
 
+    use super::*;
+
     #[test]
     fn test_get_env() {
         println!("envs {:?}", *super::CONF);
     }
+
+    #[test]
+    fn test_check_coins() {
+        let coin = |symbol: &str, near_decimal: u8| CoinConf {
+            symbol: symbol.to_string(),
+            near_contract: None,
+            erc20_contract: None,
+            near_decimal,
+            erc20_decimal: None,
+            display_decimal: 8,
+            eth_native: false,
+            transfer_enabled: true,
+            bridge_enabled: true,
+            fee_enabled: true,
+        };
+        assert_eq!(check_coins(&[coin("usdt", 18), coin("btc", 38)]), Ok(()));
+        assert_eq!(
+            check_coins(&[coin("USDT", 18)]),
+            Err(CoinConfError::NotLowercase("USDT".to_string()))
+        );
+        assert_eq!(
+            check_coins(&[coin("usdt", 18), coin("usdt", 6)]),
+            Err(CoinConfError::Duplicated("usdt".to_string()))
+        );
+        assert_eq!(
+            check_coins(&[coin("usdt", 39)]),
+            Err(CoinConfError::DecimalTooLarge(
+                "usdt".to_string(),
+                "near_decimal"
+            ))
+        );
+    }
 }
@@ -1,6 +1,7 @@
 use std::{collections::HashMap, str::FromStr};
 
-use crate::data_structures::{coin_transaction::CoinSendStage, KeyRole};
+use crate::data_structures::{coin_transaction::CoinSendStage, KeyRole, RegistryError};
+use crate::utils::math::coin_amount::AmountError;
 use serde::Deserialize;
 use thiserror::Error;
 
@@ -38,6 +39,12 @@ pub enum BackendError {
     ChainError(String),
     #[error("Authorization error: {0}")]
     Authorization(String),
+    #[error("data not found")]
+    DataNotFound,
+    #[error("data is repeated")]
+    DataRepeated,
+    #[error("data conflict: {0}")]
+    DataConflict(String),
     #[error("{0}")]
     ExternalService(#[from] ExternalServiceError),
     #[error("{0}")]
@@ -57,6 +64,23 @@ impl From<AnyhowError> for BackendError {
     }
 }
 
+impl From<RegistryError> for BackendError {
+    fn from(error: RegistryError) -> Self {
+        //注册表是配置，和用户的输入无关
+        BackendError::InternalError(error.to_string())
+    }
+}
+
+impl From<AmountError> for BackendError {
+    fn from(error: AmountError) -> Self {
+        match error {
+            AmountError::PrecisionLoss(_, _) => WalletError::UnSupportedPrecision.into(),
+            AmountError::Registry(error) => error.into(),
+            error => BackendError::RequestParamInvalid(error.to_string()),
+        }
+    }
+}
+
 pub fn to_internal_error<T: StdError>(error: T) -> BackendError {
     BackendError::InternalError(error.to_string())
 }
@@ -100,6 +124,9 @@ impl ErrorCode for BackendError {
             BackendError::SigVerifyFailed => 3,
             BackendError::ChainError(_) => 4,
             BackendError::Authorization(_) => 5,
+            BackendError::DataNotFound => 6,
+            BackendError::DataRepeated => 7,
+            BackendError::DataConflict(_) => 8,
             BackendError::ExternalService(err) => err.code(),
             BackendError::AccountManager(err) => err.code(),
             BackendError::Wallet(err) => err.code(),
@@ -126,7 +153,7 @@ pub enum AccountManagerError {
     #[error("user's password is incorrect,remain [{0}] input chance")]
     PasswordIncorrect(u8),
     #[error("Captcha request too frequently,and it is alive in [{0}] secs")]
-    CaptchaRequestTooFrequently(u8),
+    CaptchaRequestTooFrequently(u64),
     #[error("Account is locking,unlock after timestamp [{0}]")]
     AccountLocked(u64),
     #[error("Invite code not exist")]
@@ -141,6 +168,8 @@ pub enum AccountManagerError {
     ContactAlreadyReplenished,
     #[error("KYCNotRegister")]
     KYCNotRegister,
+    #[error("captcha is invalidated after [{0}] wrong attempts")]
+    CaptchaTooManyAttempts(u32),
 }
 
 impl ErrorCode for AccountManagerError {
@@ -161,6 +190,7 @@ impl ErrorCode for AccountManagerError {
             Self::PredecessorNotSetSecurity => 2016,
             Self::ContactAlreadyReplenished => 2017,
             Self::KYCNotRegister => 2018,
+            Self::CaptchaTooManyAttempts(_) => 2019,
         }
     }
 }
@@ -223,6 +253,12 @@ pub enum WalletError {
     UnSupportedPrecision,
     #[error("Subaccount Create too frequently")]
     SubaccountCreateTooFrequently,
+    #[error("tx's stage cann't move from {0} to {1}")]
+    TxStageTransitionIllegal(CoinSendStage, CoinSendStage),
+    #[error("tx {0} have been modified by other request")]
+    TxStageConflict(String),
+    #[error("{0} isn't supported transfer now")]
+    CoinTransferDisabled(String),
 }
 impl ErrorCode for WalletError {
     fn code(&self) -> u16 {
@@ -255,6 +291,9 @@ impl ErrorCode for WalletError {
             Self::ForbideTransferSelf => 3026,
             Self::UnSupportedPrecision => 3027,
             Self::SubaccountCreateTooFrequently => 3028,
+            Self::TxStageTransitionIllegal(_, _) => 3029,
+            Self::TxStageConflict(_) => 3030,
+            Self::CoinTransferDisabled(_) => 3031,
         }
     }
 }
@@ -23,8 +23,17 @@ pub fn random_num() -> u128 {
 }
 
 pub mod coin_amount {
+    use std::fmt;
+
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use thiserror::Error;
+
     use super::*;
+    use crate::data_structures::{CoinType, RegistryError};
 
+    //以下两个只用于18位精度的美元价值，币的数量用CoinAmount
     pub fn raw2display(raw: u128) -> String {
         //截取后方的10位
         let raw = raw / DEDUCT_DECIMAL;
@@ -51,6 +60,238 @@ pub mod coin_amount {
             Err("invailed display number".to_string())
         }
     }
+
+    #[derive(Error, Debug, PartialEq)]
+    pub enum AmountError {
+        #[error("invalid amount: {0}")]
+        Invalid(String),
+        #[error("{0} has more than {1} decimal places")]
+        PrecisionLoss(String, u8),
+        #[error("amount overflow")]
+        Overflow,
+        #[error("amount underflow")]
+        Underflow,
+        #[error("{0} and {1} can't be computed together")]
+        CoinMismatch(CoinType, CoinType),
+        #[error("{0}")]
+        Registry(#[from] RegistryError),
+    }
+
+    fn unit(decimal: u8) -> Result<u128, AmountError> {
+        10u128
+            .checked_pow(decimal as u32)
+            .ok_or(AmountError::Overflow)
+    }
+
+    //解析十进制字符串为最多decimal位小数的整数，超出的小数位报错而不是截断
+    fn parse_decimal(data: &str, decimal: u8) -> Result<u128, AmountError> {
+        let (integer, fraction) = data.split_once('.').unwrap_or((data, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+            Err(AmountError::Invalid(data.to_string()))?;
+        }
+        if fraction.len() > decimal as usize {
+            Err(AmountError::PrecisionLoss(data.to_string(), decimal))?;
+        }
+        let integer: u128 = integer.parse().map_err(|_| AmountError::Overflow)?;
+        let fraction_len = fraction.len() as u8;
+        let fraction: u128 = if fraction.is_empty() {
+            0
+        } else {
+            let fraction: u128 = fraction.parse().map_err(|_| AmountError::Overflow)?;
+            fraction
+                .checked_mul(unit(decimal - fraction_len)?)
+                .ok_or(AmountError::Overflow)?
+        };
+        integer
+            .checked_mul(unit(decimal)?)
+            .and_then(|raw| raw.checked_add(fraction))
+            .ok_or(AmountError::Overflow)
+    }
+
+    //保留places位小数，多余的截断
+    fn format_decimal(raw: u128, decimal: u8, places: u8) -> Result<String, AmountError> {
+        let base = unit(decimal)?;
+        let (integer, fraction) = (raw / base, raw % base);
+        if places == 0 {
+            return Ok(integer.to_string());
+        }
+        let fraction = if places <= decimal {
+            fraction / unit(decimal - places)?
+        } else {
+            fraction
+                .checked_mul(unit(places - decimal)?)
+                .ok_or(AmountError::Overflow)?
+        };
+        Ok(format!(
+            "{}.{:0width$}",
+            integer,
+            fraction,
+            width = places as usize
+        ))
+    }
+
+    /// amount of a coin in the smallest unit of its nep141 token on near
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CoinAmount {
+        pub coin: CoinType,
+        pub raw: u128,
+    }
+
+    impl CoinAmount {
+        pub fn from_raw(coin: CoinType, raw: u128) -> Self {
+            CoinAmount { coin, raw }
+        }
+
+        pub fn zero(coin: CoinType) -> Self {
+            Self::from_raw(coin, 0)
+        }
+
+        pub fn is_zero(&self) -> bool {
+            self.raw == 0
+        }
+
+        /// user input, at most `display_decimal` decimal places of the coin
+        pub fn from_display(coin: CoinType, display: &str) -> Result<Self, AmountError> {
+            let decimal = coin.nep21_decimal()?;
+            let places = coin.display_decimal()?.min(decimal);
+            let raw = parse_decimal(display, places)?
+                .checked_mul(unit(decimal - places)?)
+                .ok_or(AmountError::Overflow)?;
+            Ok(CoinAmount { coin, raw })
+        }
+
+        /// truncated to `display_decimal` decimal places
+        pub fn to_display(&self) -> Result<String, AmountError> {
+            format_decimal(
+                self.raw,
+                self.coin.nep21_decimal()?,
+                self.coin.display_decimal()?,
+            )
+        }
+
+        /// the exact amount, at most `nep21_decimal` decimal places
+        pub fn from_decimal_str(coin: CoinType, data: &str) -> Result<Self, AmountError> {
+            let raw = parse_decimal(data, coin.nep21_decimal()?)?;
+            Ok(CoinAmount { coin, raw })
+        }
+
+        pub fn to_decimal_string(&self) -> Result<String, AmountError> {
+            let decimal = self.coin.nep21_decimal()?;
+            let data = format_decimal(self.raw, decimal, decimal)?;
+            if decimal == 0 {
+                return Ok(data);
+            }
+            Ok(data.trim_end_matches('0').trim_end_matches('.').to_string())
+        }
+
+        /// amount of the bridged erc20 token, which may have other decimals
+        pub fn to_erc20_raw(&self) -> Result<u128, AmountError> {
+            let near_decimal = self.coin.nep21_decimal()?;
+            let erc20_decimal = self
+                .coin
+                .erc20_decimal()?
+                .ok_or_else(|| AmountError::Invalid(format!("{} isn't on eth", self.coin)))?;
+            if erc20_decimal >= near_decimal {
+                self.raw
+                    .checked_mul(unit(erc20_decimal - near_decimal)?)
+                    .ok_or(AmountError::Overflow)
+            } else {
+                let unit = unit(near_decimal - erc20_decimal)?;
+                if self.raw % unit != 0 {
+                    Err(AmountError::PrecisionLoss(
+                        self.to_decimal_string()?,
+                        erc20_decimal,
+                    ))?;
+                }
+                Ok(self.raw / unit)
+            }
+        }
+
+        pub fn from_erc20_raw(coin: CoinType, erc20_raw: u128) -> Result<Self, AmountError> {
+            let near_decimal = coin.nep21_decimal()?;
+            let erc20_decimal = coin
+                .erc20_decimal()?
+                .ok_or_else(|| AmountError::Invalid(format!("{} isn't on eth", coin)))?;
+            let raw = if near_decimal >= erc20_decimal {
+                erc20_raw
+                    .checked_mul(unit(near_decimal - erc20_decimal)?)
+                    .ok_or(AmountError::Overflow)?
+            } else {
+                let unit = unit(erc20_decimal - near_decimal)?;
+                if erc20_raw % unit != 0 {
+                    Err(AmountError::PrecisionLoss(
+                        erc20_raw.to_string(),
+                        near_decimal,
+                    ))?;
+                }
+                erc20_raw / unit
+            };
+            Ok(CoinAmount { coin, raw })
+        }
+
+        fn check_coin(&self, other: &CoinAmount) -> Result<(), AmountError> {
+            if self.coin != other.coin {
+                Err(AmountError::CoinMismatch(
+                    self.coin.clone(),
+                    other.coin.clone(),
+                ))?;
+            }
+            Ok(())
+        }
+
+        pub fn checked_add(&self, other: &CoinAmount) -> Result<CoinAmount, AmountError> {
+            self.check_coin(other)?;
+            let raw = self
+                .raw
+                .checked_add(other.raw)
+                .ok_or(AmountError::Overflow)?;
+            Ok(CoinAmount::from_raw(self.coin.clone(), raw))
+        }
+
+        pub fn checked_sub(&self, other: &CoinAmount) -> Result<CoinAmount, AmountError> {
+            self.check_coin(other)?;
+            let raw = self
+                .raw
+                .checked_sub(other.raw)
+                .ok_or(AmountError::Underflow)?;
+            Ok(CoinAmount::from_raw(self.coin.clone(), raw))
+        }
+    }
+
+    impl fmt::Display for CoinAmount {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            //币被移出注册表之后只能显示最小单位的数量
+            match self.to_decimal_string() {
+                Ok(amount) => write!(f, "{} {}", amount, self.coin),
+                Err(_) => write!(f, "{} raw {}", self.raw, self.coin),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct CoinAmountRepr {
+        coin: CoinType,
+        amount: String,
+    }
+
+    //序列化为精确的十进制字符串，反序列化时不允许超出币的精度
+    impl Serialize for CoinAmount {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            CoinAmountRepr {
+                coin: self.coin.clone(),
+                amount: self.to_decimal_string().map_err(S::Error::custom)?,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CoinAmount {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let CoinAmountRepr { coin, amount } = CoinAmountRepr::deserialize(deserializer)?;
+            CoinAmount::from_decimal_str(coin, &amount).map_err(D::Error::custom)
+        }
+    }
 }
 
 //生成随机值的hex字符串
@@ -80,6 +321,33 @@ mod tests {
         assert_eq!(result, 4);
     }
 
+    #[test]
+    fn test_coin_amount_with_decimals() {
+        use crate::data_structures::CoinType;
+
+        let amount = CoinAmount::from_display(CoinType::USDT, "1.5").unwrap();
+        assert_eq!(amount.raw, 15 * BASE_DECIMAL / 10);
+        assert_eq!(amount.to_display().unwrap(), "1.50000000");
+        assert_eq!(amount.to_decimal_string().unwrap(), "1.5");
+        assert!(matches!(
+            CoinAmount::from_display(CoinType::USDT, "0.123456789"),
+            Err(AmountError::PrecisionLoss(_, 8))
+        ));
+        assert!(CoinAmount::from_display(CoinType::USDT, "1.2.3").is_err());
+
+        let dust = CoinAmount::from_raw(CoinType::USDT, 123);
+        let json = serde_json::to_string(&dust).unwrap();
+        assert_eq!(json, r#"{"coin":"USDT","amount":"0.000000000000000123"}"#);
+        assert_eq!(serde_json::from_str::<CoinAmount>(&json).unwrap(), dust);
+
+        assert_eq!(amount.checked_sub(&dust).unwrap().raw, amount.raw - 123);
+        assert_eq!(dust.checked_sub(&amount), Err(AmountError::Underflow));
+        assert!(matches!(
+            amount.checked_add(&CoinAmount::zero(CoinType::BTC)),
+            Err(AmountError::CoinMismatch(_, _))
+        ));
+    }
+
     #[test]
     fn test_coin_amount() {
         assert_eq!(raw2display(BASE_DECIMAL), "1.00000000".to_string());
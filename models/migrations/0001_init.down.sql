@@ -1,7 +1,7 @@
 drop table if exists users;
-drop table if exists device_info;
-drop table if exists secret_store;
 drop table if exists coin_transaction;
+drop table if exists secret_store;
+drop table if exists device_info;
+drop table if exists wallet_manage_record;
+drop table if exists ethereum_bridge_order;
 drop table if exists airdrop;
-drop table if exists bridge;
-
@@ -20,7 +20,7 @@ create table if not exists users
     -- 令牌版本
     token_version bigint not null,
     updated_at  timestamp with time zone default current_timestamp,
-    created_at  timestamp with time zone default current_timestamp,
+    created_at  timestamp with time zone default current_timestamp
 );
 
 
@@ -37,7 +37,7 @@ create index if not exists ix_users_phone
 --ALTER TABLE users ALTER COLUMN invite_code SET DEFAULT currval('users_id_seq');
 
 
-create table coin_transaction(
+create table if not exists coin_transaction(
      -- 订单id
      order_id  text primary key,
      -- 链上tx_id
@@ -71,11 +71,11 @@ create table coin_transaction(
      updated_at  timestamp with time zone default current_timestamp,
      created_at  timestamp with time zone default current_timestamp
 );
-create index coin_transaction_tx_id on coin_transaction (tx_id);
-create index coin_transaction_user on coin_transaction (sender,receiver);
+create index if not exists coin_transaction_tx_id on coin_transaction (tx_id);
+create index if not exists coin_transaction_user on coin_transaction (sender,receiver);
 
 --密钥备份
-create table secret_store
+create table if not exists secret_store
 (
     -- 主密钥的公钥
     pubkey text primary key,
@@ -92,7 +92,7 @@ create table secret_store
 );
 
 --储蓄账户的主pubkey和从pubkey，子钱包的key不存
-create table device_info
+create table if not exists device_info
 (
     -- 设备id
     id text,
@@ -115,7 +115,7 @@ create table device_info
 );
 
 
-create table wallet_manage_record
+create table if not exists wallet_manage_record
 (
     --记录id
     record_id text primary key,
@@ -137,7 +137,7 @@ create table wallet_manage_record
     created_at  timestamp with time zone default current_timestamp
 );
 
-create table ethereum_bridge_order
+create table if not exists ethereum_bridge_order
 (
     -- 订单id
     id text,
@@ -162,7 +162,7 @@ create table ethereum_bridge_order
 );
 
 
-create table airdrop
+create table if not exists airdrop
 (
     -- 用户id
     user_id bigserial primary key,
@@ -0,0 +1 @@
+alter table coin_transaction drop column if exists version;
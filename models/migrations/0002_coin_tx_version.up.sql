@@ -0,0 +1,2 @@
+-- 订单行版本号，每次更新加一，用于乐观锁
+alter table coin_transaction add column if not exists version bigint not null default 0;
@@ -0,0 +1,34 @@
+create table if not exists audit_event(
+     -- 事件id
+     event_id text primary key,
+     -- 登录前的请求为空
+     user_id bigint,
+     device_id text,
+     device_brand text,
+     -- 请求路径
+     endpoint text not null,
+     -- 脱敏之后的请求参数
+     params text not null,
+     -- 返回的错误码，0为成功
+     result_code integer not null,
+     trace_id text not null,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp
+);
+
+create index if not exists ix_audit_event_user_created
+    on audit_event using btree
+    (user_id asc nulls last, created_at desc)
+    tablespace pg_default;
+
+-- 审计记录只能追加，禁止修改和删除
+create or replace function audit_event_append_only() returns trigger as $$
+begin
+    raise exception 'audit_event is append-only';
+end;
+$$ language plpgsql;
+
+drop trigger if exists audit_event_append_only on audit_event;
+create trigger audit_event_append_only
+    before update or delete on audit_event
+    for each row execute function audit_event_append_only();
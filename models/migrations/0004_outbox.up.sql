@@ -0,0 +1,22 @@
+create table if not exists outbox(
+     -- 事件id
+     event_id text primary key,
+     -- 事件类型
+     event_type text not null,
+     -- 事件所属的业务数据id
+     aggregate_id text not null,
+     -- json格式的事件内容
+     payload text not null,
+     -- Pending,Published,Failed
+     status text not null,
+     -- 已投递次数
+     attempts integer not null default 0,
+     last_error text,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp
+);
+
+create index if not exists ix_outbox_status_created
+    on outbox using btree
+    (status asc, created_at asc)
+    tablespace pg_default;
@@ -0,0 +1,2 @@
+drop index if exists coin_transaction_updated_at;
+drop table if exists coin_transaction_archive;
@@ -0,0 +1,15 @@
+-- 已结束和已过期的转账订单归档表，列和coin_transaction保持一致，
+-- 以后给coin_transaction加列时这里也要同步加上，并加到coin_transfer.rs的TX_COLUMNS里
+create table if not exists coin_transaction_archive(
+     like coin_transaction including defaults including constraints
+);
+alter table coin_transaction_archive add primary key (order_id);
+-- 归档时间
+alter table coin_transaction_archive add column if not exists
+     archived_at timestamp with time zone default current_timestamp;
+
+create index if not exists coin_transaction_archive_user
+    on coin_transaction_archive (sender,receiver);
+-- 归档任务按updated_at挑选旧订单
+create index if not exists coin_transaction_updated_at
+    on coin_transaction (updated_at);
@@ -0,0 +1,2 @@
+drop index if exists ethereum_bridge_order_status_height;
+alter table ethereum_bridge_order drop column if exists block_hash;
@@ -0,0 +1,6 @@
+-- 订单所在eth区块的hash，扫块时用来发现分叉
+alter table ethereum_bridge_order add column if not exists block_hash text not null default '';
+
+-- 确认和回滚都按状态和高度挑选订单
+create index if not exists ethereum_bridge_order_status_height
+    on ethereum_bridge_order (status, height);
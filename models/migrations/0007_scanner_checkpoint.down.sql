@@ -0,0 +1 @@
+drop table if exists scanner_checkpoint;
@@ -0,0 +1,16 @@
+create table if not exists scanner_checkpoint(
+     -- 扫描任务名，如eth_bridge
+     task text primary key,
+     -- 已处理的最高区块
+     height bigint not null,
+     -- 该区块的hash，为空时以链上的为准
+     block_hash text not null default '',
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp
+);
+
+-- 已有的eth_bridge扫描进度从最后的跨链订单继承
+insert into scanner_checkpoint (task,height,block_hash)
+    select 'eth_bridge',height,block_hash from ethereum_bridge_order
+    order by height desc limit 1
+    on conflict (task) do nothing;
@@ -0,0 +1,7 @@
+alter table coin_transaction_archive drop column if exists confirmed_sig;
+alter table coin_transaction_archive drop column if exists failure_reason;
+alter table coin_transaction_archive drop column if exists retry_times;
+
+alter table coin_transaction drop column if exists confirmed_sig;
+alter table coin_transaction drop column if exists failure_reason;
+alter table coin_transaction drop column if exists retry_times;
@@ -0,0 +1,11 @@
+-- 上链失败后重新提交的次数
+alter table coin_transaction add column if not exists retry_times integer not null default 0;
+-- 最后一次上链失败的原因
+alter table coin_transaction add column if not exists failure_reason text;
+-- 主设备的二次确认签名(子账户转出时为子账户的签名)，重新提交时使用
+alter table coin_transaction add column if not exists confirmed_sig text;
+
+-- 归档表和主表的列保持一致，读写时按列名对应
+alter table coin_transaction_archive add column if not exists retry_times integer not null default 0;
+alter table coin_transaction_archive add column if not exists failure_reason text;
+alter table coin_transaction_archive add column if not exists confirmed_sig text;
@@ -0,0 +1,20 @@
+create table if not exists fee_refund(
+     -- 被扣手续费的链上交易，每笔交易最多退一次
+     tx_id text primary key,
+     -- 对应的转账订单
+     order_id text not null,
+     -- 退款的接收方，用户的主账户
+     account_id text not null,
+     -- 手续费币种，没扣手续费时为空
+     fee_coin text,
+     amount text not null,
+     -- Pending,Refunding,Refunded,Failed,NoFee
+     status text not null,
+     refund_tx_id text,
+     last_error text,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp
+);
+
+create index if not exists fee_refund_status
+    on fee_refund (status, created_at);
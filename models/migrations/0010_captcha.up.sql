@@ -0,0 +1,21 @@
+create table if not exists captcha(
+     -- 邮箱或者手机号，已登陆时是user_id
+     owner text not null,
+     -- 验证码用途，如Register、Login
+     usage text not null,
+     device_id text not null,
+     -- sha256(owner:usage:code)，用过之后置空
+     code_hash text not null,
+     -- 当前验证码输错的次数
+     attempts integer not null default 0,
+     -- 记录保留期内累计发送的次数
+     sent_times integer not null default 0,
+     last_sent_at bigint not null,
+     expire_at bigint not null,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp,
+     primary key (owner, usage)
+);
+
+create index if not exists captcha_expire_at
+    on captcha (expire_at);
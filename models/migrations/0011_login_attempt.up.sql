@@ -0,0 +1,16 @@
+create table if not exists login_attempt(
+     attempt_id text primary key,
+     user_id bigint not null,
+     -- 管理员解锁时为空
+     device_id text not null,
+     ip text not null,
+     -- Failed,Succeeded,Unlocked
+     result text not null,
+     -- 毫秒时间戳
+     attempted_at bigint not null,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp
+);
+
+create index if not exists login_attempt_user_attempted_at
+    on login_attempt (user_id, attempted_at desc);
@@ -0,0 +1,18 @@
+create table if not exists user_session(
+     -- 每次登陆都会换新，access token里带着它
+     session_id text primary key,
+     user_id bigint not null,
+     device_id text not null,
+     device_brand text not null,
+     -- sha256(refresh_token)，每次刷新都会换新
+     refresh_token_hash text not null,
+     refresh_expire_at bigint not null,
+     -- 最近一次登陆或刷新的时间
+     last_active_at bigint not null,
+     updated_at  timestamp with time zone default current_timestamp,
+     created_at  timestamp with time zone default current_timestamp,
+     unique (user_id, device_id)
+);
+
+create unique index if not exists user_session_refresh_token_hash
+    on user_session (refresh_token_hash);
@@ -0,0 +1,2 @@
+-- 投递失败之后退避，到这个时间(毫秒)之前不再投递
+alter table outbox add column if not exists next_attempt_at bigint not null default 0;
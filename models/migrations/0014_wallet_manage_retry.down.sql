@@ -0,0 +1 @@
+alter table wallet_manage_record drop column if exists retry_times;
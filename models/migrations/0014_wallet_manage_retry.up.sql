@@ -0,0 +1,2 @@
+-- 上链失败后由relayer重新提交的次数，新的txid追加到tx_ids末尾
+alter table wallet_manage_record add column if not exists retry_times integer not null default 0;
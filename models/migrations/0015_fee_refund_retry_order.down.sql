@@ -0,0 +1,3 @@
+drop index if exists fee_refund_status;
+create index if not exists fee_refund_status
+    on fee_refund (status, created_at);
@@ -0,0 +1,4 @@
+-- 待核对的记录按上次尝试的时间轮流重试
+drop index if exists fee_refund_status;
+create index if not exists fee_refund_status
+    on fee_refund (status, updated_at);
@@ -4,15 +4,16 @@ use async_trait::async_trait;
 
 use tokio_postgres::Row;
 //use r2d2_postgres::postgres::{Row, Transaction};
-use std::fmt;
 
 //#[derive(Serialize)]
 use common::data_structures::account_manager::UserInfo;
 
-use crate::{PgLocalCli, PsqlOp, PsqlType};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
 use anyhow::Result;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct UserInfoEntity {
     pub user_info: UserInfo,
     pub updated_at: String,
@@ -51,52 +52,135 @@ pub enum UserUpdater<'a> {
     SubCreateRecords(Vec<u64>),
 }
 
-impl fmt::Display for UserUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for UserUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             UserUpdater::LoginPwdHash(pwd, version) => {
-                format!("login_pwd_hash='{}',token_version={}", pwd, version)
+                sql.push("login_pwd_hash=")
+                    .bind(pwd.to_string())
+                    .push(",token_version=")
+                    .bind(*version as i64);
             }
             UserUpdater::AccountIds(ids) => {
-                let new_servant_str: PsqlType = ids.to_owned().into();
-                format!("account_ids={} ", new_servant_str.to_psql_str())
+                sql.push("account_ids=").bind(ids.to_owned());
             }
             UserUpdater::SubCreateRecords(times) => {
-                let times: PsqlType = times
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .into();
-                format!("create_subacc_time={} ", times.to_psql_str())
+                let times = times.iter().map(|x| *x as i64).collect::<Vec<i64>>();
+                sql.push("create_subacc_time=").bind(times);
             }
-            UserUpdater::SecruityInfo(anwser_indexes, main_account) => format!(
-                "anwser_indexes='{}',main_account='{}'",
-                anwser_indexes, main_account
-            ),
-            UserUpdater::OpStatus(status) => format!("op_status='{}'", status),
-            UserUpdater::AnwserIndexes(anwser) => format!("anwser_indexes='{}' ", anwser),
-            UserUpdater::Email(email) => format!("email='{}'", email),
-            UserUpdater::PhoneNumber(number) => format!("phone_number='{}'", number),
-            UserUpdater::TokenVersion(version) => format!("token_version={}", version),
-        };
-        write!(f, "{}", description)
+            UserUpdater::SecruityInfo(anwser_indexes, main_account) => {
+                sql.push("anwser_indexes=")
+                    .bind(anwser_indexes.to_string())
+                    .push(",main_account=")
+                    .bind(main_account.to_string());
+            }
+            UserUpdater::OpStatus(status) => {
+                sql.push("op_status=").bind(status.to_string());
+            }
+            UserUpdater::AnwserIndexes(anwser) => {
+                sql.push("anwser_indexes=").bind(anwser.to_string());
+            }
+            UserUpdater::Email(email) => {
+                sql.push("email=").bind(email.to_string());
+            }
+            UserUpdater::PhoneNumber(number) => {
+                sql.push("phone_number=").bind(number.to_string());
+            }
+            UserUpdater::TokenVersion(version) => {
+                sql.push("token_version=").bind(*version as i64);
+            }
+        }
     }
 }
 
-impl fmt::Display for UserFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            UserFilter::ById(id) => format!("id={}", id),
-            UserFilter::ByPhone(number) => format!("phone_number='{}'", number),
-            UserFilter::ByEmail(email) => format!("email='{}'", email),
-            UserFilter::ByInviteCode(code) => format!("invite_code='{}'", code),
+impl SqlClause for UserFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            UserFilter::ById(id) => {
+                sql.push("id=").bind(**id as i64);
+            }
+            UserFilter::ByPhone(number) => {
+                sql.push("phone_number=").bind(number.to_string());
+            }
+            UserFilter::ByEmail(email) => {
+                sql.push("email=").bind(email.to_string());
+            }
+            UserFilter::ByInviteCode(code) => {
+                sql.push("invite_code=").bind(code.to_string());
+            }
             UserFilter::ByPhoneOrEmail(contact) => {
-                format!("email='{}' or phone_number='{}'", contact, contact)
+                sql.push("email=")
+                    .bind(contact.to_string())
+                    .push(" or phone_number=")
+                    .bind(contact.to_string());
             }
-            UserFilter::ByAccountId(id) => format!("'{}'=any(account_ids) ", id),
-            UserFilter::ByMainAccount(id) => format!("main_account='{}' ", id),
-        };
-        write!(f, "{}", description)
+            UserFilter::ByAccountId(id) => {
+                sql.bind(id.to_string()).push("=any(account_ids)");
+            }
+            UserFilter::ByMainAccount(id) => {
+                sql.push("main_account=").bind(id.to_string());
+            }
+        }
+    }
+}
+
+impl MemoryRecord for UserInfoEntity {
+    const TABLE: &'static str = "users";
+
+    fn matches(&self, filter: &UserFilter<'_>) -> bool {
+        let user = &self.user_info;
+        match filter {
+            UserFilter::ById(id) => user.id == **id,
+            UserFilter::ByPhone(number) => user.phone_number.as_deref() == Some(*number),
+            UserFilter::ByEmail(email) => user.email.as_deref() == Some(*email),
+            UserFilter::ByPhoneOrEmail(contact) => {
+                user.email.as_deref() == Some(*contact)
+                    || user.phone_number.as_deref() == Some(*contact)
+            }
+            //users表里没有这两列
+            UserFilter::ByInviteCode(_) | UserFilter::ByAccountId(_) => false,
+            UserFilter::ByMainAccount(id) => user.main_account.as_deref() == Some(*id),
+        }
+    }
+
+    fn apply(&mut self, updater: &UserUpdater<'_>) {
+        let user = &mut self.user_info;
+        match updater {
+            UserUpdater::LoginPwdHash(pwd, version) => {
+                user.login_pwd_hash = pwd.to_string();
+                user.token_version = *version;
+            }
+            //users表里没有这两列
+            UserUpdater::AccountIds(_) | UserUpdater::OpStatus(_) => {}
+            UserUpdater::SubCreateRecords(times) => user.create_subacc_time = times.clone(),
+            UserUpdater::SecruityInfo(anwser_indexes, main_account) => {
+                user.anwser_indexes = anwser_indexes.to_string();
+                user.main_account = Some(main_account.to_string());
+            }
+            UserUpdater::AnwserIndexes(anwser) => user.anwser_indexes = anwser.to_string(),
+            UserUpdater::Email(email) => user.email = Some(email.to_string()),
+            UserUpdater::PhoneNumber(number) => user.phone_number = Some(number.to_string()),
+            UserUpdater::TokenVersion(version) => user.token_version = *version,
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        let user = &self.user_info;
+        let mut keys = vec![format!("id:{}", user.id)];
+        if let Some(number) = &user.phone_number {
+            keys.push(format!("phone_number:{}", number));
+        }
+        if let Some(email) = &user.email {
+            keys.push(format!("email:{}", email));
+        }
+        if let Some(account) = &user.main_account {
+            keys.push(format!("main_account:{}", account));
+        }
+        keys
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
     }
 }
 
@@ -126,8 +210,11 @@ impl UserInfoEntity {
 impl PsqlOp for UserInfoEntity {
     type UpdaterContent<'a> = UserUpdater<'a>;
     type FilterContent<'b> = UserFilter<'b>;
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<Self>> {
-        let sql = format!(
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
             "select id,\
             phone_number,\
             email,\
@@ -140,11 +227,10 @@ impl PsqlOp for UserInfoEntity {
             token_version,\
             cast(updated_at as text),\
             cast(created_at as text) \
-            from users where {}",
-            filter
+            from users where ",
         );
-        //let query_res = PgLocalCli2::query(&sql).await?;
-        let query_res = PgLocalCli::query(&sql).await?;
+        sql.clause(&filter);
+        let query_res = sql.query().await?;
         //debug!("get_snapshot: raw sql {}", sql);
 
         let gen_view = |row: &Row| -> Result<UserInfoEntity> {
@@ -158,9 +244,10 @@ impl PsqlOp for UserInfoEntity {
                     is_frozen: row.get::<usize, bool>(5),
                     kyc_is_verified: row.get(6),
                     create_subacc_time: row
-                        .get::<usize, Vec<String>>(7)
+                        .get::<usize, Option<Vec<i64>>>(7)
+                        .unwrap_or_default()
                         .into_iter()
-                        .map(|t| t.parse::<u64>().unwrap())
+                        .map(|t| t as u64)
                         .collect::<Vec<u64>>(),
                     main_account: row.get(8),
                     token_version: row.get::<usize, i64>(9) as u32,
@@ -170,25 +257,31 @@ impl PsqlOp for UserInfoEntity {
             };
             Ok(view)
         };
-        query_res.iter().map(gen_view).collect()
+        Ok(query_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
 
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "UPDATE users SET {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        debug!("start update users {} ", sql);
-        let execute_res = PgLocalCli::execute(&sql).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("UPDATE users SET ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update users {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update users {} rows", execute_res);
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let UserInfo {
             id,
             phone_number,
@@ -202,13 +295,12 @@ impl PsqlOp for UserInfoEntity {
             token_version,
         } = self.into_inner();
 
-        //assembly string array to sql string
-        let create_subacc_time: PsqlType = create_subacc_time.into();
-        let main_account: PsqlType = main_account.into();
-        let phone_number: PsqlType = phone_number.into();
-        let email: PsqlType = email.into();
+        let create_subacc_time = create_subacc_time
+            .into_iter()
+            .map(|t| t as i64)
+            .collect::<Vec<i64>>();
 
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into users (\
                 id,\
                 phone_number,\
@@ -220,20 +312,23 @@ impl PsqlOp for UserInfoEntity {
                 create_subacc_time,\
                 main_account,\
                 token_version\
-            ) values ({},{},{},'{}','{}',{},{},{},{},{})",
-            id,
-            phone_number.to_psql_str(),
-            email.to_psql_str(),
-            login_pwd_hash,
-            anwser_indexes,
-            is_frozen,
-            kyc_is_verified,
-            create_subacc_time.to_psql_str(),
-            main_account.to_psql_str(),
-            token_version
+            ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let execute_res = PgLocalCli::execute(&sql).await?;
+        sql.bind_all(vec![
+            Box::new(id as i64),
+            Box::new(phone_number),
+            Box::new(email),
+            Box::new(login_pwd_hash),
+            Box::new(anwser_indexes),
+            Box::new(is_frozen),
+            Box::new(kyc_is_verified),
+            Box::new(create_subacc_time),
+            Box::new(main_account),
+            Box::new(token_version as i64),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let execute_res = sql.execute().await?;
         debug!("success insert {} rows", execute_res);
         Ok(())
     }
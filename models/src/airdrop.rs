@@ -5,14 +5,14 @@ use common::data_structures::airdrop::{Airdrop, BtcGradeStatus};
 
 use serde::{Deserialize, Serialize};
 
-use std::fmt;
-
 use tokio_postgres::Row;
 
-use crate::{PgLocalCli, PsqlOp, PsqlType};
-use anyhow::{Ok, Result};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct AirdropEntity {
     pub airdrop: Airdrop,
     pub updated_at: String,
@@ -40,52 +40,62 @@ pub enum AirdropUpdater<'a> {
     ResetBind,
 }
 
-impl fmt::Display for AirdropUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for AirdropUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             AirdropUpdater::ResetBind => {
-                format!("ref_btc_address=NULL,btc_address=NULL,btc_level=0,btc_grade_status='NotBind' ")
+                sql.push("ref_btc_address=NULL,btc_address=NULL,btc_level=0,btc_grade_status=")
+                    .bind(BtcGradeStatus::NotBind.to_string());
             }
-            AirdropUpdater::BtcAddrLevelStatus(addr,level,status) => {
-                let addr: PsqlType = addr.to_owned().into();
-                format!("btc_address={},btc_level='{}',btc_grade_status='{}' ", addr.to_psql_str(),level,status.to_string())
+            AirdropUpdater::BtcAddrLevelStatus(addr, level, status) => {
+                sql.push("btc_address=")
+                    .bind(addr.to_owned())
+                    .push(",btc_level=")
+                    .bind(*level as i16)
+                    .push(",btc_grade_status=")
+                    .bind(status.to_string());
             }
             AirdropUpdater::InviteCode(code) => {
-                format!("invite_code='{}'", code)
+                sql.push("invite_code=").bind(code.to_string());
             }
             AirdropUpdater::BtcAddress(addr) => {
-                format!("btc_address='{}'", addr)
+                sql.push("btc_address=").bind(addr.to_string());
             }
             AirdropUpdater::BtcAddressAndLevel(addr, level) => {
-                let have_grade = level.is_none();
-                let level: PsqlType = level.to_owned().into();
-                if have_grade {
-                    format!("btc_address='{}',btc_grade_status='PendingCalculate',btc_level={} ", addr, level.to_psql_str())
-                }else{
-                    format!("btc_address='{}',btc_grade_status='Calculated',btc_level={} ", addr, level.to_psql_str())
-                }
-                
+                let status = if level.is_none() {
+                    BtcGradeStatus::PendingCalculate
+                } else {
+                    BtcGradeStatus::Calculated
+                };
+                sql.push("btc_address=")
+                    .bind(addr.to_string())
+                    .push(",btc_grade_status=")
+                    .bind(status.to_string())
+                    .push(",btc_level=")
+                    .bind(level.map(|x| x as i16));
             }
-            AirdropUpdater::LevelStatus(level,status) => {
-                format!("btc_level='{}',btc_grade_status='{}' ", level,status.to_string())
+            AirdropUpdater::LevelStatus(level, status) => {
+                sql.push("btc_level=")
+                    .bind(*level as i16)
+                    .push(",btc_grade_status=")
+                    .bind(status.to_string());
             }
             AirdropUpdater::AccountId(id) => {
-                format!("account_id='{}'", id)
+                sql.push("account_id=").bind(id.to_string());
             }
             AirdropUpdater::Predecessor(user_id, account_id) => {
-                format!(
-                    "predecessor_user_id={},predecessor_account_id='{}'",
-                    user_id, account_id
-                )
+                sql.push("predecessor_user_id=")
+                    .bind(**user_id as i64)
+                    .push(",predecessor_account_id=")
+                    .bind(account_id.to_string());
             }
             AirdropUpdater::BtcLevel(level) => {
-                format!("btc_level={}", level)
+                sql.push("btc_level=").bind(*level as i16);
             }
             AirdropUpdater::GradeStatus(status) => {
-                format!("btc_grade_status='{}'", status.to_string())
+                sql.push("btc_grade_status=").bind(status.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -97,15 +107,22 @@ pub enum AirdropFilter<'b> {
     ByUserId(&'b u32),
 }
 
-impl fmt::Display for AirdropFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            AirdropFilter::ByInviteCode(code) => format!("invite_code='{}' ", code),
-            AirdropFilter::ByAccountId(id) => format!("account_id='{}' ", id),
-            AirdropFilter::ByUserId(id) => format!("user_id={} ", id),
-            AirdropFilter::ByBtcAddress(addr) => format!("btc_address='{}' ", addr),
-        };
-        write!(f, "{}", description)
+impl SqlClause for AirdropFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            AirdropFilter::ByInviteCode(code) => {
+                sql.push("invite_code=").bind(code.to_string());
+            }
+            AirdropFilter::ByAccountId(id) => {
+                sql.push("account_id=").bind(id.to_string());
+            }
+            AirdropFilter::ByUserId(id) => {
+                sql.push("user_id=").bind(**id as i64);
+            }
+            AirdropFilter::ByBtcAddress(addr) => {
+                sql.push("btc_address=").bind(addr.to_string());
+            }
+        }
     }
 }
 
@@ -132,13 +149,84 @@ impl AirdropEntity {
         }
     }
 }
+impl MemoryRecord for AirdropEntity {
+    const TABLE: &'static str = "airdrop";
+
+    fn matches(&self, filter: &AirdropFilter<'_>) -> bool {
+        let airdrop = &self.airdrop;
+        match filter {
+            AirdropFilter::ByInviteCode(code) => airdrop.invite_code == *code,
+            AirdropFilter::ByAccountId(id) => airdrop.account_id.as_deref() == Some(*id),
+            AirdropFilter::ByUserId(id) => airdrop.user_id == **id,
+            AirdropFilter::ByBtcAddress(addr) => airdrop.btc_address.as_deref() == Some(*addr),
+        }
+    }
+
+    fn apply(&mut self, updater: &AirdropUpdater<'_>) {
+        let airdrop = &mut self.airdrop;
+        match updater {
+            AirdropUpdater::ResetBind => {
+                airdrop.ref_btc_address = None;
+                airdrop.btc_address = None;
+                airdrop.btc_level = Some(0);
+                airdrop.btc_grade_status = BtcGradeStatus::NotBind;
+            }
+            AirdropUpdater::BtcAddrLevelStatus(addr, level, status) => {
+                airdrop.btc_address = addr.clone();
+                airdrop.btc_level = Some(*level);
+                airdrop.btc_grade_status = status.clone();
+            }
+            AirdropUpdater::InviteCode(code) => airdrop.invite_code = code.to_string(),
+            AirdropUpdater::BtcAddress(addr) => airdrop.btc_address = Some(addr.to_string()),
+            AirdropUpdater::BtcAddressAndLevel(addr, level) => {
+                airdrop.btc_address = Some(addr.to_string());
+                airdrop.btc_grade_status = if level.is_none() {
+                    BtcGradeStatus::PendingCalculate
+                } else {
+                    BtcGradeStatus::Calculated
+                };
+                airdrop.btc_level = *level;
+            }
+            AirdropUpdater::LevelStatus(level, status) => {
+                airdrop.btc_level = Some(*level);
+                airdrop.btc_grade_status = status.clone();
+            }
+            AirdropUpdater::AccountId(id) => airdrop.account_id = Some(id.to_string()),
+            AirdropUpdater::Predecessor(user_id, account_id) => {
+                airdrop.predecessor_user_id = **user_id;
+                airdrop.predecessor_account_id = account_id.to_string();
+            }
+            AirdropUpdater::BtcLevel(level) => airdrop.btc_level = Some(*level),
+            AirdropUpdater::GradeStatus(status) => airdrop.btc_grade_status = status.clone(),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        let mut keys = vec![
+            format!("user_id:{}", self.airdrop.user_id),
+            format!("invite_code:{}", self.airdrop.invite_code),
+        ];
+        if let Some(account_id) = &self.airdrop.account_id {
+            keys.push(format!("account_id:{}", account_id));
+        }
+        keys
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+}
+
 #[async_trait]
 impl PsqlOp for AirdropEntity {
     type UpdaterContent<'a> = AirdropUpdater<'a>;
     type FilterContent<'b> = AirdropFilter<'b>;
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<AirdropEntity>> {
-        let sql = format!(
-            "select 
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<AirdropEntity>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
             user_id,\
             account_id,\
             invite_code,\
@@ -150,11 +238,11 @@ impl PsqlOp for AirdropEntity {
             ref_btc_address,\
          cast(updated_at as text), \
          cast(created_at as text) \
-         from airdrop where {}",
-            filter
+         from airdrop where ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get_airdrop: raw sql {}", sql);
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get_airdrop: raw sql {}", sql.sql());
         let gen_view = |row: &Row| -> Result<AirdropEntity>{
             Ok(AirdropEntity {
                 airdrop: Airdrop {
@@ -173,24 +261,30 @@ impl PsqlOp for AirdropEntity {
             })
         };
 
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "update airdrop set {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        debug!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update airdrop set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update orders {} rows", execute_res);
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let Airdrop {
             user_id,
             account_id,
@@ -202,13 +296,7 @@ impl PsqlOp for AirdropEntity {
             btc_grade_status,
             ref_btc_address
         } = self.into_inner();
-        let account_id: PsqlType = account_id.into();
-        let btc_address: PsqlType = btc_address.into();
-        let btc_level: PsqlType = btc_level.into();
-        let ref_btc_address: PsqlType = ref_btc_address.into();
-
-
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into airdrop (\
                 user_id,\
                 account_id,\
@@ -218,24 +306,27 @@ impl PsqlOp for AirdropEntity {
                 btc_address,\
                 btc_level,\
                 btc_grade_status,\
-                ref_btc_address
-         ) values ('{}',{},'{}',{},'{}',{},{},'{}',{});",
-            user_id,
-            account_id.to_psql_str(),
-            invite_code,
-            predecessor_user_id,
-            predecessor_account_id,
-            btc_address.to_psql_str(),
-            btc_level.to_psql_str(),
-            btc_grade_status.to_string(),
-            ref_btc_address.to_psql_str()
+                ref_btc_address\
+         ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let _execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        sql.bind_all(vec![
+            Box::new(user_id as i64),
+            Box::new(account_id),
+            Box::new(invite_code),
+            Box::new(predecessor_user_id as i64),
+            Box::new(predecessor_account_id),
+            Box::new(btc_address),
+            Box::new(btc_level.map(|x| x as i16)),
+            Box::new(btc_grade_status.to_string()),
+            Box::new(ref_btc_address),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
         Ok(())
     }
 
-    async fn delete(_filter: Self::FilterContent<'_>) -> Result<()> {
+    async fn delete(_filter: Self::FilterContent<'_>) -> DbResult<()> {
         todo!()
     }
 }
@@ -0,0 +1,313 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::data_structures::audit_event::AuditEvent;
+use common::utils::math::generate_random_hex_string;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::{timestamp_millis, MemoryRecord};
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct AuditEventEntity {
+    pub event: AuditEvent,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl AuditEventEntity {
+    pub fn into_inner(self) -> AuditEvent {
+        self.event
+    }
+}
+
+impl AuditEventEntity {
+    pub fn new_with_specified(
+        user_id: Option<u32>,
+        device: Option<(String, String)>,
+        endpoint: &str,
+        params: &str,
+        result_code: u16,
+        trace_id: &str,
+    ) -> Self {
+        let (device_id, device_brand) = device.unzip();
+        AuditEventEntity {
+            event: AuditEvent {
+                event_id: generate_random_hex_string(32),
+                user_id,
+                device_id,
+                device_brand,
+                endpoint: endpoint.to_string(),
+                params: params.to_string(),
+                result_code,
+                trace_id: trace_id.to_string(),
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+//审计记录只能追加，没有任何更新方式
+#[derive(Debug)]
+pub enum AuditEventUpdater {}
+
+impl SqlClause for AuditEventUpdater {
+    fn write_sql(&self, _sql: &mut SqlBuilder) {
+        match *self {}
+    }
+}
+
+/// conditions of the support query, rows are ordered by `created_at desc`
+#[derive(Clone, Debug, Default)]
+pub struct AuditEventQuery {
+    pub user_id: Option<u32>,
+    pub device_id: Option<String>,
+    pub endpoint: Option<String>,
+    pub trace_id: Option<String>,
+    //毫秒时间戳
+    pub start_at: Option<u64>,
+    pub end_at: Option<u64>,
+    pub limit: u32,
+}
+
+impl AuditEventQuery {
+    fn matches(&self, event: &AuditEventEntity) -> bool {
+        let created_at = timestamp_millis(&event.created_at).unwrap_or_default();
+        let info = &event.event;
+        self.user_id.map_or(true, |id| info.user_id == Some(id))
+            && self
+                .device_id
+                .as_ref()
+                .map_or(true, |id| info.device_id.as_ref() == Some(id))
+            && self.endpoint.as_ref().map_or(true, |e| info.endpoint == *e)
+            && self.trace_id.as_ref().map_or(true, |t| info.trace_id == *t)
+            && self.start_at.map_or(true, |t| created_at >= t as i64)
+            && self.end_at.map_or(true, |t| created_at <= t as i64)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AuditEventFilter<'b> {
+    ByEventId(&'b str),
+    ByQuery(&'b AuditEventQuery),
+}
+
+impl SqlClause for AuditEventFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            AuditEventFilter::ByEventId(event_id) => {
+                sql.push("event_id=").bind(event_id.to_string());
+            }
+            AuditEventFilter::ByQuery(query) => {
+                sql.push("true");
+                if let Some(user_id) = query.user_id {
+                    sql.push(" and user_id=").bind(user_id as i64);
+                }
+                if let Some(device_id) = &query.device_id {
+                    sql.push(" and device_id=").bind(device_id.clone());
+                }
+                if let Some(endpoint) = &query.endpoint {
+                    sql.push(" and endpoint=").bind(endpoint.clone());
+                }
+                if let Some(trace_id) = &query.trace_id {
+                    sql.push(" and trace_id=").bind(trace_id.clone());
+                }
+                if let Some(start_at) = query.start_at {
+                    sql.push(" and created_at>=to_timestamp(")
+                        .bind(start_at as f64 / 1000.0)
+                        .push(")");
+                }
+                if let Some(end_at) = query.end_at {
+                    sql.push(" and created_at<=to_timestamp(")
+                        .bind(end_at as f64 / 1000.0)
+                        .push(")");
+                }
+                sql.push(" order by created_at desc limit ")
+                    .bind(query.limit as i64);
+            }
+        }
+    }
+}
+
+impl MemoryRecord for AuditEventEntity {
+    const TABLE: &'static str = "audit_event";
+
+    fn matches(&self, filter: &AuditEventFilter<'_>) -> bool {
+        match filter {
+            AuditEventFilter::ByEventId(event_id) => self.event.event_id == *event_id,
+            AuditEventFilter::ByQuery(query) => query.matches(self),
+        }
+    }
+
+    fn apply(&mut self, updater: &AuditEventUpdater) {
+        match *updater {}
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("event_id:{}", self.event.event_id)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &AuditEventFilter<'_>) -> Vec<Self> {
+        if let AuditEventFilter::ByQuery(query) = filter {
+            rows.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            rows.truncate(query.limit as usize);
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for AuditEventEntity {
+    type UpdaterContent<'a> = AuditEventUpdater;
+    type FilterContent<'b> = AuditEventFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            event_id,\
+            user_id,\
+            device_id,\
+            device_brand,\
+            endpoint,\
+            params,\
+            result_code,\
+            trace_id,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from audit_event where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get audit_event: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<AuditEventEntity> {
+            Ok(AuditEventEntity {
+                event: AuditEvent {
+                    event_id: row.get(0),
+                    user_id: row.get::<usize, Option<i64>>(1).map(|id| id as u32),
+                    device_id: row.get(2),
+                    device_brand: row.get(3),
+                    endpoint: row.get(4),
+                    params: row.get(5),
+                    result_code: row.get::<usize, i32>(6) as u16,
+                    trace_id: row.get(7),
+                },
+                updated_at: row.get(8),
+                created_at: row.get(9),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        _filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        match new_value {}
+    }
+
+    async fn delete(_filter: Self::FilterContent<'_>) -> DbResult<()> {
+        Err(DbError::Internal(anyhow!("audit_event is append-only")))
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let AuditEvent {
+            event_id,
+            user_id,
+            device_id,
+            device_brand,
+            endpoint,
+            params,
+            result_code,
+            trace_id,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into audit_event (\
+                event_id,\
+                user_id,\
+                device_id,\
+                device_brand,\
+                endpoint,\
+                params,\
+                result_code,\
+                trace_id\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(event_id),
+            Box::new(user_id.map(|id| id as i64)),
+            Box::new(device_id),
+            Box::new(device_brand),
+            Box::new(endpoint),
+            Box::new(params),
+            Box::new(result_code as i32),
+            Box::new(trace_id),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_audit_event_query() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            for (user_id, endpoint) in [(1, "/wallet/preSendMoney"), (2, "/wallet/updateStrategy")]
+            {
+                AuditEventEntity::new_with_specified(
+                    Some(user_id),
+                    Some(("device1".to_string(), "apple".to_string())),
+                    endpoint,
+                    "{}",
+                    0,
+                    "trace",
+                )
+                .insert()
+                .await
+                .unwrap();
+            }
+            let query = AuditEventQuery {
+                user_id: Some(2),
+                limit: 10,
+                ..Default::default()
+            };
+            let events = AuditEventEntity::find(AuditEventFilter::ByQuery(&query))
+                .await
+                .unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].event.endpoint, "/wallet/updateStrategy");
+            let query = AuditEventQuery {
+                limit: 1,
+                ..Default::default()
+            };
+            let events = AuditEventEntity::find(AuditEventFilter::ByQuery(&query))
+                .await
+                .unwrap();
+            assert_eq!(events.len(), 1);
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
@@ -0,0 +1,62 @@
+//! inspect the password login attempts of a user and unlock the account early, e.g.
+//! `CONFIG=config.toml login_lock attempts --user-id 1`, `login_lock unlock --user-id 1`
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use common::data_structures::login_attempt::LoginAttemptResult;
+use common::utils::time::now_millis;
+use models::general::try_run_api_call;
+use models::login_attempt::{self, LoginAttemptEntity, LoginAttemptFilter};
+use models::PsqlOp;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// show the latest login attempts of the user
+    Attempts {
+        #[arg(short, long)]
+        user_id: u32,
+        #[arg(short, long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// clear the wrong passwords counted so far, the lock is lifted at once
+    Unlock {
+        #[arg(short, long)]
+        user_id: u32,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    common::log::init_logger();
+    let args = Args::parse();
+    let command = async move {
+        match args.command {
+            Command::Attempts { user_id, limit } => {
+                for attempt in
+                    LoginAttemptEntity::find(LoginAttemptFilter::Latest(user_id, limit)).await?
+                {
+                    let attempt = attempt.into_inner();
+                    println!(
+                        "{:<14} {:<10} {:<40} {}",
+                        attempt.attempted_at, attempt.result, attempt.ip, attempt.device_id
+                    );
+                }
+            }
+            Command::Unlock { user_id } => {
+                login_attempt::record(user_id, "", "", LoginAttemptResult::Unlocked, now_millis())
+                    .await?;
+                println!("unlocked user {}", user_id);
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    try_run_api_call("", command).await??;
+    Ok(())
+}
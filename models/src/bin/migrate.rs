@@ -0,0 +1,56 @@
+//! schema migration entry point, e.g.
+//! `CONFIG=config.toml migrate up`, `migrate down --target 0`, `migrate status`
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use models::migration;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// apply pending migrations, up to the latest version by default
+    Up {
+        #[arg(short, long)]
+        target: Option<i64>,
+    },
+    /// revert applied migrations newer than target
+    Down {
+        #[arg(short, long)]
+        target: i64,
+    },
+    /// show all migrations and whether they are applied
+    Status,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    common::log::init_logger();
+    let args = Args::parse();
+    match args.command {
+        Command::Up { target } => {
+            let versions = migration::up(target).await?;
+            println!("applied migrations {:?}", versions);
+        }
+        Command::Down { target } => {
+            let versions = migration::down(target).await?;
+            println!("reverted migrations {:?}", versions);
+        }
+        Command::Status => {
+            for status in migration::status().await? {
+                println!(
+                    "{:04} {:<32} {}",
+                    status.version,
+                    status.name,
+                    status.applied_at.unwrap_or("pending".to_string())
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! inspect and move the progress of the scanner tasks, e.g.
+//! `CONFIG=config.toml scanner_checkpoint list`,
+//! `scanner_checkpoint rewind --task eth_bridge --height 1446063`, `scanner_checkpoint reset --task eth_bridge`
+//!
+//! stop the task before changing its checkpoint, a running task overwrites it with its own progress
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use models::general::try_run_api_call;
+use models::scanner_checkpoint::{self, ScannerCheckpointEntity, ScannerCheckpointFilter};
+use models::PsqlOp;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// show the checkpoints of all tasks
+    List,
+    /// rescan the task from the block after height
+    Rewind {
+        #[arg(short, long)]
+        task: String,
+        #[arg(long)]
+        height: u64,
+    },
+    /// drop the checkpoint, the task starts from its default height
+    Reset {
+        #[arg(short, long)]
+        task: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    common::log::init_logger();
+    let args = Args::parse();
+    let command = async move {
+        match args.command {
+            Command::List => {
+                for checkpoint in
+                    ScannerCheckpointEntity::find(ScannerCheckpointFilter::All).await?
+                {
+                    println!(
+                        "{:<32} {:<12} {:<66} {}",
+                        checkpoint.checkpoint.task,
+                        checkpoint.checkpoint.height,
+                        checkpoint.checkpoint.block_hash,
+                        checkpoint.updated_at
+                    );
+                }
+            }
+            Command::Rewind { task, height } => {
+                scanner_checkpoint::rewind(&task, height).await?;
+                println!("rewound {} to height {}", task, height);
+            }
+            Command::Reset { task } => {
+                scanner_checkpoint::reset(&task).await?;
+                println!("reset checkpoint of {}", task);
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    try_run_api_call("", command).await??;
+    Ok(())
+}
@@ -0,0 +1,469 @@
+//! issued captchas, one row per (owner, usage), a new code overwrites the former one
+//! while the send records are kept to track the send rate of the contact
+
+use async_trait::async_trait;
+use common::data_structures::captcha::CaptchaRecord;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct CaptchaEntity {
+    pub captcha: CaptchaRecord,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl CaptchaEntity {
+    pub fn into_inner(self) -> CaptchaRecord {
+        self.captcha
+    }
+}
+
+impl CaptchaEntity {
+    pub fn new_with_specified(
+        owner: &str,
+        usage: &str,
+        device_id: &str,
+        code_hash: &str,
+        now: u64,
+        expire_at: u64,
+    ) -> Self {
+        CaptchaEntity {
+            captcha: CaptchaRecord {
+                owner: owner.to_string(),
+                usage: usage.to_string(),
+                device_id: device_id.to_string(),
+                code_hash: code_hash.to_string(),
+                attempts: 0,
+                sent_times: 1,
+                last_sent_at: now,
+                expire_at,
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+pub async fn load(owner: &str, usage: &str) -> DbResult<Option<CaptchaRecord>> {
+    let captcha = CaptchaEntity::find(CaptchaFilter::ByOwnerUsage(owner, usage)).await?;
+    Ok(captcha.into_iter().next().map(CaptchaEntity::into_inner))
+}
+
+/// save a new code, the attempts are cleared and the send is counted
+pub async fn issue(
+    owner: &str,
+    usage: &str,
+    device_id: &str,
+    code_hash: &str,
+    now: u64,
+    expire_at: u64,
+) -> DbResult<()> {
+    let updater = CaptchaUpdater::Issued(device_id, code_hash, now, expire_at);
+    let filter = CaptchaFilter::ByOwnerUsage(owner, usage);
+    if CaptchaEntity::update(updater.clone(), filter.clone()).await? > 0 {
+        return Ok(());
+    }
+    let captcha =
+        CaptchaEntity::new_with_specified(owner, usage, device_id, code_hash, now, expire_at);
+    match captcha.insert().await {
+        Ok(()) => Ok(()),
+        //其他实例同时插入了，覆盖它的验证码
+        Err(DbError::UniqueViolation(_)) => {
+            CaptchaEntity::update_single(updater, filter).await?;
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// the code can't be used again, the send records are kept
+pub async fn consume(owner: &str, usage: &str) -> DbResult<()> {
+    CaptchaEntity::update(
+        CaptchaUpdater::Consumed,
+        CaptchaFilter::ByOwnerUsage(owner, usage),
+    )
+    .await?;
+    Ok(())
+}
+
+/// outcome of one guess of the current code
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaptchaGuess {
+    pub matched: bool,
+    //算上本次猜错之后的次数
+    pub attempts: u32,
+    pub expire_at: u64,
+}
+
+/// compare the code and count a wrong guess in one statement, so parallel guesses
+/// can't read the same attempts, None if the code is used or out of attempts
+pub async fn guess(
+    owner: &str,
+    usage: &str,
+    code_hash: &str,
+    max_attempts: u32,
+) -> DbResult<Option<CaptchaGuess>> {
+    if let Some(store) = PgLocalCli::memory_store() {
+        let mut guess = None;
+        store.update_where(|entity: &mut CaptchaEntity| {
+            let captcha = &mut entity.captcha;
+            if captcha.owner != owner
+                || captcha.usage != usage
+                || captcha.code_hash.is_empty()
+                || captcha.attempts >= max_attempts
+            {
+                return false;
+            }
+            let matched = captcha.code_hash == code_hash;
+            if !matched {
+                captcha.attempts += 1;
+            }
+            guess = Some(CaptchaGuess {
+                matched,
+                attempts: captcha.attempts,
+                expire_at: captcha.expire_at,
+            });
+            !matched
+        });
+        return Ok(guess);
+    }
+    let mut sql = SqlBuilder::new("update captcha set attempts=attempts+(case when code_hash=");
+    sql.bind(code_hash.to_string())
+        .push(" then 0 else 1 end),updated_at=CURRENT_TIMESTAMP where owner=")
+        .bind(owner.to_string())
+        .push(" and usage=")
+        .bind(usage.to_string())
+        .push(" and code_hash<>'' and attempts<")
+        .bind(max_attempts as i32)
+        .push(" returning code_hash=")
+        .bind(code_hash.to_string())
+        .push(",attempts,expire_at");
+    let rows = sql.query().await?;
+    debug!("guess captcha: raw sql {}", sql.sql());
+    Ok(rows.first().map(|row| CaptchaGuess {
+        matched: row.get(0),
+        attempts: row.get::<usize, i32>(1) as u32,
+        expire_at: row.get::<usize, i64>(2) as u64,
+    }))
+}
+
+/// use up the code if it is still valid, false if another request redeemed it first
+pub async fn redeem(
+    owner: &str,
+    usage: &str,
+    code_hash: &str,
+    max_attempts: u32,
+    now: u64,
+) -> DbResult<bool> {
+    let rows = CaptchaEntity::update(
+        CaptchaUpdater::Consumed,
+        CaptchaFilter::Redeemable(owner, usage, code_hash, max_attempts, now),
+    )
+    .await?;
+    Ok(rows > 0)
+}
+
+/// drop the expired codes which were sent before `sent_before`
+pub async fn clean_up(now: u64, sent_before: u64) -> DbResult<()> {
+    CaptchaEntity::delete(CaptchaFilter::Stale(now, sent_before)).await
+}
+
+#[derive(Clone, Debug)]
+pub enum CaptchaUpdater<'a> {
+    //(device_id,code_hash,now,expire_at)
+    Issued(&'a str, &'a str, u64, u64),
+    Consumed,
+}
+
+impl SqlClause for CaptchaUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            CaptchaUpdater::Issued(device_id, code_hash, now, expire_at) => {
+                sql.push("device_id=")
+                    .bind(device_id.to_string())
+                    .push(",code_hash=")
+                    .bind(code_hash.to_string())
+                    .push(",attempts=0,sent_times=sent_times+1,last_sent_at=")
+                    .bind(*now as i64)
+                    .push(",expire_at=")
+                    .bind(*expire_at as i64);
+            }
+            CaptchaUpdater::Consumed => {
+                sql.push("code_hash=''");
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum CaptchaFilter<'b> {
+    ByOwnerUsage(&'b str, &'b str),
+    //(owner,usage,code_hash,max_attempts,now)
+    Redeemable(&'b str, &'b str, &'b str, u32, u64),
+    //(now,sent_before)
+    Stale(u64, u64),
+}
+
+impl SqlClause for CaptchaFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            CaptchaFilter::ByOwnerUsage(owner, usage) => {
+                sql.push("owner=")
+                    .bind(owner.to_string())
+                    .push(" and usage=")
+                    .bind(usage.to_string());
+            }
+            CaptchaFilter::Redeemable(owner, usage, code_hash, max_attempts, now) => {
+                sql.push("owner=")
+                    .bind(owner.to_string())
+                    .push(" and usage=")
+                    .bind(usage.to_string())
+                    .push(" and code_hash<>'' and code_hash=")
+                    .bind(code_hash.to_string())
+                    .push(" and attempts<")
+                    .bind(*max_attempts as i32)
+                    .push(" and expire_at>")
+                    .bind(*now as i64);
+            }
+            CaptchaFilter::Stale(now, sent_before) => {
+                sql.push("expire_at<=")
+                    .bind(*now as i64)
+                    .push(" and last_sent_at<")
+                    .bind(*sent_before as i64);
+            }
+        }
+    }
+}
+
+impl MemoryRecord for CaptchaEntity {
+    const TABLE: &'static str = "captcha";
+
+    fn matches(&self, filter: &CaptchaFilter<'_>) -> bool {
+        match filter {
+            CaptchaFilter::ByOwnerUsage(owner, usage) => {
+                self.captcha.owner == *owner && self.captcha.usage == *usage
+            }
+            CaptchaFilter::Redeemable(owner, usage, code_hash, max_attempts, now) => {
+                self.captcha.owner == *owner
+                    && self.captcha.usage == *usage
+                    && !self.captcha.code_hash.is_empty()
+                    && self.captcha.code_hash == *code_hash
+                    && self.captcha.attempts < *max_attempts
+                    && self.captcha.expire_at > *now
+            }
+            CaptchaFilter::Stale(now, sent_before) => {
+                self.captcha.expire_at <= *now && self.captcha.last_sent_at < *sent_before
+            }
+        }
+    }
+
+    fn apply(&mut self, updater: &CaptchaUpdater<'_>) {
+        match updater {
+            CaptchaUpdater::Issued(device_id, code_hash, now, expire_at) => {
+                self.captcha.device_id = device_id.to_string();
+                self.captcha.code_hash = code_hash.to_string();
+                self.captcha.attempts = 0;
+                self.captcha.sent_times += 1;
+                self.captcha.last_sent_at = *now;
+                self.captcha.expire_at = *expire_at;
+            }
+            CaptchaUpdater::Consumed => self.captcha.code_hash.clear(),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!(
+            "owner_usage:{}:{}",
+            self.captcha.owner, self.captcha.usage
+        )]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+}
+
+#[async_trait]
+impl PsqlOp for CaptchaEntity {
+    type UpdaterContent<'a> = CaptchaUpdater<'a>;
+    type FilterContent<'b> = CaptchaFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            owner,\
+            usage,\
+            device_id,\
+            code_hash,\
+            attempts,\
+            sent_times,\
+            last_sent_at,\
+            expire_at,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from captcha where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get captcha: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<CaptchaEntity> {
+            Ok(CaptchaEntity {
+                captcha: CaptchaRecord {
+                    owner: row.get(0),
+                    usage: row.get(1),
+                    device_id: row.get(2),
+                    code_hash: row.get(3),
+                    attempts: row.get::<usize, i32>(4) as u32,
+                    sent_times: row.get::<usize, i32>(5) as u32,
+                    last_sent_at: row.get::<usize, i64>(6) as u64,
+                    expire_at: row.get::<usize, i64>(7) as u64,
+                },
+                updated_at: row.get(8),
+                created_at: row.get(9),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update captcha set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update captcha {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        debug!("success update captcha {} rows", execute_res);
+        Ok(execute_res)
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let CaptchaRecord {
+            owner,
+            usage,
+            device_id,
+            code_hash,
+            attempts,
+            sent_times,
+            last_sent_at,
+            expire_at,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into captcha (\
+                owner,\
+                usage,\
+                device_id,\
+                code_hash,\
+                attempts,\
+                sent_times,\
+                last_sent_at,\
+                expire_at\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(owner),
+            Box::new(usage),
+            Box::new(device_id),
+            Box::new(code_hash),
+            Box::new(attempts as i32),
+            Box::new(sent_times as i32),
+            Box::new(last_sent_at as i64),
+            Box::new(expire_at as i64),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+
+    async fn delete(filter: Self::FilterContent<'_>) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            store.delete::<Self>(&filter);
+            return Ok(());
+        }
+        let mut sql = SqlBuilder::new("delete from captcha where ");
+        sql.clause(&filter);
+        let execute_res = sql.execute().await?;
+        debug!("success delete captcha {} rows", execute_res);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_captcha_issue_and_consume() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            issue("a@gmail.com", "Register", "1", "hash1", 1000, 2000)
+                .await
+                .unwrap();
+            let wrong = guess("a@gmail.com", "Register", "hash0", 2).await.unwrap();
+            assert_eq!(wrong.unwrap().attempts, 1);
+            //重新发送覆盖旧的验证码，输错次数清零，发送次数累计
+            issue("a@gmail.com", "Register", "1", "hash2", 1500, 2500)
+                .await
+                .unwrap();
+            let captcha = load("a@gmail.com", "Register").await.unwrap().unwrap();
+            assert_eq!(captcha.code_hash, "hash2");
+            assert_eq!(captcha.attempts, 0);
+            assert_eq!(captcha.sent_times, 2);
+
+            //猜对不计次数，猜错用完次数之后连正确的也不认
+            let right = guess("a@gmail.com", "Register", "hash2", 2).await.unwrap();
+            assert!(right.unwrap().matched);
+            guess("a@gmail.com", "Register", "hash0", 2).await.unwrap();
+            guess("a@gmail.com", "Register", "hash0", 2).await.unwrap();
+            assert!(guess("a@gmail.com", "Register", "hash2", 2)
+                .await
+                .unwrap()
+                .is_none());
+            assert!(!redeem("a@gmail.com", "Register", "hash2", 2, 1600)
+                .await
+                .unwrap());
+            assert!(redeem("a@gmail.com", "Register", "hash2", 5, 1600)
+                .await
+                .unwrap());
+            //同一个验证码只能兑换一次
+            assert!(!redeem("a@gmail.com", "Register", "hash2", 5, 1600)
+                .await
+                .unwrap());
+
+            consume("a@gmail.com", "Register").await.unwrap();
+            let captcha = load("a@gmail.com", "Register").await.unwrap().unwrap();
+            assert!(captcha.code_hash.is_empty());
+            assert_eq!(captcha.sent_times, 2);
+
+            //过期了但还在发送记录的保留期内
+            clean_up(3000, 1000).await.unwrap();
+            assert!(load("a@gmail.com", "Register").await.unwrap().is_some());
+            clean_up(3000, 2000).await.unwrap();
+            assert!(load("a@gmail.com", "Register").await.unwrap().is_none());
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
@@ -1,6 +1,5 @@
 extern crate rustc_serialize;
 
-use std::fmt;
 use std::str::FromStr;
 
 use async_trait::async_trait;
@@ -8,16 +7,28 @@ use common::utils::math::generate_random_hex_string;
 
 //#[derive(Serialize)]
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio_postgres::Row;
 
-use crate::{vec_str2array_text, PgLocalCli, PsqlOp, PsqlType};
-use anyhow::{Ok, Result};
+use crate::error::{DbError, DbResult};
+use crate::fee_refund;
+use crate::memory::{self, timestamp_millis, MemoryRecord};
+use crate::outbox;
+use crate::{PgLocalCli, PgU128, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::{anyhow, Result};
 use common::data_structures::coin_transaction::{CoinSendStage, CoinTransaction, TxRole, TxType};
+use common::data_structures::outbox::OutboxEventType;
 use common::data_structures::{CoinType, TxStatusOnChain};
+use common::error_code::{BackendError, WalletError};
+use thiserror::Error;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CoinTxEntity {
     pub transaction: CoinTransaction,
+    //行版本号，每次更新加一
+    pub version: i64,
+    //归档时间，未归档的订单为None
+    pub archived_at: Option<String>,
     pub updated_at: String,
     pub created_at: String,
 }
@@ -55,9 +66,14 @@ impl CoinTxEntity {
             tx_type: TxType::Normal,
             chain_status: TxStatusOnChain::NotLaunch,
             receiver_contact: None,
+            retry_times: 0,
+            failure_reason: None,
+            confirmed_sig: None,
         };
         CoinTxEntity {
             transaction: coin_tx,
+            version: 0,
+            archived_at: None,
             updated_at: "".to_string(),
             created_at: "".to_string(),
         }
@@ -73,50 +89,356 @@ pub enum CoinTxFilter<'b> {
     BySenderUncompleted(&'b str),
     //todo: replace with u128
     ByOrderId(&'b str),
-    //role1,role1_account,role2_account,per_page,page
-    ByTxRolePage(TxRole, &'b str, Option<&'b str>, u32, u32),
+    //order_id,version
+    ByOrderIdVersion(&'b str, i64),
+    //role1,role1_account,role2_account,conditions
+    ByTxRoleCursor(TxRole, &'b str, Option<&'b str>, &'b TxListQuery),
     ByChainStatus(TxStatusOnChain),
+    //上链失败或者已取消、还没有核对过手续费的最早n条订单
+    FeeUnrefunded(u32),
+    //now,limit 在now之前过期但还未结束的订单
+    Expired(u64, u32),
 }
 
-impl fmt::Display for CoinTxFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            CoinTxFilter::ByChainStatus(status) => format!(
-                "chain_status='{}'", status
-            ),
-            CoinTxFilter::ByUser(uid) => format!("sender='{}' or receiver='{}'", uid, uid),
-            CoinTxFilter::BySender(uid) => format!("sender='{}'", uid),
-            CoinTxFilter::ByReceiver(uid) => format!("receiver='{}' ", uid),
-            CoinTxFilter::ByAccountPending(acc_id) => format!(
-                "sender='{}' and stage in ('SenderSigCompleted','ReceiverApproved','Created') or \
-                receiver='{}' and stage in ('SenderSigCompleted','ReceiverApproved')",
-                acc_id, acc_id
-            ),
-            CoinTxFilter::BySenderUncompleted(acc_id) => format!(
-                "sender='{}' and stage in ('ReceiverApproved','ReceiverRejected','Created','SenderSigCompleted')",
-                acc_id
-            ),
-            CoinTxFilter::ByOrderId(id) => format!("order_id='{}' ", id),
-            CoinTxFilter::ByTxRolePage(role,account,counterparty,per_page,page) => {
-                let offset = if *page == 1u32 {
-                    0
-                }else{
-                    (page - 1u32) * per_page - 1u32
-                };
+//主表和归档表共有的列，两张表之间读写都按名字列出，不依赖表里的列顺序
+const TX_COLUMNS: &str = "order_id,tx_id,coin_type,sender,receiver,amount,expire_at,memo,\
+    stage,coin_tx_raw,chain_tx_raw,signatures,tx_type,chain_status,receiver_contact,\
+    retry_times,failure_reason,confirmed_sig,version,updated_at,created_at";
+
+//查询的来源，归档表多出archived_at，主表补成null
+fn tx_source(includes_archive: bool) -> String {
+    let live = format!(
+        "select {},cast(null as timestamp with time zone) as archived_at from coin_transaction",
+        TX_COLUMNS
+    );
+    if includes_archive {
+        format!(
+            "({} union all select {},archived_at from coin_transaction_archive) as coin_transaction",
+            live, TX_COLUMNS
+        )
+    } else {
+        format!("({}) as coin_transaction", live)
+    }
+}
+
+impl CoinTxFilter<'_> {
+    /// only the order detail, the tx list and the fee refund read the archived orders too
+    pub fn includes_archive(&self) -> bool {
+        matches!(
+            self,
+            CoinTxFilter::ByOrderId(_)
+                | CoinTxFilter::ByTxRoleCursor(..)
+                | CoinTxFilter::FeeUnrefunded(_)
+        )
+    }
+}
+
+//作为发送方和接收方时待处理的交易进度
+const SENDER_PENDING_STAGES: [CoinSendStage; 3] = [
+    CoinSendStage::SenderSigCompleted,
+    CoinSendStage::ReceiverApproved,
+    CoinSendStage::Created,
+];
+const RECEIVER_PENDING_STAGES: [CoinSendStage; 2] = [
+    CoinSendStage::SenderSigCompleted,
+    CoinSendStage::ReceiverApproved,
+];
+const SENDER_UNCOMPLETED_STAGES: [CoinSendStage; 4] = [
+    CoinSendStage::ReceiverApproved,
+    CoinSendStage::ReceiverRejected,
+    CoinSendStage::Created,
+    CoinSendStage::SenderSigCompleted,
+];
+
+/// 订单进度只能按照下面的路径往前推进:
+/// Created -> SenderSigCompleted -> ReceiverApproved -> SenderReconfirmed,
+/// 强制转账和给子账户转账从Created直接到ReceiverApproved,
+/// 接收方确认前可以拒绝，二次确认前发送方可以取消或者订单过期
+pub fn stage_transition_allowed(from: &CoinSendStage, to: &CoinSendStage) -> bool {
+    use CoinSendStage::*;
+    matches!(
+        (from, to),
+        (
+            Created,
+            SenderSigCompleted | ReceiverApproved | SenderCanceled | MultiSigExpired
+        ) | (
+            SenderSigCompleted,
+            ReceiverApproved | ReceiverRejected | SenderCanceled | MultiSigExpired
+        ) | (
+            ReceiverApproved,
+            SenderReconfirmed | SenderCanceled | MultiSigExpired
+        )
+    )
+}
+
+//能够到达to的进度，包括to本身(只更新其他字段)
+fn stages_before(to: &CoinSendStage) -> Vec<CoinSendStage> {
+    use CoinSendStage::*;
+    [
+        Created,
+        SenderSigCompleted,
+        ReceiverApproved,
+        ReceiverRejected,
+        SenderCanceled,
+        SenderReconfirmed,
+        MultiSigExpired,
+    ]
+    .into_iter()
+    .filter(|from| from == to || stage_transition_allowed(from, to))
+    .collect()
+}
+
+//不会再变化的进度
+const ARCHIVE_FINAL_STAGES: [CoinSendStage; 3] = [
+    CoinSendStage::ReceiverRejected,
+    CoinSendStage::SenderCanceled,
+    CoinSendStage::MultiSigExpired,
+];
+//过了expire_at就不会再推进的进度
+const ARCHIVE_EXPIRABLE_STAGES: [CoinSendStage; 3] = [
+    CoinSendStage::Created,
+    CoinSendStage::SenderSigCompleted,
+    CoinSendStage::ReceiverApproved,
+];
+//上链已有结果
+const ARCHIVE_CHAIN_STATUSES: [TxStatusOnChain; 2] =
+    [TxStatusOnChain::Successful, TxStatusOnChain::Failed];
+
+#[derive(Error, Debug)]
+pub enum CoinTxStageError {
+    #[error("order {0} can't move from stage {1} to {2}")]
+    IllegalTransition(String, CoinSendStage, CoinSendStage),
+    #[error("{0}")]
+    Db(#[from] DbError),
+}
+
+impl From<CoinTxStageError> for BackendError {
+    fn from(error: CoinTxStageError) -> Self {
+        match error {
+            CoinTxStageError::IllegalTransition(_, from, to) => {
+                WalletError::TxStageTransitionIllegal(from, to).into()
+            }
+            CoinTxStageError::Db(DbError::Conflict(order_id)) => {
+                WalletError::TxStageConflict(order_id).into()
+            }
+            CoinTxStageError::Db(error) => error.into(),
+        }
+    }
+}
+
+fn stage_list(stages: &[CoinSendStage]) -> Vec<String> {
+    stages.iter().map(|stage| stage.to_string()).collect()
+}
+
+/// keyset position in the tx list, it's the last row of previous page
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxCursor {
+    pub created_at: String,
+    pub order_id: String,
+}
+
+impl TxCursor {
+    pub fn of(tx: &CoinTxEntity) -> Self {
+        TxCursor {
+            created_at: tx.created_at.clone(),
+            order_id: tx.transaction.order_id.clone(),
+        }
+    }
+
+    //对客户端是不透明的字符串
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{},{}", self.created_at, self.order_id))
+    }
+
+    pub fn decode(data: &str) -> Result<Self> {
+        let raw = String::from_utf8(hex::decode(data)?)?;
+        let (created_at, order_id) = raw
+            .split_once(',')
+            .ok_or(anyhow!("illegal tx cursor {}", data))?;
+        Ok(TxCursor {
+            created_at: created_at.to_string(),
+            order_id: order_id.to_string(),
+        })
+    }
+}
+
+/// optional conditions of the tx list, rows are ordered by `created_at desc, order_id desc`
+#[derive(Clone, Debug, Default)]
+pub struct TxListQuery {
+    pub order_id: Option<String>,
+    pub coin_type: Option<CoinType>,
+    pub tx_type: Option<TxType>,
+    pub stage: Option<CoinSendStage>,
+    pub chain_status: Option<TxStatusOnChain>,
+    pub min_amount: Option<u128>,
+    pub max_amount: Option<u128>,
+    //创建时间范围，毫秒时间戳
+    pub start_at: Option<u64>,
+    pub end_at: Option<u64>,
+    pub cursor: Option<TxCursor>,
+    pub limit: u32,
+    //旧版本接口按页码查询时跳过的行数
+    pub offset: u32,
+}
+
+impl TxListQuery {
+    fn write_conditions(&self, sql: &mut SqlBuilder) {
+        if let Some(order_id) = &self.order_id {
+            sql.push(" and order_id=").bind(order_id.clone());
+        }
+        if let Some(coin_type) = &self.coin_type {
+            sql.push(" and coin_type=").bind(coin_type.to_string());
+        }
+        if let Some(tx_type) = &self.tx_type {
+            sql.push(" and tx_type=").bind(tx_type.to_string());
+        }
+        if let Some(stage) = &self.stage {
+            sql.push(" and stage=").bind(stage.to_string());
+        }
+        if let Some(status) = &self.chain_status {
+            sql.push(" and chain_status=").bind(status.to_string());
+        }
+        //amount是text类型的列
+        if let Some(amount) = self.min_amount {
+            sql.push(" and cast(amount as numeric)>=cast(cast(")
+                .bind(PgU128(amount))
+                .push(" as text) as numeric)");
+        }
+        if let Some(amount) = self.max_amount {
+            sql.push(" and cast(amount as numeric)<=cast(cast(")
+                .bind(PgU128(amount))
+                .push(" as text) as numeric)");
+        }
+        if let Some(start_at) = self.start_at {
+            sql.push(" and created_at>=to_timestamp(")
+                .bind(start_at as f64 / 1000.0)
+                .push(")");
+        }
+        if let Some(end_at) = self.end_at {
+            sql.push(" and created_at<=to_timestamp(")
+                .bind(end_at as f64 / 1000.0)
+                .push(")");
+        }
+        if let Some(cursor) = &self.cursor {
+            sql.push(" and (created_at,order_id)<(cast(cast(")
+                .bind(cursor.created_at.clone())
+                .push(" as text) as timestamp with time zone),")
+                .bind(cursor.order_id.clone())
+                .push(")");
+        }
+        sql.push(" order by created_at desc,order_id desc limit ")
+            .bind(self.limit as i64);
+        if self.offset > 0 {
+            sql.push(" offset ").bind(self.offset as i64);
+        }
+    }
+
+    fn matches(&self, tx: &CoinTxEntity) -> bool {
+        let created_at = timestamp_millis(&tx.created_at).unwrap_or_default();
+        let tx_info = &tx.transaction;
+        self.order_id
+            .as_ref()
+            .map_or(true, |id| tx_info.order_id == *id)
+            && self
+                .coin_type
+                .as_ref()
+                .map_or(true, |c| tx_info.coin_type == *c)
+            && self
+                .tx_type
+                .as_ref()
+                .map_or(true, |t| tx_info.tx_type == *t)
+            && self.stage.as_ref().map_or(true, |s| tx_info.stage == *s)
+            && self
+                .chain_status
+                .as_ref()
+                .map_or(true, |s| tx_info.chain_status == *s)
+            && self.min_amount.map_or(true, |a| tx_info.amount >= a)
+            && self.max_amount.map_or(true, |a| tx_info.amount <= a)
+            && self.start_at.map_or(true, |t| created_at >= t as i64)
+            && self.end_at.map_or(true, |t| created_at <= t as i64)
+            && self.cursor.as_ref().map_or(true, |c| {
+                (tx.created_at.as_str(), tx_info.order_id.as_str())
+                    < (c.created_at.as_str(), c.order_id.as_str())
+            })
+    }
+}
+
+impl SqlClause for CoinTxFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            CoinTxFilter::ByChainStatus(status) => {
+                sql.push("chain_status=").bind(status.to_string());
+            }
+            CoinTxFilter::ByUser(uid) => {
+                sql.push("sender=")
+                    .bind(uid.to_string())
+                    .push(" or receiver=")
+                    .bind(uid.to_string());
+            }
+            CoinTxFilter::BySender(uid) => {
+                sql.push("sender=").bind(uid.to_string());
+            }
+            CoinTxFilter::ByReceiver(uid) => {
+                sql.push("receiver=").bind(uid.to_string());
+            }
+            CoinTxFilter::ByAccountPending(acc_id) => {
+                sql.push("sender=")
+                    .bind(acc_id.to_string())
+                    .push(" and stage=any(")
+                    .bind(stage_list(&SENDER_PENDING_STAGES))
+                    .push(") or receiver=")
+                    .bind(acc_id.to_string())
+                    .push(" and stage=any(")
+                    .bind(stage_list(&RECEIVER_PENDING_STAGES))
+                    .push(")");
+            }
+            CoinTxFilter::BySenderUncompleted(acc_id) => {
+                sql.push("sender=")
+                    .bind(acc_id.to_string())
+                    .push(" and stage=any(")
+                    .bind(stage_list(&SENDER_UNCOMPLETED_STAGES))
+                    .push(")");
+            }
+            CoinTxFilter::ByOrderId(id) => {
+                sql.push("order_id=").bind(id.to_string());
+            }
+            CoinTxFilter::ByOrderIdVersion(id, version) => {
+                sql.push("order_id=")
+                    .bind(id.to_string())
+                    .push(" and version=")
+                    .bind(*version);
+            }
+            CoinTxFilter::FeeUnrefunded(limit) => {
+                sql.push("(chain_status=")
+                    .bind(TxStatusOnChain::Failed.to_string())
+                    .push(" or stage=")
+                    .bind(CoinSendStage::SenderCanceled.to_string())
+                    .push(
+                        ") and tx_id is not null and not exists \
+                        (select 1 from fee_refund where fee_refund.tx_id=coin_transaction.tx_id) \
+                        order by updated_at limit ",
+                    )
+                    .bind(*limit as i64);
+            }
+            CoinTxFilter::Expired(now, limit) => {
+                sql.push("stage=any(")
+                    .bind(stage_list(&ARCHIVE_EXPIRABLE_STAGES))
+                    .push(") and cast(expire_at as bigint)<")
+                    .bind(*now as i64)
+                    .push(" order by cast(expire_at as bigint) limit ")
+                    .bind(*limit as i64);
+            }
+            CoinTxFilter::ByTxRoleCursor(role, account, counterparty, query) => {
+                //role是枚举出来的列名，可以直接拼接
+                sql.push(&format!("{}=", role)).bind(account.to_string());
                 //过滤自己和交易对手方
-                match  counterparty {
-                    Some(counterparty_account) => format!(
-                        "{}='{}' and {}='{}' order by updated_at desc limit {} offset {}",
-                        role,account,role.counterparty(),counterparty_account,per_page,offset
-                    ),
-                    None => format!(
-                        "{}='{}' order by updated_at desc limit {} offset {}",
-                        role,account,per_page,offset
-                    ),
+                if let Some(counterparty_account) = counterparty {
+                    sql.push(&format!(" and {}=", role.counterparty()))
+                        .bind(counterparty_account.to_string());
                 }
+                query.write_conditions(sql);
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -125,53 +447,432 @@ pub enum CoinTxUpdater<'a> {
     Stage(CoinSendStage),
     StageChainStatus(CoinSendStage, TxStatusOnChain),
     TxidStageChainStatus(&'a str, CoinSendStage, TxStatusOnChain),
+    //tx_id,confirmed_sig,二次确认之后由relayer提交
+    TxidSigStageChainStatus(&'a str, &'a str, CoinSendStage, TxStatusOnChain),
+    //重新提交之后的tx_id,已重试次数,上一次失败的原因
+    Resubmitted(&'a str, u32, &'a str),
+    //重新提交没有发出去，已重试次数,失败原因，等下一轮再试
+    RetryFailed(u32, &'a str),
+    //不再重试,已重试次数,失败原因
+    ChainFailed(u32, &'a str),
     ChainTxInfo(&'a str, &'a str, CoinSendStage),
     TxidTxRaw(&'a str, &'a str),
     Signature(Vec<String>),
 }
 
-impl fmt::Display for CoinTxUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            CoinTxUpdater::Stage(stage) => format!("stage='{}'", stage),
+impl CoinTxUpdater<'_> {
+    /// the stage this updater moves the order to
+    pub fn stage(&self) -> Option<&CoinSendStage> {
+        match self {
+            CoinTxUpdater::Stage(stage)
+            | CoinTxUpdater::StageChainStatus(stage, _)
+            | CoinTxUpdater::TxidStageChainStatus(_, stage, _)
+            | CoinTxUpdater::TxidSigStageChainStatus(_, _, stage, _)
+            | CoinTxUpdater::ChainTxInfo(_, _, stage) => Some(stage),
+            CoinTxUpdater::TxidTxRaw(_, _)
+            | CoinTxUpdater::Signature(_)
+            | CoinTxUpdater::Resubmitted(..)
+            | CoinTxUpdater::RetryFailed(..)
+            | CoinTxUpdater::ChainFailed(..) => None,
+        }
+    }
+}
+
+impl SqlClause for CoinTxUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            CoinTxUpdater::Stage(stage) => {
+                sql.push("stage=").bind(stage.to_string());
+            }
             CoinTxUpdater::StageChainStatus(stage, status) => {
-                format!("(stage,chain_status)=('{}','{}')", stage, status)
+                sql.push("stage=")
+                    .bind(stage.to_string())
+                    .push(",chain_status=")
+                    .bind(status.to_string());
+            }
+            CoinTxUpdater::TxidStageChainStatus(txid, stage, status) => {
+                sql.push("tx_id=")
+                    .bind(txid.to_string())
+                    .push(",stage=")
+                    .bind(stage.to_string())
+                    .push(",chain_status=")
+                    .bind(status.to_string());
+            }
+            CoinTxUpdater::TxidSigStageChainStatus(txid, sig, stage, status) => {
+                sql.push("tx_id=")
+                    .bind(txid.to_string())
+                    .push(",confirmed_sig=")
+                    .bind(sig.to_string())
+                    .push(",stage=")
+                    .bind(stage.to_string())
+                    .push(",chain_status=")
+                    .bind(status.to_string());
+            }
+            CoinTxUpdater::Resubmitted(txid, retry_times, reason) => {
+                sql.push("tx_id=")
+                    .bind(txid.to_string())
+                    .push(",retry_times=")
+                    .bind(*retry_times as i32)
+                    .push(",failure_reason=")
+                    .bind(reason.to_string())
+                    .push(",chain_status=")
+                    .bind(TxStatusOnChain::Pending.to_string());
+            }
+            CoinTxUpdater::RetryFailed(retry_times, reason) => {
+                sql.push("retry_times=")
+                    .bind(*retry_times as i32)
+                    .push(",failure_reason=")
+                    .bind(reason.to_string());
+            }
+            CoinTxUpdater::ChainFailed(retry_times, reason) => {
+                sql.push("retry_times=")
+                    .bind(*retry_times as i32)
+                    .push(",failure_reason=")
+                    .bind(reason.to_string())
+                    .push(",chain_status=")
+                    .bind(TxStatusOnChain::Failed.to_string());
             }
-            CoinTxUpdater::TxidStageChainStatus(txid, stage, status) => format!(
-                "(tx_id,stage,chain_status)=('{}','{}','{}')",
-                txid, stage, status
-            ),
             CoinTxUpdater::ChainTxInfo(tx_id, chain_tx_raw, stage) => {
-                format!(
-                    "(tx_id,chain_tx_raw,stage)=('{}','{}','{}')",
-                    tx_id, chain_tx_raw, stage
-                )
+                sql.push("tx_id=")
+                    .bind(tx_id.to_string())
+                    .push(",chain_tx_raw=")
+                    .bind(chain_tx_raw.to_string())
+                    .push(",stage=")
+                    .bind(stage.to_string());
             }
             CoinTxUpdater::TxidTxRaw(tx_id, chain_tx_raw) => {
-                format!("(tx_id,chain_tx_raw)=('{}','{}')", tx_id, chain_tx_raw,)
+                sql.push("tx_id=")
+                    .bind(tx_id.to_string())
+                    .push(",chain_tx_raw=")
+                    .bind(chain_tx_raw.to_string());
             }
             CoinTxUpdater::Signature(sigs) => {
-                format!("signatures={}", vec_str2array_text(sigs.to_owned()))
+                sql.push("signatures=").bind(sigs.to_owned());
+            }
+        }
+    }
+}
+
+impl CoinTxEntity {
+    fn role_account(&self, role: &TxRole) -> &str {
+        match role {
+            TxRole::Sender => &self.transaction.sender,
+            TxRole::Receiver => &self.transaction.receiver,
+        }
+    }
+
+    /// update the order read before, the stage transition is checked against the
+    /// transition table and the row is only touched if its version is unchanged,
+    /// the row and its stage event are written in one transaction,
+    /// on success the change is also applied to `self`
+    pub async fn update_with_version(
+        &mut self,
+        new_value: CoinTxUpdater<'_>,
+    ) -> std::result::Result<(), CoinTxStageError> {
+        self.update_with_version_and_emit(new_value, None).await
+    }
+
+    /// same as `update_with_version`, and write one more event with the same transaction
+    pub async fn update_with_version_and_emit(
+        &mut self,
+        new_value: CoinTxUpdater<'_>,
+        event: Option<(OutboxEventType, serde_json::Value)>,
+    ) -> std::result::Result<(), CoinTxStageError> {
+        if let Some(to) = new_value.stage() {
+            let from = &self.transaction.stage;
+            if from != to && !stage_transition_allowed(from, to) {
+                Err(CoinTxStageError::IllegalTransition(
+                    self.transaction.order_id.to_owned(),
+                    from.clone(),
+                    to.clone(),
+                ))?;
+            }
+        }
+        //自动提交的unit of work里也不能只写入订单而丢掉事件
+        let uow = PgLocalCli::current();
+        uow.begin().await.map_err(DbError::from)?;
+        let updated = match self.write_with_version(&new_value, event).await {
+            Ok(updated) => updated,
+            Err(err) => {
+                uow.rollback().await.map_err(DbError::from)?;
+                Err(err)?
             }
         };
-        write!(f, "{}", description)
+        uow.commit().await.map_err(DbError::from)?;
+        *self = updated;
+        Ok(())
+    }
+
+    //写入新版本的订单、进度变化事件和调用方附带的事件，返回更新之后的订单
+    async fn write_with_version(
+        &self,
+        new_value: &CoinTxUpdater<'_>,
+        event: Option<(OutboxEventType, serde_json::Value)>,
+    ) -> DbResult<Self> {
+        let order_id = &self.transaction.order_id;
+        let row_num = Self::update(
+            new_value.clone(),
+            CoinTxFilter::ByOrderIdVersion(order_id, self.version),
+        )
+        .await?;
+        if row_num == 0 {
+            debug!(
+                "order {} was modified since version {}",
+                order_id, self.version
+            );
+            Err(DbError::Conflict(order_id.to_owned()))?;
+        }
+        //重新提交会覆盖tx_id，失败的那笔交易先记下来等待核对手续费
+        if let (CoinTxUpdater::Resubmitted(..), Some(failed_tx_id)) =
+            (new_value, &self.transaction.tx_id)
+        {
+            fee_refund::record_failed_attempt(
+                failed_tx_id,
+                order_id,
+                fee_refund::refund_account(&self.transaction),
+            )
+            .await?;
+        }
+        let mut updated = self.clone();
+        updated.apply(new_value);
+        let from = &self.transaction.stage;
+        if *from != updated.transaction.stage {
+            outbox::emit(
+                OutboxEventType::CoinTxStageChanged,
+                order_id,
+                &json!({
+                    "order_id": order_id,
+                    "from": from,
+                    "to": updated.transaction.stage,
+                    "version": updated.version
+                }),
+            )
+            .await?;
+        }
+        if let Some((event_type, payload)) = event {
+            outbox::emit(event_type, order_id, &payload).await?;
+        }
+        Ok(updated)
+    }
+}
+
+impl CoinTxEntity {
+    //已经结束或者过期，并且在updated_before之前就没有再变化
+    fn archivable(&self, updated_before: u64, now: u64) -> bool {
+        let tx = &self.transaction;
+        let updated_at = timestamp_millis(&self.updated_at).unwrap_or(i64::MAX);
+        self.archived_at.is_none()
+            && updated_at < updated_before as i64
+            && (ARCHIVE_FINAL_STAGES.contains(&tx.stage)
+                || tx.stage == CoinSendStage::SenderReconfirmed
+                    && ARCHIVE_CHAIN_STATUSES.contains(&tx.chain_status)
+                || ARCHIVE_EXPIRABLE_STAGES.contains(&tx.stage) && tx.expire_at < now)
+    }
+
+    /// move at most `limit` finished or expired orders which haven't changed since
+    /// `updated_before` (ms) into `coin_transaction_archive`, return the number moved
+    pub async fn archive_finished(updated_before: u64, now: u64, limit: u32) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            let archived_at = memory::now_timestamp();
+            return Ok(store.update_where(|tx: &mut CoinTxEntity| {
+                if tx.archivable(updated_before, now) {
+                    tx.archived_at = Some(archived_at.clone());
+                    return true;
+                }
+                false
+            }));
+        }
+        let chain_statuses = ARCHIVE_CHAIN_STATUSES
+            .iter()
+            .map(|status| status.to_string())
+            .collect::<Vec<_>>();
+        //删除和写入在同一条语句里，不会出现两张表都有或者都没有的情况
+        let mut sql = SqlBuilder::new(
+            "with moved as (delete from coin_transaction where order_id in (\
+            select order_id from coin_transaction where updated_at<to_timestamp(",
+        );
+        sql.bind(updated_before as f64 / 1000.0)
+            .push(") and (stage=any(")
+            .bind(stage_list(&ARCHIVE_FINAL_STAGES))
+            .push(") or stage=")
+            .bind(CoinSendStage::SenderReconfirmed.to_string())
+            .push(" and chain_status=any(")
+            .bind(chain_statuses)
+            .push(") or stage=any(")
+            .bind(stage_list(&ARCHIVE_EXPIRABLE_STAGES))
+            .push(") and cast(expire_at as bigint)<")
+            .bind(now as i64)
+            .push(") order by updated_at limit ")
+            .bind(limit as i64)
+            .push(&format!(
+                " for update skip locked) returning {0}) \
+                insert into coin_transaction_archive ({0},archived_at) \
+                select {0},current_timestamp from moved",
+                TX_COLUMNS
+            ));
+        debug!("start archive orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        info!("success archive {} orders", execute_res);
+        Ok(execute_res)
+    }
+}
+
+impl MemoryRecord for CoinTxEntity {
+    const TABLE: &'static str = "coin_transaction";
+
+    fn matches(&self, filter: &CoinTxFilter<'_>) -> bool {
+        if self.archived_at.is_some() && !filter.includes_archive() {
+            return false;
+        }
+        let tx = &self.transaction;
+        match filter {
+            CoinTxFilter::ByChainStatus(status) => tx.chain_status == *status,
+            CoinTxFilter::ByUser(uid) => {
+                tx.sender == uid.to_string() || tx.receiver == uid.to_string()
+            }
+            CoinTxFilter::BySender(uid) => tx.sender == uid.to_string(),
+            CoinTxFilter::ByReceiver(uid) => tx.receiver == uid.to_string(),
+            CoinTxFilter::ByAccountPending(acc_id) => {
+                tx.sender == *acc_id && SENDER_PENDING_STAGES.contains(&tx.stage)
+                    || tx.receiver == *acc_id && RECEIVER_PENDING_STAGES.contains(&tx.stage)
+            }
+            CoinTxFilter::BySenderUncompleted(acc_id) => {
+                tx.sender == *acc_id && SENDER_UNCOMPLETED_STAGES.contains(&tx.stage)
+            }
+            CoinTxFilter::ByOrderId(id) => tx.order_id == *id,
+            CoinTxFilter::ByOrderIdVersion(id, version) => {
+                tx.order_id == *id && self.version == *version
+            }
+            //是否已经核对过由fee_refund的主键保证
+            CoinTxFilter::FeeUnrefunded(_) => {
+                (tx.chain_status == TxStatusOnChain::Failed
+                    || tx.stage == CoinSendStage::SenderCanceled)
+                    && tx.tx_id.is_some()
+            }
+            CoinTxFilter::Expired(now, _) => {
+                ARCHIVE_EXPIRABLE_STAGES.contains(&tx.stage) && tx.expire_at < *now
+            }
+            CoinTxFilter::ByTxRoleCursor(role, account, counterparty, query) => {
+                self.role_account(role) == *account
+                    && counterparty
+                        .map(|acc| self.role_account(&role.counterparty()) == acc)
+                        .unwrap_or(true)
+                    && query.matches(self)
+            }
+        }
+    }
+
+    fn accepts(&self, updater: &CoinTxUpdater<'_>) -> bool {
+        //归档的订单不再更新
+        self.archived_at.is_none()
+            && updater
+                .stage()
+                .map(|to| stages_before(to).contains(&self.transaction.stage))
+                .unwrap_or(true)
+    }
+
+    fn apply(&mut self, updater: &CoinTxUpdater<'_>) {
+        self.version += 1;
+        let tx = &mut self.transaction;
+        match updater {
+            CoinTxUpdater::Stage(stage) => tx.stage = stage.clone(),
+            CoinTxUpdater::StageChainStatus(stage, status) => {
+                tx.stage = stage.clone();
+                tx.chain_status = status.clone();
+            }
+            CoinTxUpdater::TxidStageChainStatus(txid, stage, status) => {
+                tx.tx_id = Some(txid.to_string());
+                tx.stage = stage.clone();
+                tx.chain_status = status.clone();
+            }
+            CoinTxUpdater::TxidSigStageChainStatus(txid, sig, stage, status) => {
+                tx.tx_id = Some(txid.to_string());
+                tx.confirmed_sig = Some(sig.to_string());
+                tx.stage = stage.clone();
+                tx.chain_status = status.clone();
+            }
+            CoinTxUpdater::Resubmitted(txid, retry_times, reason) => {
+                tx.tx_id = Some(txid.to_string());
+                tx.retry_times = *retry_times;
+                tx.failure_reason = Some(reason.to_string());
+                tx.chain_status = TxStatusOnChain::Pending;
+            }
+            CoinTxUpdater::RetryFailed(retry_times, reason) => {
+                tx.retry_times = *retry_times;
+                tx.failure_reason = Some(reason.to_string());
+            }
+            CoinTxUpdater::ChainFailed(retry_times, reason) => {
+                tx.retry_times = *retry_times;
+                tx.failure_reason = Some(reason.to_string());
+                tx.chain_status = TxStatusOnChain::Failed;
+            }
+            CoinTxUpdater::ChainTxInfo(tx_id, chain_tx_raw, stage) => {
+                tx.tx_id = Some(tx_id.to_string());
+                tx.chain_tx_raw = Some(chain_tx_raw.to_string());
+                tx.stage = stage.clone();
+            }
+            CoinTxUpdater::TxidTxRaw(tx_id, chain_tx_raw) => {
+                tx.tx_id = Some(tx_id.to_string());
+                tx.chain_tx_raw = Some(chain_tx_raw.to_string());
+            }
+            CoinTxUpdater::Signature(sigs) => tx.signatures = sigs.clone(),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        let mut keys = vec![format!("order_id:{}", self.transaction.order_id)];
+        if let Some(tx_id) = &self.transaction.tx_id {
+            keys.push(format!("tx_id:{}", tx_id));
+        }
+        keys
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &CoinTxFilter<'_>) -> Vec<Self> {
+        if let CoinTxFilter::ByTxRoleCursor(_, _, _, query) = filter {
+            rows.sort_by(|a, b| {
+                (&b.created_at, &b.transaction.order_id)
+                    .cmp(&(&a.created_at, &a.transaction.order_id))
+            });
+            rows = rows
+                .into_iter()
+                .skip(query.offset as usize)
+                .take(query.limit as usize)
+                .collect();
+        }
+        if let CoinTxFilter::FeeUnrefunded(limit) = filter {
+            rows.sort_by(|a, b| a.updated_at.cmp(&b.updated_at));
+            rows.truncate(*limit as usize);
+        }
+        if let CoinTxFilter::Expired(_, limit) = filter {
+            rows.sort_by_key(|tx| tx.transaction.expire_at);
+            rows.truncate(*limit as usize);
+        }
+        rows
     }
 }
+
 #[async_trait]
 impl PsqlOp for CoinTxEntity {
     type UpdaterContent<'a> = CoinTxUpdater<'a>;
     type FilterContent<'b> = CoinTxFilter<'b>;
 
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<CoinTxEntity>> {
-        let sql = format!(
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<CoinTxEntity>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
             "select order_id,\
          tx_id,\
          coin_type,\
          sender,\
          receiver,\
          amount,\
-         expire_at,
-         memo,
+         expire_at,\
+         memo,\
          stage,\
          coin_tx_raw,\
          chain_tx_raw,\
@@ -179,13 +880,20 @@ impl PsqlOp for CoinTxEntity {
          tx_type,\
          chain_status,\
          receiver_contact,\
+         retry_times,\
+         failure_reason,\
+         confirmed_sig,\
+         version,\
+         cast(archived_at as text), \
          cast(updated_at as text), \
          cast(created_at as text) \
-         from coin_transaction where {}",
-            filter
+         from ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get_snapshot: raw sql {}", sql);
+        sql.push(&tx_source(filter.includes_archive()))
+            .push(" where ")
+            .clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get_snapshot: raw sql {}", sql.sql());
 
         let gen_view = |row: &Row| -> Result<CoinTxEntity> {
             Ok(CoinTxEntity {
@@ -195,7 +903,7 @@ impl PsqlOp for CoinTxEntity {
                     coin_type: CoinType::from_str(row.get::<usize, &str>(2))?,
                     sender: row.get(3),
                     receiver: row.get(4),
-                    amount: u128::from_str(row.get::<usize, &str>(5))?,
+                    amount: row.get::<usize, PgU128>(5).0,
                     expire_at: row.get::<usize, String>(6).parse()?,
                     memo: row.get(7),
                     stage: row.get::<usize, &str>(8).parse()?,
@@ -205,30 +913,48 @@ impl PsqlOp for CoinTxEntity {
                     tx_type: row.get::<usize, &str>(12).parse()?,
                     chain_status: row.get::<usize, &str>(13).parse()?,
                     receiver_contact: row.get::<usize, Option<String>>(14),
+                    retry_times: row.get::<usize, i32>(15) as u32,
+                    failure_reason: row.get(16),
+                    confirmed_sig: row.get(17),
                 },
-                updated_at: row.get(15),
-                created_at: row.get(16),
+                version: row.get(18),
+                archived_at: row.get(19),
+                updated_at: row.get(20),
+                created_at: row.get(21),
             })
         };
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
 
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "UPDATE coin_transaction SET {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        info!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("UPDATE coin_transaction SET ");
+        sql.clause(&new_value)
+            .push(",version=version+1,updated_at=CURRENT_TIMESTAMP where (")
+            .clause(&filter)
+            .push(")");
+        //进度只能沿着状态机往前推进，不满足的行不会被更新
+        if let Some(to) = new_value.stage() {
+            sql.push(" and stage=any(")
+                .bind(stage_list(&stages_before(to)))
+                .push(")");
+        }
+        info!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         info!("success update orders {} rows", execute_res);
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let CoinTransaction {
             order_id,
             tx_id,
@@ -245,16 +971,13 @@ impl PsqlOp for CoinTxEntity {
             tx_type,
             chain_status,
             receiver_contact,
+            retry_times,
+            failure_reason,
+            confirmed_sig,
         } = self.into_inner();
-        let tx_id: PsqlType = tx_id.into();
-        let chain_raw_data: PsqlType = chain_tx_raw.into();
-        let memo: PsqlType = memo.into();
-        let receiver_contact: PsqlType = receiver_contact.into();
-
-        //todo: amount specific type short or long
-        let sql = format!(
-            "insert into coin_transaction (order_id,
-                tx_id,\
+        let mut sql = SqlBuilder::new(
+            "insert into coin_transaction (order_id,\
+         tx_id,\
          coin_type,\
          sender,\
          receiver,\
@@ -262,32 +985,41 @@ impl PsqlOp for CoinTxEntity {
          expire_at,\
          memo,\
          stage,\
-        coin_tx_raw,\
+         coin_tx_raw,\
          chain_tx_raw,\
          signatures,\
          tx_type,\
          chain_status,\
-         receiver_contact
-         ) values ('{}',{},'{}','{}','{}','{}','{}',{},'{}','{}',{},{},'{}','{}',{});",
-            order_id,
-            tx_id.to_psql_str(),
-            coin_type,
-            sender,
-            receiver,
-            amount,
-            expire_at,
-            memo.to_psql_str(),
-            stage,
-            coin_tx_raw,
-            chain_raw_data.to_psql_str(),
-            vec_str2array_text(signatures),
-            tx_type,
-            chain_status,
-            receiver_contact.to_psql_str(),
+         receiver_contact,\
+         retry_times,\
+         failure_reason,\
+         confirmed_sig\
+         ) values (",
         );
-        println!("row sql {} rows", sql);
+        sql.bind_all(vec![
+            Box::new(order_id),
+            Box::new(tx_id),
+            Box::new(coin_type.to_string()),
+            Box::new(sender),
+            Box::new(receiver),
+            Box::new(PgU128(amount)),
+            Box::new(expire_at.to_string()),
+            Box::new(memo),
+            Box::new(stage.to_string()),
+            Box::new(coin_tx_raw),
+            Box::new(chain_tx_raw),
+            Box::new(signatures),
+            Box::new(tx_type.to_string()),
+            Box::new(chain_status.to_string()),
+            Box::new(receiver_contact),
+            Box::new(retry_times as i32),
+            Box::new(failure_reason),
+            Box::new(confirmed_sig),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
 
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        let execute_res = sql.execute().await?;
         info!("success insert {} rows", execute_res);
 
         Ok(())
@@ -296,11 +1028,15 @@ impl PsqlOp for CoinTxEntity {
 
 #[cfg(test)]
 mod tests {
-    use crate::general::{self, run_api_call, table_clear};
+    use crate::general::{run_api_call, table_clear};
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
 
     use super::*;
 
     use std::env;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_db_coin_transfer() {
@@ -338,8 +1074,307 @@ mod tests {
                 .unwrap();
             println!("after update {:?}", res);
         };
-        let (conn_ptr,_res) = run_api_call("", task).await.unwrap();
-        general::commit().await.unwrap();
-        general::clean_conn(conn_ptr);
+        run_api_call("", task).await.unwrap();
+    }
+
+    #[test]
+    fn test_stage_transition_table() {
+        use CoinSendStage::*;
+        assert!(stage_transition_allowed(&Created, &SenderSigCompleted));
+        assert!(stage_transition_allowed(
+            &ReceiverApproved,
+            &SenderReconfirmed
+        ));
+        assert!(!stage_transition_allowed(
+            &SenderReconfirmed,
+            &SenderCanceled
+        ));
+        assert!(!stage_transition_allowed(
+            &ReceiverApproved,
+            &SenderSigCompleted
+        ));
+        assert!(!stage_transition_allowed(&SenderCanceled, &Created));
+    }
+
+    #[tokio::test]
+    async fn test_update_with_version_conflict() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let coin_tx = CoinTxEntity::new_with_specified(
+                CoinType::BTC,
+                "1.test".to_string(),
+                "2.test".to_string(),
+                1,
+                "".to_string(),
+                None,
+                1715740449000,
+                CoinSendStage::Created,
+            );
+            let order_id = coin_tx.transaction.order_id.clone();
+            coin_tx.insert().await.unwrap();
+
+            let mut first = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
+                .await
+                .unwrap();
+            let mut second = first.clone();
+            first
+                .update_with_version(CoinTxUpdater::Stage(CoinSendStage::SenderCanceled))
+                .await
+                .unwrap();
+            assert_eq!(first.version, 1);
+            //second读到的是旧版本，附带的事件也不会写入
+            let err = second
+                .update_with_version_and_emit(
+                    CoinTxUpdater::Stage(CoinSendStage::MultiSigExpired),
+                    Some((OutboxEventType::CoinTxExpired, json!({}))),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(err, CoinTxStageError::Db(DbError::Conflict(_))));
+            let events = outbox::OutboxEntity::find(outbox::OutboxFilter::Pending(10))
+                .await
+                .unwrap();
+            assert!(events
+                .iter()
+                .all(|event| event.event.event_type != OutboxEventType::CoinTxExpired));
+            //已经取消的订单不能再往回走
+            let err = first
+                .update_with_version(CoinTxUpdater::Stage(CoinSendStage::Created))
+                .await
+                .unwrap_err();
+            assert!(matches!(err, CoinTxStageError::IllegalTransition(..)));
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+
+    #[tokio::test]
+    async fn test_resubmit_failed_tx() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let mut coin_tx = CoinTxEntity::new_with_specified(
+                CoinType::BTC,
+                "1.test".to_string(),
+                "2.test".to_string(),
+                1,
+                "".to_string(),
+                None,
+                1715740449000,
+                CoinSendStage::ReceiverApproved,
+            );
+            coin_tx.transaction.tx_type = TxType::MainToSub;
+            let order_id = coin_tx.transaction.order_id.clone();
+            coin_tx.insert().await.unwrap();
+
+            let mut tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
+                .await
+                .unwrap();
+            tx.update_with_version(CoinTxUpdater::TxidSigStageChainStatus(
+                "tx1",
+                "master_sig",
+                CoinSendStage::SenderReconfirmed,
+                TxStatusOnChain::Pending,
+            ))
+            .await
+            .unwrap();
+            tx.update_with_version(CoinTxUpdater::Resubmitted("tx2", 1, "tx tx1 failed"))
+                .await
+                .unwrap();
+            tx.update_with_version(CoinTxUpdater::ChainFailed(3, "resubmit failed"))
+                .await
+                .unwrap();
+
+            //重试不改变订单进度
+            let tx = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_id))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(tx.stage, CoinSendStage::SenderReconfirmed);
+            assert_eq!(tx.chain_status, TxStatusOnChain::Failed);
+            assert_eq!(tx.tx_id.as_deref(), Some("tx2"));
+            assert_eq!(tx.confirmed_sig.as_deref(), Some("master_sig"));
+            assert_eq!(tx.retry_times, 3);
+            assert_eq!(tx.failure_reason.as_deref(), Some("resubmit failed"));
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+
+    #[tokio::test]
+    async fn test_archive_finished_tx() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let mut order_ids = vec![];
+            //未过期的Created，已取消，已过期的Created
+            for (expire_at, stage) in [
+                (u64::MAX, CoinSendStage::Created),
+                (u64::MAX, CoinSendStage::SenderCanceled),
+                (1715740449000, CoinSendStage::Created),
+            ] {
+                let coin_tx = CoinTxEntity::new_with_specified(
+                    CoinType::BTC,
+                    "1.test".to_string(),
+                    "2.test".to_string(),
+                    1,
+                    "".to_string(),
+                    None,
+                    expire_at,
+                    stage,
+                );
+                order_ids.push(coin_tx.transaction.order_id.clone());
+                coin_tx.insert().await.unwrap();
+            }
+            let now = common::utils::time::now_millis();
+            //还在保留期内的不归档
+            let moved = CoinTxEntity::archive_finished(0, now, 10).await.unwrap();
+            assert_eq!(moved, 0);
+            let moved = CoinTxEntity::archive_finished(now + 1000, now, 10)
+                .await
+                .unwrap();
+            assert_eq!(moved, 2);
+
+            let uncompleted = CoinTxEntity::find(CoinTxFilter::BySenderUncompleted("1.test"))
+                .await
+                .unwrap();
+            assert_eq!(uncompleted.len(), 1);
+            assert_eq!(uncompleted[0].transaction.order_id, order_ids[0]);
+            //详情和列表依然能查到归档的订单
+            let archived = CoinTxEntity::find_single(CoinTxFilter::ByOrderId(&order_ids[1]))
+                .await
+                .unwrap();
+            assert!(archived.archived_at.is_some());
+            let query = TxListQuery {
+                limit: 10,
+                ..Default::default()
+            };
+            let filter = CoinTxFilter::ByTxRoleCursor(TxRole::Sender, "1.test", None, &query);
+            assert_eq!(CoinTxEntity::find(filter).await.unwrap().len(), 3);
+            //归档之后不再更新
+            let rows = CoinTxEntity::update(
+                CoinTxUpdater::Stage(CoinSendStage::MultiSigExpired),
+                CoinTxFilter::ByOrderId(&order_ids[2]),
+            )
+            .await
+            .unwrap();
+            assert_eq!(rows, 0);
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+
+    #[tokio::test]
+    async fn test_find_expired_tx() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let now = common::utils::time::now_millis();
+            let mut order_ids = vec![];
+            //未过期，已过期，已取消的过期订单
+            for (expire_at, stage) in [
+                (now + 1000, CoinSendStage::SenderSigCompleted),
+                (now - 1000, CoinSendStage::ReceiverApproved),
+                (now - 1000, CoinSendStage::SenderCanceled),
+            ] {
+                let coin_tx = CoinTxEntity::new_with_specified(
+                    CoinType::BTC,
+                    "1.test".to_string(),
+                    "2.test".to_string(),
+                    1,
+                    "".to_string(),
+                    None,
+                    expire_at,
+                    stage,
+                );
+                order_ids.push(coin_tx.transaction.order_id.clone());
+                coin_tx.insert().await.unwrap();
+            }
+            let mut expired = CoinTxEntity::find(CoinTxFilter::Expired(now, 10))
+                .await
+                .unwrap();
+            assert_eq!(expired.len(), 1);
+            assert_eq!(expired[0].transaction.order_id, order_ids[1]);
+
+            expired[0]
+                .update_with_version(CoinTxUpdater::Stage(CoinSendStage::MultiSigExpired))
+                .await
+                .unwrap();
+            let expired = CoinTxEntity::find(CoinTxFilter::Expired(now, 10))
+                .await
+                .unwrap();
+            assert!(expired.is_empty());
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+
+    #[tokio::test]
+    async fn test_tx_list_cursor() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            for amount in 1..=5u128 {
+                let mut coin_tx = CoinTxEntity::new_with_specified(
+                    CoinType::BTC,
+                    "1.test".to_string(),
+                    "2.test".to_string(),
+                    amount,
+                    "".to_string(),
+                    None,
+                    1715740449000,
+                    CoinSendStage::Created,
+                );
+                coin_tx.transaction.tx_type = if amount % 2 == 0 {
+                    TxType::Forced
+                } else {
+                    TxType::Normal
+                };
+                coin_tx.insert().await.unwrap();
+            }
+
+            let mut query = TxListQuery {
+                limit: 2,
+                ..Default::default()
+            };
+            let mut amounts = vec![];
+            loop {
+                let filter = CoinTxFilter::ByTxRoleCursor(TxRole::Sender, "1.test", None, &query);
+                let txs = CoinTxEntity::find(filter).await.unwrap();
+                amounts.extend(txs.iter().map(|tx| tx.transaction.amount));
+                match txs.last() {
+                    Some(tx) if txs.len() == 2 => {
+                        let cursor = TxCursor::of(tx).encode();
+                        query.cursor = Some(TxCursor::decode(&cursor).unwrap());
+                    }
+                    _ => break,
+                }
+            }
+            amounts.sort();
+            assert_eq!(amounts, vec![1, 2, 3, 4, 5]);
+
+            let query = TxListQuery {
+                tx_type: Some(TxType::Forced),
+                min_amount: Some(3),
+                limit: 10,
+                ..Default::default()
+            };
+            let filter = CoinTxFilter::ByTxRoleCursor(TxRole::Sender, "1.test", None, &query);
+            let txs = CoinTxEntity::find(filter).await.unwrap();
+            assert_eq!(txs.len(), 1);
+            assert_eq!(txs[0].transaction.amount, 4);
+
+            //按订单号查询时其他条件同样生效
+            let query = TxListQuery {
+                order_id: Some(txs[0].transaction.order_id.clone()),
+                tx_type: Some(TxType::Normal),
+                limit: 10,
+                ..Default::default()
+            };
+            let filter = CoinTxFilter::ByTxRoleCursor(TxRole::Sender, "1.test", None, &query);
+            assert!(CoinTxEntity::find(filter).await.unwrap().is_empty());
+
+            //旧版本接口的页码分页
+            let query = TxListQuery {
+                limit: 2,
+                offset: 4,
+                ..Default::default()
+            };
+            let filter = CoinTxFilter::ByTxRoleCursor(TxRole::Sender, "1.test", None, &query);
+            assert_eq!(CoinTxEntity::find(filter).await.unwrap().len(), 1);
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
     }
 }
@@ -2,16 +2,17 @@ extern crate rustc_serialize;
 
 use async_trait::async_trait;
 use common::data_structures::device_info::DeviceInfo;
-use std::fmt;
 
 use tokio_postgres::Row;
 //#[derive(Serialize)]
 
-use common::data_structures::SecretKeyState;
+use common::data_structures::{KeyRole, SecretKeyState};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{PgLocalCli, PsqlOp, PsqlType};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
 use anyhow::Result;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -37,38 +38,40 @@ pub enum DeviceInfoUpdater<'a> {
     BecomeUndefined(&'a str),
 }
 
-impl fmt::Display for DeviceInfoUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for DeviceInfoUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             DeviceInfoUpdater::State(new_state) => {
-                format!("state='{}'", new_state)
+                sql.push("state=").bind(new_state.to_string());
             }
             DeviceInfoUpdater::HolderSaved(saved) => {
-                format!("holder_confirm_saved={} ", saved)
+                sql.push("holder_confirm_saved=").bind(*saved);
             }
             DeviceInfoUpdater::BecomeMaster(key) => {
-                format!(
-                    "(hold_pubkey,holder_confirm_saved,key_role)=('{}',true,'Master') ",
-                    key
-                )
+                sql.push("hold_pubkey=")
+                    .bind(key.to_string())
+                    .push(",holder_confirm_saved=true,key_role=")
+                    .bind(KeyRole::Master.to_string());
             }
             DeviceInfoUpdater::BecomeServant(key) => {
-                format!(
-                    "(hold_pubkey,holder_confirm_saved,key_role)=('{}','true','Servant') ",
-                    key
-                )
+                sql.push("hold_pubkey=")
+                    .bind(key.to_string())
+                    .push(",holder_confirm_saved=true,key_role=")
+                    .bind(KeyRole::Servant.to_string());
             }
             DeviceInfoUpdater::AddServant(key) => {
-                format!("(hold_pubkey,key_role)=('{}','Servant') ", key)
+                sql.push("hold_pubkey=")
+                    .bind(key.to_string())
+                    .push(",key_role=")
+                    .bind(KeyRole::Servant.to_string());
             }
             DeviceInfoUpdater::BecomeUndefined(key) => {
-                format!(
-                    "(hold_pubkey,holder_confirm_saved,key_role)=('{}',true,'Undefined') ",
-                    key
-                )
+                sql.push("hold_pubkey=")
+                    .bind(key.to_string())
+                    .push(",holder_confirm_saved=true,key_role=")
+                    .bind(KeyRole::Undefined.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -81,22 +84,32 @@ pub enum DeviceInfoFilter<'b> {
     ByHoldKey(&'b str),
 }
 
-impl fmt::Display for DeviceInfoFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            DeviceInfoFilter::ByUser(user_id) => format!("user_id={} order by created_at", user_id),
+impl SqlClause for DeviceInfoFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            DeviceInfoFilter::ByUser(user_id) => {
+                sql.push("user_id=")
+                    .bind(**user_id as i64)
+                    .push(" order by created_at");
+            }
             DeviceInfoFilter::ByDeviceUser(device_id, user_id) => {
-                format!("id='{}' and user_id={} ", device_id, user_id)
+                sql.push("id=")
+                    .bind(device_id.to_string())
+                    .push(" and user_id=")
+                    .bind(**user_id as i64);
+            }
+            DeviceInfoFilter::ByUserDeviceHoldSecret(user_id, device_id, saved) => {
+                sql.push("user_id=")
+                    .bind(*user_id as i64)
+                    .push(" and id=")
+                    .bind(device_id.to_string())
+                    .push(" and holder_confirm_saved=")
+                    .bind(*saved);
             }
-            DeviceInfoFilter::ByUserDeviceHoldSecret(user_id, device_id, saved) => format!(
-                "user_id={} and id='{}' and holder_confirm_saved={} ",
-                user_id, device_id, saved
-            ),
             DeviceInfoFilter::ByHoldKey(key) => {
-                format!("hold_pubkey='{}' ", key)
+                sql.push("hold_pubkey=").bind(key.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -117,13 +130,70 @@ impl DeviceInfoEntity {
     }
 }
 
+impl MemoryRecord for DeviceInfoEntity {
+    const TABLE: &'static str = "device_info";
+
+    fn matches(&self, filter: &DeviceInfoFilter<'_>) -> bool {
+        let device = &self.device_info;
+        match filter {
+            DeviceInfoFilter::ByUser(user_id) => device.user_id == **user_id,
+            DeviceInfoFilter::ByDeviceUser(device_id, user_id) => {
+                device.id == *device_id && device.user_id == **user_id
+            }
+            DeviceInfoFilter::ByUserDeviceHoldSecret(user_id, device_id, saved) => {
+                device.user_id == *user_id
+                    && device.id == *device_id
+                    && device.holder_confirm_saved == *saved
+            }
+            DeviceInfoFilter::ByHoldKey(key) => device.hold_pubkey.as_deref() == Some(*key),
+        }
+    }
+
+    fn apply(&mut self, updater: &DeviceInfoUpdater<'_>) {
+        let device = &mut self.device_info;
+        match updater {
+            //DeviceInfo里的state是DeviceState，这个更新目前没有使用
+            DeviceInfoUpdater::State(_) => {}
+            DeviceInfoUpdater::HolderSaved(saved) => device.holder_confirm_saved = *saved,
+            DeviceInfoUpdater::BecomeMaster(key)
+            | DeviceInfoUpdater::BecomeServant(key)
+            | DeviceInfoUpdater::BecomeUndefined(key) => {
+                device.hold_pubkey = Some(key.to_string());
+                device.holder_confirm_saved = true;
+            }
+            DeviceInfoUpdater::AddServant(key) => device.hold_pubkey = Some(key.to_string()),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!(
+            "device_user:{}:{}",
+            self.device_info.id, self.device_info.user_id
+        )]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &DeviceInfoFilter<'_>) -> Vec<Self> {
+        if let DeviceInfoFilter::ByUser(_) = filter {
+            rows.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        }
+        rows
+    }
+}
+
 #[async_trait]
 impl PsqlOp for DeviceInfoEntity {
     type UpdaterContent<'a> = DeviceInfoUpdater<'a>;
     type FilterContent<'b> = DeviceInfoFilter<'b>;
 
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<Self>> {
-        let sql = format!(
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
             "select \
             id,\
             user_id,\
@@ -133,11 +203,11 @@ impl PsqlOp for DeviceInfoEntity {
             holder_confirm_saved,\
          cast(updated_at as text), \
          cast(created_at as text) \
-         from device_info where {}",
-            filter
+         from device_info where ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get device: raw sql {}", sql);
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get device: raw sql {}", sql.sql());
         let gen_view = |row: &Row| -> Result<DeviceInfoEntity> {
             Ok(DeviceInfoEntity {
                 device_info: DeviceInfo {
@@ -153,24 +223,30 @@ impl PsqlOp for DeviceInfoEntity {
             })
         };
 
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "update device_info set {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        debug!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update device_info set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update orders {} rows", execute_res);
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let DeviceInfo {
             id,
             user_id,
@@ -179,26 +255,27 @@ impl PsqlOp for DeviceInfoEntity {
             brand: device_type,
             holder_confirm_saved,
         } = self.into_inner();
-        let hold_pubkey: PsqlType = hold_pubkey.to_owned().into();
-
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into device_info (\
                 id,\
                 user_id,\
                 state,\
                 hold_pubkey,\
                 brand,\
-                holder_confirm_saved
-        ) values ('{}',{},'{}',{},'{}',{});",
-            id,
-            user_id,
-            state,
-            hold_pubkey.to_psql_str(),
-            device_type,
-            holder_confirm_saved
+                holder_confirm_saved\
+        ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let _execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        sql.bind_all(vec![
+            Box::new(id),
+            Box::new(user_id as i64),
+            Box::new(state.to_string()),
+            Box::new(hold_pubkey),
+            Box::new(device_type),
+            Box::new(holder_confirm_saved),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
         Ok(())
     }
 }
@@ -206,7 +283,7 @@ impl PsqlOp for DeviceInfoEntity {
 #[cfg(test)]
 mod tests {
 
-    use crate::general::{self, run_api_call, table_clear};
+    use crate::general::{run_api_call, table_clear};
 
     use super::*;
     use common::log::init_logger;
@@ -236,8 +313,6 @@ mod tests {
             .await
             .unwrap();
         };
-        let (conn_ptr,_res) = run_api_call("", task).await.unwrap();
-        general::commit().await.unwrap();
-        general::clean_conn(conn_ptr);
+        run_api_call("", task).await.unwrap();
     }
 }
@@ -0,0 +1,96 @@
+//! typed errors of the database layer, callers match on the variant
+//! instead of the message text
+
+use common::error_code::{BackendError, ExternalServiceError};
+use deadpool_postgres::PoolError;
+use thiserror::Error;
+use tokio_postgres::error::SqlState;
+
+pub type DbResult<T> = std::result::Result<T, DbError>;
+
+#[derive(Error, Debug)]
+pub enum DbError {
+    #[error("data isn't existed")]
+    NotFound,
+    #[error("data is repeated")]
+    Repeated,
+    #[error("unique key violated: {0}")]
+    UniqueViolation(String),
+    //乐观锁检查失败，数据已被其他请求修改
+    #[error("data was modified concurrently: {0}")]
+    Conflict(String),
+    #[error("database connection error: {0}")]
+    Connection(String),
+    #[error("database internal error: {0}")]
+    Internal(anyhow::Error),
+}
+
+impl DbError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, DbError::NotFound)
+    }
+}
+
+impl From<tokio_postgres::Error> for DbError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        if err.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            DbError::UniqueViolation(err.to_string())
+        } else if err.is_closed() {
+            DbError::Connection(err.to_string())
+        } else {
+            DbError::Internal(err.into())
+        }
+    }
+}
+
+impl From<PoolError> for DbError {
+    fn from(err: PoolError) -> Self {
+        DbError::Connection(err.to_string())
+    }
+}
+
+impl From<anyhow::Error> for DbError {
+    fn from(err: anyhow::Error) -> Self {
+        //经过anyhow传递的DbError还原成原来的类型
+        match err.downcast::<DbError>() {
+            Ok(err) => err,
+            Err(err) => match err.downcast::<tokio_postgres::Error>() {
+                Ok(err) => err.into(),
+                Err(err) => DbError::Internal(err),
+            },
+        }
+    }
+}
+
+impl From<DbError> for BackendError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::NotFound => BackendError::DataNotFound,
+            DbError::Repeated => BackendError::DataRepeated,
+            DbError::UniqueViolation(msg) | DbError::Conflict(msg) => {
+                BackendError::DataConflict(msg)
+            }
+            DbError::Connection(msg) => {
+                BackendError::ExternalService(ExternalServiceError::Database(msg))
+            }
+            DbError::Internal(err) => BackendError::InternalError(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use common::error_code::ErrorCode;
+
+    #[test]
+    fn test_db_error_through_anyhow() {
+        let err: anyhow::Error = DbError::NotFound.into();
+        assert!(DbError::from(err).is_not_found());
+        let err = DbError::from(anyhow!("other"));
+        assert!(matches!(err, DbError::Internal(_)));
+        let backend_err: BackendError = DbError::Conflict("order 1".to_string()).into();
+        assert_eq!(backend_err.code(), 8);
+    }
+}
@@ -3,18 +3,21 @@ extern crate rustc_serialize;
 use async_trait::async_trait;
 use common::data_structures::{
     bridge::{EthBridgeOrder, EthOrderStatus, OrderType as BridgeOrderType},
+    outbox::OutboxEventType,
     CoinType,
 };
 use serde::{Deserialize, Serialize};
-
-use std::fmt;
+use serde_json::json;
 
 use tokio_postgres::Row;
 
-use crate::{PgLocalCli, PsqlOp};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::outbox;
+use crate::{PgLocalCli, PgU128, PsqlOp, SqlBuilder, SqlClause};
 use anyhow::Result;
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct EthBridgeOrderEntity {
     pub order: EthBridgeOrder,
     pub updated_at: String,
@@ -33,20 +36,19 @@ pub enum BridgeOrderUpdater<'a> {
     Status(EthOrderStatus),
 }
 
-impl fmt::Display for BridgeOrderUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for BridgeOrderUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             BridgeOrderUpdater::EncrypedPrikey(by_password, by_answer) => {
-                format!(
-                    "(encrypted_prikey_by_password,encrypted_prikey_by_answer)=('{}','{}')",
-                    by_password, by_answer
-                )
+                sql.push("encrypted_prikey_by_password=")
+                    .bind(by_password.to_string())
+                    .push(",encrypted_prikey_by_answer=")
+                    .bind(by_answer.to_string());
             }
             BridgeOrderUpdater::Status(status) => {
-                format!("status='{}' ", status)
+                sql.push("status=").bind(status.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -54,22 +56,46 @@ impl fmt::Display for BridgeOrderUpdater<'_> {
 pub enum BridgeOrderFilter<'b> {
     ByTypeAndId(BridgeOrderType, &'b str),
     ByTypeAndAccountId(BridgeOrderType, &'b str),
+    //高度不超过指定值的订单，按高度升序
+    ByStatusBelowHeight(EthOrderStatus, u64),
+    //高度大于指定值的订单，分叉后回滚用
+    ByStatusAboveHeight(EthOrderStatus, u64),
     Limit(u32),
 }
 
-impl fmt::Display for BridgeOrderFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for BridgeOrderFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             BridgeOrderFilter::ByTypeAndId(order_type, id) => {
-                format!("where order_type='{}' and id='{}' ", order_type, id)
+                sql.push("where order_type=")
+                    .bind(order_type.to_string())
+                    .push(" and id=")
+                    .bind(id.to_string());
             }
-            BridgeOrderFilter::ByTypeAndAccountId(order_type, id) => format!(
-                "where order_type='{}' and chainless_acc='{}' order by created_at desc",
-                order_type, id
-            ),
-            BridgeOrderFilter::Limit(num) => format!("order by created_at desc limit {} ", num),
-        };
-        write!(f, "{}", description)
+            BridgeOrderFilter::ByTypeAndAccountId(order_type, id) => {
+                sql.push("where order_type=")
+                    .bind(order_type.to_string())
+                    .push(" and chainless_acc=")
+                    .bind(id.to_string())
+                    .push(" order by created_at desc");
+            }
+            BridgeOrderFilter::ByStatusBelowHeight(status, height) => {
+                sql.push("where status=")
+                    .bind(status.to_string())
+                    .push(" and height<=")
+                    .bind(*height as i64)
+                    .push(" order by height");
+            }
+            BridgeOrderFilter::ByStatusAboveHeight(status, height) => {
+                sql.push("where status=")
+                    .bind(status.to_string())
+                    .push(" and height>")
+                    .bind(*height as i64);
+            }
+            BridgeOrderFilter::Limit(num) => {
+                sql.push("order by created_at desc limit ").bind(*num as i64);
+            }
+        }
     }
 }
 
@@ -83,6 +109,7 @@ impl EthBridgeOrderEntity {
         amount: u128,
         status: EthOrderStatus,
         height: u64,
+        block_hash: &str,
     ) -> Self {
         EthBridgeOrderEntity {
             order: EthBridgeOrder {
@@ -94,6 +121,7 @@ impl EthBridgeOrderEntity {
                 amount,
                 status,
                 height,
+                block_hash: block_hash.to_owned(),
             },
             updated_at: "".to_string(),
             created_at: "".to_string(),
@@ -101,13 +129,103 @@ impl EthBridgeOrderEntity {
     }
 }
 
+impl MemoryRecord for EthBridgeOrderEntity {
+    const TABLE: &'static str = "ethereum_bridge_order";
+
+    fn matches(&self, filter: &BridgeOrderFilter<'_>) -> bool {
+        let order = &self.order;
+        match filter {
+            BridgeOrderFilter::ByTypeAndId(order_type, id) => {
+                order.order_type == *order_type && order.id == *id
+            }
+            BridgeOrderFilter::ByTypeAndAccountId(order_type, id) => {
+                order.order_type == *order_type && order.chainless_acc == *id
+            }
+            BridgeOrderFilter::ByStatusBelowHeight(status, height) => {
+                order.status == *status && order.height <= *height
+            }
+            BridgeOrderFilter::ByStatusAboveHeight(status, height) => {
+                order.status == *status && order.height > *height
+            }
+            BridgeOrderFilter::Limit(_) => true,
+        }
+    }
+
+    fn apply(&mut self, updater: &BridgeOrderUpdater<'_>) {
+        match updater {
+            //表里没有密钥相关的列
+            BridgeOrderUpdater::EncrypedPrikey(_, _) => {}
+            BridgeOrderUpdater::Status(status) => self.order.status = status.clone(),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!(
+            "type_status_id:{}:{}:{}",
+            self.order.order_type, self.order.status, self.order.id
+        )]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &BridgeOrderFilter<'_>) -> Vec<Self> {
+        match filter {
+            BridgeOrderFilter::ByTypeAndAccountId(_, _) => {
+                rows.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            }
+            BridgeOrderFilter::Limit(num) => {
+                rows.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                rows.truncate(*num as usize);
+            }
+            BridgeOrderFilter::ByStatusBelowHeight(_, _) => {
+                rows.sort_by_key(|row| row.order.height);
+            }
+            BridgeOrderFilter::ByTypeAndId(_, _) => {}
+            BridgeOrderFilter::ByStatusAboveHeight(_, _) => {}
+        }
+        rows
+    }
+}
+
+//跨链订单状态变化通过outbox通知下游
+async fn emit_status_changed(
+    new_value: &BridgeOrderUpdater<'_>,
+    filter: &BridgeOrderFilter<'_>,
+    row_num: u64,
+) -> DbResult<()> {
+    if let (
+        BridgeOrderUpdater::Status(status),
+        BridgeOrderFilter::ByTypeAndId(order_type, order_id),
+    ) = (new_value, filter)
+    {
+        if row_num > 0 {
+            outbox::emit(
+                OutboxEventType::BridgeOrderStatusChanged,
+                order_id,
+                &json!({
+                    "order_type": order_type,
+                    "order_id": order_id,
+                    "status": status
+                }),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
 #[async_trait]
 impl PsqlOp for EthBridgeOrderEntity {
     type UpdaterContent<'a> = BridgeOrderUpdater<'a>;
     type FilterContent<'b> = BridgeOrderFilter<'b>;
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<EthBridgeOrderEntity>> {
-        let sql = format!(
-            "select 
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<EthBridgeOrderEntity>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
             id,\
             order_type,\
             chainless_acc,\
@@ -116,13 +234,14 @@ impl PsqlOp for EthBridgeOrderEntity {
             amount,\
             status,\
             height,\
+            block_hash,\
          cast(updated_at as text), \
          cast(created_at as text) \
-         from ethereum_bridge_order {}",
-            filter
+         from ethereum_bridge_order ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get_secret: raw sql {}", sql);
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get_secret: raw sql {}", sql.sql());
         let gen_view = |row: &Row| {
             Ok(EthBridgeOrderEntity {
                 order: EthBridgeOrder {
@@ -131,34 +250,44 @@ impl PsqlOp for EthBridgeOrderEntity {
                     chainless_acc: row.get(2),
                     eth_addr: row.get(3),
                     coin: row.get::<usize, String>(4).parse()?,
-                    amount: row.get::<usize, String>(5).parse()?,
+                    amount: row.get::<usize, PgU128>(5).0,
                     status: row.get::<usize, String>(6).parse()?,
                     height: row.get::<usize, i64>(7) as u64,
+                    block_hash: row.get(8),
                 },
-                updated_at: row.get(8),
-                created_at: row.get(9),
+                updated_at: row.get(9),
+                created_at: row.get(10),
             })
         };
 
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
     //没有更新的需求
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "update ethereum_bridge_order set {} ,updated_at=CURRENT_TIMESTAMP {}",
-            new_value, filter
-        );
-        debug!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            let row_num = store.update::<Self>(&new_value, &filter);
+            emit_status_changed(&new_value, &filter, row_num).await?;
+            return Ok(row_num);
+        }
+        let mut sql = SqlBuilder::new("update ethereum_bridge_order set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP ")
+            .clause(&filter);
+        debug!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update orders {} rows", execute_res);
+        emit_status_changed(&new_value, &filter, execute_res).await?;
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let EthBridgeOrder {
             id,
             order_type,
@@ -168,8 +297,9 @@ impl PsqlOp for EthBridgeOrderEntity {
             coin,
             status,
             height,
+            block_hash,
         } = self.into_inner();
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into ethereum_bridge_order (\
                 id,\
                 order_type,\
@@ -178,17 +308,37 @@ impl PsqlOp for EthBridgeOrderEntity {
                 coin,\
                 amount,\
                 status,\
-                height
-         ) values ('{}','{}','{}','{}','{}','{}','{}',{});",
-            id, order_type, chainless_acc, eth_addr, coin, amount, status, height
+                height,\
+                block_hash\
+         ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let _execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        sql.bind_all(vec![
+            Box::new(id),
+            Box::new(order_type.to_string()),
+            Box::new(chainless_acc),
+            Box::new(eth_addr),
+            Box::new(coin.to_string()),
+            Box::new(PgU128(amount)),
+            Box::new(status.to_string()),
+            Box::new(height as i64),
+            Box::new(block_hash),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
         Ok(())
     }
 
-    async fn delete(_filter: Self::FilterContent<'_>) -> Result<()> {
-        todo!()
+    async fn delete(filter: Self::FilterContent<'_>) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            store.delete::<Self>(&filter);
+            return Ok(());
+        }
+        let mut sql = SqlBuilder::new("delete from ethereum_bridge_order ");
+        sql.clause(&filter);
+        let execute_res = sql.execute().await?;
+        debug!("success delete orders {} rows", execute_res);
+        Ok(())
     }
 }
 
@@ -216,6 +366,7 @@ mod tests {
                 10000u128,
                 EthOrderStatus::Pending,
                 0u64,
+                "",
             );
             secret.insert().await.unwrap();
             let find_res = EthBridgeOrderEntity::find_single(BridgeOrderFilter::ByTypeAndId(
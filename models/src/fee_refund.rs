@@ -0,0 +1,437 @@
+//! fee refunds of failed or cancelled coin transactions, the tx_id is the primary key
+//! so a transaction is refunded at most once
+
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use common::data_structures::coin_transaction::{CoinTransaction, TxType};
+use common::data_structures::fee_refund::{FeeRefund, FeeRefundStatus};
+use common::data_structures::CoinType;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PgU128, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct FeeRefundEntity {
+    pub refund: FeeRefund,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl FeeRefundEntity {
+    pub fn into_inner(self) -> FeeRefund {
+        self.refund
+    }
+}
+
+//扣过手续费的待退还，否则不用退
+fn checked_fee(fee: &Option<(CoinType, u128)>) -> (Option<CoinType>, u128, FeeRefundStatus) {
+    match fee {
+        Some((coin, amount)) if *amount > 0 => {
+            (Some(coin.clone()), *amount, FeeRefundStatus::Pending)
+        }
+        _ => (None, 0, FeeRefundStatus::NoFee),
+    }
+}
+
+impl FeeRefundEntity {
+    pub fn new_with_specified(
+        tx_id: &str,
+        order_id: &str,
+        account_id: &str,
+        fee: Option<(CoinType, u128)>,
+    ) -> Self {
+        let (fee_coin, amount, status) = checked_fee(&fee);
+        FeeRefundEntity {
+            refund: FeeRefund {
+                tx_id: tx_id.to_string(),
+                order_id: order_id.to_string(),
+                account_id: account_id.to_string(),
+                fee_coin,
+                amount,
+                status,
+                refund_tx_id: None,
+                last_error: None,
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+/// the refund goes to the main account of the user,
+/// it's the receiver when the sub account sends to the main account
+pub fn refund_account(tx: &CoinTransaction) -> &str {
+    match tx.tx_type {
+        TxType::SubToMain => &tx.receiver,
+        _ => &tx.sender,
+    }
+}
+
+/// keep the failed tx of an order before it's resubmitted under a new tx_id,
+/// its fee is checked and refunded later by the refund task
+pub async fn record_failed_attempt(
+    failed_tx_id: &str,
+    order_id: &str,
+    account_id: &str,
+) -> DbResult<()> {
+    let mut refund = FeeRefundEntity::new_with_specified(failed_tx_id, order_id, account_id, None);
+    refund.refund.status = FeeRefundStatus::Unchecked;
+    if let Some(store) = PgLocalCli::memory_store() {
+        return match store.insert(refund) {
+            Err(DbError::UniqueViolation(_)) => Ok(()),
+            res => res,
+        };
+    }
+    //在订单的事务里执行，重复的记录直接忽略而不是报错中断事务
+    let mut sql = SqlBuilder::new(
+        "insert into fee_refund (tx_id,order_id,account_id,amount,status) values (",
+    );
+    sql.bind_all(vec![
+        Box::new(refund.refund.tx_id),
+        Box::new(refund.refund.order_id),
+        Box::new(refund.refund.account_id),
+        Box::new(PgU128(0)),
+        Box::new(FeeRefundStatus::Unchecked.to_string()),
+    ])
+    .push(") on conflict (tx_id) do nothing");
+    debug!("record failed attempt: raw sql {}", sql.sql());
+    sql.execute().await?;
+    Ok(())
+}
+
+/// move a pending refund to Refunding, false if another task has taken it,
+/// only the caller who claimed it is allowed to send the refund
+pub async fn claim(tx_id: &str) -> DbResult<bool> {
+    let row_num = FeeRefundEntity::update(
+        FeeRefundUpdater::Status(FeeRefundStatus::Refunding),
+        FeeRefundFilter::ByTxIdStatus(tx_id, FeeRefundStatus::Pending),
+    )
+    .await?;
+    Ok(row_num == 1)
+}
+
+#[derive(Debug)]
+pub enum FeeRefundUpdater<'a> {
+    Status(FeeRefundStatus),
+    //refund_tx_id
+    Refunded(&'a str),
+    //error
+    Failed(&'a str),
+    //核对过的手续费，没有扣费时为None
+    Checked(Option<(CoinType, u128)>),
+}
+
+impl SqlClause for FeeRefundUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            FeeRefundUpdater::Status(status) => {
+                sql.push("status=").bind(status.to_string());
+            }
+            FeeRefundUpdater::Refunded(refund_tx_id) => {
+                sql.push("status=")
+                    .bind(FeeRefundStatus::Refunded.to_string())
+                    .push(",refund_tx_id=")
+                    .bind(refund_tx_id.to_string());
+            }
+            FeeRefundUpdater::Failed(error) => {
+                sql.push("status=")
+                    .bind(FeeRefundStatus::Failed.to_string())
+                    .push(",last_error=")
+                    .bind(error.to_string());
+            }
+            FeeRefundUpdater::Checked(fee) => {
+                let (fee_coin, amount, status) = checked_fee(fee);
+                sql.push("status=")
+                    .bind(status.to_string())
+                    .push(",fee_coin=")
+                    .bind(fee_coin.map(|coin| coin.to_string()))
+                    .push(",amount=")
+                    .bind(PgU128(amount));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FeeRefundFilter<'b> {
+    ByTxId(&'b str),
+    ByTxIdStatus(&'b str, FeeRefundStatus),
+    //最久没有处理过的n条
+    ByStatus(FeeRefundStatus, u32),
+}
+
+impl SqlClause for FeeRefundFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            FeeRefundFilter::ByTxId(tx_id) => {
+                sql.push("tx_id=").bind(tx_id.to_string());
+            }
+            FeeRefundFilter::ByTxIdStatus(tx_id, status) => {
+                sql.push("tx_id=")
+                    .bind(tx_id.to_string())
+                    .push(" and status=")
+                    .bind(status.to_string());
+            }
+            FeeRefundFilter::ByStatus(status, limit) => {
+                sql.push("status=")
+                    .bind(status.to_string())
+                    .push(" order by updated_at asc limit ")
+                    .bind(*limit as i64);
+            }
+        }
+    }
+}
+
+impl MemoryRecord for FeeRefundEntity {
+    const TABLE: &'static str = "fee_refund";
+
+    fn matches(&self, filter: &FeeRefundFilter<'_>) -> bool {
+        match filter {
+            FeeRefundFilter::ByTxId(tx_id) => self.refund.tx_id == *tx_id,
+            FeeRefundFilter::ByTxIdStatus(tx_id, status) => {
+                self.refund.tx_id == *tx_id && self.refund.status == *status
+            }
+            FeeRefundFilter::ByStatus(status, _) => self.refund.status == *status,
+        }
+    }
+
+    fn apply(&mut self, updater: &FeeRefundUpdater<'_>) {
+        match updater {
+            FeeRefundUpdater::Status(status) => self.refund.status = status.clone(),
+            FeeRefundUpdater::Refunded(refund_tx_id) => {
+                self.refund.status = FeeRefundStatus::Refunded;
+                self.refund.refund_tx_id = Some(refund_tx_id.to_string());
+            }
+            FeeRefundUpdater::Failed(error) => {
+                self.refund.status = FeeRefundStatus::Failed;
+                self.refund.last_error = Some(error.to_string());
+            }
+            FeeRefundUpdater::Checked(fee) => {
+                let (fee_coin, amount, status) = checked_fee(fee);
+                self.refund.fee_coin = fee_coin;
+                self.refund.amount = amount;
+                self.refund.status = status;
+            }
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("tx_id:{}", self.refund.tx_id)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &FeeRefundFilter<'_>) -> Vec<Self> {
+        if let FeeRefundFilter::ByStatus(_, limit) = filter {
+            rows.sort_by(|a, b| a.updated_at.cmp(&b.updated_at));
+            rows.truncate(*limit as usize);
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for FeeRefundEntity {
+    type UpdaterContent<'a> = FeeRefundUpdater<'a>;
+    type FilterContent<'b> = FeeRefundFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            tx_id,\
+            order_id,\
+            account_id,\
+            fee_coin,\
+            amount,\
+            status,\
+            refund_tx_id,\
+            last_error,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from fee_refund where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get fee_refund: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<FeeRefundEntity> {
+            Ok(FeeRefundEntity {
+                refund: FeeRefund {
+                    tx_id: row.get(0),
+                    order_id: row.get(1),
+                    account_id: row.get(2),
+                    fee_coin: row
+                        .get::<usize, Option<&str>>(3)
+                        .map(CoinType::from_str)
+                        .transpose()?,
+                    amount: row.get::<usize, PgU128>(4).0,
+                    status: row.get::<usize, String>(5).parse()?,
+                    refund_tx_id: row.get(6),
+                    last_error: row.get(7),
+                },
+                updated_at: row.get(8),
+                created_at: row.get(9),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update fee_refund set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update fee_refund {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        debug!("success update fee_refund {} rows", execute_res);
+        Ok(execute_res)
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let FeeRefund {
+            tx_id,
+            order_id,
+            account_id,
+            fee_coin,
+            amount,
+            status,
+            refund_tx_id,
+            last_error,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into fee_refund (\
+                tx_id,\
+                order_id,\
+                account_id,\
+                fee_coin,\
+                amount,\
+                status,\
+                refund_tx_id,\
+                last_error\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(tx_id),
+            Box::new(order_id),
+            Box::new(account_id),
+            Box::new(fee_coin.map(|coin| coin.to_string())),
+            Box::new(PgU128(amount)),
+            Box::new(status.to_string()),
+            Box::new(refund_tx_id),
+            Box::new(last_error),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_fee_refund_only_once() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let refund = FeeRefundEntity::new_with_specified(
+                "tx1",
+                "order1",
+                "1.test",
+                Some((CoinType::USDT, 100)),
+            );
+            refund.clone().insert().await.unwrap();
+            //同一笔交易不能记录两次
+            let err = refund.insert().await.unwrap_err();
+            assert!(matches!(err, DbError::UniqueViolation(_)));
+
+            assert!(claim("tx1").await.unwrap());
+            //已经被领走的退款不能再发
+            assert!(!claim("tx1").await.unwrap());
+            FeeRefundEntity::update_single(
+                FeeRefundUpdater::Refunded("refund_tx1"),
+                FeeRefundFilter::ByTxId("tx1"),
+            )
+            .await
+            .unwrap();
+            assert!(!claim("tx1").await.unwrap());
+
+            let pending =
+                FeeRefundEntity::find(FeeRefundFilter::ByStatus(FeeRefundStatus::Pending, 10))
+                    .await
+                    .unwrap();
+            assert!(pending.is_empty());
+
+            let no_fee = FeeRefundEntity::new_with_specified("tx2", "order2", "1.test", None);
+            assert_eq!(no_fee.refund.status, FeeRefundStatus::NoFee);
+
+            //重新提交之前失败的交易先记下，核对手续费之后才能退
+            record_failed_attempt("tx3", "order3", "1.test")
+                .await
+                .unwrap();
+            record_failed_attempt("tx3", "order3", "1.test")
+                .await
+                .unwrap();
+            assert!(!claim("tx3").await.unwrap());
+            FeeRefundEntity::update_single(
+                FeeRefundUpdater::Checked(Some((CoinType::USDT, 50))),
+                FeeRefundFilter::ByTxIdStatus("tx3", FeeRefundStatus::Unchecked),
+            )
+            .await
+            .unwrap();
+            let refund = FeeRefundEntity::find_single(FeeRefundFilter::ByTxId("tx3"))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(refund.account_id, "1.test");
+            assert_eq!(refund.amount, 50);
+            assert!(claim("tx3").await.unwrap());
+
+            //待核对的按更新时间轮流取，刚重试过的排到后面
+            record_failed_attempt("tx4", "order4", "1.test")
+                .await
+                .unwrap();
+            record_failed_attempt("tx5", "order5", "1.test")
+                .await
+                .unwrap();
+            //时间戳精度到微秒，隔开一点保证先后
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            FeeRefundEntity::update(
+                FeeRefundUpdater::Status(FeeRefundStatus::Unchecked),
+                FeeRefundFilter::ByTxIdStatus("tx4", FeeRefundStatus::Unchecked),
+            )
+            .await
+            .unwrap();
+            let unchecked =
+                FeeRefundEntity::find(FeeRefundFilter::ByStatus(FeeRefundStatus::Unchecked, 1))
+                    .await
+                    .unwrap();
+            assert_eq!(unchecked[0].refund.tx_id, "tx5");
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
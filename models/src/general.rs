@@ -1,92 +1,83 @@
-use crate::LocalConn;
 use crate::PgLocalCli;
 use crate::LOCAL_CLI;
 use anyhow::Result;
+use common::constants::PG_POOL_SIZE;
 use futures::Future;
-use std::cell::RefCell;
 use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
-use anyhow::anyhow;
+use crate::account_manager::UserInfoEntity;
+use crate::airdrop::AirdropEntity;
+use crate::memory::MemoryStore;
+use crate::unit_of_work::UnitOfWork;
 
-use crate::PG_POOL;
-
-pub async fn gen_db_cli(method: &str) -> Result<(PgLocalCli, *mut LocalConn)> {
-    let conn = PG_POOL.get().await?;
-    debug!("pool_status {:?}", PG_POOL.status());
-    let conn = Box::new(conn);
-    let conn: &'static mut LocalConn = Box::leak(conn);
-    let conn_ptr = conn as *mut LocalConn;
-
-    let db_cli = match method {
-        "POST" => {
-            let trans = conn.transaction().await?;
-            PgLocalCli::Trans(trans)
-        }
-        _ => PgLocalCli::Conn(conn),
-    };
-    Ok((db_cli, conn_ptr))
+/// post请求在事务里执行，其他请求自动提交
+pub async fn gen_unit_of_work(method: &str) -> Result<Arc<UnitOfWork>> {
+    let uow = UnitOfWork::new(method == "POST").await?;
+    Ok(Arc::new(uow))
 }
 
-/*** 
-pub async fn clean_db_cli(conn_ptr: *mut LocalConn) -> Result<()> {
-    let db_cli = LOCAL_CLI.with(|db_cli| -> Result<PgLocalCli> {
-        let mut db_cli = db_cli.borrow_mut();
-        let db_cli = db_cli.take().ok_or(anyhow!(""))?;
-        let db_cli = Arc::into_inner(db_cli).ok_or(anyhow!(""))?;
-        Ok(db_cli)
-    })?;
-
-    db_cli.commit().await?;
-    unsafe {
-        let _ = Box::from_raw(conn_ptr);
-    };
-    Ok(())
+lazy_static! {
+    //请求的unit of work最多占一半连接，另一半留给请求里单独提交的写入，两者不会互相等死
+    static ref REQUEST_SLOTS: Arc<Semaphore> = Arc::new(Semaphore::new(PG_POOL_SIZE / 2));
 }
-***/
 
-
-pub fn clean_conn(conn_ptr: *mut LocalConn) {
-    unsafe {
-        let _ = Box::from_raw(conn_ptr);
-    };
-    debug!("pool_status {:?}", PG_POOL.status());
+/// the unit of work of a request, keep the permit until its connection is released,
+/// the writes committing on their own within the request always find a free connection
+pub async fn gen_request_unit_of_work(
+    method: &str,
+) -> Result<(Arc<UnitOfWork>, OwnedSemaphorePermit)> {
+    let permit = REQUEST_SLOTS.clone().acquire_owned().await?;
+    let uow = gen_unit_of_work(method).await?;
+    Ok((uow, permit))
 }
 
-pub fn into_local_cli() -> Result<PgLocalCli>{
-    LOCAL_CLI.with(|db_cli| -> Result<PgLocalCli> {
-        let mut db_cli = db_cli.borrow_mut();
-        let db_cli = db_cli.take().ok_or(anyhow!(""))?;
-        let db_cli = Arc::into_inner(db_cli).ok_or(anyhow!(""))?;
-        Ok(db_cli)
-    })
+pub async fn begin() -> Result<()> {
+    PgLocalCli::current().begin().await
 }
 
 pub async fn commit() -> Result<()> {
-    let db_cli = into_local_cli()?;
-    db_cli.commit().await?;
-    Ok(())
+    PgLocalCli::current().commit().await
 }
 
 pub async fn rollback() -> Result<()> {
-    let db_cli = into_local_cli()?;
-    db_cli.rollback().await?;
-    Ok(())
+    PgLocalCli::current().rollback().await
 }
 
-pub async fn run_api_call<Fut, R>(method: &str, task: Fut) -> Result<(*mut LocalConn,R)>
+/// run the task which can't fail within a new unit of work, commit it when the task returns
+pub async fn run_api_call<Fut>(method: &str, task: Fut) -> Result<()>
 where
-    Fut: Future<Output = R> + 'static,
+    Fut: Future<Output = ()>,
 {
-    let (db_cli, conn_ptr) = gen_db_cli(method).await?;
-    let res = crate::LOCAL_CLI
-        .scope(RefCell::new(Some(Arc::new(db_cli))), async move {
-            task.await
-        })
-        .await;
-    Ok((conn_ptr,res))
+    let uow = gen_unit_of_work(method).await?;
+    LOCAL_CLI.scope(uow.clone(), task).await;
+    uow.commit().await
+}
+
+/// run the task within a new unit of work, commit it when the task succeeds
+/// and roll it back when the task fails, same as the request middleware
+pub async fn try_run_api_call<Fut, T, E>(
+    method: &str,
+    task: Fut,
+) -> Result<std::result::Result<T, E>>
+where
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let uow = gen_unit_of_work(method).await?;
+    let res = LOCAL_CLI.scope(uow.clone(), task).await;
+    if res.is_ok() {
+        uow.commit().await?;
+    } else {
+        uow.rollback().await?;
+    }
+    Ok(res)
 }
 
 pub async fn table_clear(table_name: &str) -> Result<(), String> {
+    if let Some(store) = crate::memory::backend() {
+        store.clear(table_name);
+        return Ok(());
+    }
     let sql = format!("truncate table {} restart identity", table_name);
     crate::PG_POOL
         .get()
@@ -99,6 +90,9 @@ pub async fn table_clear(table_name: &str) -> Result<(), String> {
 }
 
 pub async fn init_system_config() -> Result<(), String> {
+    if let Some(store) = crate::memory::backend() {
+        return init_memory_system_config(&store).map_err(|e| e.to_string());
+    }
     let insert_root_user = "insert into users (
         id,
         phone_number,
@@ -130,13 +124,39 @@ pub async fn init_system_config() -> Result<(), String> {
     Ok(())
 }
 
+//和init_system_config插入的数据保持一致
+pub(crate) fn init_memory_system_config(store: &MemoryStore) -> Result<()> {
+    let mut root_user = UserInfoEntity::new_with_specified(66, "a6666666");
+    root_user.user_info.email = Some("1@gmail.com".to_string());
+    root_user.user_info.anwser_indexes = "answer123".to_string();
+    root_user.user_info.kyc_is_verified = true;
+    root_user.user_info.main_account = Some("66.local".to_string());
+    store.insert(root_user)?;
+
+    let mut root_airdrop = AirdropEntity::new_with_specified(66, 0, "0.local");
+    root_airdrop.airdrop.account_id = Some("66.local".to_string());
+    root_airdrop.airdrop.invite_code = "chainless.hk".to_string();
+    root_airdrop.airdrop.btc_address = Some("btc_address_abc".to_string());
+    root_airdrop.airdrop.btc_level = Some(0);
+    store.insert(root_airdrop)?;
+    Ok(())
+}
+
 pub async fn table_all_clear() {
     table_clear("airdrop").await.unwrap();
     table_clear("users").await.unwrap();
     table_clear("coin_transaction").await.unwrap();
+    table_clear("coin_transaction_archive").await.unwrap();
     table_clear("device_info").await.unwrap();
     table_clear("secret_store").await.unwrap();
     table_clear("ethereum_bridge_order").await.unwrap();
     table_clear("wallet_manage_record").await.unwrap();
+    table_clear("audit_event").await.unwrap();
+    table_clear("outbox").await.unwrap();
+    table_clear("scanner_checkpoint").await.unwrap();
+    table_clear("fee_refund").await.unwrap();
+    table_clear("captcha").await.unwrap();
+    table_clear("login_attempt").await.unwrap();
+    table_clear("user_session").await.unwrap();
     init_system_config().await.unwrap();
 }
@@ -6,12 +6,23 @@
 
 pub mod account_manager;
 pub mod airdrop;
+pub mod audit_event;
+pub mod captcha;
 #[macro_use]
 pub mod general;
 pub mod coin_transfer;
 pub mod device_info;
+pub mod error;
 pub mod eth_bridge_order;
+pub mod fee_refund;
+pub mod login_attempt;
+pub mod memory;
+pub mod migration;
+pub mod outbox;
+pub mod scanner_checkpoint;
 pub mod secret_store;
+pub mod unit_of_work;
+pub mod user_session;
 pub mod wallet_manage_record;
 
 //#[macro_use]
@@ -26,110 +37,58 @@ extern crate jsonrpc_client_http;
 extern crate rustc_serialize;
 extern crate tokio_postgres;
 
-use anyhow::anyhow;
-use anyhow::Ok;
 use anyhow::Result;
-use common::constants::PG_POOL_SIZE;
+use common::constants::{PG_POOL_SIZE, PG_POOL_WAIT_SECS};
 use deadpool::managed::Object;
-use std::cell::RefCell;
-use std::fmt::Display;
+use std::fmt::Debug;
 
 use std::sync::Arc;
 
 use deadpool_postgres::Manager;
 use deadpool_postgres::Pool;
-use deadpool_postgres::Transaction;
-use deadpool_postgres::{Config, ManagerConfig, RecyclingMethod};
+use deadpool_postgres::{Config, ManagerConfig, RecyclingMethod, Runtime, Timeouts};
+use bytes::BytesMut;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
 use tokio_postgres::NoTls;
 use tokio_postgres::Row;
 
 use async_trait::async_trait;
+use error::{DbError, DbResult};
+use memory::MemoryStore;
+use unit_of_work::UnitOfWork;
 
 pub type LocalConn = Object<Manager>;
 
 static TRY_TIMES: u8 = 5;
 
-/****
-
-    DBError::RepeatedData,
-    DBError::DataNotFound,
-    DBError::KeyAlreadyExsit,
-*/
-
 lazy_static! {
     pub static ref PG_POOL: Pool = connect_pool().unwrap();
 }
 
 tokio::task_local! {
-    pub static  LOCAL_CLI: RefCell<Option<Arc<PgLocalCli>>>;
+    pub static LOCAL_CLI: Arc<UnitOfWork>;
 }
 
-pub enum PgLocalCli {
-    Conn(&'static mut LocalConn),
-    Trans(Transaction<'static>),
-}
+/// sql entry bound to the unit of work of current task
+pub struct PgLocalCli;
 
 impl PgLocalCli {
-    pub async fn execute(sql: &str) -> Result<u64> {
-        debug!(sql);
-        let cli = LOCAL_CLI.with(|cli| cli.borrow().as_ref().unwrap().clone());
-        let line = match cli.as_ref() {
-            PgLocalCli::Conn(c) => c.execute(sql, &[]).await?,
-            PgLocalCli::Trans(t) => t.execute(sql, &[]).await?,
-        };
-        Ok(line)
-    }
-    pub async fn query(sql: &str) -> Result<Vec<Row>> {
-        debug!(sql);
-        let cli = LOCAL_CLI.with(|cli| cli.borrow().as_ref().unwrap().clone());
-        let row = match cli.as_ref() {
-            PgLocalCli::Conn(c) => c.query(sql, &[]).await?,
-            PgLocalCli::Trans(t) => t.query(sql, &[]).await?,
-        };
-        Ok(row)
-    }
-    pub async fn commit(self) -> Result<()> {
-        match self {
-            PgLocalCli::Conn(_c) => {
-                debug!("it's not a trans");
-                Ok(())
-            }
-            PgLocalCli::Trans(t) => Ok(t.commit().await?),
-        }
+    pub fn current() -> Arc<UnitOfWork> {
+        LOCAL_CLI.with(|uow| uow.clone())
     }
 
-    pub async fn rollback(self) -> Result<()> {
-        match self {
-            PgLocalCli::Conn(_c) => {
-                debug!("it's not a trans");
-                Ok(())
-            }
-            PgLocalCli::Trans(t) => Ok(t.rollback().await?),
-        }
+    /// the store to use instead of sql when the memory backend is enabled
+    pub fn memory_store() -> Option<Arc<MemoryStore>> {
+        LOCAL_CLI.with(|uow| uow.memory_store())
     }
 
-    pub async fn begin(&'static mut self) -> Result<PgLocalCli> {
-        match self {
-            PgLocalCli::Conn(c) => {
-                let trans = c.transaction().await?;
-                Ok(PgLocalCli::Trans(trans))
-            }
-            PgLocalCli::Trans(_t) => {
-                panic!("It is already a trans");
-            }
-        }
+    pub async fn execute(sql: &str, params: &[&(dyn ToSql + Sync)]) -> DbResult<u64> {
+        debug!("{} {:?}", sql, params);
+        Self::current().execute(sql, params).await
     }
-}
-
-impl From<&'static mut LocalConn> for PgLocalCli {
-    fn from(value: &'static mut LocalConn) -> Self {
-        Self::Conn(value)
-    }
-}
-
-impl From<Transaction<'static>> for PgLocalCli {
-    fn from(value: Transaction<'static>) -> Self {
-        Self::Trans(value)
+    pub async fn query(sql: &str, params: &[&(dyn ToSql + Sync)]) -> DbResult<Vec<Row>> {
+        debug!("{} {:?}", sql, params);
+        Self::current().query(sql, params).await
     }
 }
 
@@ -147,208 +106,224 @@ fn connect_pool() -> Result<Pool> {
 
     cfg.pool = Some(deadpool_postgres::PoolConfig {
         max_size: PG_POOL_SIZE,
-        timeouts: Default::default(),
+        timeouts: Timeouts {
+            wait: Some(std::time::Duration::from_secs(PG_POOL_WAIT_SECS)),
+            ..Default::default()
+        },
         queue_mode: Default::default(),
     });
 
-    let pool = cfg.create_pool(None, NoTls).unwrap();
+    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls).unwrap();
     Ok(pool)
 }
 
 #[async_trait]
 pub trait PsqlOp {
-    type UpdaterContent<'a>: Display + Send;
-    type FilterContent<'b>: Display + Send;
+    type UpdaterContent<'a>: SqlClause + Debug + Send;
+    type FilterContent<'b>: SqlClause + Debug + Send;
 
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<Self>>
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>>
     where
         Self: Sized + Send;
-    async fn find_single(filter: Self::FilterContent<'_>) -> Result<Self>
+    async fn find_single(filter: Self::FilterContent<'_>) -> DbResult<Self>
     where
         Self: Sized + Send,
     {
         let mut get_res: Vec<Self> = Self::find(filter).await?;
         let data_len = get_res.len();
         if data_len == 0 {
-            //todo:return db error type
-            let error_info = "DBError::DataNotFound: data isn't existed";
-            error!("{}", error_info);
-            Err(anyhow!(error_info.to_string()))
+            Err(DbError::NotFound)
         } else if data_len > 1 {
-            let error_info = "DBError::RepeatedData: data is repeated";
-            error!("{}", error_info);
-            Err(anyhow!(error_info.to_string()))
+            error!("find single but get {} rows", data_len);
+            Err(DbError::Repeated)
         } else {
             Ok(get_res.pop().unwrap())
         }
     }
-    async fn delete(_filter: Self::FilterContent<'_>) -> Result<()> {
+    async fn delete(_filter: Self::FilterContent<'_>) -> DbResult<()> {
         todo!()
     }
 
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64>;
+    ) -> DbResult<u64>;
 
     async fn update_single(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<()>
+    ) -> DbResult<()>
     where
         Self: Sized + Send,
     {
         let row_num = Self::update(new_value, filter).await?;
         if row_num == 0 {
-            //todo:return db error type
-            let error_info = "DBError::DataNotFound: data isn't existed";
-            error!("{}", error_info);
-            Err(anyhow!(error_info.to_string()))
+            Err(DbError::NotFound)
         } else if row_num > 1 {
-            let error_info = "DBError::RepeatedData: data is repeated";
-            error!("{}", error_info);
-            Err(anyhow!(error_info.to_string()))
+            error!("update single but affect {} rows", row_num);
+            Err(DbError::Repeated)
         } else {
             Ok(())
         }
     }
 
-    async fn insert(self) -> Result<()>;
+    async fn insert(self) -> DbResult<()>;
 
     //insert after check key
-    async fn safe_insert(self, filter: Self::FilterContent<'_>) -> Result<()>
+    async fn safe_insert(self, filter: Self::FilterContent<'_>) -> DbResult<()>
     where
         Self: Sized + Send,
     {
-        let filter_str = filter.to_string();
+        let filter_str = format!("{:?}", filter);
         let find_res: Vec<Self> = Self::find(filter).await?;
         if find_res.is_empty() {
             self.insert().await
         } else {
-            //let error_info = "DBError::KeyAlreadyExsit: key already existed";
-            //error!("{}", error_info);
-            //Err(anyhow!(error_info.to_string()))
             info!("data {} already exist", filter_str);
             Ok(())
         }
     }
 }
 
-pub trait FormatSql {
-    fn string4sql(&self) -> String;
+/// A sql fragment that can be appended to a [`SqlBuilder`],
+/// every value goes to the bound parameters instead of the sql text
+pub trait SqlClause {
+    fn write_sql(&self, sql: &mut SqlBuilder);
 }
 
-impl FormatSql for String {
-    fn string4sql(&self) -> String {
-        format!("'{}'", self)
-    }
+pub type SqlParam = Box<dyn ToSql + Sync + Send>;
+
+/// sql statement with `$n` placeholders and the parameters bound to them
+#[derive(Debug, Default)]
+pub struct SqlBuilder {
+    sql: String,
+    params: Vec<SqlParam>,
 }
 
-fn assembly_insert_values(lines: Vec<Vec<String>>) -> String {
-    let mut lines_str = "".to_string();
-    let mut index = 0;
-    let len = lines.len();
-    for line in lines {
-        let mut line_str = "".to_string();
-        for i in 0..line.len() {
-            if i < line.len() - 1 {
-                line_str = format!("{}{},", line_str, line[i]);
-            } else {
-                line_str = format!("{}{}", line_str, line[i]);
-            }
-        }
-        if index < len - 1 {
-            lines_str = format!("{}{}),(", lines_str, line_str);
-        } else {
-            lines_str = format!("{}{})", lines_str, line_str);
+impl SqlBuilder {
+    pub fn new(sql: &str) -> Self {
+        SqlBuilder {
+            sql: sql.to_owned(),
+            params: vec![],
         }
-        index += 1;
     }
-    lines_str
-}
 
-pub fn vec_str2array_text(vec: Vec<String>) -> String {
-    let array_elements: Vec<String> = vec
-        .into_iter()
-        .map(|s| format!("'{}'", s.replace('\'', "''")))
-        .collect();
+    /// append raw sql text, never pass user input here
+    pub fn push(&mut self, sql: &str) -> &mut Self {
+        self.sql.push_str(sql);
+        self
+    }
 
-    format!("ARRAY[{}]::text[]", array_elements.join(","))
-}
+    /// append a placeholder and bind the value to it
+    pub fn bind<T>(&mut self, value: T) -> &mut Self
+    where
+        T: ToSql + Sync + Send + 'static,
+    {
+        self.params.push(Box::new(value));
+        self.sql.push_str(&format!("${}", self.params.len()));
+        self
+    }
 
-pub enum PsqlType {
-    VecStr(Vec<String>),
-    VecU64(Vec<u64>),
-    OptionStr(Option<String>),
-    OptionU64(Option<u64>),
-    OptionU8(Option<u8>),
-}
+    /// append several values separated by comma, e.g. for insert values
+    pub fn bind_all(&mut self, values: Vec<SqlParam>) -> &mut Self {
+        let len = values.len();
+        for (index, value) in values.into_iter().enumerate() {
+            self.params.push(value);
+            self.sql.push_str(&format!("${}", self.params.len()));
+            if index < len - 1 {
+                self.sql.push(',');
+            }
+        }
+        self
+    }
 
-impl PsqlType {
-    pub fn to_psql_str(&self) -> String {
-        match self {
-            PsqlType::VecStr(data) => {
-                let array_elements: Vec<String> = data
-                    .iter()
-                    .map(|s| format!("'{}'", s.replace('\'', "''")))
-                    .collect();
+    pub fn clause<C: SqlClause + ?Sized>(&mut self, clause: &C) -> &mut Self {
+        clause.write_sql(self);
+        self
+    }
 
-                format!("ARRAY[{}]::text[]", array_elements.join(","))
-            }
-            PsqlType::VecU64(data) => {
-                let array_elements: Vec<String> = data.iter().map(|s| format!("{}", s)).collect();
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
 
-                format!("ARRAY[{}]::int4[]", array_elements.join(","))
-            }
-            PsqlType::OptionStr(data) => data
-                .to_owned()
-                .map(|x| format!("'{}'", x))
-                .unwrap_or("NULL".to_string()),
-            PsqlType::OptionU64(data) => {
-                data.map(|x| format!("{}", x)).unwrap_or("NULL".to_string())
-            }
-            PsqlType::OptionU8(data) => {
-                data.map(|x| format!("{}", x)).unwrap_or("NULL".to_string())
-            }
-        }
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect()
     }
-}
 
-impl From<Vec<String>> for PsqlType {
-    fn from(value: Vec<String>) -> Self {
-        PsqlType::VecStr(value)
+    pub async fn execute(&self) -> DbResult<u64> {
+        PgLocalCli::execute(&self.sql, &self.params()).await
     }
-}
 
-impl From<Vec<u64>> for PsqlType {
-    fn from(value: Vec<u64>) -> Self {
-        PsqlType::VecU64(value)
+    pub async fn query(&self) -> DbResult<Vec<Row>> {
+        PgLocalCli::query(&self.sql, &self.params()).await
     }
 }
 
-impl From<Option<String>> for PsqlType {
-    fn from(value: Option<String>) -> Self {
-        PsqlType::OptionStr(value)
+/// u128 amount saved in a text column,
+/// tokio_postgres has no native mapping for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgU128(pub u128);
+
+impl ToSql for PgU128 {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> std::result::Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.0.to_string().as_str().to_sql(ty, out)
     }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
 }
 
-impl From<Option<u64>> for PsqlType {
-    fn from(value: Option<u64>) -> Self {
-        PsqlType::OptionU64(value)
+impl<'a> FromSql<'a> for PgU128 {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let value = <&str as FromSql>::from_sql(ty, raw)?;
+        std::result::Result::Ok(PgU128(value.parse()?))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
     }
 }
 
-impl From<Option<u8>> for PsqlType {
-    fn from(value: Option<u8>) -> Self {
-        PsqlType::OptionU8(value)
+impl From<u128> for PgU128 {
+    fn from(value: u128) -> Self {
+        PgU128(value)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn test_sql_builder_placeholder() {
+        let mut sql = SqlBuilder::new("insert into t (a,b,c) values (");
+        sql.bind_all(vec![
+            Box::new("it's".to_string()),
+            Box::new(PgU128(u128::MAX)),
+            Box::new(vec!["1".to_string()]),
+        ])
+        .push(") where d=")
+        .bind(1i64);
+        assert_eq!(sql.sql(), "insert into t (a,b,c) values ($1,$2,$3) where d=$4");
+        assert_eq!(sql.params().len(), 4);
+    }
 }
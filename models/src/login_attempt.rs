@@ -0,0 +1,373 @@
+//! password login attempts, the failures since the latest success or unlock decide the lockout
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::data_structures::login_attempt::{LoginAttempt, LoginAttemptResult};
+use common::utils::math::generate_random_hex_string;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct LoginAttemptEntity {
+    pub attempt: LoginAttempt,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl LoginAttemptEntity {
+    pub fn into_inner(self) -> LoginAttempt {
+        self.attempt
+    }
+}
+
+impl LoginAttemptEntity {
+    pub fn new_with_specified(
+        user_id: u32,
+        device_id: &str,
+        ip: &str,
+        result: LoginAttemptResult,
+        attempted_at: u64,
+    ) -> Self {
+        LoginAttemptEntity {
+            attempt: LoginAttempt {
+                attempt_id: generate_random_hex_string(32),
+                user_id,
+                device_id: device_id.to_string(),
+                ip: ip.to_string(),
+                result,
+                attempted_at,
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+pub async fn record(
+    user_id: u32,
+    device_id: &str,
+    ip: &str,
+    result: LoginAttemptResult,
+    attempted_at: u64,
+) -> DbResult<()> {
+    LoginAttemptEntity::new_with_specified(user_id, device_id, ip, result, attempted_at)
+        .insert()
+        .await
+}
+
+/// the latest failures in a row, newest first, at most `limit`
+pub async fn failures_in_row(user_id: u32, limit: u32) -> DbResult<Vec<LoginAttempt>> {
+    let attempts = LoginAttemptEntity::find(LoginAttemptFilter::Latest(user_id, limit)).await?;
+    Ok(in_row(attempts))
+}
+
+fn in_row(attempts: Vec<LoginAttemptEntity>) -> Vec<LoginAttempt> {
+    attempts
+        .into_iter()
+        .map(LoginAttemptEntity::into_inner)
+        .take_while(|attempt| attempt.result == LoginAttemptResult::Failed)
+        .collect()
+}
+
+/// password login checked against the lockout
+#[derive(Debug, PartialEq)]
+pub enum LoginGate {
+    /// locked until the time (ms), the attempt isn't recorded
+    Locked(u64),
+    /// the attempt is recorded, chances left before one more wrong password locks the user
+    Recorded(u8),
+}
+
+//同一个用户的登陆在数据库里排队的锁空间
+const LOGIN_LOCK_SPACE: i32 = 0x4c4f47;
+
+//按最近连续的错误决定是否放行，放行时返回要追加的记录
+fn decide(
+    failures: &[LoginAttempt],
+    attempt: LoginAttemptEntity,
+    max_failures: u8,
+    unlock_ms: u64,
+) -> (LoginGate, Vec<LoginAttemptEntity>) {
+    let mut failed = failures.len();
+    let mut records = Vec::new();
+    if failed > 0 && failed >= max_failures as usize {
+        let unlock_time = failures[0].attempted_at + unlock_ms;
+        if attempt.attempt.attempted_at <= unlock_time {
+            return (LoginGate::Locked(unlock_time), records);
+        }
+        //锁定到期，记在解锁的时刻，之前的错误次数清零
+        records.push(LoginAttemptEntity::new_with_specified(
+            attempt.attempt.user_id,
+            "",
+            "",
+            LoginAttemptResult::Unlocked,
+            unlock_time,
+        ));
+        failed = 0;
+    }
+    records.push(attempt);
+    let remain = max_failures.saturating_sub(1 + failed as u8);
+    (LoginGate::Recorded(remain), records)
+}
+
+/// check the lockout and record the attempt in one step, the attempts of a user are
+/// serialized by a transaction level lock, so run it in a transaction of its own
+pub async fn attempt(
+    user_id: u32,
+    device_id: &str,
+    ip: &str,
+    passed: bool,
+    attempted_at: u64,
+    max_failures: u8,
+    unlock_ms: u64,
+) -> DbResult<LoginGate> {
+    let result = if passed {
+        LoginAttemptResult::Succeeded
+    } else {
+        LoginAttemptResult::Failed
+    };
+    let attempt =
+        LoginAttemptEntity::new_with_specified(user_id, device_id, ip, result, attempted_at);
+    let filter = LoginAttemptFilter::Latest(user_id, max_failures as u32);
+    if let Some(store) = PgLocalCli::memory_store() {
+        //整张表加锁，判断和写入之间没有其他请求插进来
+        return Ok(store.locked(|rows: &[LoginAttemptEntity]| {
+            let latest = rows
+                .iter()
+                .filter(|row| row.matches(&filter))
+                .cloned()
+                .collect();
+            let failures = in_row(LoginAttemptEntity::select(latest, &filter));
+            decide(&failures, attempt, max_failures, unlock_ms)
+        }));
+    }
+    let mut sql = SqlBuilder::new("select pg_advisory_xact_lock(");
+    sql.bind(LOGIN_LOCK_SPACE)
+        .push(",")
+        .bind(user_id as i32)
+        .push(")");
+    sql.execute().await?;
+    let failures = in_row(LoginAttemptEntity::find(filter).await?);
+    let (gate, records) = decide(&failures, attempt, max_failures, unlock_ms);
+    for record in records {
+        record.insert().await?;
+    }
+    Ok(gate)
+}
+
+//登陆记录只能追加，没有任何更新方式
+#[derive(Debug)]
+pub enum LoginAttemptUpdater {}
+
+impl SqlClause for LoginAttemptUpdater {
+    fn write_sql(&self, _sql: &mut SqlBuilder) {
+        match *self {}
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LoginAttemptFilter {
+    //最近的n条
+    Latest(u32, u32),
+}
+
+impl SqlClause for LoginAttemptFilter {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            LoginAttemptFilter::Latest(user_id, limit) => {
+                sql.push("user_id=")
+                    .bind(*user_id as i64)
+                    .push(" order by attempted_at desc limit ")
+                    .bind(*limit as i64);
+            }
+        }
+    }
+}
+
+impl MemoryRecord for LoginAttemptEntity {
+    const TABLE: &'static str = "login_attempt";
+
+    fn matches(&self, filter: &LoginAttemptFilter) -> bool {
+        match filter {
+            LoginAttemptFilter::Latest(user_id, _) => self.attempt.user_id == *user_id,
+        }
+    }
+
+    fn apply(&mut self, updater: &LoginAttemptUpdater) {
+        match *updater {}
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("attempt_id:{}", self.attempt.attempt_id)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &LoginAttemptFilter) -> Vec<Self> {
+        let LoginAttemptFilter::Latest(_, limit) = filter;
+        rows.sort_by(|a, b| b.attempt.attempted_at.cmp(&a.attempt.attempted_at));
+        rows.truncate(*limit as usize);
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for LoginAttemptEntity {
+    type UpdaterContent<'a> = LoginAttemptUpdater;
+    type FilterContent<'b> = LoginAttemptFilter;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            attempt_id,\
+            user_id,\
+            device_id,\
+            ip,\
+            result,\
+            attempted_at,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from login_attempt where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get login_attempt: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<LoginAttemptEntity> {
+            Ok(LoginAttemptEntity {
+                attempt: LoginAttempt {
+                    attempt_id: row.get(0),
+                    user_id: row.get::<usize, i64>(1) as u32,
+                    device_id: row.get(2),
+                    ip: row.get(3),
+                    result: row.get::<usize, String>(4).parse()?,
+                    attempted_at: row.get::<usize, i64>(5) as u64,
+                },
+                updated_at: row.get(6),
+                created_at: row.get(7),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        _filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        match new_value {}
+    }
+
+    async fn delete(_filter: Self::FilterContent<'_>) -> DbResult<()> {
+        Err(DbError::Internal(anyhow!("login_attempt is append-only")))
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let LoginAttempt {
+            attempt_id,
+            user_id,
+            device_id,
+            ip,
+            result,
+            attempted_at,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into login_attempt (\
+                attempt_id,\
+                user_id,\
+                device_id,\
+                ip,\
+                result,\
+                attempted_at\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(attempt_id),
+            Box::new(user_id as i64),
+            Box::new(device_id),
+            Box::new(ip),
+            Box::new(result.to_string()),
+            Box::new(attempted_at as i64),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_login_failures_in_row() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            use LoginAttemptResult::*;
+            for (attempted_at, result) in [(1, Failed), (2, Succeeded), (3, Failed), (4, Failed)] {
+                record(1, "device1", "127.0.0.1", result, attempted_at)
+                    .await
+                    .unwrap();
+            }
+            record(2, "device2", "127.0.0.1", Failed, 5).await.unwrap();
+            //成功登陆之前的错误不算
+            let failures = failures_in_row(1, 10).await.unwrap();
+            assert_eq!(failures.len(), 2);
+            assert_eq!(failures[0].attempted_at, 4);
+
+            record(1, "", "", Unlocked, 6).await.unwrap();
+            assert!(failures_in_row(1, 10).await.unwrap().is_empty());
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_login_attempts_gated_at_once() {
+        let store = Arc::new(MemoryStore::default());
+        //并发的错误密码最多记录max_failures次，其余的直接被锁定
+        let tasks = (0..20).map(|i| {
+            let uow = Arc::new(UnitOfWork::memory(store.clone()));
+            tokio::spawn(LOCAL_CLI.scope(uow, async move {
+                attempt(1, "device1", "127.0.0.1", false, 100 + i, 5, 1000)
+                    .await
+                    .unwrap()
+            }))
+        });
+        let gates = futures::future::join_all(tasks).await;
+        let recorded = gates
+            .iter()
+            .filter(|gate| matches!(gate.as_ref().unwrap(), LoginGate::Recorded(_)))
+            .count();
+        assert_eq!(recorded, 5);
+
+        let uow = Arc::new(UnitOfWork::memory(store));
+        let task = async {
+            assert_eq!(failures_in_row(1, 10).await.unwrap().len(), 5);
+            //锁定期间密码正确也不放行
+            let gate = attempt(1, "device1", "127.0.0.1", true, 1000, 5, 1000).await;
+            assert!(matches!(gate.unwrap(), LoginGate::Locked(_)));
+            //到期之后错误次数清零
+            let gate = attempt(1, "device1", "127.0.0.1", false, 2200, 5, 1000).await;
+            assert_eq!(gate.unwrap(), LoginGate::Recorded(4));
+            assert_eq!(failures_in_row(1, 10).await.unwrap().len(), 1);
+        };
+        LOCAL_CLI.scope(uow, task).await;
+    }
+}
@@ -0,0 +1,401 @@
+//! in-memory storage backend，used by tests which shouldn't depend on a live postgresql
+//!
+//! within [`scope`], every [`UnitOfWork`](crate::unit_of_work::UnitOfWork) is bound to the store and
+//! every `PsqlOp` call is served by the [`MemoryRecord`] implementation of the entity,
+//! which interprets the same filters and updaters as the sql version
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+
+use crate::error::{DbError, DbResult};
+use crate::PsqlOp;
+
+/// entity that can be stored in [`MemoryStore`]
+pub trait MemoryRecord: PsqlOp + Clone + Send + Sync + Sized + 'static {
+    /// name of the corresponding postgresql table
+    const TABLE: &'static str;
+
+    /// whether the row is selected by the filter, same as the sql `where` clause
+    fn matches(&self, filter: &Self::FilterContent<'_>) -> bool;
+
+    /// apply the updater to the row, same as the sql `set` clause
+    fn apply(&mut self, updater: &Self::UpdaterContent<'_>);
+
+    /// values of the primary key and unique columns, e.g. `email:a@b.c`
+    fn unique_keys(&self) -> Vec<String>;
+
+    /// guard of the updater itself on the row, e.g. a stage can only move forward
+    fn accepts(&self, _updater: &Self::UpdaterContent<'_>) -> bool {
+        true
+    }
+
+    /// (updated_at, created_at)
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String);
+
+    /// ordering and paging part of the filter, applied to the matched rows
+    fn select(rows: Vec<Self>, _filter: &Self::FilterContent<'_>) -> Vec<Self> {
+        rows
+    }
+}
+
+/// same text format as `cast(updated_at as text)` in postgresql
+pub fn now_timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S%.6f+00").to_string()
+}
+
+//created_at转成毫秒时间戳
+pub fn timestamp_millis(time: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f%#z")
+        .ok()
+        .map(|time| time.timestamp_millis())
+}
+
+type Tables = HashMap<&'static str, Box<dyn Any + Send>>;
+type Undo = Box<dyn FnOnce(&mut Tables) + Send>;
+
+fn table_of<E: MemoryRecord>(tables: &mut Tables) -> &mut Vec<E> {
+    tables
+        .entry(E::TABLE)
+        .or_insert_with(|| Box::new(Vec::<E>::new()))
+        .downcast_mut::<Vec<E>>()
+        .unwrap()
+}
+
+/// the tables shared by every session of the store, and the undo journal of the session
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+    //事务里每次写入之后记下撤销方法，自动提交时为None
+    journal: Mutex<Option<Vec<Undo>>>,
+}
+
+impl MemoryStore {
+    /// a view on the same tables with a journal of its own, one per unit of work,
+    /// so rolling back only undoes the writes made through this view
+    pub fn session(&self) -> Self {
+        MemoryStore {
+            tables: self.tables.clone(),
+            journal: Mutex::new(None),
+        }
+    }
+
+    /// start journaling, return the mark to roll back to
+    pub(crate) fn begin(&self) -> usize {
+        let mut journal = self.journal.lock().unwrap();
+        journal.get_or_insert_with(Vec::new).len()
+    }
+
+    /// keep the writes after `mark`, the journal is dropped once the outermost commits
+    pub(crate) fn commit(&self, mark: usize) {
+        if mark == 0 {
+            *self.journal.lock().unwrap() = None;
+        }
+    }
+
+    /// undo the writes after `mark` in the reverse order
+    pub(crate) fn rollback(&self, mark: usize) {
+        let undos = {
+            let mut journal = self.journal.lock().unwrap();
+            let undos = match journal.as_mut() {
+                Some(journal) if journal.len() > mark => journal.split_off(mark),
+                _ => vec![],
+            };
+            if mark == 0 {
+                *journal = None;
+            }
+            undos
+        };
+        let mut tables = self.tables.lock().unwrap();
+        for undo in undos.into_iter().rev() {
+            undo(&mut tables);
+        }
+    }
+
+    fn with_table<E: MemoryRecord, R>(&self, f: impl FnOnce(&mut Vec<E>) -> R) -> R {
+        let mut tables = self.tables.lock().unwrap();
+        f(table_of::<E>(&mut tables))
+    }
+
+    fn journal<E: MemoryRecord>(&self, undo: impl FnOnce(&mut Vec<E>) + Send + 'static) {
+        if let Some(journal) = self.journal.lock().unwrap().as_mut() {
+            journal.push(Box::new(move |tables: &mut Tables| {
+                undo(table_of::<E>(tables))
+            }));
+        }
+    }
+
+    //按更新之后的唯一键找回行，恢复成更新之前的样子，已经被其他事务改掉的行不动
+    fn journal_updated<E: MemoryRecord>(&self, changed: Vec<(Vec<String>, E)>) {
+        if changed.is_empty() {
+            return;
+        }
+        self.journal(move |rows: &mut Vec<E>| {
+            for (keys, before) in changed {
+                if let Some(row) = rows.iter_mut().find(|row| row.unique_keys() == keys) {
+                    *row = before;
+                }
+            }
+        });
+    }
+
+    fn journal_inserted<E: MemoryRecord>(&self, inserted: Vec<Vec<String>>) {
+        if inserted.is_empty() {
+            return;
+        }
+        self.journal(move |rows: &mut Vec<E>| {
+            rows.retain(|row| !inserted.contains(&row.unique_keys()))
+        });
+    }
+
+    pub fn find<E: MemoryRecord>(&self, filter: &E::FilterContent<'_>) -> Vec<E> {
+        let rows = self.with_table(|rows: &mut Vec<E>| {
+            rows.iter()
+                .filter(|row| row.matches(filter))
+                .cloned()
+                .collect::<Vec<E>>()
+        });
+        E::select(rows, filter)
+    }
+
+    pub fn update<E: MemoryRecord>(
+        &self,
+        updater: &E::UpdaterContent<'_>,
+        filter: &E::FilterContent<'_>,
+    ) -> u64 {
+        let now = now_timestamp();
+        let changed = self.with_table(|rows: &mut Vec<E>| {
+            let mut changed = vec![];
+            for row in rows
+                .iter_mut()
+                .filter(|row| row.matches(filter) && row.accepts(updater))
+            {
+                let before = row.clone();
+                row.apply(updater);
+                *row.timestamps_mut().0 = now.clone();
+                changed.push((row.unique_keys(), before));
+            }
+            changed
+        });
+        let line = changed.len() as u64;
+        self.journal_updated(changed);
+        line
+    }
+
+    /// modify the rows in place for the operations which aren't expressed by an updater,
+    /// `f` returns whether the row is changed
+    pub fn update_where<E: MemoryRecord>(&self, mut f: impl FnMut(&mut E) -> bool) -> u64 {
+        let changed = self.with_table(|rows: &mut Vec<E>| {
+            let mut changed = vec![];
+            for row in rows.iter_mut() {
+                let before = row.clone();
+                if f(row) {
+                    changed.push((row.unique_keys(), before));
+                }
+            }
+            changed
+        });
+        let line = changed.len() as u64;
+        self.journal_updated(changed);
+        line
+    }
+
+    /// read all the rows of the table and append the returned ones under its lock,
+    /// for the check-then-write operations which must not interleave
+    pub fn locked<E: MemoryRecord, R>(&self, f: impl FnOnce(&[E]) -> (R, Vec<E>)) -> R {
+        let now = now_timestamp();
+        let (res, inserted) = self.with_table(|rows: &mut Vec<E>| {
+            let (res, records) = f(rows);
+            let mut inserted = vec![];
+            for mut record in records {
+                let (updated_at, created_at) = record.timestamps_mut();
+                *updated_at = now.clone();
+                *created_at = now.clone();
+                inserted.push(record.unique_keys());
+                rows.push(record);
+            }
+            (res, inserted)
+        });
+        self.journal_inserted::<E>(inserted);
+        res
+    }
+
+    pub fn insert<E: MemoryRecord>(&self, mut record: E) -> DbResult<()> {
+        let keys = record.unique_keys();
+        let now = now_timestamp();
+        {
+            let (updated_at, created_at) = record.timestamps_mut();
+            *updated_at = now.clone();
+            *created_at = now;
+        }
+        self.with_table(|rows: &mut Vec<E>| -> DbResult<()> {
+            for row in rows.iter() {
+                if let Some(key) = row.unique_keys().into_iter().find(|k| keys.contains(k)) {
+                    return Err(DbError::UniqueViolation(format!(
+                        "duplicate key value violates unique constraint of {}: {}",
+                        E::TABLE,
+                        key
+                    )));
+                }
+            }
+            rows.push(record);
+            Ok(())
+        })?;
+        self.journal_inserted::<E>(vec![keys]);
+        Ok(())
+    }
+
+    pub fn delete<E: MemoryRecord>(&self, filter: &E::FilterContent<'_>) -> u64 {
+        let deleted = self.with_table(|rows: &mut Vec<E>| {
+            let (deleted, kept): (Vec<E>, Vec<E>) =
+                rows.drain(..).partition(|row| row.matches(filter));
+            *rows = kept;
+            deleted
+        });
+        let line = deleted.len() as u64;
+        if !deleted.is_empty() {
+            self.journal(move |rows: &mut Vec<E>| rows.extend(deleted));
+        }
+        line
+    }
+
+    /// drop the table, it isn't journaled, same as a truncate outside of the transaction
+    pub fn clear(&self, table_name: &str) {
+        self.tables.lock().unwrap().remove(table_name);
+    }
+
+    pub fn clear_all(&self) {
+        self.tables.lock().unwrap().clear();
+    }
+}
+
+tokio::task_local! {
+    static MEMORY_BACKEND: Arc<MemoryStore>;
+}
+
+/// run the task on the memory backend, the other tasks and the tasks spawned by it
+/// still use postgresql, so the tests on their own stores can run in parallel
+pub async fn scope<F: Future>(store: Arc<MemoryStore>, task: F) -> F::Output {
+    MEMORY_BACKEND.scope(store, task).await
+}
+
+pub fn backend() -> Option<Arc<MemoryStore>> {
+    MEMORY_BACKEND.try_with(|store| store.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use common::data_structures::coin_transaction::CoinSendStage;
+    use common::data_structures::CoinType;
+
+    use super::*;
+    use crate::account_manager::{UserFilter, UserInfoEntity, UserUpdater};
+    use crate::airdrop::{AirdropEntity, AirdropFilter, AirdropUpdater};
+    use crate::coin_transfer::{CoinTxEntity, CoinTxFilter, CoinTxUpdater};
+    use crate::device_info::{DeviceInfoEntity, DeviceInfoFilter, DeviceInfoUpdater};
+    use crate::secret_store::{SecretFilter, SecretStoreEntity};
+    use crate::unit_of_work::UnitOfWork;
+    use crate::{PgLocalCli, LOCAL_CLI};
+    use anyhow::Result;
+
+    //register -> create_main_account -> pre_send_money 涉及的数据库操作
+    async fn register_to_send_money() -> Result<()> {
+        let store = PgLocalCli::memory_store().unwrap();
+        crate::general::init_memory_system_config(&store)?;
+
+        //register
+        let predecessor = AirdropEntity::find_single(AirdropFilter::ByInviteCode("chainless.hk"))
+            .await?
+            .into_inner();
+        let mut user = UserInfoEntity::new_with_specified(1, "pwd_hash");
+        user.user_info.email = Some("test@gmail.com".to_string());
+        user.insert().await?;
+        AirdropEntity::new_with_specified(
+            1,
+            predecessor.user_id,
+            predecessor.account_id.as_deref().unwrap(),
+        )
+        .insert()
+        .await?;
+        DeviceInfoEntity::new_with_specified("device1", "apple", 1)
+            .insert()
+            .await?;
+        //邮箱唯一
+        let mut repeated = UserInfoEntity::new_with_specified(2, "pwd_hash");
+        repeated.user_info.email = Some("test@gmail.com".to_string());
+        assert!(matches!(
+            repeated.insert().await,
+            Err(DbError::UniqueViolation(_))
+        ));
+
+        //create_main_account
+        SecretStoreEntity::new_with_specified("main_pubkey", 1, "by_password", "by_answer")
+            .insert()
+            .await?;
+        UserInfoEntity::update_single(
+            UserUpdater::SecruityInfo("1,2,3", "main_pubkey"),
+            UserFilter::ById(&1),
+        )
+        .await?;
+        AirdropEntity::update_single(
+            AirdropUpdater::AccountId("main_pubkey"),
+            AirdropFilter::ByUserId(&1),
+        )
+        .await?;
+        DeviceInfoEntity::update_single(
+            DeviceInfoUpdater::BecomeMaster("main_pubkey"),
+            DeviceInfoFilter::ByDeviceUser("device1", &1),
+        )
+        .await?;
+        let user = UserInfoEntity::find_single(UserFilter::ByMainAccount("main_pubkey")).await?;
+        assert_eq!(user.user_info.id, 1);
+        let device = DeviceInfoEntity::find_single(DeviceInfoFilter::ByHoldKey("main_pubkey"))
+            .await?
+            .into_inner();
+        assert!(device.holder_confirm_saved);
+        SecretStoreEntity::find_single(SecretFilter::ByIncumbentPubkey("main_pubkey")).await?;
+
+        //pre_send_money
+        let uncompleted =
+            CoinTxEntity::find(CoinTxFilter::BySenderUncompleted("main_pubkey")).await?;
+        assert!(uncompleted.is_empty());
+        let coin_tx = CoinTxEntity::new_with_specified(
+            CoinType::USDT,
+            "main_pubkey".to_string(),
+            "66.local".to_string(),
+            1_000_000,
+            "".to_string(),
+            Some("memo".to_string()),
+            u64::MAX,
+            CoinSendStage::Created,
+        );
+        let order_id = coin_tx.transaction.order_id.clone();
+        coin_tx.insert().await?;
+        let pending = CoinTxEntity::find(CoinTxFilter::ByAccountPending("66.local")).await?;
+        assert!(pending.is_empty());
+        CoinTxEntity::update_single(
+            CoinTxUpdater::Stage(CoinSendStage::SenderSigCompleted),
+            CoinTxFilter::ByOrderId(&order_id),
+        )
+        .await?;
+        let pending = CoinTxEntity::find(CoinTxFilter::ByAccountPending("66.local")).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].transaction.amount, 1_000_000);
+        let uncompleted =
+            CoinTxEntity::find(CoinTxFilter::BySenderUncompleted("main_pubkey")).await?;
+        assert_eq!(uncompleted.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_register_to_send_money() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        LOCAL_CLI
+            .scope(Arc::new(uow), register_to_send_money())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+//! versioned schema migrations embedded into the binary
+//!
+//! every migration is a pair of sql files under `models/migrations`,
+//! the applied versions and their checksums are recorded in `schema_migrations`
+
+use anyhow::{anyhow, Result};
+use common::hash::hash_str;
+use tokio_postgres::Client;
+
+use crate::PG_POOL;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        hash_str(self.up)
+    }
+}
+
+//新增迁移只能往后追加，已经发布的迁移文件不能再修改
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "init",
+        up: include_str!("../migrations/0001_init.up.sql"),
+        down: include_str!("../migrations/0001_init.down.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "coin_tx_version",
+        up: include_str!("../migrations/0002_coin_tx_version.up.sql"),
+        down: include_str!("../migrations/0002_coin_tx_version.down.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "audit_event",
+        up: include_str!("../migrations/0003_audit_event.up.sql"),
+        down: include_str!("../migrations/0003_audit_event.down.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "outbox",
+        up: include_str!("../migrations/0004_outbox.up.sql"),
+        down: include_str!("../migrations/0004_outbox.down.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "coin_tx_archive",
+        up: include_str!("../migrations/0005_coin_tx_archive.up.sql"),
+        down: include_str!("../migrations/0005_coin_tx_archive.down.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "eth_bridge_block_hash",
+        up: include_str!("../migrations/0006_eth_bridge_block_hash.up.sql"),
+        down: include_str!("../migrations/0006_eth_bridge_block_hash.down.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "scanner_checkpoint",
+        up: include_str!("../migrations/0007_scanner_checkpoint.up.sql"),
+        down: include_str!("../migrations/0007_scanner_checkpoint.down.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "coin_tx_retry",
+        up: include_str!("../migrations/0008_coin_tx_retry.up.sql"),
+        down: include_str!("../migrations/0008_coin_tx_retry.down.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "fee_refund",
+        up: include_str!("../migrations/0009_fee_refund.up.sql"),
+        down: include_str!("../migrations/0009_fee_refund.down.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "captcha",
+        up: include_str!("../migrations/0010_captcha.up.sql"),
+        down: include_str!("../migrations/0010_captcha.down.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "login_attempt",
+        up: include_str!("../migrations/0011_login_attempt.up.sql"),
+        down: include_str!("../migrations/0011_login_attempt.down.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "user_session",
+        up: include_str!("../migrations/0012_user_session.up.sql"),
+        down: include_str!("../migrations/0012_user_session.down.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "outbox_next_attempt",
+        up: include_str!("../migrations/0013_outbox_next_attempt.up.sql"),
+        down: include_str!("../migrations/0013_outbox_next_attempt.down.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "wallet_manage_retry",
+        up: include_str!("../migrations/0014_wallet_manage_retry.up.sql"),
+        down: include_str!("../migrations/0014_wallet_manage_retry.down.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "fee_refund_retry_order",
+        up: include_str!("../migrations/0015_fee_refund_retry_order.up.sql"),
+        down: include_str!("../migrations/0015_fee_refund_retry_order.down.sql"),
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: Option<String>,
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+async fn ensure_migration_table(cli: &Client) -> Result<()> {
+    cli.batch_execute(
+        "create table if not exists schema_migrations(\
+            version bigint primary key,\
+            name text not null,\
+            checksum text not null,\
+            applied_at timestamp with time zone default current_timestamp\
+        )",
+    )
+    .await?;
+    Ok(())
+}
+
+async fn applied_migrations(cli: &Client) -> Result<Vec<AppliedMigration>> {
+    ensure_migration_table(cli).await?;
+    let rows = cli
+        .query(
+            "select version,name,checksum,cast(applied_at as text) \
+            from schema_migrations order by version",
+            &[],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get(0),
+            name: row.get(1),
+            checksum: row.get(2),
+            applied_at: row.get(3),
+        })
+        .collect())
+}
+
+//已执行的迁移必须和内嵌的迁移一一对应且内容未被改动
+fn validate(applied: &[AppliedMigration]) -> Result<()> {
+    for record in applied {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == record.version)
+            .ok_or(anyhow!(
+                "schema version {}({}) is unknown to this binary",
+                record.version,
+                record.name
+            ))?;
+        if migration.checksum() != record.checksum {
+            Err(anyhow!(
+                "checksum of migration {}({}) mismatch, applied {} but embedded {}",
+                migration.version,
+                migration.name,
+                record.checksum,
+                migration.checksum()
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// apply all pending migrations up to `target`(the latest one by default),
+/// return the versions applied this time
+pub async fn up(target: Option<i64>) -> Result<Vec<i64>> {
+    let target = target.unwrap_or(latest_version());
+    let mut conn = PG_POOL.get().await?;
+    let applied = applied_migrations(&conn).await?;
+    validate(&applied)?;
+
+    let mut versions = vec![];
+    for migration in MIGRATIONS.iter().filter(|m| m.version <= target) {
+        if applied.iter().any(|a| a.version == migration.version) {
+            continue;
+        }
+        info!("apply migration {}({})", migration.version, migration.name);
+        let trans = conn.transaction().await?;
+        trans.batch_execute(migration.up).await?;
+        trans
+            .execute(
+                "insert into schema_migrations (version,name,checksum) values ($1,$2,$3)",
+                &[&migration.version, &migration.name, &migration.checksum()],
+            )
+            .await?;
+        trans.commit().await?;
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+/// revert the applied migrations newer than `target` in reverse order,
+/// return the versions reverted this time
+pub async fn down(target: i64) -> Result<Vec<i64>> {
+    let mut conn = PG_POOL.get().await?;
+    let applied = applied_migrations(&conn).await?;
+    validate(&applied)?;
+
+    let mut versions = vec![];
+    for record in applied.iter().rev().filter(|a| a.version > target) {
+        //validate保证了这里一定能找到
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == record.version)
+            .unwrap();
+        info!("revert migration {}({})", migration.version, migration.name);
+        let trans = conn.transaction().await?;
+        trans.batch_execute(migration.down).await?;
+        trans
+            .execute(
+                "delete from schema_migrations where version=$1",
+                &[&migration.version],
+            )
+            .await?;
+        trans.commit().await?;
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+pub async fn status() -> Result<Vec<MigrationStatus>> {
+    let conn = PG_POOL.get().await?;
+    let applied = applied_migrations(&conn).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name.to_string(),
+            applied_at: applied
+                .iter()
+                .find(|a| a.version == m.version)
+                .map(|a| a.applied_at.clone()),
+        })
+        .collect())
+}
+
+/// services call it at startup and refuse to run against a stale or tampered schema
+pub async fn check_up_to_date() -> Result<()> {
+    let conn = PG_POOL.get().await?;
+    let applied = applied_migrations(&conn).await?;
+    validate(&applied)?;
+    let pending: Vec<String> = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| format!("{}({})", m.version, m.name))
+        .collect();
+    if !pending.is_empty() {
+        Err(anyhow!(
+            "database schema is stale, pending migrations: {}, run `migrate up` first",
+            pending.join(",")
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_ordered() {
+        let mut last = 0;
+        for migration in MIGRATIONS {
+            assert!(migration.version > last);
+            assert!(!migration.up.is_empty() && !migration.down.is_empty());
+            last = migration.version;
+        }
+        assert_eq!(last, latest_version());
+    }
+}
@@ -0,0 +1,314 @@
+//! transactional outbox, domain events are written with the same unit of work
+//! as the state change and published later by the scanner
+
+use async_trait::async_trait;
+use common::data_structures::outbox::{OutboxEvent, OutboxEventType, OutboxStatus};
+use common::utils::math::generate_random_hex_string;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+//超过之后不再重试，状态置为Failed
+pub const MAX_PUBLISH_ATTEMPTS: u32 = 10;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct OutboxEntity {
+    pub event: OutboxEvent,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl OutboxEntity {
+    pub fn into_inner(self) -> OutboxEvent {
+        self.event
+    }
+}
+
+impl OutboxEntity {
+    pub fn new_with_specified(
+        event_type: OutboxEventType,
+        aggregate_id: &str,
+        payload: &str,
+    ) -> Self {
+        OutboxEntity {
+            event: OutboxEvent {
+                event_id: generate_random_hex_string(32),
+                event_type,
+                aggregate_id: aggregate_id.to_string(),
+                payload: payload.to_string(),
+                status: OutboxStatus::Pending,
+                attempts: 0,
+                last_error: None,
+                next_attempt_at: 0,
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+/// write a domain event within the unit of work of current task,
+/// it's committed or rolled back together with the state change
+pub async fn emit<P: Serialize>(
+    event_type: OutboxEventType,
+    aggregate_id: &str,
+    payload: &P,
+) -> DbResult<()> {
+    let payload = serde_json::to_string(payload).map_err(anyhow::Error::from)?;
+    debug!("emit {} event of {}", event_type, aggregate_id);
+    OutboxEntity::new_with_specified(event_type, aggregate_id, &payload)
+        .insert()
+        .await
+}
+
+#[derive(Debug)]
+pub enum OutboxUpdater<'a> {
+    Published,
+    //(error of this attempt,next_attempt_at)
+    Failed(&'a str, u64),
+}
+
+impl SqlClause for OutboxUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            OutboxUpdater::Published => {
+                sql.push("status=")
+                    .bind(OutboxStatus::Published.to_string())
+                    .push(",attempts=attempts+1,last_error=null");
+            }
+            OutboxUpdater::Failed(error, next_attempt_at) => {
+                sql.push("status=case when attempts+1>=")
+                    .bind(MAX_PUBLISH_ATTEMPTS as i32)
+                    .push(" then ")
+                    .bind(OutboxStatus::Failed.to_string())
+                    .push(" else status end,attempts=attempts+1,last_error=")
+                    .bind(error.to_string())
+                    .push(",next_attempt_at=")
+                    .bind(*next_attempt_at as i64);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OutboxFilter<'b> {
+    ByEventId(&'b str),
+    //最早的n条待投递事件
+    Pending(u32),
+}
+
+impl SqlClause for OutboxFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            OutboxFilter::ByEventId(event_id) => {
+                sql.push("event_id=").bind(event_id.to_string());
+            }
+            OutboxFilter::Pending(limit) => {
+                sql.push("status=")
+                    .bind(OutboxStatus::Pending.to_string())
+                    .push(" order by created_at asc limit ")
+                    .bind(*limit as i64);
+            }
+        }
+    }
+}
+
+impl MemoryRecord for OutboxEntity {
+    const TABLE: &'static str = "outbox";
+
+    fn matches(&self, filter: &OutboxFilter<'_>) -> bool {
+        match filter {
+            OutboxFilter::ByEventId(event_id) => self.event.event_id == *event_id,
+            OutboxFilter::Pending(_) => self.event.status == OutboxStatus::Pending,
+        }
+    }
+
+    fn apply(&mut self, updater: &OutboxUpdater<'_>) {
+        self.event.attempts += 1;
+        match updater {
+            OutboxUpdater::Published => {
+                self.event.status = OutboxStatus::Published;
+                self.event.last_error = None;
+            }
+            OutboxUpdater::Failed(error, next_attempt_at) => {
+                if self.event.attempts >= MAX_PUBLISH_ATTEMPTS {
+                    self.event.status = OutboxStatus::Failed;
+                }
+                self.event.last_error = Some(error.to_string());
+                self.event.next_attempt_at = *next_attempt_at;
+            }
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("event_id:{}", self.event.event_id)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &OutboxFilter<'_>) -> Vec<Self> {
+        if let OutboxFilter::Pending(limit) = filter {
+            rows.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+            rows.truncate(*limit as usize);
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for OutboxEntity {
+    type UpdaterContent<'a> = OutboxUpdater<'a>;
+    type FilterContent<'b> = OutboxFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            event_id,\
+            event_type,\
+            aggregate_id,\
+            payload,\
+            status,\
+            attempts,\
+            last_error,\
+            next_attempt_at,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from outbox where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get outbox: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<OutboxEntity> {
+            Ok(OutboxEntity {
+                event: OutboxEvent {
+                    event_id: row.get(0),
+                    event_type: row.get::<usize, String>(1).parse()?,
+                    aggregate_id: row.get(2),
+                    payload: row.get(3),
+                    status: row.get::<usize, String>(4).parse()?,
+                    attempts: row.get::<usize, i32>(5) as u32,
+                    last_error: row.get(6),
+                    next_attempt_at: row.get::<usize, i64>(7) as u64,
+                },
+                updated_at: row.get(8),
+                created_at: row.get(9),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update outbox set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update outbox {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        debug!("success update outbox {} rows", execute_res);
+        Ok(execute_res)
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let OutboxEvent {
+            event_id,
+            event_type,
+            aggregate_id,
+            payload,
+            status,
+            attempts,
+            last_error,
+            next_attempt_at,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into outbox (\
+                event_id,\
+                event_type,\
+                aggregate_id,\
+                payload,\
+                status,\
+                attempts,\
+                last_error,\
+                next_attempt_at\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(event_id),
+            Box::new(event_type.to_string()),
+            Box::new(aggregate_id),
+            Box::new(payload),
+            Box::new(status.to_string()),
+            Box::new(attempts as i32),
+            Box::new(last_error),
+            Box::new(next_attempt_at as i64),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_outbox_retry_until_failed() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            emit(
+                OutboxEventType::CoinTxStageChanged,
+                "order1",
+                &serde_json::json!({"order_id": "order1"}),
+            )
+            .await
+            .unwrap();
+            let event = OutboxEntity::find_single(OutboxFilter::Pending(10))
+                .await
+                .unwrap()
+                .into_inner();
+            for _ in 0..MAX_PUBLISH_ATTEMPTS {
+                OutboxEntity::update_single(
+                    OutboxUpdater::Failed("webhook timeout", 1000),
+                    OutboxFilter::ByEventId(&event.event_id),
+                )
+                .await
+                .unwrap();
+            }
+            //重试次数用完之后不再投递
+            let pending = OutboxEntity::find(OutboxFilter::Pending(10)).await.unwrap();
+            assert!(pending.is_empty());
+            let event = OutboxEntity::find_single(OutboxFilter::ByEventId(&event.event_id))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(event.status, OutboxStatus::Failed);
+            assert_eq!(event.last_error.as_deref(), Some("webhook timeout"));
+            assert_eq!(event.next_attempt_at, 1000);
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
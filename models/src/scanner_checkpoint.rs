@@ -0,0 +1,287 @@
+//! progress of the chain scanner tasks, one row per task
+
+use async_trait::async_trait;
+use common::data_structures::scanner_checkpoint::ScannerCheckpoint;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::{anyhow, Result};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ScannerCheckpointEntity {
+    pub checkpoint: ScannerCheckpoint,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl ScannerCheckpointEntity {
+    pub fn into_inner(self) -> ScannerCheckpoint {
+        self.checkpoint
+    }
+}
+
+impl ScannerCheckpointEntity {
+    pub fn new_with_specified(task: &str, height: u64, block_hash: &str) -> Self {
+        ScannerCheckpointEntity {
+            checkpoint: ScannerCheckpoint {
+                task: task.to_string(),
+                height,
+                block_hash: block_hash.to_string(),
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+/// checkpoint of the task, None if it has never been saved or has been reset
+pub async fn load(task: &str) -> DbResult<Option<ScannerCheckpoint>> {
+    let checkpoint = ScannerCheckpointEntity::find(ScannerCheckpointFilter::ByTask(task)).await?;
+    Ok(checkpoint
+        .into_iter()
+        .next()
+        .map(ScannerCheckpointEntity::into_inner))
+}
+
+pub async fn save(task: &str, height: u64, block_hash: &str) -> DbResult<()> {
+    let updated = ScannerCheckpointEntity::update(
+        ScannerCheckpointUpdater::Block(height, block_hash),
+        ScannerCheckpointFilter::ByTask(task),
+    )
+    .await?;
+    if updated == 0 {
+        ScannerCheckpointEntity::new_with_specified(task, height, block_hash)
+            .insert()
+            .await?;
+    }
+    Ok(())
+}
+
+/// rescan the task from the block after `height`,
+/// the hash is cleared and taken from the chain when the task restarts
+pub async fn rewind(task: &str, height: u64) -> DbResult<()> {
+    let checkpoint = load(task).await?.ok_or(DbError::NotFound)?;
+    //往前跳会漏掉区块
+    if height > checkpoint.height {
+        Err(anyhow!(
+            "can't rewind {} from {} to a higher height {}",
+            task,
+            checkpoint.height,
+            height
+        ))?;
+    }
+    save(task, height, "").await
+}
+
+/// drop the checkpoint, the task starts from its default height next time
+pub async fn reset(task: &str) -> DbResult<()> {
+    ScannerCheckpointEntity::delete(ScannerCheckpointFilter::ByTask(task)).await
+}
+
+#[derive(Debug)]
+pub enum ScannerCheckpointUpdater<'a> {
+    //(height,block_hash)
+    Block(u64, &'a str),
+}
+
+impl SqlClause for ScannerCheckpointUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            ScannerCheckpointUpdater::Block(height, block_hash) => {
+                sql.push("height=")
+                    .bind(*height as i64)
+                    .push(",block_hash=")
+                    .bind(block_hash.to_string());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ScannerCheckpointFilter<'b> {
+    ByTask(&'b str),
+    All,
+}
+
+impl SqlClause for ScannerCheckpointFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            ScannerCheckpointFilter::ByTask(task) => {
+                sql.push("task=").bind(task.to_string());
+            }
+            ScannerCheckpointFilter::All => {
+                sql.push("true order by task");
+            }
+        }
+    }
+}
+
+impl MemoryRecord for ScannerCheckpointEntity {
+    const TABLE: &'static str = "scanner_checkpoint";
+
+    fn matches(&self, filter: &ScannerCheckpointFilter<'_>) -> bool {
+        match filter {
+            ScannerCheckpointFilter::ByTask(task) => self.checkpoint.task == *task,
+            ScannerCheckpointFilter::All => true,
+        }
+    }
+
+    fn apply(&mut self, updater: &ScannerCheckpointUpdater<'_>) {
+        match updater {
+            ScannerCheckpointUpdater::Block(height, block_hash) => {
+                self.checkpoint.height = *height;
+                self.checkpoint.block_hash = block_hash.to_string();
+            }
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("task:{}", self.checkpoint.task)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &ScannerCheckpointFilter<'_>) -> Vec<Self> {
+        if let ScannerCheckpointFilter::All = filter {
+            rows.sort_by(|a, b| a.checkpoint.task.cmp(&b.checkpoint.task));
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for ScannerCheckpointEntity {
+    type UpdaterContent<'a> = ScannerCheckpointUpdater<'a>;
+    type FilterContent<'b> = ScannerCheckpointFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            task,\
+            height,\
+            block_hash,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from scanner_checkpoint where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get scanner_checkpoint: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<ScannerCheckpointEntity> {
+            Ok(ScannerCheckpointEntity {
+                checkpoint: ScannerCheckpoint {
+                    task: row.get(0),
+                    height: row.get::<usize, i64>(1) as u64,
+                    block_hash: row.get(2),
+                },
+                updated_at: row.get(3),
+                created_at: row.get(4),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update scanner_checkpoint set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update scanner_checkpoint {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        debug!("success update scanner_checkpoint {} rows", execute_res);
+        Ok(execute_res)
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let ScannerCheckpoint {
+            task,
+            height,
+            block_hash,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into scanner_checkpoint (\
+                task,\
+                height,\
+                block_hash\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(task),
+            Box::new(height as i64),
+            Box::new(block_hash),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+
+    async fn delete(filter: Self::FilterContent<'_>) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            store.delete::<Self>(&filter);
+            return Ok(());
+        }
+        let mut sql = SqlBuilder::new("delete from scanner_checkpoint where ");
+        sql.clause(&filter);
+        let execute_res = sql.execute().await?;
+        debug!("success delete scanner_checkpoint {} rows", execute_res);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_scanner_checkpoint_rewind_and_reset() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            assert_eq!(load("eth_bridge").await.unwrap(), None);
+            save("eth_bridge", 100, "0xaa").await.unwrap();
+            save("eth_bridge", 120, "0xbb").await.unwrap();
+            let checkpoint = load("eth_bridge").await.unwrap().unwrap();
+            assert_eq!(
+                (checkpoint.height, checkpoint.block_hash.as_str()),
+                (120, "0xbb")
+            );
+
+            //只能往回退
+            assert!(rewind("eth_bridge", 130).await.is_err());
+            rewind("eth_bridge", 90).await.unwrap();
+            let checkpoint = load("eth_bridge").await.unwrap().unwrap();
+            assert_eq!(
+                (checkpoint.height, checkpoint.block_hash.as_str()),
+                (90, "")
+            );
+
+            reset("eth_bridge").await.unwrap();
+            assert_eq!(load("eth_bridge").await.unwrap(), None);
+            let err = rewind("eth_bridge", 80).await.unwrap_err();
+            assert!(err.is_not_found());
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
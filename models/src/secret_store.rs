@@ -5,14 +5,14 @@ use common::data_structures::secret_store::SecretStore;
 use common::data_structures::SecretKeyState;
 use serde::{Deserialize, Serialize};
 
-use std::fmt;
-
 use tokio_postgres::Row;
 
-use crate::{PgLocalCli, PsqlOp};
-use anyhow::{Ok, Result};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
 
-#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct SecretStoreEntity {
     pub secret_store: SecretStore,
     pub updated_at: String,
@@ -31,20 +31,19 @@ pub enum SecretUpdater<'a> {
     State(SecretKeyState),
 }
 
-impl fmt::Display for SecretUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for SecretUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             SecretUpdater::EncrypedPrikey(by_password, by_answer) => {
-                format!(
-                    "(encrypted_prikey_by_password,encrypted_prikey_by_answer)=('{}','{}')",
-                    by_password, by_answer
-                )
+                sql.push("encrypted_prikey_by_password=")
+                    .bind(by_password.to_string())
+                    .push(",encrypted_prikey_by_answer=")
+                    .bind(by_answer.to_string());
             }
             SecretUpdater::State(new_state) => {
-                format!("state='{}'", new_state)
+                sql.push("state=").bind(new_state.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -54,15 +53,19 @@ pub enum SecretFilter<'b> {
     ByIncumbentPubkey(&'b str),
 }
 
-impl fmt::Display for SecretFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            SecretFilter::ByPubkey(key) => format!("pubkey='{}' ", key),
+impl SqlClause for SecretFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            SecretFilter::ByPubkey(key) => {
+                sql.push("pubkey=").bind(key.to_string());
+            }
             SecretFilter::ByIncumbentPubkey(key) => {
-                format!("state='Incumbent' and pubkey='{}' ", key)
+                sql.push("state=")
+                    .bind(SecretKeyState::Incumbent.to_string())
+                    .push(" and pubkey=")
+                    .bind(key.to_string());
             }
-        };
-        write!(f, "{}", description)
+        }
     }
 }
 
@@ -86,13 +89,49 @@ impl SecretStoreEntity {
         }
     }
 }
+impl MemoryRecord for SecretStoreEntity {
+    const TABLE: &'static str = "secret_store";
+
+    fn matches(&self, filter: &SecretFilter<'_>) -> bool {
+        let secret = &self.secret_store;
+        match filter {
+            SecretFilter::ByPubkey(key) => secret.pubkey == *key,
+            SecretFilter::ByIncumbentPubkey(key) => {
+                secret.state == SecretKeyState::Incumbent && secret.pubkey == *key
+            }
+        }
+    }
+
+    fn apply(&mut self, updater: &SecretUpdater<'_>) {
+        let secret = &mut self.secret_store;
+        match updater {
+            SecretUpdater::EncrypedPrikey(by_password, by_answer) => {
+                secret.encrypted_prikey_by_password = by_password.to_string();
+                secret.encrypted_prikey_by_answer = by_answer.to_string();
+            }
+            SecretUpdater::State(new_state) => secret.state = new_state.clone(),
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("pubkey:{}", self.secret_store.pubkey)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+}
+
 #[async_trait]
 impl PsqlOp for SecretStoreEntity {
     type UpdaterContent<'a> = SecretUpdater<'a>;
     type FilterContent<'b> = SecretFilter<'b>;
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<SecretStoreEntity>> {
-        let sql = format!(
-            "select 
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<SecretStoreEntity>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
             pubkey,\
             state,\
             user_id,\
@@ -100,11 +139,11 @@ impl PsqlOp for SecretStoreEntity {
             encrypted_prikey_by_answer,\
          cast(updated_at as text), \
          cast(created_at as text) \
-         from secret_store where {}",
-            filter
+         from secret_store where ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get_secret: raw sql {}", sql);
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get_secret: raw sql {}", sql.sql());
         let gen_view = |row: &Row| {
             Ok(SecretStoreEntity {
                 secret_store: SecretStore {
@@ -114,29 +153,35 @@ impl PsqlOp for SecretStoreEntity {
                     encrypted_prikey_by_password: row.get(3),
                     encrypted_prikey_by_answer: row.get(4),
                 },
-                updated_at: row.get(4),
-                created_at: row.get(5),
+                updated_at: row.get(5),
+                created_at: row.get(6),
             })
         };
 
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "update secret_store set {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        debug!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update secret_store set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update orders {} rows", execute_res);
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let SecretStore {
             pubkey,
             state,
@@ -145,22 +190,29 @@ impl PsqlOp for SecretStoreEntity {
             encrypted_prikey_by_answer,
         } = self.into_inner();
 
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into secret_store (\
                 pubkey,\
                 state,\
                 user_id,\
                 encrypted_prikey_by_password,\
                 encrypted_prikey_by_answer\
-         ) values ('{}','{}',{},'{}','{}');",
-            pubkey, state, user_id, encrypted_prikey_by_password, encrypted_prikey_by_answer
+         ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let _execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        sql.bind_all(vec![
+            Box::new(pubkey),
+            Box::new(state.to_string()),
+            Box::new(user_id as i64),
+            Box::new(encrypted_prikey_by_password),
+            Box::new(encrypted_prikey_by_answer),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
         Ok(())
     }
 
-    async fn delete(_filter: Self::FilterContent<'_>) -> Result<()> {
+    async fn delete(_filter: Self::FilterContent<'_>) -> DbResult<()> {
         todo!()
     }
 }
@@ -0,0 +1,239 @@
+//! unit of work which owns a pooled connection and the transaction opened on it
+//!
+//! nested `begin` calls are mapped to savepoints, and a connection dropped
+//! with an open transaction (panic, cancelled request) is discarded instead of
+//! being returned to the pool, so postgresql rolls the transaction back
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use deadpool::managed::Object;
+use tokio::sync::Mutex;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Row;
+
+use crate::error::{DbError, DbResult};
+use crate::memory::MemoryStore;
+use crate::{LocalConn, PG_POOL};
+
+struct UowState {
+    conn: Option<LocalConn>,
+    //0: autocommit, 1: transaction, >1: savepoints
+    depth: usize,
+    //内存后端每一层事务开始时的日志位置
+    marks: Vec<usize>,
+}
+
+impl UowState {
+    fn conn(&self) -> DbResult<&LocalConn> {
+        self.conn.as_ref().ok_or(DbError::Connection(
+            "connection of unit of work is released".to_string(),
+        ))
+    }
+}
+
+impl Drop for UowState {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if self.depth > 0 {
+                //事务没有结束，直接关闭连接由数据库回滚，不能放回连接池
+                warn!("unit of work dropped within transaction, discard the connection");
+                drop(Object::take(conn));
+            }
+        }
+    }
+}
+
+pub struct UnitOfWork {
+    memory: Option<Arc<MemoryStore>>,
+    state: Mutex<UowState>,
+}
+
+impl UnitOfWork {
+    /// take a connection from the pool, and open a transaction on it if `transactional`
+    pub async fn new(transactional: bool) -> Result<Self> {
+        let uow = match crate::memory::backend() {
+            Some(store) => Self::memory(store),
+            None => {
+                let conn = PG_POOL.get().await?;
+                debug!("pool_status {:?}", PG_POOL.status());
+                UnitOfWork {
+                    memory: None,
+                    state: Mutex::new(UowState {
+                        conn: Some(conn),
+                        depth: 0,
+                        marks: vec![],
+                    }),
+                }
+            }
+        };
+        if transactional {
+            uow.begin().await?;
+        }
+        Ok(uow)
+    }
+
+    /// bind to a session of the store, so its rollback only undoes its own writes
+    pub fn memory(store: Arc<MemoryStore>) -> Self {
+        UnitOfWork {
+            memory: Some(Arc::new(store.session())),
+            state: Mutex::new(UowState {
+                conn: None,
+                depth: 0,
+                marks: vec![],
+            }),
+        }
+    }
+
+    pub fn memory_store(&self) -> Option<Arc<MemoryStore>> {
+        self.memory.clone()
+    }
+
+    pub async fn depth(&self) -> usize {
+        self.state.lock().await.depth
+    }
+
+    pub async fn execute(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> DbResult<u64> {
+        if self.memory.is_some() {
+            return Err(DbError::Internal(anyhow!(
+                "memory backend can't execute sql"
+            )));
+        }
+        let state = self.state.lock().await;
+        Ok(state.conn()?.execute(sql, params).await?)
+    }
+
+    pub async fn query(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> DbResult<Vec<Row>> {
+        if self.memory.is_some() {
+            return Err(DbError::Internal(anyhow!(
+                "memory backend can't execute sql"
+            )));
+        }
+        let state = self.state.lock().await;
+        Ok(state.conn()?.query(sql, params).await?)
+    }
+
+    /// open a transaction, or a savepoint when already in one
+    pub async fn begin(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(store) = &self.memory {
+            state.marks.push(store.begin());
+        } else {
+            let sql = match state.depth {
+                0 => "BEGIN".to_string(),
+                depth => format!("SAVEPOINT sp_{}", depth),
+            };
+            state.conn()?.batch_execute(&sql).await?;
+        }
+        state.depth += 1;
+        Ok(())
+    }
+
+    /// commit the innermost transaction or savepoint
+    pub async fn commit(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if state.depth == 0 {
+            debug!("it's not a trans");
+            return Ok(());
+        }
+        //内存后端的写入是立即生效的，提交只是丢掉撤销日志
+        if let Some(store) = &self.memory {
+            store.commit(state.marks.pop().unwrap_or_default());
+        } else {
+            let sql = match state.depth {
+                1 => "COMMIT".to_string(),
+                depth => format!("RELEASE SAVEPOINT sp_{}", depth - 1),
+            };
+            state.conn()?.batch_execute(&sql).await?;
+        }
+        state.depth -= 1;
+        Ok(())
+    }
+
+    /// roll back the innermost transaction or savepoint
+    pub async fn rollback(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if state.depth == 0 {
+            debug!("it's not a trans");
+            return Ok(());
+        }
+        if let Some(store) = &self.memory {
+            store.rollback(state.marks.pop().unwrap_or_default());
+        } else {
+            let sql = match state.depth {
+                1 => "ROLLBACK".to_string(),
+                depth => format!(
+                    "ROLLBACK TO SAVEPOINT sp_{0};RELEASE SAVEPOINT sp_{0}",
+                    depth - 1
+                ),
+            };
+            state.conn()?.batch_execute(&sql).await?;
+        }
+        state.depth -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret_store::{SecretFilter, SecretStoreEntity};
+
+    #[tokio::test]
+    async fn test_unit_of_work_nested_depth() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        uow.begin().await.unwrap();
+        uow.begin().await.unwrap();
+        assert_eq!(uow.depth().await, 2);
+        uow.rollback().await.unwrap();
+        uow.commit().await.unwrap();
+        assert_eq!(uow.depth().await, 0);
+        //没有事务时提交是空操作
+        uow.commit().await.unwrap();
+        assert_eq!(uow.depth().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_rollback_discards_own_writes() {
+        let store = Arc::new(MemoryStore::default());
+        let secret = |pubkey: &str| SecretStoreEntity::new_with_specified(pubkey, 1, "", "");
+        let pubkeys = || {
+            ["key1", "key2", "key3", "key4"]
+                .into_iter()
+                .filter(|key| {
+                    !store
+                        .find::<SecretStoreEntity>(&SecretFilter::ByPubkey(key))
+                        .is_empty()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let uow = UnitOfWork::memory(store.clone());
+        let other = UnitOfWork::memory(store.clone());
+        uow.begin().await.unwrap();
+        let tx_store = uow.memory_store().unwrap();
+        tx_store.insert(secret("key1")).unwrap();
+        //savepoint回滚只撤销它之后的写入
+        uow.begin().await.unwrap();
+        tx_store.insert(secret("key2")).unwrap();
+        tx_store.delete::<SecretStoreEntity>(&SecretFilter::ByIncumbentPubkey("key1"));
+        uow.rollback().await.unwrap();
+        assert_eq!(pubkeys(), vec!["key1"]);
+
+        //其他unit of work自动提交的写入不受影响
+        other
+            .memory_store()
+            .unwrap()
+            .insert(secret("key3"))
+            .unwrap();
+        uow.rollback().await.unwrap();
+        assert_eq!(pubkeys(), vec!["key3"]);
+
+        //提交之后不能再回滚
+        uow.begin().await.unwrap();
+        tx_store.insert(secret("key4")).unwrap();
+        uow.commit().await.unwrap();
+        uow.rollback().await.unwrap();
+        assert_eq!(pubkeys(), vec!["key3", "key4"]);
+    }
+}
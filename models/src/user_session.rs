@@ -0,0 +1,462 @@
+//! login sessions, one row per (user, device), a new login on the device replaces the former session
+//! and the refresh token is rotated on every refresh
+
+use async_trait::async_trait;
+use common::data_structures::user_session::UserSession;
+use common::utils::math::generate_random_hex_string;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
+use anyhow::Result;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct UserSessionEntity {
+    pub session: UserSession,
+    pub updated_at: String,
+    pub created_at: String,
+}
+
+impl UserSessionEntity {
+    pub fn into_inner(self) -> UserSession {
+        self.session
+    }
+}
+
+impl UserSessionEntity {
+    pub fn new_with_specified(
+        session_id: &str,
+        user_id: u32,
+        device_id: &str,
+        device_brand: &str,
+        refresh_token_hash: &str,
+        now: u64,
+        refresh_expire_at: u64,
+    ) -> Self {
+        UserSessionEntity {
+            session: UserSession {
+                session_id: session_id.to_string(),
+                user_id,
+                device_id: device_id.to_string(),
+                device_brand: device_brand.to_string(),
+                refresh_token_hash: refresh_token_hash.to_string(),
+                refresh_expire_at,
+                last_active_at: now,
+            },
+            updated_at: "".to_string(),
+            created_at: "".to_string(),
+        }
+    }
+}
+
+pub async fn load(session_id: &str) -> DbResult<Option<UserSession>> {
+    let session = UserSessionEntity::find(UserSessionFilter::BySessionId(session_id)).await?;
+    Ok(session.into_iter().next().map(UserSessionEntity::into_inner))
+}
+
+pub async fn load_by_refresh_token(refresh_token_hash: &str) -> DbResult<Option<UserSession>> {
+    let session =
+        UserSessionEntity::find(UserSessionFilter::ByRefreshToken(refresh_token_hash)).await?;
+    Ok(session.into_iter().next().map(UserSessionEntity::into_inner))
+}
+
+/// sessions of the user, the expired ones are skipped
+pub async fn list(user_id: u32, now: u64) -> DbResult<Vec<UserSession>> {
+    let sessions = UserSessionEntity::find(UserSessionFilter::ByUser(user_id)).await?;
+    Ok(sessions
+        .into_iter()
+        .map(UserSessionEntity::into_inner)
+        .filter(|session| session.refresh_expire_at > now)
+        .collect())
+}
+
+/// start a new session on the device, return its session_id
+pub async fn open(
+    user_id: u32,
+    device_id: &str,
+    device_brand: &str,
+    refresh_token_hash: &str,
+    now: u64,
+    refresh_expire_at: u64,
+) -> DbResult<String> {
+    let session_id = generate_random_hex_string(32);
+    if PgLocalCli::memory_store().is_some() {
+        let updater = UserSessionUpdater::Opened(
+            &session_id,
+            device_brand,
+            refresh_token_hash,
+            now,
+            refresh_expire_at,
+        );
+        let filter = UserSessionFilter::ByUserDevice(user_id, device_id);
+        if UserSessionEntity::update(updater, filter).await? == 0 {
+            UserSessionEntity::new_with_specified(
+                &session_id,
+                user_id,
+                device_id,
+                device_brand,
+                refresh_token_hash,
+                now,
+                refresh_expire_at,
+            )
+            .insert()
+            .await?;
+        }
+        return Ok(session_id);
+    }
+    //同一设备同时登陆时由一条语句覆盖前一个会话，唯一键冲突不会中断请求所在的事务
+    let mut sql = SqlBuilder::new(
+        "insert into user_session (\
+            session_id,\
+            user_id,\
+            device_id,\
+            device_brand,\
+            refresh_token_hash,\
+            refresh_expire_at,\
+            last_active_at\
+     ) values (",
+    );
+    sql.bind_all(vec![
+        Box::new(session_id.clone()),
+        Box::new(user_id as i64),
+        Box::new(device_id.to_string()),
+        Box::new(device_brand.to_string()),
+        Box::new(refresh_token_hash.to_string()),
+        Box::new(refresh_expire_at as i64),
+        Box::new(now as i64),
+    ])
+    .push(
+        ") on conflict (user_id,device_id) do update set \
+            session_id=excluded.session_id,\
+            device_brand=excluded.device_brand,\
+            refresh_token_hash=excluded.refresh_token_hash,\
+            refresh_expire_at=excluded.refresh_expire_at,\
+            last_active_at=excluded.last_active_at,\
+            updated_at=CURRENT_TIMESTAMP",
+    );
+    debug!("open user_session: raw sql {}", sql.sql());
+    sql.execute().await?;
+    Ok(session_id)
+}
+
+/// swap the refresh token, false if it was already swapped by another refresh
+pub async fn rotate(
+    session_id: &str,
+    old_token_hash: &str,
+    new_token_hash: &str,
+    now: u64,
+    refresh_expire_at: u64,
+) -> DbResult<bool> {
+    let rows = UserSessionEntity::update(
+        UserSessionUpdater::Refreshed(new_token_hash, now, refresh_expire_at),
+        UserSessionFilter::ByRefreshSwap(session_id, old_token_hash),
+    )
+    .await?;
+    Ok(rows > 0)
+}
+
+/// end the session of the device, its access and refresh tokens are invalid at once
+pub async fn revoke(user_id: u32, device_id: &str) -> DbResult<()> {
+    UserSessionEntity::delete(UserSessionFilter::ByUserDevice(user_id, device_id)).await
+}
+
+/// end the sessions of every device of the user
+pub async fn revoke_all(user_id: u32) -> DbResult<()> {
+    UserSessionEntity::delete(UserSessionFilter::ByUser(user_id)).await
+}
+
+#[derive(Clone, Debug)]
+pub enum UserSessionUpdater<'a> {
+    //(session_id,device_brand,refresh_token_hash,now,refresh_expire_at)
+    Opened(&'a str, &'a str, &'a str, u64, u64),
+    //(refresh_token_hash,now,refresh_expire_at)
+    Refreshed(&'a str, u64, u64),
+}
+
+impl SqlClause for UserSessionUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            UserSessionUpdater::Opened(session_id, device_brand, token_hash, now, expire_at) => {
+                sql.push("session_id=")
+                    .bind(session_id.to_string())
+                    .push(",device_brand=")
+                    .bind(device_brand.to_string())
+                    .push(",refresh_token_hash=")
+                    .bind(token_hash.to_string())
+                    .push(",last_active_at=")
+                    .bind(*now as i64)
+                    .push(",refresh_expire_at=")
+                    .bind(*expire_at as i64);
+            }
+            UserSessionUpdater::Refreshed(token_hash, now, expire_at) => {
+                sql.push("refresh_token_hash=")
+                    .bind(token_hash.to_string())
+                    .push(",last_active_at=")
+                    .bind(*now as i64)
+                    .push(",refresh_expire_at=")
+                    .bind(*expire_at as i64);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum UserSessionFilter<'b> {
+    BySessionId(&'b str),
+    ByRefreshToken(&'b str),
+    ByUser(u32),
+    //(user_id,device_id)
+    ByUserDevice(u32, &'b str),
+    //(session_id,refresh_token_hash)，刷新时比较并交换
+    ByRefreshSwap(&'b str, &'b str),
+}
+
+impl SqlClause for UserSessionFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            UserSessionFilter::BySessionId(session_id) => {
+                sql.push("session_id=").bind(session_id.to_string());
+            }
+            UserSessionFilter::ByRefreshToken(token_hash) => {
+                sql.push("refresh_token_hash=").bind(token_hash.to_string());
+            }
+            UserSessionFilter::ByUser(user_id) => {
+                sql.push("user_id=")
+                    .bind(*user_id as i64)
+                    .push(" order by last_active_at desc");
+            }
+            UserSessionFilter::ByUserDevice(user_id, device_id) => {
+                sql.push("user_id=")
+                    .bind(*user_id as i64)
+                    .push(" and device_id=")
+                    .bind(device_id.to_string());
+            }
+            UserSessionFilter::ByRefreshSwap(session_id, token_hash) => {
+                sql.push("session_id=")
+                    .bind(session_id.to_string())
+                    .push(" and refresh_token_hash=")
+                    .bind(token_hash.to_string());
+            }
+        }
+    }
+}
+
+impl MemoryRecord for UserSessionEntity {
+    const TABLE: &'static str = "user_session";
+
+    fn matches(&self, filter: &UserSessionFilter<'_>) -> bool {
+        let session = &self.session;
+        match filter {
+            UserSessionFilter::BySessionId(session_id) => session.session_id == *session_id,
+            UserSessionFilter::ByRefreshToken(token_hash) => {
+                session.refresh_token_hash == *token_hash
+            }
+            UserSessionFilter::ByUser(user_id) => session.user_id == *user_id,
+            UserSessionFilter::ByUserDevice(user_id, device_id) => {
+                session.user_id == *user_id && session.device_id == *device_id
+            }
+            UserSessionFilter::ByRefreshSwap(session_id, token_hash) => {
+                session.session_id == *session_id && session.refresh_token_hash == *token_hash
+            }
+        }
+    }
+
+    fn apply(&mut self, updater: &UserSessionUpdater<'_>) {
+        let session = &mut self.session;
+        match updater {
+            UserSessionUpdater::Opened(session_id, device_brand, token_hash, now, expire_at) => {
+                session.session_id = session_id.to_string();
+                session.device_brand = device_brand.to_string();
+                session.refresh_token_hash = token_hash.to_string();
+                session.last_active_at = *now;
+                session.refresh_expire_at = *expire_at;
+            }
+            UserSessionUpdater::Refreshed(token_hash, now, expire_at) => {
+                session.refresh_token_hash = token_hash.to_string();
+                session.last_active_at = *now;
+                session.refresh_expire_at = *expire_at;
+            }
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![
+            format!("session_id:{}", self.session.session_id),
+            format!(
+                "user_device:{}:{}",
+                self.session.user_id, self.session.device_id
+            ),
+            format!("refresh_token_hash:{}", self.session.refresh_token_hash),
+        ]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+
+    fn select(mut rows: Vec<Self>, filter: &UserSessionFilter<'_>) -> Vec<Self> {
+        if let UserSessionFilter::ByUser(_) = filter {
+            rows.sort_by(|a, b| b.session.last_active_at.cmp(&a.session.last_active_at));
+        }
+        rows
+    }
+}
+
+#[async_trait]
+impl PsqlOp for UserSessionEntity {
+    type UpdaterContent<'a> = UserSessionUpdater<'a>;
+    type FilterContent<'b> = UserSessionFilter<'b>;
+
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
+            session_id,\
+            user_id,\
+            device_id,\
+            device_brand,\
+            refresh_token_hash,\
+            refresh_expire_at,\
+            last_active_at,\
+         cast(updated_at as text), \
+         cast(created_at as text) \
+         from user_session where ",
+        );
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get user_session: raw sql {}", sql.sql());
+        let gen_view = |row: &Row| -> Result<UserSessionEntity> {
+            Ok(UserSessionEntity {
+                session: UserSession {
+                    session_id: row.get(0),
+                    user_id: row.get::<usize, i64>(1) as u32,
+                    device_id: row.get(2),
+                    device_brand: row.get(3),
+                    refresh_token_hash: row.get(4),
+                    refresh_expire_at: row.get::<usize, i64>(5) as u64,
+                    last_active_at: row.get::<usize, i64>(6) as u64,
+                },
+                updated_at: row.get(7),
+                created_at: row.get(8),
+            })
+        };
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
+    }
+
+    async fn update(
+        new_value: Self::UpdaterContent<'_>,
+        filter: Self::FilterContent<'_>,
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.update::<Self>(&new_value, &filter));
+        }
+        let mut sql = SqlBuilder::new("update user_session set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update user_session {} ", sql.sql());
+        let execute_res = sql.execute().await?;
+        debug!("success update user_session {} rows", execute_res);
+        Ok(execute_res)
+    }
+
+    async fn insert(self) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
+        let UserSession {
+            session_id,
+            user_id,
+            device_id,
+            device_brand,
+            refresh_token_hash,
+            refresh_expire_at,
+            last_active_at,
+        } = self.into_inner();
+        let mut sql = SqlBuilder::new(
+            "insert into user_session (\
+                session_id,\
+                user_id,\
+                device_id,\
+                device_brand,\
+                refresh_token_hash,\
+                refresh_expire_at,\
+                last_active_at\
+         ) values (",
+        );
+        sql.bind_all(vec![
+            Box::new(session_id),
+            Box::new(user_id as i64),
+            Box::new(device_id),
+            Box::new(device_brand),
+            Box::new(refresh_token_hash),
+            Box::new(refresh_expire_at as i64),
+            Box::new(last_active_at as i64),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
+        Ok(())
+    }
+
+    async fn delete(filter: Self::FilterContent<'_>) -> DbResult<()> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            store.delete::<Self>(&filter);
+            return Ok(());
+        }
+        let mut sql = SqlBuilder::new("delete from user_session where ");
+        sql.clause(&filter);
+        let execute_res = sql.execute().await?;
+        debug!("success delete user_session {} rows", execute_res);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::MemoryStore;
+    use crate::unit_of_work::UnitOfWork;
+    use crate::LOCAL_CLI;
+
+    #[tokio::test]
+    async fn test_user_session_open_rotate_revoke() {
+        let uow = UnitOfWork::memory(Arc::new(MemoryStore::default()));
+        let task = async {
+            let first = open(1, "device1", "Huawei", "hash1", 1000, 5000)
+                .await
+                .unwrap();
+            open(1, "device2", "Xiaomi", "hash2", 1100, 5000)
+                .await
+                .unwrap();
+            //同一设备再次登陆，之前的会话作废
+            let second = open(1, "device1", "Huawei", "hash3", 1200, 6000)
+                .await
+                .unwrap();
+            assert_ne!(first, second);
+            assert!(load(&first).await.unwrap().is_none());
+            assert_eq!(list(1, 2000).await.unwrap().len(), 2);
+
+            assert!(rotate(&second, "hash3", "hash4", 1300, 7000).await.unwrap());
+            //旧的refresh token不能再换
+            assert!(!rotate(&second, "hash3", "hash5", 1400, 7000).await.unwrap());
+            let session = load_by_refresh_token("hash4").await.unwrap().unwrap();
+            assert_eq!(session.session_id, second);
+            assert_eq!(session.last_active_at, 1300);
+            //device2的会话已过期
+            assert_eq!(list(1, 5500).await.unwrap().len(), 1);
+
+            revoke(1, "device1").await.unwrap();
+            assert!(load(&second).await.unwrap().is_none());
+            revoke_all(1).await.unwrap();
+            assert!(list(1, 0).await.unwrap().is_empty());
+        };
+        LOCAL_CLI.scope(Arc::new(uow), task).await;
+    }
+}
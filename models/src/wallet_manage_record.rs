@@ -4,17 +4,21 @@ use async_trait::async_trait;
 
 use common::data_structures::wallet_namage_record::{WalletManageRecord, WalletOperateType};
 use common::utils::math::generate_random_hex_string;
-use std::fmt;
 
 use tokio_postgres::Row;
 //#[derive(Serialize)]
 
+use common::data_structures::outbox::OutboxEventType;
 use common::data_structures::TxStatusOnChain;
 
 use derive_more::AsRef;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::{vec_str2array_text, PgLocalCli, PsqlOp};
+use crate::error::DbResult;
+use crate::memory::MemoryRecord;
+use crate::outbox;
+use crate::{PgLocalCli, PsqlOp, SqlBuilder, SqlClause};
 use anyhow::Result;
 
 #[derive(Deserialize, Serialize, Debug, AsRef, Clone)]
@@ -35,19 +39,31 @@ impl WalletManageRecordEntity {
 pub enum WalletManageRecordUpdater<'a> {
     TxIds(&'a Vec<String>),
     Status(TxStatusOnChain),
+    //(重新提交的txid,已重试次数)
+    Resubmitted(&'a str, u32),
+    //重新提交没有发出去，已重试次数
+    RetryFailed(u32),
 }
 
-impl fmt::Display for WalletManageRecordUpdater<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
+impl SqlClause for WalletManageRecordUpdater<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
             WalletManageRecordUpdater::TxIds(ids) => {
-                format!("tx_ids={} ", vec_str2array_text(ids.to_vec()))
+                sql.push("tx_ids=").bind(ids.to_vec());
             }
             WalletManageRecordUpdater::Status(key) => {
-                format!("status='{}' ", key)
+                sql.push("status=").bind(key.to_string());
             }
-        };
-        write!(f, "{}", description)
+            WalletManageRecordUpdater::Resubmitted(tx_id, retry_times) => {
+                sql.push("tx_ids=array_append(tx_ids,")
+                    .bind(tx_id.to_string())
+                    .push("),retry_times=")
+                    .bind(*retry_times as i32);
+            }
+            WalletManageRecordUpdater::RetryFailed(retry_times) => {
+                sql.push("retry_times=").bind(*retry_times as i32);
+            }
+        }
     }
 }
 
@@ -58,15 +74,16 @@ pub enum WalletManageRecordFilter<'b> {
     ByStatus(&'b TxStatusOnChain),
 }
 
-impl fmt::Display for WalletManageRecordFilter<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let description = match self {
-            Self::ByRecordId(record_id) => format!("record_id='{}' ", record_id),
-            &Self::ByStatus(status) => {
-                format!("status='{}' ", status)
+impl SqlClause for WalletManageRecordFilter<'_> {
+    fn write_sql(&self, sql: &mut SqlBuilder) {
+        match self {
+            Self::ByRecordId(record_id) => {
+                sql.push("record_id=").bind(record_id.to_string());
             }
-        };
-        write!(f, "{}", description)
+            Self::ByStatus(status) => {
+                sql.push("status=").bind(status.to_string());
+            }
+        }
     }
 }
 
@@ -89,6 +106,7 @@ impl WalletManageRecordEntity {
                 operator_device_brand: operator_device_brand.to_string(),
                 tx_ids,
                 status: TxStatusOnChain::Pending,
+                retry_times: 0,
             },
             updated_at: "".to_string(),
             created_at: "".to_string(),
@@ -96,15 +114,74 @@ impl WalletManageRecordEntity {
     }
 }
 
+impl MemoryRecord for WalletManageRecordEntity {
+    const TABLE: &'static str = "wallet_manage_record";
+
+    fn matches(&self, filter: &WalletManageRecordFilter<'_>) -> bool {
+        match filter {
+            WalletManageRecordFilter::ByRecordId(record_id) => self.record.record_id == *record_id,
+            WalletManageRecordFilter::ByStatus(status) => self.record.status == **status,
+        }
+    }
+
+    fn apply(&mut self, updater: &WalletManageRecordUpdater<'_>) {
+        match updater {
+            WalletManageRecordUpdater::TxIds(ids) => self.record.tx_ids = ids.to_vec(),
+            WalletManageRecordUpdater::Status(status) => self.record.status = status.clone(),
+            WalletManageRecordUpdater::Resubmitted(tx_id, retry_times) => {
+                self.record.tx_ids.push(tx_id.to_string());
+                self.record.retry_times = *retry_times;
+            }
+            WalletManageRecordUpdater::RetryFailed(retry_times) => {
+                self.record.retry_times = *retry_times;
+            }
+        }
+    }
+
+    fn unique_keys(&self) -> Vec<String> {
+        vec![format!("record_id:{}", self.record.record_id)]
+    }
+
+    fn timestamps_mut(&mut self) -> (&mut String, &mut String) {
+        (&mut self.updated_at, &mut self.created_at)
+    }
+}
+
+//上链结果通过outbox通知下游
+async fn emit_status_changed(
+    new_value: &WalletManageRecordUpdater<'_>,
+    filter: &WalletManageRecordFilter<'_>,
+    row_num: u64,
+) -> DbResult<()> {
+    if let (
+        WalletManageRecordUpdater::Status(status),
+        WalletManageRecordFilter::ByRecordId(record_id),
+    ) = (new_value, filter)
+    {
+        if row_num > 0 {
+            outbox::emit(
+                OutboxEventType::WalletManageStatusChanged,
+                record_id,
+                &json!({ "record_id": record_id, "status": status }),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
 //wallet_manage_history
 #[async_trait]
 impl PsqlOp for WalletManageRecordEntity {
     type UpdaterContent<'a> = WalletManageRecordUpdater<'a>;
     type FilterContent<'b> = WalletManageRecordFilter<'b>;
 
-    async fn find(filter: Self::FilterContent<'_>) -> Result<Vec<Self>> {
-        let sql = format!(
-            "select 
+    async fn find(filter: Self::FilterContent<'_>) -> DbResult<Vec<Self>> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            return Ok(store.find(&filter));
+        }
+        let mut sql = SqlBuilder::new(
+            "select \
             record_id,\
             user_id,\
             operation_type,\
@@ -113,13 +190,14 @@ impl PsqlOp for WalletManageRecordEntity {
             operator_device_brand,\
             tx_ids,\
             status,\
+            retry_times,\
          cast(updated_at as text), \
          cast(created_at as text) \
-         from wallet_manage_record where {}",
-            filter
+         from wallet_manage_record where ",
         );
-        let execute_res = PgLocalCli::query(sql.as_str()).await?;
-        debug!("get device: raw sql {}", sql);
+        sql.clause(&filter);
+        let execute_res = sql.query().await?;
+        debug!("get device: raw sql {}", sql.sql());
         let gen_view = |row: &Row| -> Result<WalletManageRecordEntity> {
             Ok(WalletManageRecordEntity {
                 record: WalletManageRecord {
@@ -131,30 +209,46 @@ impl PsqlOp for WalletManageRecordEntity {
                     operator_device_brand: row.get(5),
                     tx_ids: row.get::<usize, Vec<String>>(6),
                     status: row.get::<usize, String>(7).parse()?,
+                    retry_times: row.get::<usize, i32>(8) as u32,
                 },
-                updated_at: row.get(8),
-                created_at: row.get(9),
+                updated_at: row.get(9),
+                created_at: row.get(10),
             })
         };
 
-        execute_res.iter().map(gen_view).collect()
+        Ok(execute_res.iter().map(gen_view).collect::<Result<_>>()?)
     }
     async fn update(
         new_value: Self::UpdaterContent<'_>,
         filter: Self::FilterContent<'_>,
-    ) -> Result<u64> {
-        let sql = format!(
-            "update wallet_manage_record set {} ,updated_at=CURRENT_TIMESTAMP where {}",
-            new_value, filter
-        );
-        debug!("start update orders {} ", sql);
-        let execute_res = PgLocalCli::execute(sql.as_str()).await?;
+    ) -> DbResult<u64> {
+        if let Some(store) = PgLocalCli::memory_store() {
+            let row_num = store.update::<Self>(&new_value, &filter);
+            emit_status_changed(&new_value, &filter, row_num).await?;
+            return Ok(row_num);
+        }
+        let mut sql = SqlBuilder::new("update wallet_manage_record set ");
+        sql.clause(&new_value)
+            .push(",updated_at=CURRENT_TIMESTAMP where ")
+            .clause(&filter);
+        debug!("start update orders {} ", sql.sql());
+        let execute_res = sql.execute().await?;
         //assert_ne!(execute_res, 0);
         debug!("success update orders {} rows", execute_res);
+        emit_status_changed(&new_value, &filter, execute_res).await?;
         Ok(execute_res)
     }
 
-    async fn insert(self) -> Result<()> {
+    async fn insert(self) -> DbResult<()> {
+        outbox::emit(
+            OutboxEventType::WalletManageRequested,
+            &self.record.record_id,
+            &self.record,
+        )
+        .await?;
+        if let Some(store) = PgLocalCli::memory_store() {
+            return store.insert(self);
+        }
         let WalletManageRecord {
             record_id,
             user_id,
@@ -164,8 +258,9 @@ impl PsqlOp for WalletManageRecordEntity {
             operator_device_brand,
             tx_ids,
             status,
+            retry_times,
         } = self.into_inner();
-        let sql = format!(
+        let mut sql = SqlBuilder::new(
             "insert into wallet_manage_record (\
                 record_id,\
                 user_id,\
@@ -174,19 +269,24 @@ impl PsqlOp for WalletManageRecordEntity {
                 operator_device_id,\
                 operator_device_brand,\
                 tx_ids,\
-                status\
-         ) values ('{}','{}','{}','{}','{}','{}',{},'{}');",
-            record_id,
-            user_id,
-            operation_type,
-            operator_pubkey,
-            operator_device_id,
-            operator_device_brand,
-            vec_str2array_text(tx_ids),
-            status
+                status,\
+                retry_times\
+         ) values (",
         );
-        debug!("row sql {} rows", sql);
-        let _execute_res = PgLocalCli::execute(sql.as_str()).await?;
+        sql.bind_all(vec![
+            Box::new(record_id),
+            Box::new(user_id as i64),
+            Box::new(operation_type.to_string()),
+            Box::new(operator_pubkey),
+            Box::new(operator_device_id),
+            Box::new(operator_device_brand),
+            Box::new(tx_ids),
+            Box::new(status.to_string()),
+            Box::new(retry_times as i32),
+        ])
+        .push(")");
+        debug!("row sql {} rows", sql.sql());
+        let _execute_res = sql.execute().await?;
         Ok(())
     }
 }
@@ -2,8 +2,12 @@
 //#![allow(unused_imports)]
 #![allow(dead_code)]
 
+pub mod sink;
 pub mod task;
 
+#[macro_use]
+extern crate lazy_static;
+
 use anyhow::Result;
 use clap::Parser;
 use tracing::info;
@@ -19,6 +23,8 @@ struct Args {
 async fn main() -> Result<()> {
     common::log::init_logger();
     let args = Args::parse();
+    //数据库结构没有迁移到最新版本时拒绝启动
+    models::migration::check_up_to_date().await?;
     //剥离wallet_manage和coin_transfer的原因是考虑到是否relayer签名、是否重试、以及业务的解耦
     match args.task.as_str() {
         "eth_bridge" => {
@@ -33,9 +39,21 @@ async fn main() -> Result<()> {
             info!("start task listening on chainless_coin_transfer");
             task::chainless_coin_transfer::start().await?;
         }
+        "outbox" => {
+            info!("start task publishing outbox events");
+            task::outbox::start().await?;
+        }
+        "coin_tx_archive" => {
+            info!("start task archiving finished coin transactions");
+            task::coin_tx_archive::start().await?;
+        }
         "refund_fee" => {
-            //todo:
-            info!("start task refund_fee");
+            info!("start task refunding fees of failed or canceled transactions");
+            task::refund_fee::start().await?;
+        }
+        "coin_tx_expiry" => {
+            info!("start task expiring multi-sig orders");
+            task::coin_tx_expiry::start().await?;
         }
         _ => panic!("unknown task"),
     }
@@ -0,0 +1,119 @@
+//! destinations of the outbox events, every sink must accept an event more than once
+//! since a batch is retried as a whole when any sink fails
+
+use anyhow::Result;
+use async_trait::async_trait;
+use common::data_structures::outbox::OutboxEvent;
+use common::env::OutboxConf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn publish(&self, event: &OutboxEvent) -> Result<()>;
+}
+
+lazy_static! {
+    //进程内的订阅者通过EVENT_CHANNEL.subscribe()接收事件
+    pub static ref EVENT_CHANNEL: broadcast::Sender<OutboxEvent> = broadcast::channel(1024).0;
+}
+
+pub struct WebhookSink {
+    url: String,
+    cli: reqwest::Client,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        WebhookSink {
+            url: url.to_string(),
+            cli: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn publish(&self, event: &OutboxEvent) -> Result<()> {
+        self.cli
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(event)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+pub struct LogFileSink {
+    path: String,
+}
+
+impl LogFileSink {
+    pub fn new(path: &str) -> Self {
+        LogFileSink {
+            path: path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for LogFileSink {
+    fn name(&self) -> &'static str {
+        "log_file"
+    }
+
+    async fn publish(&self, event: &OutboxEvent) -> Result<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        let line = format!("{}\n", serde_json::to_string(event)?);
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+pub struct ChannelSink {
+    sender: broadcast::Sender<OutboxEvent>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: broadcast::Sender<OutboxEvent>) -> Self {
+        ChannelSink { sender }
+    }
+}
+
+#[async_trait]
+impl EventSink for ChannelSink {
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+
+    async fn publish(&self, event: &OutboxEvent) -> Result<()> {
+        //没有订阅者不算失败
+        if self.sender.receiver_count() > 0 {
+            self.sender.send(event.clone())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn from_config(conf: &OutboxConf) -> Vec<Box<dyn EventSink>> {
+    let mut sinks: Vec<Box<dyn EventSink>> =
+        vec![Box::new(ChannelSink::new(EVENT_CHANNEL.clone()))];
+    if let Some(url) = &conf.webhook_url {
+        sinks.push(Box::new(WebhookSink::new(url)));
+    }
+    if let Some(path) = &conf.log_file {
+        sinks.push(Box::new(LogFileSink::new(path)));
+    }
+    sinks
+}
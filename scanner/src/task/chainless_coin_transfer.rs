@@ -1,38 +1,150 @@
-use common::data_structures::TxStatusOnChain;
+use blockchain::multi_sig::{AccountSignInfo, MultiSig};
+use blockchain::ContractClient;
+use common::data_structures::coin_transaction::{CoinTransaction, TxType};
+use common::data_structures::{PubkeySignInfo, TxStatusOnChain};
+use common::utils::time::now_millis;
 use models::coin_transfer::CoinTxEntity;
+use models::memory::timestamp_millis;
 
 use models::{
-    coin_transfer::{CoinTxFilter, CoinTxUpdater},
+    coin_transfer::{CoinTxFilter, CoinTxStageError, CoinTxUpdater},
+    error::DbError,
     PsqlOp,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
 
-use anyhow::Result;
+use super::in_round;
+use anyhow::{anyhow, Result};
 
-pub async fn start() -> Result<()> {
-    loop {
-        //check manage_opcord
-        let txs = CoinTxEntity::find(CoinTxFilter::ByChainStatus(TxStatusOnChain::Pending)).await?;
-
-        for tx in txs {
-            let tx_id = if let Some(txid) = tx.transaction.tx_id {
-                txid
-            } else {
-                error!("pending tx have no txid?");
-                continue;
-            };
-
-            debug!("start check tx {}", tx_id);
-            let status = blockchain::general::tx_status(&tx_id).await?;
-            if status != TxStatusOnChain::Pending {
-                CoinTxEntity::update_single(
-                    CoinTxUpdater::StageChainStatus(tx.transaction.stage, status),
-                    CoinTxFilter::ByOrderId(&tx.transaction.order_id),
+//用保存的签名重新构建交易，由relayer用新的nonce提交
+async fn resubmit(tx: &CoinTransaction) -> Result<String> {
+    let confirmed_sig = tx
+        .confirmed_sig
+        .as_ref()
+        .ok_or(anyhow!("confirmed signature isn't saved"))?;
+    let mut multi_cli = ContractClient::<MultiSig>::new_update_cli().await?;
+    match tx.tx_type {
+        TxType::MainToSub => {
+            if now_millis() > tx.expire_at {
+                Err(anyhow!("tx is expired"))?;
+            }
+            let master_sig: PubkeySignInfo = confirmed_sig.parse()?;
+            let servant_sigs = tx
+                .signatures
+                .iter()
+                .map(|data| data.parse())
+                .collect::<Result<Vec<PubkeySignInfo>, _>>()?;
+            multi_cli
+                .internal_transfer_main_to_sub(
+                    master_sig,
+                    servant_sigs,
+                    &tx.sender,
+                    &tx.receiver,
+                    tx.coin_type.clone(),
+                    tx.amount,
+                    tx.expire_at,
                 )
-                .await?;
+                .await
+        }
+        TxType::SubToMain => {
+            multi_cli
+                .internal_transfer_sub_to_main(
+                    &tx.receiver,
+                    AccountSignInfo::new(&tx.sender, confirmed_sig),
+                    tx.coin_type.clone(),
+                    tx.amount,
+                )
+                .await
+        }
+        TxType::Normal | TxType::Forced | TxType::MainToBridge => {
+            Err(anyhow!("{} tx needs the sender to sign again", tx.tx_type))
+        }
+    }
+}
+
+//按交易类型的重试策略重新提交，每轮扫描最多提交一次，次数用完之后置为Failed并记录原因
+async fn retry_failed(tx: &mut CoinTxEntity, tx_id: &str) -> Result<(), CoinTxStageError> {
+    let retry_times = tx.transaction.retry_times;
+    let (max_retry_times, delay) = match tx.transaction.tx_type.retry_conf() {
+        Some(conf) => (conf.max_times, conf.delay_millis(retry_times)),
+        None => (0, 0),
+    };
+    let reason = format!("tx {} failed on chain", tx_id);
+    if retry_times >= max_retry_times {
+        error!("order {} failed: {}", tx.transaction.order_id, reason);
+        return tx
+            .update_with_version(CoinTxUpdater::ChainFailed(retry_times, &reason))
+            .await;
+    }
+    //距离上一次提交不到退避时间的留给后面的扫描
+    let last_attempt_at = timestamp_millis(&tx.updated_at).unwrap_or_default() as u64;
+    if now_millis() < last_attempt_at + delay {
+        debug!(
+            "order {} will be retried after {} ms",
+            tx.transaction.order_id, delay
+        );
+        return Ok(());
+    }
+    let retry_times = retry_times + 1;
+    match resubmit(&tx.transaction).await {
+        Ok(new_tx_id) => {
+            info!(
+                "order {} resubmitted as tx {} ({}/{})",
+                tx.transaction.order_id, new_tx_id, retry_times, max_retry_times
+            );
+            tx.update_with_version(CoinTxUpdater::Resubmitted(&new_tx_id, retry_times, &reason))
+                .await
+        }
+        Err(err) => {
+            warn!(
+                "resubmit order {} failed ({}/{}): {}",
+                tx.transaction.order_id, retry_times, max_retry_times, err
+            );
+            let reason = format!("resubmit failed: {}", err);
+            tx.update_with_version(CoinTxUpdater::RetryFailed(retry_times, &reason))
+                .await
+        }
+    }
+}
+
+async fn check_pending() -> Result<()> {
+    let txs = CoinTxEntity::find(CoinTxFilter::ByChainStatus(TxStatusOnChain::Pending)).await?;
+
+    for mut tx in txs {
+        let tx_id = if let Some(txid) = tx.transaction.tx_id.clone() {
+            txid
+        } else {
+            error!("pending tx have no txid?");
+            continue;
+        };
+
+        debug!("start check tx {}", tx_id);
+        let status = blockchain::general::tx_status(&tx_id).await?;
+        let res = match status {
+            TxStatusOnChain::Pending => continue,
+            TxStatusOnChain::Failed => retry_failed(&mut tx, &tx_id).await,
+            _ => {
+                let stage = tx.transaction.stage.clone();
+                tx.update_with_version(CoinTxUpdater::StageChainStatus(stage, status))
+                    .await
+            }
+        };
+        match res {
+            Ok(()) => {}
+            //订单在查询之后被其他请求修改了，下一轮重新检查
+            Err(CoinTxStageError::Db(DbError::Conflict(order_id))) => {
+                warn!("order {} changed since version {}", order_id, tx.version);
             }
-            //todo: try to call again when failed
+            Err(err) => Err(err)?,
         }
+    }
+    Ok(())
+}
+
+pub async fn start() -> Result<()> {
+    loop {
+        //check manage_opcord
+        in_round(check_pending()).await?;
         tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
     }
 }
@@ -1,36 +1,178 @@
 use common::data_structures::TxStatusOnChain;
+use common::env::CONF;
+use common::utils::time::now_millis;
+use models::memory::timestamp_millis;
 use models::{
     wallet_manage_record::{
         WalletManageRecordEntity, WalletManageRecordFilter, WalletManageRecordUpdater,
     },
     PsqlOp,
 };
-use tracing::debug;
+use tracing::{debug, error, info, warn};
 
+use super::in_round;
 use anyhow::Result;
 
-pub async fn start() -> Result<()> {
-    loop {
-        //check manage_opcord
-        let ops = WalletManageRecordEntity::find(WalletManageRecordFilter::ByStatus(
-            &TxStatusOnChain::Pending,
-        ))
+//relayer提交的操作失败之后按配置重新提交，每轮扫描最多提交一次，次数用完之后置为Failed
+async fn retry_failed(op: &WalletManageRecordEntity, tx_id: &str) -> Result<()> {
+    let record = &op.record;
+    let conf = &CONF.tx_retry.wallet_manage;
+    let filter = WalletManageRecordFilter::ByRecordId(&record.record_id);
+    if record.retry_times >= conf.max_times {
+        error!(
+            "wallet manage {} {} failed on chain in tx {}",
+            record.operation_type, record.record_id, tx_id
+        );
+        WalletManageRecordEntity::update_single(
+            WalletManageRecordUpdater::Status(TxStatusOnChain::Failed),
+            filter,
+        )
         .await?;
+        return Ok(());
+    }
+    //距离上一次提交不到退避时间的留给后面的扫描
+    let delay = conf.delay_millis(record.retry_times);
+    let last_attempt_at = timestamp_millis(&op.updated_at).unwrap_or_default() as u64;
+    if now_millis() < last_attempt_at + delay {
+        debug!(
+            "wallet manage {} will be retried after {} ms",
+            record.record_id, delay
+        );
+        return Ok(());
+    }
+    let retry_times = record.retry_times + 1;
+    let resubmitted = blockchain::general::resubmit_by_relayer(tx_id).await;
+    let updater = match &resubmitted {
+        Ok(new_tx_id) => {
+            info!(
+                "wallet manage {} resubmitted as tx {} ({}/{})",
+                record.record_id, new_tx_id, retry_times, conf.max_times
+            );
+            WalletManageRecordUpdater::Resubmitted(new_tx_id, retry_times)
+        }
+        Err(err) => {
+            warn!(
+                "resubmit wallet manage {} failed ({}/{}): {}",
+                record.record_id, retry_times, conf.max_times, err
+            );
+            WalletManageRecordUpdater::RetryFailed(retry_times)
+        }
+    };
+    WalletManageRecordEntity::update_single(updater, filter).await?;
+    Ok(())
+}
 
-        for op in ops {
-            //有些业务(如创建从设备换成主设备) 会产生多个txid，此时以最后一个id为准
-            let tx_id = op.record.tx_ids.last().unwrap();
-            debug!("start check tx {}", tx_id);
-            let status = blockchain::general::tx_status(tx_id).await.unwrap();
-            if status != TxStatusOnChain::Pending {
+//检查所有上链中的操作，成功的更新状态，失败的按配置重试
+async fn check_pending() -> Result<()> {
+    let ops = WalletManageRecordEntity::find(WalletManageRecordFilter::ByStatus(
+        &TxStatusOnChain::Pending,
+    ))
+    .await?;
+
+    for op in ops {
+        //有些业务(如创建从设备换成主设备) 会产生多个txid，此时以最后一个id为准
+        let tx_id = op.record.tx_ids.last().unwrap();
+        debug!("start check tx {}", tx_id);
+        let status = blockchain::general::tx_status(tx_id).await.unwrap();
+        match status {
+            TxStatusOnChain::Pending => {}
+            TxStatusOnChain::Failed => retry_failed(&op, tx_id).await?,
+            _ => {
                 let _ = WalletManageRecordEntity::update_single(
                     WalletManageRecordUpdater::Status(status),
                     WalletManageRecordFilter::ByRecordId(&op.record.record_id),
                 )
                 .await;
             }
-            //todo: try to call again when failed
         }
+    }
+    Ok(())
+}
+
+pub async fn start() -> Result<()> {
+    loop {
+        //check manage_opcord
+        in_round(check_pending()).await?;
         tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use blockchain::mock_chain::{self, MockChain};
+    use common::data_structures::wallet_namage_record::WalletOperateType;
+    use common::utils::math::hex_to_bs58;
+    use models::memory::{self, MemoryStore};
+
+    use super::*;
+
+    fn tx_id(seed: u8) -> String {
+        hex::encode([seed; 32])
+    }
+
+    async fn insert_op(tx_id: &str, retry_times: u32) -> String {
+        let mut op = WalletManageRecordEntity::new_with_specified(
+            1,
+            WalletOperateType::AddServant,
+            "pubkey",
+            "device1",
+            "apple",
+            vec![tx_id.to_string()],
+        );
+        op.record.retry_times = retry_times;
+        let record_id = op.record.record_id.clone();
+        op.insert().await.unwrap();
+        record_id
+    }
+
+    async fn status_of(record_id: &str) -> (TxStatusOnChain, u32) {
+        let op =
+            WalletManageRecordEntity::find_single(WalletManageRecordFilter::ByRecordId(record_id))
+                .await
+                .unwrap()
+                .into_inner();
+        (op.status, op.retry_times)
+    }
+
+    #[tokio::test]
+    async fn test_check_pending_wallet_manage() {
+        let chain = Arc::new(MockChain::default());
+        let set_status = |seed: u8, status: TxStatusOnChain| {
+            let tx_hash = hex_to_bs58(&tx_id(seed)).unwrap().parse().unwrap();
+            chain.set_tx_status(tx_hash, status);
+        };
+        set_status(1, TxStatusOnChain::Successful);
+        set_status(2, TxStatusOnChain::Pending);
+        set_status(3, TxStatusOnChain::Failed);
+        set_status(4, TxStatusOnChain::Failed);
+
+        let task = async {
+            let succeeded = insert_op(&tx_id(1), 0).await;
+            let pending = insert_op(&tx_id(2), 0).await;
+            //刚提交的失败交易要等退避时间之后才重试
+            let backoff = insert_op(&tx_id(3), 0).await;
+            let exhausted = insert_op(&tx_id(4), CONF.tx_retry.wallet_manage.max_times).await;
+
+            check_pending().await.unwrap();
+
+            let max_times = CONF.tx_retry.wallet_manage.max_times;
+            assert_eq!(
+                status_of(&succeeded).await,
+                (TxStatusOnChain::Successful, 0)
+            );
+            assert_eq!(status_of(&pending).await, (TxStatusOnChain::Pending, 0));
+            assert_eq!(status_of(&backoff).await, (TxStatusOnChain::Pending, 0));
+            assert_eq!(
+                status_of(&exhausted).await,
+                (TxStatusOnChain::Failed, max_times)
+            );
+        };
+        let task = models::general::run_api_call("", task);
+        let store = Arc::new(MemoryStore::default());
+        memory::scope(store, mock_chain::scope(chain.clone(), task))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,31 @@
+use common::env::CONF;
+use common::utils::time::now_millis;
+use models::coin_transfer::CoinTxEntity;
+use tracing::info;
+
+use super::in_round;
+use anyhow::Result;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+pub async fn start() -> Result<()> {
+    let conf = &CONF.archive;
+    let sweep = &conf.sweep;
+    loop {
+        let now = now_millis();
+        let updated_before = now.saturating_sub(conf.coin_tx_retention_days * DAY_MILLIS);
+        let moved = in_round(CoinTxEntity::archive_finished(
+            updated_before,
+            now,
+            sweep.batch_size,
+        ))
+        .await?;
+        if moved > 0 {
+            info!("archived {} coin transactions", moved);
+        }
+        //没有剩余的旧订单了才休眠
+        if moved < sweep.batch_size as u64 {
+            tokio::time::sleep(std::time::Duration::from_secs(sweep.interval_secs)).await;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use blockchain::multi_sig::{send_money_tx_index, MultiSig};
+use blockchain::ContractClient;
+use common::data_structures::coin_transaction::{CoinSendStage, CoinTransaction};
+use common::data_structures::outbox::OutboxEventType;
+use common::env::CONF;
+use common::utils::time::now_millis;
+use models::coin_transfer::{CoinTxEntity, CoinTxFilter, CoinTxStageError, CoinTxUpdater};
+use models::error::DbError;
+use models::PsqlOp;
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use super::in_round;
+use anyhow::Result;
+
+//合约里还登记着这笔交易的tx_index时先移除，避免过期的签名之后还能被执行
+async fn release_tx_index(
+    multi_cli: &mut ContractClient<MultiSig>,
+    tx: &CoinTransaction,
+) -> Result<()> {
+    let tx_index = match tx.chain_tx_raw.as_deref().map(send_money_tx_index) {
+        Some(tx_index) => tx_index?,
+        None => None,
+    };
+    if let Some(tx_index) = tx_index {
+        let registered = multi_cli
+            .get_tx_state(vec![tx_index])
+            .await?
+            .map_or(false, |states| {
+                states.iter().any(|(index, _)| *index == tx_index)
+            });
+        if registered {
+            let tx_id = multi_cli.remove_tx_index(tx_index).await?;
+            debug!(
+                "order {} removed tx_index {} in tx {}",
+                tx.order_id, tx_index, tx_id
+            );
+        }
+    }
+    Ok(())
+}
+
+//置为MultiSigExpired之后冻结的金额就不再计入未完成的订单，并通知发起方
+async fn expire(tx: &mut CoinTxEntity) -> Result<(), CoinTxStageError> {
+    let order = &tx.transaction;
+    let payload = json!({
+        "order_id": order.order_id,
+        "sender": order.sender,
+        "coin_type": order.coin_type,
+        "amount": order.amount.to_string()
+    });
+    //和订单状态在同一个事务里写入，不会只过期而丢掉通知
+    tx.update_with_version_and_emit(
+        CoinTxUpdater::Stage(CoinSendStage::MultiSigExpired),
+        Some((OutboxEventType::CoinTxExpired, payload)),
+    )
+    .await?;
+    let tx = &tx.transaction;
+    info!("order {} of {} expired", tx.order_id, tx.sender);
+    Ok(())
+}
+
+async fn sweep(multi_cli: &mut ContractClient<MultiSig>, limit: u32) -> Result<usize> {
+    let txs = CoinTxEntity::find(CoinTxFilter::Expired(now_millis(), limit)).await?;
+    let mut swept = 0;
+    for mut tx in txs {
+        //合约调用失败时先不改状态，下一轮再试
+        if let Err(err) = release_tx_index(multi_cli, &tx.transaction).await {
+            warn!(
+                "release tx_index of order {} failed: {}",
+                tx.transaction.order_id, err
+            );
+            continue;
+        }
+        match expire(&mut tx).await {
+            Ok(()) => swept += 1,
+            //用户在查询之后刚好操作了订单，以用户的操作为准
+            Err(CoinTxStageError::Db(DbError::Conflict(order_id))) => {
+                warn!("order {} changed since version {}", order_id, tx.version);
+            }
+            Err(err) => Err(err)?,
+        }
+    }
+    Ok(swept)
+}
+
+pub async fn start() -> Result<()> {
+    let conf = &CONF.coin_tx_expiry;
+    let mut multi_cli = ContractClient::<MultiSig>::new_update_cli().await?;
+    loop {
+        let swept = in_round(sweep(&mut multi_cli, conf.batch_size)).await?;
+        //一整批都过期了说明可能还有剩余，不休眠继续处理
+        if swept < conf.batch_size as usize {
+            tokio::time::sleep(std::time::Duration::from_secs(conf.interval_secs)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use blockchain::mock_chain::{self, MockChain};
+    use models::memory::{self, MemoryStore};
+    use models::outbox::{OutboxEntity, OutboxFilter};
+
+    use super::*;
+
+    async fn insert_order(expire_at: u64) -> String {
+        let coin_tx = CoinTxEntity::new_with_specified(
+            "usdt".parse().unwrap(),
+            "1.test".to_string(),
+            "2.test".to_string(),
+            1,
+            "".to_string(),
+            None,
+            expire_at,
+            CoinSendStage::SenderSigCompleted,
+        );
+        let order_id = coin_tx.transaction.order_id.clone();
+        coin_tx.insert().await.unwrap();
+        order_id
+    }
+
+    async fn stage_of(order_id: &str) -> CoinSendStage {
+        CoinTxEntity::find_single(CoinTxFilter::ByOrderId(order_id))
+            .await
+            .unwrap()
+            .into_inner()
+            .stage
+    }
+
+    #[tokio::test]
+    async fn test_sweep_expired_orders() {
+        let task = async {
+            let now = now_millis();
+            let expired = insert_order(now - 1000).await;
+            let alive = insert_order(now + 60_000).await;
+
+            //没有登记tx_index的订单不需要调用合约
+            let mut multi_cli = ContractClient::<MultiSig>::new_update_cli().await.unwrap();
+            assert_eq!(sweep(&mut multi_cli, 10).await.unwrap(), 1);
+            assert_eq!(sweep(&mut multi_cli, 10).await.unwrap(), 0);
+
+            assert_eq!(stage_of(&expired).await, CoinSendStage::MultiSigExpired);
+            assert_eq!(stage_of(&alive).await, CoinSendStage::SenderSigCompleted);
+
+            let events = OutboxEntity::find(OutboxFilter::Pending(10)).await.unwrap();
+            assert!(events.iter().any(|event| {
+                event.event.event_type == OutboxEventType::CoinTxExpired
+                    && event.event.aggregate_id == expired
+            }));
+        };
+        let chain = Arc::new(MockChain::default());
+        chain.register_relayers().await;
+        let task = models::general::run_api_call("", task);
+        let task = mock_chain::scope(chain, task);
+        memory::scope(Arc::new(MemoryStore::default()), task)
+            .await
+            .unwrap();
+    }
+}
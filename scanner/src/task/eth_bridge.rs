@@ -1,158 +1,284 @@
-use std::time;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
 
-use anyhow::Ok;
-use anyhow::Result;
-use blockchain::bridge_on_eth::Bridge;
+use anyhow::{anyhow, Result};
+use blockchain::bridge_on_eth::{Bridge, OrderEventInfo};
 use blockchain::eth_cli::general::*;
 use blockchain::eth_cli::EthContractClient;
 use common::constants::ETH_TX_CONFIRM_BLOCK_NUM;
 use common::data_structures::bridge::EthOrderStatus;
-use common::data_structures::bridge::OrderType;
+use common::env::{EthBridgeConf, CONF};
+use models::error::DbError;
 use models::eth_bridge_order::BridgeOrderUpdater;
 use models::eth_bridge_order::{BridgeOrderFilter, EthBridgeOrderEntity};
-
+use models::scanner_checkpoint;
 use models::PsqlOp;
-use tracing::info;
-
-//如果没历史监控数据，则从固定检查点开始扫,如果有则从历史数据中的最后高度开始扫
-pub async fn get_last_process_height() -> Result<u64> {
-    let last_order = EthBridgeOrderEntity::find(BridgeOrderFilter::Limit(1)).await?;
-    if last_order.is_empty() {
-        //Ok(get_current_block().await)
-        Ok(1446063)
-    } else {
-        //Ok(1322262)
-        Ok(last_order[0].order.height)
+use tracing::{debug, info, warn};
+
+use super::in_round;
+
+const TASK: &str = "eth_bridge";
+//没有扫描进度时的起始高度
+const START_HEIGHT: u64 = 1446063;
+//用于发现分叉的最近已扫描区块数
+const REORG_WINDOW: usize = 64;
+
+/// recently scanned blocks in ascending order, the last one is the scanned tip
+struct Checkpoint {
+    blocks: VecDeque<(u64, String)>,
+}
+
+impl Checkpoint {
+    fn new(height: u64, block_hash: String) -> Self {
+        Checkpoint {
+            blocks: VecDeque::from([(height, block_hash)]),
+        }
+    }
+
+    fn tip(&self) -> u64 {
+        self.blocks.back().map(|(height, _)| *height).unwrap_or(0)
+    }
+
+    fn push(&mut self, height: u64, block_hash: String) {
+        if height <= self.tip() {
+            return;
+        }
+        self.blocks.push_back((height, block_hash));
+        if self.blocks.len() > REORG_WINDOW {
+            self.blocks.pop_front();
+        }
+    }
+
+    fn truncate_above(&mut self, height: u64) {
+        self.blocks
+            .retain(|(block_height, _)| *block_height <= height);
     }
 }
 
-//listen and then insert pending
-pub async fn listen_newest_block(bridge: &EthContractClient<Bridge>, height: u64) -> Result<()> {
-    let block_hash = get_block(height).await?.unwrap().hash.unwrap();
-    let block_hash = hex::encode(block_hash.as_bytes());
+/// exponential backoff after rpc or db errors
+struct Backoff {
+    failures: u32,
+    max: Duration,
+}
 
-    let deposit_orders = bridge.filter_deposit_event(&block_hash).await.unwrap();
-    if deposit_orders.is_empty() {
-        //info!("Not found deposit0 orders created at height {}",height);
-    } else {
-        info!(
-            "filter_deposit_event {:?} at height {}",
-            deposit_orders, height
+impl Backoff {
+    async fn wait(&mut self, err: &anyhow::Error) {
+        let delay = Duration::from_secs(2u64.saturating_pow(self.failures)).min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        warn!(
+            "eth_bridge scan failed {} times: {}, retry in {:?}",
+            self.failures, err, delay
         );
-        //todo: batch insert
-        for order in deposit_orders {
-            let order = EthBridgeOrderEntity::new_with_specified(
-                &order.id,
-                &order.chainless_acc,
-                &order.eth_addr,
-                order.order_type,
-                order.coin,
-                order.amount,
-                EthOrderStatus::Pending,
-                height,
-            );
-            order.insert().await?;
-        }
+        tokio::time::sleep(delay).await;
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
     }
+}
 
-    let withdraw_orders = bridge.filter_withdraw_event(&block_hash).await.unwrap();
+async fn block_hash_of(height: u64) -> Result<String> {
+    get_block_hash(height)
+        .await?
+        .ok_or(anyhow!("eth block {} not found", height))
+}
 
-    if withdraw_orders.is_empty() {
-        //info!("Not found new filter_withdraw_event3 created at height {}", height);
+//没有扫描进度则从固定检查点开始扫，有则从记录的高度之后开始扫
+async fn load_checkpoint() -> Result<Checkpoint> {
+    let (height, block_hash) = match scanner_checkpoint::load(TASK).await? {
+        Some(checkpoint) => (checkpoint.height, checkpoint.block_hash),
+        None => (START_HEIGHT, String::new()),
+    };
+    //回退过的进度没有区块hash，以链上当前的为准
+    let block_hash = if block_hash.is_empty() {
+        block_hash_of(height).await?
     } else {
-        info!(
-            "filter_withdraw_event {:?} at height {}",
-            withdraw_orders, height
-        );
-        for order in withdraw_orders {
-            let order = EthBridgeOrderEntity::new_with_specified(
-                &order.id,
-                &order.chainless_acc,
-                &order.eth_addr,
+        block_hash
+    };
+    Ok(Checkpoint::new(height, block_hash))
+}
+
+async fn save_checkpoint(checkpoint: &Checkpoint) -> Result<()> {
+    if let Some((height, block_hash)) = checkpoint.blocks.back() {
+        scanner_checkpoint::save(TASK, *height, block_hash).await?;
+    }
+    Ok(())
+}
+
+//已扫描的区块被替换时，返回还在主链上的最高的已扫描区块
+async fn find_reorg(checkpoint: &Checkpoint) -> Result<Option<u64>> {
+    for (index, (height, block_hash)) in checkpoint.blocks.iter().rev().enumerate() {
+        if get_block_hash(*height).await?.as_ref() == Some(block_hash) {
+            return Ok((index != 0).then_some(*height));
+        }
+    }
+    //分叉比记录的区块还深，再往前退一个窗口重扫
+    let oldest = checkpoint.blocks.front().map(|(height, _)| *height);
+    let rewind = oldest
+        .unwrap_or(START_HEIGHT)
+        .saturating_sub(REORG_WINDOW as u64);
+    warn!(
+        "eth reorg is deeper than the scanned window, rewind to {}",
+        rewind
+    );
+    Ok(Some(rewind))
+}
+
+//被替换区块里的待确认订单删掉，重新扫描时还在主链上的会再次插入
+async fn rollback(checkpoint: &mut Checkpoint, height: u64) -> Result<()> {
+    warn!("eth reorg detected, roll back to height {}", height);
+    EthBridgeOrderEntity::delete(BridgeOrderFilter::ByStatusAboveHeight(
+        EthOrderStatus::Pending,
+        height,
+    ))
+    .await?;
+    checkpoint.truncate_above(height);
+    if checkpoint.blocks.is_empty() {
+        *checkpoint = Checkpoint::new(height, block_hash_of(height).await?);
+    }
+    save_checkpoint(checkpoint).await
+}
+
+//日志可能来自扫描期间被替换的区块，只接受区块hash还在主链上的
+async fn check_canonical(orders: &[OrderEventInfo], to: u64, to_hash: &str) -> Result<()> {
+    let mut canonical = HashMap::from([(to, to_hash.to_string())]);
+    for order in orders {
+        let block_hash = match canonical.get(&order.height) {
+            Some(block_hash) => block_hash.clone(),
+            None => {
+                let block_hash = block_hash_of(order.height).await?;
+                canonical.insert(order.height, block_hash.clone());
+                block_hash
+            }
+        };
+        if block_hash != order.block_hash {
+            Err(anyhow!(
+                "{} order {} is in block {} which isn't canonical at height {}",
                 order.order_type,
-                order.coin,
-                order.amount,
-                EthOrderStatus::Confirmed,
-                height,
-            );
-            order.insert().await?;
+                order.id,
+                order.block_hash,
+                order.height
+            ))?;
         }
     }
     Ok(())
 }
 
-//listen and then update to confirm
-//DRY
-pub async fn listen_confirmed_block(bridge: &EthContractClient<Bridge>, height: u64) -> Result<()> {
-    let block_hash = get_block(height).await?.unwrap().hash.unwrap();
-    let block_hash = hex::encode(block_hash.as_bytes());
-    //info!("check block_hash {}", block_hash);
+//重扫时订单可能已经存在了
+async fn save_order(order: &OrderEventInfo) -> Result<()> {
+    let entity = EthBridgeOrderEntity::new_with_specified(
+        &order.id,
+        &order.chainless_acc,
+        &order.eth_addr,
+        order.order_type.clone(),
+        order.coin.clone(),
+        order.amount,
+        //充值和提现都等确认数足够之后才是Confirmed，在此之前分叉时删掉重扫
+        EthOrderStatus::Pending,
+        order.height,
+        &order.block_hash,
+    );
+    match entity.insert().await {
+        Ok(()) => Ok(()),
+        Err(DbError::UniqueViolation(_)) => {
+            debug!("{} order {} is already saved", order.order_type, order.id);
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
 
-    let deposit_orders = bridge.filter_deposit_event(&block_hash).await.unwrap();
-    if deposit_orders.is_empty() {
-        //info!("Not found deposit0 orders created at height {}",height);
-    } else {
+//确认数足够的订单改为Confirmed
+async fn confirm_orders(height: u64) -> Result<()> {
+    let confirmed_height = height.saturating_sub(ETH_TX_CONFIRM_BLOCK_NUM as u64);
+    let orders = EthBridgeOrderEntity::find(BridgeOrderFilter::ByStatusBelowHeight(
+        EthOrderStatus::Pending,
+        confirmed_height,
+    ))
+    .await?;
+    for order in orders {
         info!(
-            "filter_deposit_event {:?} at height {}",
-            deposit_orders, height
+            "confirm {} order {} at height {}",
+            order.order.order_type, order.order.id, order.order.height
         );
-        //todo: batch insert
-        for order in deposit_orders {
-            EthBridgeOrderEntity::update_single(
-                BridgeOrderUpdater::Status(EthOrderStatus::Confirmed),
-                BridgeOrderFilter::ByTypeAndId(OrderType::Deposit, &order.id),
-            )
-            .await?;
-        }
+        EthBridgeOrderEntity::update_single(
+            BridgeOrderUpdater::Status(EthOrderStatus::Confirmed),
+            BridgeOrderFilter::ByTypeAndId(order.order.order_type, &order.order.id),
+        )
+        .await?;
     }
+    Ok(())
+}
 
-    let withdraw_orders = bridge.filter_withdraw_event(&block_hash).await.unwrap();
+//扫描一批区块，已经扫到最新高度时返回false
+async fn scan_once(
+    bridge: &EthContractClient<Bridge>,
+    checkpoint: &mut Checkpoint,
+    conf: &EthBridgeConf,
+) -> Result<bool> {
+    if let Some(height) = find_reorg(checkpoint).await? {
+        rollback(checkpoint, height).await?;
+    }
 
-    if withdraw_orders.is_empty() {
-        //info!("Not found new filter_withdraw_event3 created at height {}", height);
-    } else {
+    let current_height = get_current_height().await?;
+    let from = checkpoint.tip() + 1;
+    if from > current_height {
+        return Ok(false);
+    }
+    let to = current_height.min(from + conf.batch_size.max(1) - 1);
+
+    //先确定区块hash再查日志，日志和它对不上说明扫描期间分叉了，退避之后整批重扫
+    let to_hash = block_hash_of(to).await?;
+    let mut orders = bridge.filter_deposit_event(from, to).await?;
+    orders.append(&mut bridge.filter_withdraw_event(from, to).await?);
+    orders.sort_by_key(|order| order.height);
+    check_canonical(&orders, to, &to_hash).await?;
+    if !orders.is_empty() {
         info!(
-            "filter_withdraw_event {:?} at height {}",
-            withdraw_orders, height
+            "found {} bridge orders in blocks {}..={}",
+            orders.len(),
+            from,
+            to
         );
-        for order in withdraw_orders {
-            EthBridgeOrderEntity::update_single(
-                BridgeOrderUpdater::Status(EthOrderStatus::Confirmed),
-                BridgeOrderFilter::ByTypeAndId(OrderType::Withdraw, &order.id),
-            )
-            .await?;
-        }
     }
-    Ok(())
+
+    for order in &orders {
+        save_order(order).await?;
+    }
+    //全部保存之后才推进，中途失败时下一轮从原来的位置重扫
+    for order in orders {
+        checkpoint.push(order.height, order.block_hash);
+    }
+    checkpoint.push(to, to_hash);
+    confirm_orders(to).await?;
+    save_checkpoint(checkpoint).await?;
+    Ok(true)
 }
 
 pub async fn start() -> Result<()> {
-    let mut last_process_height = get_last_process_height().await?;
+    let conf = &CONF.eth_bridge;
     let bridge: EthContractClient<Bridge> = EthContractClient::<Bridge>::new()?;
-    //let cli = EthContractClient::<crate::bridge_on_eth::Bridge>::new().await.unwrap();
-    loop {
-        let current_height = get_current_height().await?;
-        info!(
-            "current chain height1 {},wait for new block",
-            current_height
-        );
+    let mut backoff = Backoff {
+        failures: 0,
+        max: Duration::from_secs(conf.max_backoff_secs),
+    };
 
-        if last_process_height == current_height {
-            info!(
-                "current chain height2 {},wait for new block",
-                current_height
-            );
-            tokio::time::sleep(time::Duration::from_millis(1000)).await;
-        } else if last_process_height < current_height {
-            //初始化和监听最新区块复用了此逻辑(8区块的时候confirm，当前的pending)
-            for height in last_process_height + 1..=current_height {
-                listen_newest_block(&bridge, height).await?;
-                listen_confirmed_block(&bridge, height - ETH_TX_CONFIRM_BLOCK_NUM as u64).await?;
+    let mut checkpoint = loop {
+        match in_round(load_checkpoint()).await {
+            Ok(checkpoint) => break checkpoint,
+            Err(err) => backoff.wait(&err).await,
+        }
+    };
+    info!("eth_bridge scan from height {}", checkpoint.tip() + 1);
+
+    loop {
+        match in_round(scan_once(&bridge, &mut checkpoint, conf)).await {
+            Ok(true) => backoff.reset(),
+            Ok(false) => {
+                backoff.reset();
+                tokio::time::sleep(Duration::from_millis(conf.poll_interval_millis)).await;
             }
-            last_process_height = current_height;
-        } else {
-            //it is impossible for big than current_height
-            unreachable!()
+            Err(err) => backoff.wait(&err).await,
         }
     }
 }
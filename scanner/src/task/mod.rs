@@ -1,3 +1,21 @@
 pub mod chainless_coin_transfer;
 pub mod chainless_wallet_manage;
+pub mod coin_tx_archive;
+pub mod coin_tx_expiry;
 pub mod eth_bridge;
+pub mod outbox;
+pub mod refund_fee;
+
+use std::future::Future;
+
+/// run one round of a task in a fresh autocommit unit of work, the connection goes back
+/// to the pool after the round, so a dropped connection only fails the round it served
+pub async fn in_round<Fut, T, E>(round: Fut) -> anyhow::Result<T>
+where
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<anyhow::Error>,
+{
+    models::general::try_run_api_call("", round)
+        .await?
+        .map_err(Into::into)
+}
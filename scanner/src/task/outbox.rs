@@ -0,0 +1,75 @@
+use common::env::CONF;
+use common::utils::time::now_millis;
+use models::outbox::{OutboxEntity, OutboxFilter, OutboxUpdater};
+use models::PsqlOp;
+use tracing::{debug, warn};
+
+use super::in_round;
+use crate::sink::{self, EventSink};
+use anyhow::Result;
+
+async fn publish(sinks: &[Box<dyn EventSink>], entity: &OutboxEntity) -> Result<()> {
+    for sink in sinks {
+        sink.publish(&entity.event)
+            .await
+            .map_err(|err| anyhow::anyhow!("sink {} failed: {}", sink.name(), err))?;
+    }
+    Ok(())
+}
+
+/// delay before the next try of an event which has failed `attempts` times
+fn retry_delay_millis(attempts: u32) -> u64 {
+    let base = CONF.outbox.retry_base_secs * 1000;
+    let times = attempts.saturating_sub(1).min(32);
+    base.saturating_mul(1 << times)
+        .min(CONF.outbox.max_backoff_secs * 1000)
+}
+
+//投递一批事件，返回是否应该休眠
+async fn publish_batch(sinks: &[Box<dyn EventSink>]) -> Result<bool> {
+    let events = OutboxEntity::find(OutboxFilter::Pending(CONF.outbox.batch_size)).await?;
+    let mut blocked = false;
+    for event in &events {
+        let event_id = &event.event.event_id;
+        //保持事件的先后顺序，最早的事件还在退避时整批等待
+        if event.event.next_attempt_at > now_millis() {
+            blocked = true;
+            break;
+        }
+        debug!("publish event {} {}", event.event.event_type, event_id);
+        match publish(sinks, event).await {
+            Ok(()) => {
+                OutboxEntity::update_single(
+                    OutboxUpdater::Published,
+                    OutboxFilter::ByEventId(event_id),
+                )
+                .await?;
+            }
+            Err(err) => {
+                let delay = retry_delay_millis(event.event.attempts + 1);
+                warn!(
+                    "publish event {} failed: {}, retry in {} ms",
+                    event_id, err, delay
+                );
+                OutboxEntity::update_single(
+                    OutboxUpdater::Failed(&err.to_string(), now_millis() + delay),
+                    OutboxFilter::ByEventId(event_id),
+                )
+                .await?;
+                blocked = true;
+                break;
+            }
+        }
+    }
+    Ok(blocked || events.len() < CONF.outbox.batch_size as usize)
+}
+
+pub async fn start() -> Result<()> {
+    let sinks = sink::from_config(&CONF.outbox);
+    loop {
+        //投递失败之后总是先休眠，不会对下游反复重试
+        if in_round(publish_batch(&sinks)).await? {
+            tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
+        }
+    }
+}
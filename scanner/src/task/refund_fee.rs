@@ -0,0 +1,142 @@
+use blockchain::coin::Coin;
+use blockchain::fees_call::FeesCall;
+use blockchain::ContractClient;
+use common::data_structures::fee_refund::{FeeRefund, FeeRefundStatus};
+use common::env::CONF;
+use models::coin_transfer::{CoinTxEntity, CoinTxFilter};
+use models::error::DbError;
+use models::fee_refund::{
+    self, refund_account, FeeRefundEntity, FeeRefundFilter, FeeRefundUpdater,
+};
+use models::PsqlOp;
+use tracing::{debug, error, info, warn};
+
+use super::in_round;
+use anyhow::{anyhow, Result};
+
+//核对失败和取消的订单在链上扣过的手续费，每笔交易只记录一次，返回核对成功的数量
+async fn record_refunds(fees_cli: &ContractClient<FeesCall>, limit: u32) -> Result<usize> {
+    let txs = CoinTxEntity::find(CoinTxFilter::FeeUnrefunded(limit)).await?;
+    let mut recorded = 0;
+    for tx in &txs {
+        let tx = &tx.transaction;
+        let tx_id = match &tx.tx_id {
+            Some(tx_id) => tx_id,
+            None => continue,
+        };
+        //查询失败的先记为待核对，交给核对任务重试，不再占着这里的批次
+        let fee = match fees_cli.get_tx_base_fee(tx_id).await {
+            Ok(fee) => fee,
+            Err(err) => {
+                warn!("query fee of tx {} failed: {}", tx_id, err);
+                fee_refund::record_failed_attempt(tx_id, &tx.order_id, refund_account(tx)).await?;
+                continue;
+            }
+        };
+        debug!("order {} tx {} charged fee {:?}", tx.order_id, tx_id, fee);
+        let refund =
+            FeeRefundEntity::new_with_specified(tx_id, &tx.order_id, refund_account(tx), fee);
+        match refund.insert().await {
+            Ok(()) => {}
+            Err(DbError::UniqueViolation(_)) => {
+                debug!("fee of tx {} is already recorded", tx_id);
+            }
+            Err(err) => Err(err)?,
+        }
+        recorded += 1;
+    }
+    Ok(recorded)
+}
+
+//订单重新提交之前失败的那些交易，补上手续费
+async fn check_failed_attempts(fees_cli: &ContractClient<FeesCall>, limit: u32) -> Result<usize> {
+    let refunds =
+        FeeRefundEntity::find(FeeRefundFilter::ByStatus(FeeRefundStatus::Unchecked, limit)).await?;
+    let mut checked = 0;
+    for refund in refunds {
+        let tx_id = &refund.refund.tx_id;
+        //查询失败的刷新一下更新时间，排到队尾，不挡住后面的记录
+        let fee = match fees_cli.get_tx_base_fee(tx_id).await {
+            Ok(fee) => fee,
+            Err(err) => {
+                warn!("query fee of tx {} failed: {}", tx_id, err);
+                FeeRefundEntity::update(
+                    FeeRefundUpdater::Status(FeeRefundStatus::Unchecked),
+                    FeeRefundFilter::ByTxIdStatus(tx_id, FeeRefundStatus::Unchecked),
+                )
+                .await?;
+                continue;
+            }
+        };
+        debug!("failed attempt {} charged fee {:?}", tx_id, fee);
+        FeeRefundEntity::update(
+            FeeRefundUpdater::Checked(fee),
+            FeeRefundFilter::ByTxIdStatus(tx_id, FeeRefundStatus::Unchecked),
+        )
+        .await?;
+        checked += 1;
+    }
+    Ok(checked)
+}
+
+async fn send_refund(refund: &FeeRefund) -> Result<String> {
+    let fee_coin = refund
+        .fee_coin
+        .clone()
+        .ok_or(anyhow!("fee coin of tx {} is missing", refund.tx_id))?;
+    let mut coin_cli = ContractClient::<Coin>::new_update_cli(fee_coin).await?;
+    coin_cli.send_coin(&refund.account_id, refund.amount).await
+}
+
+//先占用再转账，发出之后不论结果如何都不会自动重发，失败的需要人工核对
+async fn send_refunds(limit: u32) -> Result<()> {
+    let refunds =
+        FeeRefundEntity::find(FeeRefundFilter::ByStatus(FeeRefundStatus::Pending, limit)).await?;
+    for refund in refunds {
+        let refund = refund.into_inner();
+        if !fee_refund::claim(&refund.tx_id).await? {
+            debug!("refund of tx {} is taken by others", refund.tx_id);
+            continue;
+        }
+        match send_refund(&refund).await {
+            Ok(refund_tx_id) => {
+                info!(
+                    "refund {} fee of tx {} to {} in tx {}",
+                    refund.amount, refund.tx_id, refund.account_id, refund_tx_id
+                );
+                FeeRefundEntity::update_single(
+                    FeeRefundUpdater::Refunded(&refund_tx_id),
+                    FeeRefundFilter::ByTxId(&refund.tx_id),
+                )
+                .await?;
+            }
+            Err(err) => {
+                error!("refund fee of tx {} failed: {}", refund.tx_id, err);
+                FeeRefundEntity::update_single(
+                    FeeRefundUpdater::Failed(&err.to_string()),
+                    FeeRefundFilter::ByTxId(&refund.tx_id),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn start() -> Result<()> {
+    let conf = &CONF.refund_fee;
+    let fees_cli = ContractClient::<FeesCall>::new_query_cli().await?;
+    loop {
+        let (recorded, checked) = in_round(async {
+            let recorded = record_refunds(&fees_cli, conf.batch_size).await?;
+            let checked = check_failed_attempts(&fees_cli, conf.batch_size).await?;
+            send_refunds(conf.batch_size).await?;
+            Ok::<_, anyhow::Error>((recorded, checked))
+        })
+        .await?;
+        //没有剩余待核对的订单了才休眠
+        if recorded < conf.batch_size as usize && checked < conf.batch_size as usize {
+            tokio::time::sleep(std::time::Duration::from_secs(conf.interval_secs)).await;
+        }
+    }
+}
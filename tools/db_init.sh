@@ -1,7 +1,4 @@
 #!/bin/bash
-##sudo -u postgres psql -d backend_local -U postgres -f down.sql
-sudo psql -d backend_local -U postgres -f down.sql
-##sudo -u postgres psql -d backend_local -U postgres -f up.sql
-sudo psql -d backend_local -U postgres -f up.sql
-
-##psql -U postgres -d postgres -h 127.0.0.1 -p 5432
\ No newline at end of file
+#先回滚全部迁移再重新执行，CONFIG需要指向目标库的配置
+cargo run -p models --bin migrate -- down --target 0
+cargo run -p models --bin migrate -- up